@@ -0,0 +1,15 @@
+// Generates the gRPC service from proto/wikipedia.proto when the grpc feature is on, with a vendored protoc so
+// building doesn't need one installed
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto/wikipedia.proto");
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No vendored protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/wikipedia.proto"], &["proto"])
+            .expect("Failed to compile proto/wikipedia.proto");
+    }
+}
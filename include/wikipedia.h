@@ -0,0 +1,71 @@
+/* C API for reading articles and links out of a dump prepared by the wikipedia tool. Build the library with
+ *
+ *   cargo rustc --release --lib --features cdylib --crate-type cdylib
+ *
+ * and link against target/release/libwikipedia.so (.dylib on macOS, wikipedia.dll on Windows).
+ *
+ * Page IDs are always 64-bit. A handle can be shared between threads. Functions that fail return NULL or -1 and
+ * leave a message for wiki_last_error. Strings and ID arrays returned by the library belong to the caller, who must
+ * release them with wiki_string_free and wiki_ids_free.
+ */
+
+#ifndef WIKIPEDIA_H
+#define WIKIPEDIA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WIKI_ABI_VERSION 1
+
+typedef struct WikiHandle WikiHandle;
+
+/* The ABI version the library was built with, to compare with WIKI_ABI_VERSION */
+uint32_t wiki_abi_version(void);
+
+/* The message of the last error on the calling thread, or NULL. Valid until the next failing call on that thread. */
+const char *wiki_last_error(void);
+
+/* Opens the dump in data_path, keeping up to cache_size decompressed chunks in memory (0 for the default). Loading
+ * the index takes a while on a full dump. Returns NULL if the directory has no dump or its index files can't be
+ * read. */
+WikiHandle *wiki_open(const char *data_path, size_t cache_size);
+
+/* Closes a handle from wiki_open. Passing NULL does nothing. */
+void wiki_close(WikiHandle *handle);
+
+/* The number of pages in the dump's index */
+uint64_t wiki_article_count(const WikiHandle *handle);
+
+/* Looks up a title, ignoring case. Returns 1 and stores the page's ID in *id (if id isn't NULL) when found, 0 when
+ * there's no such page, and -1 on error. */
+int32_t wiki_find(const WikiHandle *handle, const char *title, uint64_t *id);
+
+/* The title of a page, or NULL if there's no page with that ID */
+char *wiki_title(const WikiHandle *handle, uint64_t id);
+
+/* The wikitext of a page, or NULL if there's no page with that ID */
+char *wiki_article_text(const WikiHandle *handle, uint64_t id);
+
+/* The IDs of the articles a page links to, storing their number in *length. Returns NULL if links.bin hasn't been
+ * built by the index command. The first call loads the whole graph unless the index was split. */
+uint64_t *wiki_links(const WikiHandle *handle, uint64_t id, size_t *length);
+
+/* The IDs of the articles that link to a page, storing their number in *length. The first call loads the whole
+ * graph. Returns NULL if links.bin hasn't been built by the index command. */
+uint64_t *wiki_backlinks(const WikiHandle *handle, uint64_t id, size_t *length);
+
+/* Frees a string returned by the library. Passing NULL does nothing. */
+void wiki_string_free(char *string);
+
+/* Frees an ID array returned by the library, along with the length it was returned with. Passing NULL does nothing. */
+void wiki_ids_free(uint64_t *ids, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,182 @@
+// Python bindings for reading articles straight out of a dump and querying the link graph the index command builds.
+// Build and install into the current environment with `maturin develop --release` from this directory, then:
+//
+//   import wikipedia_py
+//   dump = wikipedia_py.Dump("data")
+//   text = dump.get("Alan Turing")
+//   for article_id, title, text in dump:
+//       ...
+//   graph = wikipedia_py.Graph("data")
+//   graph.neighbors(graph.find("Alan Turing"))
+//   [graph.title(id) for id in graph.shortest_path(graph.find("Alan Turing"), graph.find("Philosophy"))]
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use pyo3::exceptions::{PyFileNotFoundError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use rustc_hash::FxHashMap;
+use wikipedia::helpers::{PageId, find_dump_files};
+use wikipedia::lookup::{ArticleLookup, DEFAULT_CACHE_SIZE};
+use wikipedia::query::shortest_path;
+use wikipedia::split::{try_load_graph, try_load_titles};
+
+fn check_data_path(data_path: &Path) -> PyResult<()> {
+    if data_path.is_dir() {
+        Ok(())
+    } else {
+        Err(PyFileNotFoundError::new_err(format!("No data directory at {}", data_path.display())))
+    }
+}
+
+// Random access to the articles of a multistream dump by title or ID, keeping the last `cache_size` decompressed
+// chunks in memory. Iterating over it yields (id, title, wikitext) tuples for every page, chunk by chunk in file order.
+#[pyclass]
+struct Dump {
+    lookup: Arc<ArticleLookup>,
+}
+
+#[pymethods]
+impl Dump {
+    #[new]
+    #[pyo3(signature = (data_path, cache_size = DEFAULT_CACHE_SIZE))]
+    fn new(py: Python<'_>, data_path: PathBuf, cache_size: usize) -> PyResult<Self> {
+        check_data_path(&data_path)?;
+        let lookup = py.allow_threads(|| {
+            let (index_path, articles_path) = find_dump_files(&data_path)?;
+            Ok::<_, String>(ArticleLookup::new(&index_path, &articles_path, cache_size))
+        }).map_err(PyFileNotFoundError::new_err)?;
+        Ok(Dump { lookup: Arc::new(lookup) })
+    }
+
+    fn __len__(&self) -> usize {
+        self.lookup.len()
+    }
+
+    fn __contains__(&self, title: &str) -> bool {
+        self.lookup.find(title).is_some()
+    }
+
+    // The ID of the page with the given title, ignoring case
+    fn find(&self, title: &str) -> Option<PageId> {
+        self.lookup.find(title)
+    }
+
+    fn title(&self, id: PageId) -> Option<String> {
+        self.lookup.title(id).map(str::to_string)
+    }
+
+    // The wikitext of the page with the given title, or None if there's no such page
+    fn get(&self, py: Python<'_>, title: &str) -> Option<String> {
+        let id = self.lookup.find(title)?;
+        py.allow_threads(|| self.lookup.get(id))
+    }
+
+    fn get_by_id(&self, py: Python<'_>, id: PageId) -> Option<String> {
+        py.allow_threads(|| self.lookup.get(id))
+    }
+
+    // (id, title) pairs of the pages whose titles contain `query`, shortest titles first
+    #[pyo3(signature = (query, limit = 20))]
+    fn search(&self, query: &str, limit: usize) -> Vec<(PageId, String)> {
+        self.lookup.search(query, limit).into_iter().map(|id| (id, self.lookup.title(id).unwrap_or_default().to_string())).collect()
+    }
+
+    fn __iter__(&self) -> Articles {
+        Articles { lookup: Arc::clone(&self.lookup), next_chunk: 0, pending: Vec::new().into_iter() }
+    }
+}
+
+#[pyclass]
+struct Articles {
+    lookup: Arc<ArticleLookup>,
+    next_chunk: usize,
+    pending: std::vec::IntoIter<(PageId, String, String)>,  // the rest of the current chunk's pages
+}
+
+#[pymethods]
+impl Articles {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> Option<(PageId, String, String)> {
+        loop {
+            if let Some(article) = self.pending.next() {
+                return Some(article);
+            }
+            let position = *self.lookup.positions().get(self.next_chunk)?;
+            self.next_chunk += 1;
+            let lookup = &self.lookup;
+            let mut articles: Vec<(PageId, String, String)> = py.allow_threads(|| {
+                lookup.get_chunk(position).iter().map(|(id, (title, text))| (*id, title.clone(), text.clone())).collect()
+            });
+            articles.sort_unstable_by_key(|(id, _, _)| *id);
+            self.pending = articles.into_iter();
+        }
+    }
+}
+
+// The link graph from links.bin, or graph.bin and titles.bin when the index wrote the split files
+#[pyclass]
+struct Graph {
+    links: FxHashMap<PageId, Vec<PageId>>,
+    titles: FxHashMap<PageId, String>,
+    ids: HashMap<String, PageId>,  // lowercase title -> id
+}
+
+#[pymethods]
+impl Graph {
+    #[new]
+    fn new(py: Python<'_>, data_path: PathBuf) -> PyResult<Self> {
+        check_data_path(&data_path)?;
+        // Stale or corrupt index files raise ValueError, since exiting would take the interpreter down with it
+        let (links, titles) = py.allow_threads(|| Ok::<_, String>((try_load_graph(&data_path)?, try_load_titles(&data_path)?)))
+            .map_err(PyValueError::new_err)?;
+        let (Some(mut links), Some(titles)) = (links, titles) else {
+            return Err(PyFileNotFoundError::new_err(format!("links.bin not found in {}, run the index command first", data_path.display())));
+        };
+        for article_links in links.values_mut() {
+            article_links.sort_unstable();
+            article_links.dedup();
+        }
+        let ids = titles.iter().map(|(id, title)| (title.to_lowercase(), *id)).collect();
+        Ok(Graph { links, titles, ids })
+    }
+
+    fn __len__(&self) -> usize {
+        self.links.len()
+    }
+
+    fn find(&self, title: &str) -> Option<PageId> {
+        self.ids.get(&title.trim().to_lowercase()).copied()
+    }
+
+    fn title(&self, id: PageId) -> Option<String> {
+        self.titles.get(&id).cloned()
+    }
+
+    // The IDs of the articles `id` links to, in ascending order
+    fn neighbors(&self, id: PageId) -> PyResult<Vec<PageId>> {
+        self.links.get(&id).cloned().ok_or_else(|| PyKeyError::new_err(id))
+    }
+
+    // The IDs of the articles on a shortest path of links from `source` to `target`, both included, or None if
+    // `target` can't be reached
+    fn shortest_path(&self, py: Python<'_>, source: PageId, target: PageId) -> PyResult<Option<Vec<PageId>>> {
+        for id in [source, target] {
+            if !self.links.contains_key(&id) {
+                return Err(PyKeyError::new_err(id));
+            }
+        }
+        Ok(py.allow_threads(|| shortest_path(&self.links, source, target)))
+    }
+}
+
+#[pymodule]
+fn wikipedia_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Dump>()?;
+    module.add_class::<Articles>()?;
+    module.add_class::<Graph>()?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::Request;
+use hyper::body::{Bytes, Incoming};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use indicatif::ProgressBar;
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Runtime;
+use tracing::warn;
+use crate::storage::{HttpRequest, RETRIES};
+
+// Threads driving the network I/O. Requests spend nearly all their time waiting, so a couple of threads keep hundreds
+// of them going, while decompressing and parsing what they fetch stays on the blocking pool and the pipeline's workers.
+const IO_THREADS: usize = 2;
+
+type HttpClient = Client<HttpsConnector<HttpConnector>, Empty<Bytes>>;
+
+// The runtime shared by the HTTP server, the range requests for remote dumps and downloads, started on first use
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread().worker_threads(IO_THREADS).thread_name("async-io").enable_all().build()
+            .expect("Failed to start the async runtime")
+    })
+}
+
+// Connections are pooled, so the requests for the chunks of a remote dump reuse a few of them
+fn client() -> &'static HttpClient {
+    static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let connector = HttpsConnectorBuilder::new().with_webpki_roots().https_or_http().enable_http1().build();
+        Client::builder(TokioExecutor::new()).build(connector)
+    })
+}
+
+async fn send(request: &HttpRequest) -> Result<hyper::Response<Incoming>, String> {
+    let mut builder = Request::get(&request.url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    let response = client().request(builder.body(Empty::new()).map_err(|err| err.to_string())?).await.map_err(|err| err.to_string())?;
+    if response.status().as_u16() >= 400 {
+        return Err(format!("GET {} returned {}", request.url, response.status()));
+    }
+    Ok(response)
+}
+
+// Like storage::retry, but waits on a timer rather than holding up a thread
+pub async fn retry<T, F: Future<Output = Result<T, String>>>(description: &str, mut request: impl FnMut() -> F) -> Result<T, String> {
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(err) if attempt < RETRIES => {
+                attempt += 1;
+                warn!("{} failed, retrying: {}", description, err);
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Reads a range of an object, checking that the server honored the range rather than sending the whole file
+pub async fn read_range(request: HttpRequest, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    let response = send(&request).await?;
+    if response.status() != 206 {
+        return Err(format!("expected a partial response but got {}", response.status()));
+    }
+    let body = Limited::new(response.into_body(), (end - start) as usize + 1).collect().await.map_err(|err| err.to_string())?;
+    let buffer = body.to_bytes().to_vec();
+    if buffer.len() as u64 != end - start {
+        return Err(format!("got {} of {} bytes", buffer.len(), end - start));
+    }
+    Ok(buffer)
+}
+
+// Streams an object to a file, adding its size to the progress bar's length once the response gives it
+pub async fn download(request: HttpRequest, path: PathBuf, progress_bar: ProgressBar) -> Result<(), String> {
+    let response = send(&request).await?;
+    let length = response.headers().get("Content-Length").and_then(|length| length.to_str().ok()?.parse().ok());
+    progress_bar.inc_length(length.unwrap_or(0));
+    let mut file = tokio::fs::File::create(&path).await.map_err(|err| err.to_string())?;
+    let mut body = response.into_body();
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame.map_err(|err| err.to_string())?.into_data() {
+            file.write_all(&data).await.map_err(|err| err.to_string())?;
+            progress_bar.inc(data.len() as u64);
+        }
+    }
+    file.flush().await.map_err(|err| err.to_string())
+}
@@ -1,54 +1,241 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::path::Path;
 use std::fs::File;
-use std::io::{BufReader, Read};
-use indicatif::ProgressIterator;
-use crate::helpers::create_progress_bar;
-
-pub fn analyse(data_path: &Path) {
-    let links_file_path = data_path.join("links.bin");
-    if !links_file_path.exists() {
-        eprintln!("Error: Unable to locate links.bin in {}", data_path.to_str().unwrap());
-        std::process::exit(1);
-    }
-
-    let file = File::open(&links_file_path).expect("Unable to open links.bin");
-    let mut reader = BufReader::new(file);
-    let mut buffer = Vec::new();
-    reader.read_to_end(&mut buffer).expect("Unable to read links.bin");
-
-    // Parse the binary data
-    let progress_bar = create_progress_bar(buffer.len() as u64, "Parsing links.bin");
-    let mut links: HashMap<u32, Vec<u32>> = HashMap::new();
-    let mut titles: HashMap<u32, String> = HashMap::new();
-    let mut i = 0;
-    while i < buffer.len() {
-        let article_id = u32::from_le_bytes(buffer[i..i+4].try_into().unwrap());
-        let title_length = u32::from_le_bytes(buffer[i+4..i+8].try_into().unwrap()) as usize;
-        let title = String::from_utf8_lossy(&buffer[i+8..i+8+title_length]).to_string();
-        let link_count = u32::from_le_bytes(buffer[i+8+title_length..i+8+title_length+4].try_into().unwrap()) as usize;
-        let article_links: Vec<u32> = (0..link_count)
-            .map(|j| { u32::from_le_bytes(buffer[i+8+title_length+4+4*j..i+8+title_length+4+4*j+4].try_into().unwrap()) })
-            .collect();
-        let separator = u32::from_le_bytes(buffer[i+8+title_length+4+4*link_count..i+8+title_length+4+4*link_count+4].try_into().unwrap());
-        assert_eq!(separator, u32::MAX, "Expected separator u32::MAX not found");
-
-        i += 8 + title_length + 4 + 4 * link_count + 4;
-        titles.insert(article_id, title.to_lowercase());
-        links.insert(article_id, article_links);
-
-        progress_bar.set_position(i as u64);
-    }
-    progress_bar.finish_and_clear();
+use std::io::{BufWriter, Write};
+use indicatif::{ParallelProgressIterator, ProgressIterator};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use crate::categories::{CATEGORIES_FILE, DEFAULT_ROOT, read_categories, top_level_groups};
+use crate::graph::{build_dense_graph, build_undirected_graph, core_numbers};
+use crate::helpers::{Args, PageId, create_progress_bar};
+use crate::hyperanf::{DEFAULT_REGISTERS, print_distance_stats};
+use crate::links::{LinkGraph, PageInfo, load_links};
+use crate::namespaces::namespace_name;
+use crate::positions::{POSITIONS_FILE, print_position_stats};
+use crate::files::{FILES_FILE, print_file_stats};
+use crate::redirects::{BROKEN_REDIRECTS_FILE, DOUBLE_REDIRECTS_FILE, write_redirect_reports};
+use crate::split::{RecordIndex, read_graph, read_page_info, split_files_exist};
+use crate::summary::RunSummary;
+
+const INNERMOST_CORE_FILE: &str = "innermost-core.tsv";
+
+fn percentile(sorted: &[u32], fraction: f64) -> u32 {
+    sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
+
+// Pearson correlation of log(1 + x) with log(1 + y), since both sizes and degrees are heavy-tailed
+fn log_correlation(pairs: &[(f64, f64)]) -> f64 {
+    let n = pairs.len() as f64;
+    let logs: Vec<(f64, f64)> = pairs.iter().map(|&(x, y)| (x.ln_1p(), y.ln_1p())).collect();
+    let (mean_x, mean_y) = logs.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x / n, sy + y / n));
+    let (covariance, variance_x, variance_y) = logs.iter().fold((0.0, 0.0, 0.0), |(c, vx, vy), &(x, y)| {
+        (c + (x - mean_x) * (y - mean_y), vx + (x - mean_x).powi(2), vy + (y - mean_y).powi(2))
+    });
+    covariance / (variance_x * variance_y).sqrt()
+}
+
+// Reports the distribution of article sizes, the longest and shortest articles, and how size relates to link
+// degree. Redirects are left out, since they're all a single line.
+fn print_size_stats(pages: &FxHashMap<PageId, PageInfo>, links: &FxHashMap<PageId, Vec<PageId>>, incoming: &FxHashMap<PageId, usize>, title: impl Fn(&PageId) -> String) {
+    let mut articles: Vec<(PageId, PageInfo)> = pages.iter().filter(|(_, info)| !info.redirect).map(|(id, info)| (*id, *info)).collect();
+    if articles.is_empty() || articles.iter().all(|(_, info)| info.text_length == 0) {
+        println!("\nArticle sizes: not recorded in this links file, re-run the index command");
+        return;
+    }
+
+    println!("\nArticle sizes:");
+    let bytes = articles.iter().map(|(_, info)| info.text_length).collect();
+    let words = articles.iter().map(|(_, info)| info.word_count).collect();
+    for (name, mut values) in [("Bytes", bytes), ("Words", words)] as [(&str, Vec<u32>); 2] {
+        values.sort_unstable();
+        let mean = values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64;
+        println!("  {}: min {}, median {}, mean {:.0}, p90 {}, p99 {}, max {}",
+            name, values[0], percentile(&values, 0.5), mean, percentile(&values, 0.9), percentile(&values, 0.99), values[values.len() - 1]);
+    }
+
+    let degree_pairs = |degree: &dyn Fn(&PageId) -> usize| -> Vec<(f64, f64)> {
+        articles.iter().map(|(id, info)| (info.word_count as f64, degree(id) as f64)).collect()
+    };
+    let outgoing = degree_pairs(&|id| links.get(id).map_or(0, Vec::len));
+    let incoming = degree_pairs(&|id| incoming.get(id).copied().unwrap_or(0));
+    println!("  Correlation of word count with outgoing links: {:.3}", log_correlation(&outgoing));
+    println!("  Correlation of word count with incoming links: {:.3}", log_correlation(&incoming));
+
+    articles.sort_by_key(|&(id, info)| (std::cmp::Reverse(info.word_count), id));
+    println!("\nTop 10 longest articles:");
+    for (rank, (article_id, info)) in articles.iter().take(10).enumerate() {
+        println!("{:>2}) {} ({} words)", rank + 1, title(article_id), info.word_count);
+    }
+    println!("\nTop 10 shortest articles:");
+    for (rank, (article_id, info)) in articles.iter().rev().take(10).enumerate() {
+        println!("{:>2}) {} ({} words)", rank + 1, title(article_id), info.word_count);
+    }
+}
+
+// Reports the page and link counts and the out- and in-degrees of each group of pages, largest group first
+fn print_breakdown(heading: &str, groups: &[(String, Vec<PageId>)], links: &FxHashMap<PageId, Vec<PageId>>, incoming: &FxHashMap<PageId, usize>) {
+    println!("\n{}:", heading);
+    for (name, ids) in groups {
+        let mut outgoing: Vec<u32> = ids.iter().map(|id| links.get(id).map_or(0, Vec::len) as u32).collect();
+        let mut incoming: Vec<u32> = ids.iter().map(|id| incoming.get(id).copied().unwrap_or(0) as u32).collect();
+        outgoing.sort_unstable();
+        incoming.sort_unstable();
+        let total_links: u64 = outgoing.iter().map(|&degree| degree as u64).sum();
+        let mean = |degrees: &[u32]| degrees.iter().map(|&degree| degree as f64).sum::<f64>() / degrees.len().max(1) as f64;
+        println!("  {}: {} pages, {} links, out-degree mean {:.2} median {} max {}, in-degree mean {:.2} median {} max {}",
+            name, ids.len(), total_links, mean(&outgoing), percentile(&outgoing, 0.5), outgoing[outgoing.len() - 1],
+            mean(&incoming), percentile(&incoming, 0.5), incoming[incoming.len() - 1]);
+    }
+}
+
+// Breaks the statistics down by namespace, and by top-level category when the index recorded categories.bin
+fn print_group_stats(data_path: &Path, args: &Args, links: &FxHashMap<PageId, Vec<PageId>>, pages: &FxHashMap<PageId, PageInfo>, incoming: &FxHashMap<PageId, usize>, title: impl Fn(&PageId) -> Option<String>) {
+    let mut namespaces: FxHashMap<u32, Vec<PageId>> = FxHashMap::default();
+    for id in links.keys() {
+        namespaces.entry(pages.get(id).map_or(0, |info| info.namespace)).or_default().push(*id);
+    }
+    let mut namespaces: Vec<(u32, Vec<PageId>)> = namespaces.into_iter().collect();
+    namespaces.sort_unstable_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+    let groups: Vec<(String, Vec<PageId>)> = namespaces.into_iter()
+        .map(|(namespace, ids)| (namespace_name(namespace).map_or_else(|| format!("Namespace {}", namespace), |name| format!("{} ({})", name, namespace)), ids))
+        .collect();
+    print_breakdown("By namespace", &groups, links, incoming);
+
+    let categories_path = data_path.join(CATEGORIES_FILE);
+    if categories_path.exists() {
+        let categories = read_categories(&categories_path);
+        let (description, groups) = top_level_groups(&categories, pages, args.value("top-category").unwrap_or(DEFAULT_ROOT), title);
+        print_breakdown(&format!("By top-level category ({})", description), &groups, links, incoming);
+    }
+}
+
+// Reports self-links, how many links are reciprocated, and the pairs of articles with the most links between them.
+// Expects each article's links to be sorted. Returns the number of self-links and reciprocated links.
+fn print_reciprocity_stats(links: &FxHashMap<PageId, Vec<PageId>>, incoming: &FxHashMap<PageId, usize>, title: impl Fn(&PageId) -> String) -> (usize, usize) {
+    let occurrences = |links: &[PageId], target: PageId| links.binary_search(&target).map_or(0, |index| {
+        let start = links[..index].iter().rev().take_while(|&&id| id == target).count();
+        let end = links[index..].iter().take_while(|&&id| id == target).count();
+        start + end
+    });
+    let popularity = |id: &PageId| incoming.get(id).copied().unwrap_or(0);
+
+    // Each worker keeps its own top pairs, ranked by the links between them and then by the less linked-to article
+    let progress_bar = create_progress_bar(links.len() as u64, "Finding reciprocal links");
+    let (self_links, articles_with_self_links, edges, reciprocated, mut top_pairs) = links.par_iter()
+        .progress_with(progress_bar)
+        .fold(|| (0, 0, 0, 0, Vec::new()), |(mut self_links, mut articles_with_self_links, mut edges, mut reciprocated, mut top_pairs), (&id, article_links)| {
+            let own = occurrences(article_links, id);
+            self_links += own;
+            articles_with_self_links += (own > 0) as usize;
+            let mut targets = article_links.clone();
+            targets.dedup();
+            for target in targets.into_iter().filter(|&target| target != id) {
+                edges += 1;
+                let Some(back) = links.get(&target).map(|target_links| occurrences(target_links, id)).filter(|&back| back > 0) else { continue };
+                reciprocated += 1;
+                if id < target {
+                    top_pairs.push((occurrences(article_links, target) + back, popularity(&id).min(popularity(&target)), id, target));
+                    if top_pairs.len() > 100 {
+                        top_pairs.sort_unstable_by(|a, b| b.cmp(a));
+                        top_pairs.truncate(10);
+                    }
+                }
+            }
+            (self_links, articles_with_self_links, edges, reciprocated, top_pairs)
+        })
+        .reduce(|| (0, 0, 0, 0, Vec::new()), |a, b| {
+            (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3, a.4.into_iter().chain(b.4).collect())
+        });
+    top_pairs.sort_unstable_by(|a, b| b.cmp(a));
+
+    println!("\nSelf-links: {} in {} articles", self_links, articles_with_self_links);
+    println!("Reciprocated links: {} of {} ({:.1}%)", reciprocated, edges, 100.0 * reciprocated as f64 / edges.max(1) as f64);
+    println!("\nTop 10 mutually linked pairs:");
+    for (rank, (link_count, _, a, b)) in top_pairs.iter().take(10).enumerate() {
+        println!("{:>2}) {} <-> {} ({})", rank + 1, title(a), title(b), link_count);
+    }
+    (self_links, reciprocated)
+}
+
+// Reports how core numbers are distributed and writes the articles in the innermost core to `data_path`. Returns the
+// degeneracy (the largest core number) and the size of the innermost core.
+fn print_kcore_stats(data_path: &Path, links: &FxHashMap<PageId, Vec<PageId>>, title: impl Fn(&PageId) -> String) -> (usize, usize) {
+    let graph = build_dense_graph(links);
+    let cores = core_numbers(&build_undirected_graph(&graph));
+    let degeneracy = cores.iter().max().copied().unwrap_or(0);
+
+    // Group the core numbers into power-of-two ranges: 0, 1, 2-3, 4-7, ...
+    let mut ranges = [0; 33];
+    for &core in &cores {
+        ranges[(u32::BITS - core.leading_zeros()) as usize] += 1;
+    }
+    println!("\nk-core decomposition (links treated as undirected):");
+    println!("  Degeneracy: {}", degeneracy);
+    for (range, count) in ranges.iter().enumerate().filter(|(_, count)| **count > 0) {
+        let label = match range {
+            0 => "0".to_string(),
+            1 => "1".to_string(),
+            _ => format!("{}-{}", 1u64 << (range - 1), (1u64 << range) - 1),
+        };
+        println!("  Core {}: {} articles", label, count);
+    }
+
+    let innermost: Vec<PageId> = cores.iter().enumerate().filter(|(_, &core)| core == degeneracy).map(|(node, _)| graph.ids[node]).collect();
+    let mut output_file = BufWriter::new(File::create(data_path.join(INNERMOST_CORE_FILE)).expect("Failed to create output file"));
+    writeln!(output_file, "article_id\ttitle").expect("Failed to write output file");
+    for id in &innermost {
+        writeln!(output_file, "{}\t{}", id, title(id)).expect("Failed to write output file");
+    }
+    output_file.flush().expect("Failed to flush output file");
+    println!("  Innermost core: {} articles (listed in {})", innermost.len(), INNERMOST_CORE_FILE);
+    (degeneracy as usize, innermost.len())
+}
+
+pub fn analyse(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let mut summary = RunSummary::new("analyse", args);
+
+    // With the split files, only the graph is read up front and the few titles that get printed are fetched by ID
+    let record_index = split_files_exist(data_path).then(|| RecordIndex::open(data_path));
+    let (mut links, titles, pages) = match record_index {
+        Some(_) => {
+            summary.stage("hash inputs");
+            for file_name in ["titles.bin", "graph.bin", "offsets.idx"] {
+                summary.input(&data_path.join(file_name));
+            }
+            summary.stage("load graph");
+            (read_graph(data_path), FxHashMap::default(), read_page_info(data_path))
+        }
+        None => {
+            let links_file_path = data_path.join("links.bin");
+            if !links_file_path.exists() {
+                eprintln!("Error: Unable to locate links.bin in {}", data_path.to_str().unwrap());
+                std::process::exit(1);
+            }
+            summary.stage("hash inputs");
+            summary.input(&links_file_path);
+            summary.stage("load graph");
+            let LinkGraph { links, titles, pages } = load_links(&links_file_path);
+            (links, titles, pages)
+        }
+    };
+    let original_title = |id: &PageId| match &record_index {
+        Some(record_index) => record_index.title(*id),
+        None => titles.get(id).cloned(),
+    };
+    let title = |id: &PageId| original_title(id).map(|title| title.to_lowercase()).unwrap_or_else(|| format!("Unknown (ID: {})", id));
     println!("Found {} articles", links.len());
+    // Version 1 files don't keep each article's links sorted
+    links.par_iter_mut().for_each(|(_, article_links)| article_links.sort_unstable());
 
     // Analyze the link structure
+    summary.stage("analyse links");
     let total_articles = links.len();
     let total_links: usize = links.values().map(|v| v.len()).sum();
     let articles_with_links = links.values().filter(|v| !v.is_empty()).count();
 
     let progress_bar = create_progress_bar(links.len() as u64, "Analyzing links");
-    let mut unique_links = HashSet::<u32>::new();
+    let mut unique_links = HashSet::<PageId>::new();
     for links in links.values().progress_with(progress_bar) {
         unique_links.extend(links);
     }
@@ -57,14 +244,23 @@ pub fn analyse(data_path: &Path) {
     let mut outgoing_links = links.iter().map(|(k, v)| (*k, v.len())).collect::<Vec<_>>();
     outgoing_links.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
 
+    // Each rayon worker counts into its own map, and the per-worker maps are merged at the end
     let progress_bar = create_progress_bar(links.len() as u64, "Calculating incoming links");
-    let mut incoming_links = HashMap::new();
-    for (_, links) in links.iter().progress_with(progress_bar) {
-        for &link in links {
-            *incoming_links.entry(link).or_insert(0) += 1;
-        }
-    }
-    let mut incoming_links = incoming_links.into_iter().collect::<Vec<_>>();
+    let incoming_counts = links.par_iter()
+        .progress_with(progress_bar)
+        .fold(FxHashMap::default, |mut incoming_links, (_, links)| {
+            for &link in links {
+                *incoming_links.entry(link).or_insert(0) += 1;
+            }
+            incoming_links
+        })
+        .reduce(FxHashMap::default, |mut merged, incoming_links| {
+            for (link, count) in incoming_links {
+                *merged.entry(link).or_insert(0) += count;
+            }
+            merged
+        });
+    let mut incoming_links = incoming_counts.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
     incoming_links.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
 
     // Print analysis results
@@ -73,14 +269,63 @@ pub fn analyse(data_path: &Path) {
     println!("Articles with outgoing links: {}", articles_with_links);
     println!("Unique link targets: {}", unique_links.len());
     println!("Average links per article: {:.2}", total_links as f64 / total_articles as f64);
+    let redirects = pages.values().filter(|info| info.redirect).count();
+    let non_main_namespace_pages = pages.values().filter(|info| info.namespace != 0).count();
+    println!("Redirect pages: {}", redirects);
+    println!("Pages outside the main namespace: {}", non_main_namespace_pages);
+    let (double_redirects, broken_redirects) = match redirects {
+        0 => (0, 0),
+        _ => write_redirect_reports(data_path, &links, &pages, |id| original_title(id).unwrap_or_else(|| format!("Unknown (ID: {})", id))),
+    };
+    if redirects > 0 {
+        println!("Double redirects: {} (listed in {})", double_redirects, DOUBLE_REDIRECTS_FILE);
+        println!("Broken redirects: {} (listed in {})", broken_redirects, BROKEN_REDIRECTS_FILE);
+    }
 
     println!("\nTop 10 articles with most outgoing links:");
     for (rank, (article_id, link_count)) in outgoing_links.iter().take(10).enumerate() {
-        println!("{:>2}) {} ({})", rank + 1, titles.get(article_id).unwrap_or(&format!("Unknown (ID: {})", article_id)), link_count);
+        println!("{:>2}) {} ({})", rank + 1, title(article_id), link_count);
     }
 
     println!("\nTop 10 articles with most incoming links:");
     for (rank, (article_id, link_count)) in incoming_links.iter().take(10).enumerate() {
-        println!("{:>2}) {} ({})", rank + 1, titles.get(article_id).unwrap_or(&format!("Unknown (ID: {})", article_id)), link_count);
+        println!("{:>2}) {} ({})", rank + 1, title(article_id), link_count);
+    }
+
+    print_group_stats(data_path, args, &links, &pages, &incoming_counts, original_title);
+    let (self_links, reciprocated_links) = print_reciprocity_stats(&links, &incoming_counts, title);
+    print_size_stats(&pages, &links, &incoming_counts, title);
+    if args.flag("distances") {
+        let register_count = args.parse_value("registers").unwrap_or(DEFAULT_REGISTERS);
+        if !register_count.is_power_of_two() || register_count < 16 {
+            eprintln!("Error: --registers must be a power of two of at least 16");
+            std::process::exit(1);
+        }
+        print_distance_stats(&build_dense_graph(&links), register_count);
+    }
+    let kcore = args.flag("k-core").then(|| print_kcore_stats(data_path, &links, |id| original_title(id).unwrap_or_else(|| format!("Unknown (ID: {})", id))));
+    let positions_path = data_path.join(POSITIONS_FILE);
+    if positions_path.exists() {
+        print_position_stats(&positions_path, &pages);
+    }
+    let files_path = data_path.join(FILES_FILE);
+    if files_path.exists() {
+        print_file_stats(&files_path, |id| original_title(id).unwrap_or_else(|| format!("Unknown (ID: {})", id)));
+    }
+
+    summary.count("articles", total_articles);
+    summary.count("links", total_links);
+    summary.count("articles_with_links", articles_with_links);
+    summary.count("unique_link_targets", unique_links.len());
+    summary.count("redirects", redirects);
+    summary.count("non_main_namespace_pages", non_main_namespace_pages);
+    summary.count("self_links", self_links);
+    summary.count("reciprocated_links", reciprocated_links);
+    summary.count("double_redirects", double_redirects);
+    summary.count("broken_redirects", broken_redirects);
+    if let Some((degeneracy, innermost_core)) = kcore {
+        summary.count("degeneracy", degeneracy);
+        summary.count("innermost_core", innermost_core);
     }
+    summary.write(data_path);
 }
\ No newline at end of file
@@ -0,0 +1,191 @@
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rustc_hash::FxHashMap;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::plain_text;
+use crate::helpers::{Args, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+
+// Splits plain text into words at anything that isn't a letter or digit, lowercasing them unless `keep_case`
+pub fn words(text: &str, keep_case: bool) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| if keep_case { word.to_string() } else { word.to_lowercase() })
+}
+
+fn csv_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+// Fits log(count) = log(c) - s * log(rank) by least squares, returning the exponent s, the constant c and the R² of
+// the fit
+fn fit_zipf(counts: &[u64]) -> (f64, f64, f64) {
+    let points: Vec<(f64, f64)> = counts.iter().enumerate().map(|(rank, &count)| (((rank + 1) as f64).ln(), (count as f64).ln())).collect();
+    let n = points.len() as f64;
+    let (mean_x, mean_y) = points.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x / n, sy + y / n));
+    let (covariance, variance_x, variance_y) = points.iter().fold((0.0, 0.0, 0.0), |(c, vx, vy), &(x, y)| {
+        (c + (x - mean_x) * (y - mean_y), vx + (x - mean_x).powi(2), vy + (y - mean_y).powi(2))
+    });
+    let slope = covariance / variance_x;
+    (-slope, (mean_y - slope * mean_x).exp(), covariance * covariance / (variance_x * variance_y))
+}
+
+// Converts the CSV files to Parquet with DuckDB and removes them
+#[cfg(feature = "duckdb")]
+fn convert_to_parquet(csv_paths: &[PathBuf]) {
+    let connection = duckdb::Connection::open_in_memory().expect("Failed to open DuckDB");
+    for csv_path in csv_paths {
+        let parquet_path = csv_path.with_extension("parquet");
+        let query = format!("COPY (SELECT * FROM read_csv('{}', header = true)) TO '{}' (FORMAT parquet)",
+            csv_path.to_str().unwrap().replace('\'', "''"), parquet_path.to_str().unwrap().replace('\'', "''"));
+        connection.execute_batch(&query).expect("Failed to write Parquet file");
+        std::fs::remove_file(csv_path).expect("Failed to remove CSV file");
+    }
+}
+
+#[cfg(not(feature = "duckdb"))]
+fn convert_to_parquet(_csv_paths: &[PathBuf]) {
+    eprintln!("Error: --format parquet requires building with --features duckdb");
+    std::process::exit(1);
+}
+
+// Tokenizes the plain text of every article in the main namespace and writes corpus-level word frequencies to
+// word-frequencies.csv, each article's token and distinct term counts to articles.csv, and with --article-terms the
+// count of every term in every article to article-terms.csv, which is many times larger than the rest. Also fits
+// Zipf's law to the frequencies and writes the observed and fitted counts at logarithmically spaced ranks to zipf.csv.
+pub fn analyse_text(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let output_dir = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("text-stats"));
+    let format = args.value("format").unwrap_or("csv");
+    if !["csv", "parquet"].contains(&format) {
+        eprintln!("Error: Unknown format {} (expected csv or parquet)", format);
+        std::process::exit(1);
+    }
+    // Checked before the dump is read, rather than when the CSVs are converted at the end
+    if format == "parquet" && cfg!(not(feature = "duckdb")) {
+        eprintln!("Error: --format parquet requires building with --features duckdb");
+        std::process::exit(1);
+    }
+    let keep_case = args.flag("keep-case");
+    let article_terms = args.flag("article-terms");
+    let min_count: u64 = args.parse_value("min-count").unwrap_or(1);
+    create_dir_all(&output_dir).expect("Failed to create output directory");
+
+    let articles_csv_path = output_dir.join("articles.csv");
+    let terms_path = output_dir.join("article-terms.csv");
+    let articles_file = Arc::new(Mutex::new(BufWriter::new(File::create(&articles_csv_path).expect("Failed to create output file"))));
+    writeln!(articles_file.lock().unwrap(), "article_id,title,tokens,unique_terms").expect("Failed to write articles file");
+    let terms_file = Arc::new(article_terms.then(|| {
+        let mut terms_file = BufWriter::new(File::create(&terms_path).expect("Failed to create output file"));
+        writeln!(terms_file, "article_id,term,count").expect("Failed to write article terms file");
+        Mutex::new(terms_file)
+    }));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let frequencies: Arc<Mutex<FxHashMap<String, (u64, u64)>>> = Arc::new(Mutex::new(FxHashMap::default()));  // word -> (count, documents)
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Counting words"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let articles_file = Arc::clone(&articles_file);
+        let terms_file = Arc::clone(&terms_file);
+        let frequencies = Arc::clone(&frequencies);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut pages: Vec<_> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(_, page)| page.namespace == 0 && !page.redirect)
+                .collect();
+            pages.sort_unstable_by_key(|(id, _)| *id);
+
+            let mut chunk_frequencies: FxHashMap<String, (u64, u64)> = FxHashMap::default();
+            let mut articles_output = Vec::new();
+            let mut terms_output = Vec::new();
+            for (id, page) in &pages {
+                let mut counts: FxHashMap<String, u64> = FxHashMap::default();
+                for word in words(&plain_text(&page.text), keep_case) {
+                    *counts.entry(word).or_default() += 1;
+                }
+                let tokens: u64 = counts.values().sum();
+                writeln!(articles_output, "{},{},{},{}", id, csv_quote(&page.title), tokens, counts.len()).unwrap();
+                let mut terms: Vec<(String, u64)> = counts.into_iter().collect();
+                terms.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                for (term, count) in terms {
+                    if article_terms {
+                        writeln!(terms_output, "{},{},{}", id, term, count).unwrap();
+                    }
+                    let entry = chunk_frequencies.entry(term).or_default();
+                    entry.0 += count;
+                    entry.1 += 1;
+                }
+            }
+
+            articles_file.lock().unwrap().write_all(&articles_output).expect("Failed to write articles file");
+            if let Some(terms_file) = terms_file.as_ref() {
+                terms_file.lock().unwrap().write_all(&terms_output).expect("Failed to write article terms file");
+            }
+            let mut frequencies = frequencies.lock().unwrap();
+            for (word, (count, documents)) in chunk_frequencies {
+                let entry = frequencies.entry(word).or_default();
+                entry.0 += count;
+                entry.1 += documents;
+            }
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    articles_file.lock().unwrap().flush().expect("Failed to flush articles file");
+    if let Some(terms_file) = terms_file.as_ref() {
+        terms_file.lock().unwrap().flush().expect("Failed to flush article terms file");
+    }
+
+    let frequencies = Arc::try_unwrap(frequencies).ok().unwrap().into_inner().unwrap();
+    let total_tokens: u64 = frequencies.values().map(|(count, _)| count).sum();
+    let hapaxes = frequencies.values().filter(|(count, _)| *count == 1).count();
+    let mut ranked: Vec<(String, u64, u64)> = frequencies.into_iter().map(|(word, (count, documents))| (word, count, documents)).collect();
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if ranked.is_empty() {
+        println!("No words found");
+        return;
+    }
+
+    let frequencies_path = output_dir.join("word-frequencies.csv");
+    let mut frequencies_file = BufWriter::new(File::create(&frequencies_path).expect("Failed to create output file"));
+    writeln!(frequencies_file, "rank,word,count,documents,frequency").expect("Failed to write word frequencies file");
+    for (rank, (word, count, documents)) in ranked.iter().enumerate().take_while(|(_, (_, count, _))| *count >= min_count) {
+        writeln!(frequencies_file, "{},{},{},{},{:.9}", rank + 1, word, count, documents, *count as f64 / total_tokens as f64).expect("Failed to write word frequencies file");
+    }
+    frequencies_file.flush().expect("Failed to flush word frequencies file");
+
+    // Ranks grow by about 10% per row, which keeps the file small while still covering the whole range
+    let counts: Vec<u64> = ranked.iter().map(|(_, count, _)| *count).collect();
+    let (exponent, scale, r_squared) = fit_zipf(&counts);
+    let zipf_path = output_dir.join("zipf.csv");
+    let mut zipf_file = BufWriter::new(File::create(&zipf_path).expect("Failed to create output file"));
+    writeln!(zipf_file, "rank,count,fitted_count").expect("Failed to write Zipf file");
+    let mut rank = 1;
+    while rank <= counts.len() {
+        writeln!(zipf_file, "{},{},{:.1}", rank, counts[rank - 1], scale * (rank as f64).powf(-exponent)).expect("Failed to write Zipf file");
+        rank = (rank + 1).max((rank as f64 * 1.1) as usize);
+    }
+    zipf_file.flush().expect("Failed to flush Zipf file");
+
+    println!("Tokens: {}, vocabulary: {} words, {} ({:.1}%) appear only once", total_tokens, ranked.len(), hapaxes, 100.0 * hapaxes as f64 / ranked.len() as f64);
+    println!("Zipf exponent: {:.3} (R² {:.3})", exponent, r_squared);
+    println!("\nTop 20 words:");
+    for (rank, (word, count, documents)) in ranked.iter().take(20).enumerate() {
+        println!("{:>2}) {}: {} ({:.2}%), in {} articles", rank + 1, word, count, 100.0 * *count as f64 / total_tokens as f64, documents);
+    }
+
+    if format == "parquet" {
+        let mut csv_paths = vec![frequencies_path, articles_csv_path, zipf_path];
+        if article_terms { csv_paths.push(terms_path); }
+        convert_to_parquet(&csv_paths);
+    }
+    println!("\nWrote word statistics to {}", output_dir.to_str().unwrap());
+}
@@ -0,0 +1,208 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, ZSTD_MAGIC, create_progress_bar, decompress_chunk, dump_size, load_chunk_pages, load_index, locate_dump_files, parse_pages, read_chunk_bytes};
+use crate::index::extract_links;
+use crate::preflight::format_size;
+use crate::stats::format_duration;
+use crate::titles::TitleTable;
+
+const DEFAULT_CHUNKS: usize = 20;
+const DEFAULT_ITERATIONS: usize = 3;
+
+// What one pass over the sampled chunks on a single thread processed, and how long each stage took
+#[derive(Default)]
+struct Pass {
+    decompress: Duration,
+    parse: Duration,
+    extract: Duration,
+    xml_bytes: u64,
+    text_bytes: u64,
+    pages: usize,
+    links: usize,
+}
+
+fn rate(amount: u64, duration: Duration) -> f64 {
+    amount as f64 / duration.as_secs_f64().max(1e-9)
+}
+
+fn format_rate(bytes: u64, duration: Duration) -> String {
+    format!("{}/s", format_size(rate(bytes, duration) as u64))
+}
+
+// Extracts the links from every page and looks them up like the index does, returning the bytes of text and the
+// number of links
+fn extract(pages: impl Iterator<Item = String>, titles: &TitleTable) -> (u64, usize) {
+    let (mut text_bytes, mut links) = (0, 0);
+    for text in pages {
+        for (_, link) in extract_links(&text) {
+            std::hint::black_box(titles.find(&link));
+            links += 1;
+        }
+        text_bytes += text.len() as u64;
+    }
+    (text_bytes, links)
+}
+
+// Returns the pass with the median total time, so that a run is compared as a whole rather than stage by stage
+fn median_pass(mut passes: Vec<Pass>) -> Pass {
+    passes.sort_unstable_by_key(|pass| pass.decompress + pass.parse + pass.extract);
+    let middle = (passes.len() - 1) / 2;
+    passes.swap_remove(middle)
+}
+
+// Measures how fast this machine gets through the stages of the index on a fixed sample of the local dump: reading
+// chunks, decompressing them, parsing the XML and extracting and resolving links, each on one thread, and then whole
+// chunks on every thread. The sample is spread evenly over the dump so that runs on the same dump are comparable.
+pub fn bench(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let articles_path = articles_path.to_str().unwrap().to_string();
+    let sample_size = args.parse_value("chunks").unwrap_or(DEFAULT_CHUNKS).max(1);
+    let iterations = args.parse_value("iterations").unwrap_or(DEFAULT_ITERATIONS).max(1);
+    let threads = config().threads;
+
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    if seek_position_map.is_empty() {
+        eprintln!("Error: No pages in {}", index_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let titles = Arc::new(TitleTable::new(seek_position_map.values().flatten()));
+    let mut positions: Vec<u64> = seek_position_map.keys().copied().collect();
+    positions.push(dump_size(Path::new(&articles_path)));
+    positions.sort_unstable();
+    let total_chunks = positions.len() - 1;
+    let mut sample: Vec<(u64, u64)> = (0..sample_size.min(total_chunks))
+        .map(|i| i * total_chunks / sample_size.min(total_chunks))
+        .map(|chunk_index| (positions[chunk_index], positions[chunk_index + 1]))
+        .collect();
+    sample.dedup();
+
+    // Reading comes first so the later stages run on chunks that are already in memory, and on a local dump it
+    // depends on whether the sample is already in the page cache
+    let progress_bar = create_progress_bar(sample.len() as u64, "Reading chunks");
+    let started = Instant::now();
+    let raw_chunks: Vec<(u64, Vec<u8>)> = sample.iter()
+        .map(|&(start_position, end_position)| {
+            let buffer = read_chunk_bytes(&articles_path, start_position, end_position);
+            progress_bar.inc(1);
+            (start_position, buffer)
+        })
+        .collect();
+    let read_time = started.elapsed();
+    progress_bar.finish_and_clear();
+    let compressed_bytes: u64 = raw_chunks.iter().map(|(_, buffer)| buffer.len() as u64).sum();
+
+    let progress_bar = create_progress_bar((iterations * raw_chunks.len()) as u64, "Timing stages on one thread");
+    let mut passes = Vec::new();
+    for _ in 0..iterations {
+        let mut pass = Pass::default();
+        for (start_position, buffer) in &raw_chunks {
+            let buffer = buffer.clone();
+            let started = Instant::now();
+            let xml = decompress_chunk(buffer);
+            pass.decompress += started.elapsed();
+
+            let started = Instant::now();
+            std::str::from_utf8(&xml).expect("Failed to convert decompressed bytes to UTF-8");
+            let pages = parse_pages(&xml[..], *start_position);
+            pass.parse += started.elapsed();
+
+            let started = Instant::now();
+            pass.pages += pages.len();
+            let (text_bytes, links) = extract(pages.into_values().map(|page| page.text), &titles);
+            pass.extract += started.elapsed();
+            pass.xml_bytes += xml.len() as u64;
+            pass.text_bytes += text_bytes;
+            pass.links += links;
+            progress_bar.inc(1);
+        }
+        passes.push(pass);
+    }
+    progress_bar.finish_and_clear();
+    let pass = median_pass(passes);
+    let single_thread = pass.decompress + pass.parse + pass.extract;
+
+    // Every iteration of the sample goes in one queue, so that the threads stay busy until the last few chunks
+    let pool = ThreadPool::new(threads);
+    let jobs = iterations * sample.len();
+    let progress_bar = Arc::new(create_progress_bar(jobs as u64, "Timing whole chunks on every thread"));
+    let pages = Arc::new(Mutex::new(0));
+    let articles_path = Arc::new(articles_path);
+    let started = Instant::now();
+    for _ in 0..iterations {
+        for &(start_position, end_position) in &sample {
+            let (articles_path, titles, pages, progress_bar) = (Arc::clone(&articles_path), Arc::clone(&titles), Arc::clone(&pages), Arc::clone(&progress_bar));
+            pool.execute(move || {
+                let chunk_pages = load_chunk_pages(&articles_path, start_position, end_position);
+                *pages.lock().unwrap() += chunk_pages.len();
+                extract(chunk_pages.into_values().map(|page| page.text), &titles);
+                progress_bar.inc(1);
+            });
+        }
+    }
+    pool.join();
+    let end_to_end = started.elapsed();
+    progress_bar.finish_and_clear();
+    let end_to_end_pages = *pages.lock().unwrap();
+    let chunks_per_second = rate(jobs as u64, end_to_end);
+    let speedup = rate(compressed_bytes * iterations as u64, end_to_end) / rate(compressed_bytes, read_time + single_thread);
+
+    let file_name = Path::new(articles_path.as_str()).file_name().unwrap().to_str().unwrap().to_string();
+    let compressed = raw_chunks.iter().any(|(_, buffer)| buffer.starts_with(b"BZh") || buffer.starts_with(&ZSTD_MAGIC));
+    let cpus = std::thread::available_parallelism().map_or(0, |cpus| cpus.get());
+    let projected = Duration::from_secs_f64(total_chunks as f64 / chunks_per_second.max(1e-9));
+
+    println!("Dump: {}, {} of {} chunks ({} in the dump, {} of XML, {} pages)",
+        file_name, sample.len(), total_chunks, format_size(compressed_bytes), format_size(pass.xml_bytes), pass.pages);
+    println!("Machine: {} CPUs, {} threads, wikipedia {}", cpus, threads, env!("CARGO_PKG_VERSION"));
+    println!("\nOne thread, median of {} passes:", iterations);
+    println!("  Read:          {} in {:.2}s", format_rate(compressed_bytes, read_time), read_time.as_secs_f64());
+    if compressed {
+        println!("  Decompress:    {} in, {} out", format_rate(compressed_bytes, pass.decompress), format_rate(pass.xml_bytes, pass.decompress));
+    } else {
+        println!("  Decompress:    not needed for an uncompressed dump");
+    }
+    println!("  Parse XML:     {}, {:.0} pages/s", format_rate(pass.xml_bytes, pass.parse), rate(pass.pages as u64, pass.parse));
+    println!("  Extract links: {} of text, {:.0} links/s", format_rate(pass.text_bytes, pass.extract), rate(pass.links as u64, pass.extract));
+    println!("\nEnd to end on {} threads, {} chunks:", threads, jobs);
+    println!("  {:.1} chunks/s, {} of dump, {:.0} pages/s ({:.1}x one thread)",
+        chunks_per_second, format_rate(compressed_bytes * iterations as u64, end_to_end), rate(end_to_end_pages as u64, end_to_end), speedup);
+    println!("  Projected time to index all {} chunks: about {}", total_chunks, format_duration(projected));
+
+    if let Some(output) = args.value("output") {
+        let micros = |duration: Duration| duration.as_micros() as u64;
+        let report = json!({
+            "dump": file_name,
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "cpus": cpus,
+            "threads": threads,
+            "iterations": iterations,
+            "sample_chunks": sample.len(),
+            "total_chunks": total_chunks,
+            "compressed_bytes": compressed_bytes,
+            "xml_bytes": pass.xml_bytes,
+            "text_bytes": pass.text_bytes,
+            "pages": pass.pages,
+            "links": pass.links,
+            "single_thread_micros": {
+                "read": micros(read_time),
+                "decompress": micros(pass.decompress),
+                "parse": micros(pass.parse),
+                "extract": micros(pass.extract),
+            },
+            "end_to_end_micros": micros(end_to_end),
+            "chunks_per_second": chunks_per_second,
+            "speedup": speedup,
+            "projected_index_seconds": projected.as_secs(),
+        });
+        fs::write(output, serde_json::to_string_pretty(&report).unwrap() + "\n").unwrap_or_else(|err| {
+            eprintln!("Error: Failed to write {}: {}", output, err);
+            std::process::exit(1);
+        });
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::categories;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files, open_dump_stream};
+use crate::infobox::{Template, infobox, templates};
+use crate::stubs::get_dump_path;
+use tracing::info;
+
+// The page_props table maps pages to their Wikidata items, among other properties
+pub const PAGE_PROPS_DUMP: &str = "page_props.sql.gz";
+
+// Infoboxes that are only used for people. Articles with other infoboxes still count when they give a birth date.
+const PERSON_INFOBOXES: [&str; 14] = [
+    "person", "officeholder", "scientist", "writer", "musical artist", "football biography", "sportsperson", "royalty",
+    "military person", "artist", "actor", "philosopher", "religious biography", "criminal",
+];
+const BIRTH_TEMPLATES: [&str; 5] = ["birth date", "birth date and age", "bda", "dob", "birth year and age"];
+const DEATH_TEMPLATES: [&str; 4] = ["death date", "death date and age", "dda", "death year and age"];
+
+struct Person {
+    name: String,
+    birth_date: Option<String>,
+    birth_place: Option<String>,
+    death_date: Option<String>,
+    death_place: Option<String>,
+    occupation: Option<String>,
+    qid: Option<String>,
+}
+
+// Formats the year, month and day parameters of a date template as YYYY, YYYY-MM or YYYY-MM-DD
+fn template_date(template: &Template) -> Option<String> {
+    let part = |key: &str| template.get(key).and_then(|value| value.trim().parse::<i32>().ok());
+    let year = part("1")?;
+    Some(match (part("2"), part("3")) {
+        (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+        (Some(month), None) => format!("{:04}-{:02}", year, month),
+        _ => format!("{:04}", year),
+    })
+}
+
+// Reads a date from the first of the named templates in a value, falling back to the value's plain text
+fn find_date(infobox: Option<&Template>, key: &str, names: &[&str], text: &str) -> Option<String> {
+    let value = infobox.and_then(|infobox| infobox.get(key));
+    let found = templates(value.unwrap_or(text)).into_iter().find(|template| names.iter().any(|name| template.is(name)));
+    match found {
+        Some(template) => template_date(&template),
+        None => infobox.and_then(|infobox| infobox.text(key)),
+    }
+}
+
+// Recognizes an article about a person by its infobox, date templates or "YYYY births" categories, and pulls out
+// their details. Dates missing from the infobox fall back to the year in the births and deaths categories.
+fn extract_person(title: &str, text: &str) -> Option<Person> {
+    let infobox = infobox(text);
+    let article_categories = categories(text);
+    let year_category = |suffix: &str| article_categories.iter()
+        .find_map(|category| category.strip_suffix(suffix).filter(|year| year.parse::<i32>().is_ok()).map(str::to_string));
+    let person_infobox = infobox.as_ref().is_some_and(|infobox| {
+        let kind = infobox.name.to_lowercase();
+        PERSON_INFOBOXES.iter().any(|name| kind.trim_start_matches("infobox").trim() == *name)
+    });
+
+    let birth_date = find_date(infobox.as_ref(), "birth_date", &BIRTH_TEMPLATES, text).or_else(|| year_category(" births"));
+    let death_date = find_date(infobox.as_ref(), "death_date", &DEATH_TEMPLATES, text).or_else(|| year_category(" deaths"));
+    let living = article_categories.iter().any(|category| category == "Living people");
+    if !person_infobox && !living && birth_date.is_none() {
+        return None;
+    }
+
+    let field = |key: &str| infobox.as_ref().and_then(|infobox| infobox.text(key));
+    Some(Person {
+        name: field("name").unwrap_or_else(|| title.split(" (").next().unwrap().to_string()),
+        birth_date,
+        birth_place: field("birth_place"),
+        death_date,
+        death_place: field("death_place"),
+        occupation: field("occupation").or_else(|| field("occupations")),
+        qid: field("qid").or_else(|| field("wikidata")),
+    })
+}
+
+// Splits the tuples of an SQL dump's INSERT statements into their fields, with quoted strings unescaped and NULL as
+// an empty string
+fn parse_insert(line: &str, mut visit: impl FnMut(&[String])) {
+    let Some(start) = line.find(" VALUES ") else { return };
+    let mut chars = line[start + 8..].chars().peekable();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => { fields.clear(); field.clear(); }
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => field.extend(chars.next()),
+                        '\'' => break,
+                        _ => field.push(c),
+                    }
+                }
+            }
+            ',' => fields.push(std::mem::take(&mut field)),
+            ')' => {
+                fields.push(std::mem::take(&mut field));
+                visit(&fields);
+                // Skip the comma between tuples, so it isn't read as a field separator
+                chars.next_if_eq(&',');
+            }
+            _ if c == 'N' && field.is_empty() => {
+                // NULL
+                while chars.next_if(|c| c.is_ascii_alphabetic()).is_some() {}
+            }
+            _ => field.push(c),
+        }
+    }
+}
+
+// Loads the Wikidata item of every page from the page_props dump, if it's been downloaded
+pub fn load_wikidata_items(data_path: &Path) -> Option<HashMap<PageId, String>> {
+    let page_props_path = get_dump_path(data_path, PAGE_PROPS_DUMP);
+    if !page_props_path.exists() { return None; }
+    info!("Reading Wikidata items from {}", page_props_path.to_str().unwrap());
+    let mut items = HashMap::new();
+    let reader = BufReader::new(open_dump_stream(&page_props_path, "Reading page props"));
+    for line in reader.split(b'\n').map_while(Result::ok) {
+        if !line.starts_with(b"INSERT INTO") { continue; }
+        parse_insert(&String::from_utf8_lossy(&line), |fields| {
+            if let [page, name, value, ..] = fields {
+                if name == "wikibase_item" {
+                    if let Ok(page) = page.parse() {
+                        items.insert(page, value.clone());
+                    }
+                }
+            }
+        });
+    }
+    Some(items)
+}
+
+// Writes a dataset of the people with articles to biographies.ndjson (or --format tsv), with their name, birth and
+// death dates and places, occupation and Wikidata item. The item comes from the page_props dump when it's in the data
+// directory, and otherwise from the infobox's qid or wikidata parameter when it has one.
+pub fn biographies(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let format = args.value("format").unwrap_or("ndjson");
+    if !["ndjson", "tsv"].contains(&format) {
+        eprintln!("Error: Unknown format {} (expected ndjson or tsv)", format);
+        std::process::exit(1);
+    }
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join(format!("biographies.{}", format)));
+    let wikidata_items = load_wikidata_items(data_path);
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let people = Arc::new(Mutex::new(Vec::new()));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Extracting biographies"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let people = Arc::clone(&people);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let chunk_people: Vec<(PageId, String, Person)> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(_, page)| page.namespace == 0 && !page.redirect)
+                .filter_map(|(id, page)| Some((id, page.title.clone(), extract_person(&page.title, &page.text)?)))
+                .collect();
+            people.lock().unwrap().extend(chunk_people);
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    let mut people = Arc::try_unwrap(people).ok().unwrap().into_inner().unwrap();
+    people.sort_unstable_by_key(|(id, _, _)| *id);
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    if format == "tsv" {
+        writeln!(output_file, "id\ttitle\tname\tbirth_date\tbirth_place\tdeath_date\tdeath_place\toccupation\tqid").expect("Failed to write output file");
+    }
+    let mut with_qid = 0;
+    for (id, title, person) in &people {
+        let qid = wikidata_items.as_ref().and_then(|items| items.get(id)).or(person.qid.as_ref());
+        with_qid += usize::from(qid.is_some());
+        if format == "tsv" {
+            let fields = [&person.birth_date, &person.birth_place, &person.death_date, &person.death_place, &person.occupation];
+            let fields: Vec<&str> = fields.iter().map(|field| field.as_deref().unwrap_or("")).collect();
+            writeln!(output_file, "{}\t{}\t{}\t{}\t{}", id, title, person.name, fields.join("\t"), qid.map_or("", String::as_str)).expect("Failed to write output file");
+        } else {
+            let record = json!({
+                "id": id, "title": title, "name": person.name, "birth_date": person.birth_date, "birth_place": person.birth_place,
+                "death_date": person.death_date, "death_place": person.death_place, "occupation": person.occupation, "qid": qid,
+            });
+            writeln!(output_file, "{}", record).expect("Failed to write output file");
+        }
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    let born = people.iter().filter(|(_, _, person)| person.birth_date.is_some()).count();
+    let died = people.iter().filter(|(_, _, person)| person.death_date.is_some()).count();
+    println!("Found {} people, {} with a birth date, {} with a death date and {} with a Wikidata item", people.len(), born, died, with_qid);
+    println!("Wrote biographies to {}", output_path.to_str().unwrap());
+}
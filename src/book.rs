@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, read_to_string};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use indicatif::ProgressIterator;
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::SimpleFileOptions;
+use crate::categories::{CATEGORIES_FILE, read_categories};
+use crate::config::config;
+use crate::helpers::{Args, PageId, create_progress_bar, locate_dump_files};
+use crate::index::normalize_link;
+use crate::lookup::{ArticleLookup, DEFAULT_CACHE_SIZE};
+use crate::namespaces::is_ignored;
+use crate::pdf::write_pdf;
+use crate::render::{HtmlRenderer, STYLE};
+
+const DEFAULT_MAX_ARTICLES: usize = 1000;
+const CATEGORY_PREFIX: &str = "Category:";
+// Redirects are followed this many times at most, since a few redirect to other redirects
+const MAX_REDIRECTS: usize = 3;
+
+type Chapter = (PageId, String, String);  // id, title, wikitext
+
+const CONTAINER: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+<rootfiles><rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/></rootfiles>
+</container>
+";
+
+// The target of a redirect's wikitext, or None if it isn't a redirect
+fn redirect_target(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    if !text.get(..9).is_some_and(|start| start.eq_ignore_ascii_case("#redirect")) { return None; }
+    let start = text.find("[[")? + 2;
+    let end = start + text[start..].find("]]")?;
+    Some(text[start..end].split(['|', '#']).next().unwrap().trim())
+}
+
+// Resolves the titles in a file, one per line, to page IDs in the file's order
+fn titles_from_file(path: &str, lookup: &ArticleLookup) -> Vec<PageId> {
+    let contents = read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Error: Failed to read {}: {}", path, err);
+        std::process::exit(1);
+    });
+    let wanted: Vec<&str> = contents.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let ids: Vec<PageId> = wanted.iter().filter_map(|title| lookup.find(&title.replace('_', " "))).collect();
+    if ids.len() < wanted.len() {
+        println!("Skipping {} titles from {} that aren't in the dump", wanted.len() - ids.len(), path);
+    }
+    ids
+}
+
+// Collects the articles in a category and in its subcategories down to `depth` levels below it, sorted by title.
+// Subcategories are only known when the category pages were indexed along with their categories.
+fn category_articles(data_path: &Path, lookup: &ArticleLookup, category: &str, depth: usize) -> Vec<PageId> {
+    let categories_path = data_path.join(CATEGORIES_FILE);
+    if !categories_path.exists() {
+        eprintln!("Error: {} not found, run index with --with-categories first", categories_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let mut members: HashMap<String, Vec<PageId>> = HashMap::new();
+    for (id, names) in read_categories(&categories_path) {
+        for name in names {
+            members.entry(name).or_default().push(id);
+        }
+    }
+
+    // Category names are stored with their first letter uppercase, as in their titles
+    let name = category.strip_prefix(CATEGORY_PREFIX).unwrap_or(category).trim().replace('_', " ");
+    let mut chars = name.chars();
+    let root: String = chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default();
+    let mut articles = HashSet::new();
+    let mut visited = HashSet::from([root.clone()]);
+    let mut queue = VecDeque::from([(root.clone(), 0)]);
+    let mut subcategories = 0;
+    while let Some((name, level)) = queue.pop_front() {
+        for &id in members.get(&name).into_iter().flatten() {
+            let Some(title) = lookup.title(id) else { continue };
+            if let Some(subcategory) = title.strip_prefix(CATEGORY_PREFIX) {
+                if level < depth && visited.insert(subcategory.to_string()) {
+                    queue.push_back((subcategory.to_string(), level + 1));
+                    subcategories += 1;
+                }
+            } else if !is_ignored(title) {
+                articles.insert(id);
+            }
+        }
+    }
+    if articles.is_empty() {
+        eprintln!("Error: No articles in {}{}", CATEGORY_PREFIX, root);
+        std::process::exit(1);
+    }
+    println!("Found {} articles in {}{} and {} subcategories", articles.len(), CATEGORY_PREFIX, root, subcategories);
+    let mut articles: Vec<PageId> = articles.into_iter().collect();
+    articles.sort_unstable_by_key(|id| lookup.title(*id).unwrap_or_default().to_lowercase());
+    articles
+}
+
+// Reads the articles' wikitext, following redirects to the articles they point to, and returns the (id, title,
+// wikitext) of each distinct article in order, along with the redirects that were followed
+fn load_chapters(lookup: &ArticleLookup, ids: &[PageId]) -> (Vec<Chapter>, HashMap<PageId, PageId>) {
+    // Reading in dump order decompresses each chunk once, where the book's order could go back and forth between them
+    let mut by_position = ids.to_vec();
+    by_position.sort_unstable_by_key(|id| lookup.position(*id));
+    let mut texts: HashMap<PageId, String> = by_position.into_iter()
+        .progress_with(create_progress_bar(ids.len() as u64, "Reading articles"))
+        .filter_map(|id| Some((id, lookup.get(id)?)))
+        .collect();
+
+    let mut chapters = Vec::new();
+    let mut redirects = HashMap::new();
+    let mut seen = HashSet::new();
+    for &requested in ids {
+        let mut id = requested;
+        let mut text = texts.remove(&id);
+        for _ in 0..MAX_REDIRECTS {
+            let Some(target) = text.as_deref().and_then(redirect_target).and_then(|target| lookup.find(target)) else { break };
+            id = target;
+            text = texts.remove(&id).or_else(|| lookup.get(id));
+        }
+        let Some(text) = text.filter(|text| redirect_target(text).is_none()) else {
+            println!("Skipping {}, a redirect that doesn't lead to an article in the dump", lookup.title(requested).unwrap_or_default());
+            continue;
+        };
+        if id != requested {
+            redirects.insert(requested, id);
+        }
+        if seen.insert(id) {
+            chapters.push((id, lookup.title(id).unwrap_or_default().to_string(), text));
+        }
+    }
+    (chapters, redirects)
+}
+
+// The wiki's language code, from --language or the dump prefix like enwiki-20240801
+fn language(args: &Args) -> String {
+    if let Some(language) = args.value("language") {
+        return language.to_string();
+    }
+    let code: String = config().dump_prefix.chars().take_while(|c| c.is_ascii_lowercase()).collect();
+    code.strip_suffix("wiktionary").or_else(|| code.strip_suffix("wiki")).unwrap_or(&code).to_string()
+}
+
+fn chapter_path(index: usize) -> String {
+    format!("{}.xhtml", index + 1)
+}
+
+fn xhtml_document(title: &str, language: &str, head: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{language}\" xml:lang=\"{language}\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{title}</title>\n{head}</head>\n<body>\n{body}</body>\n</html>\n",
+        title = encode_text(title), language = encode_double_quoted_attribute(language),
+    )
+}
+
+// Writes an EPUB 3 book with a chapter for each article, rendered like build-site's pages with links between the
+// chapters, and a table of contents both as the EPUB 3 navigation document and as the NCX older readers use
+fn write_epub(path: &Path, title: &str, language: &str, chapters: &[Chapter], redirects: &HashMap<PageId, PageId>, lookup: &ArticleLookup) {
+    let chapter_index: HashMap<PageId, usize> = chapters.iter().enumerate().map(|(index, (id, _, _))| (*id, index)).collect();
+    // Links through the redirects that chose articles for the book go to those articles' chapters too
+    let renderer = HtmlRenderer::new(|target| {
+        let id = lookup.find(&normalize_link(target)?)?;
+        chapter_index.get(redirects.get(&id).unwrap_or(&id)).map(|&index| chapter_path(index))
+    });
+
+    // The identifier only has to be unique to the book, so it's a hash of the articles in it
+    let digest = format!("{:x}", md5::compute(chapters.iter().map(|(id, _, _)| id.to_string()).collect::<Vec<_>>().join(",")));
+    let uuid = format!("{}-{}-{}-{}-{}", &digest[..8], &digest[8..12], &digest[12..16], &digest[16..20], &digest[20..]);
+    let prefix = &config().dump_prefix;
+    let date = prefix.rsplit('-').next().filter(|date| date.len() == 8 && date.bytes().all(|c| c.is_ascii_digit()))
+        .map_or("1970-01-01".to_string(), |date| format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]));
+
+    let file = BufWriter::new(File::create(path).expect("Failed to create EPUB file"));
+    let mut epub = ZipWriter::new(file);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, options: SimpleFileOptions, contents: &[u8]| {
+        epub.start_file(name, options).expect("Failed to write EPUB file");
+        epub.write_all(contents).expect("Failed to write EPUB file");
+    };
+    // The media type has to come first and uncompressed, so readers can recognise the file from its first bytes
+    add("mimetype", stored, b"application/epub+zip");
+    add("META-INF/container.xml", deflated, CONTAINER.as_bytes());
+    add("OEBPS/style.css", deflated, STYLE.as_bytes());
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut nav_items = String::new();
+    let mut nav_points = String::new();
+    let stylesheet = "<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n";
+    for (index, (_, chapter_title, text)) in chapters.iter().enumerate() {
+        let body = format!("<main>\n<h1>{}</h1>\n{}</main>\n", encode_text(chapter_title), renderer.render(text));
+        // Control characters other than tabs and newlines aren't allowed anywhere in XML
+        let body: String = body.chars().filter(|c| !c.is_control() || *c == '\n' || *c == '\t').collect();
+        let path = chapter_path(index);
+        add(&format!("OEBPS/{}", path), deflated, xhtml_document(chapter_title, language, stylesheet, &body).as_bytes());
+        let title = encode_text(chapter_title);
+        manifest.push_str(&format!("<item id=\"chapter{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n", index + 1, path));
+        spine.push_str(&format!("<itemref idref=\"chapter{}\"/>\n", index + 1));
+        nav_items.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", path, title));
+        nav_points.push_str(&format!("<navPoint id=\"point{n}\" playOrder=\"{n}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/></navPoint>\n",
+            title, path, n = index + 1));
+    }
+
+    let book_title = encode_text(title);
+    let nav = xhtml_document(title, language, stylesheet, &format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>\n", book_title, nav_items));
+    add("OEBPS/nav.xhtml", deflated, nav.as_bytes());
+    let ncx = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+         <head><meta name=\"dtb:uid\" content=\"urn:uuid:{uuid}\"/></head>\n<docTitle><text>{book_title}</text></docTitle>\n\
+         <navMap>\n{nav_points}</navMap>\n</ncx>\n");
+    add("OEBPS/toc.ncx", deflated, ncx.as_bytes());
+    let package = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"book-id\">urn:uuid:{uuid}</dc:identifier>\n\
+         <dc:title>{book_title}</dc:title>\n<dc:language>{language}</dc:language>\n<dc:source>{source}</dc:source>\n\
+         <meta property=\"dcterms:modified\">{date}T00:00:00Z</meta>\n</metadata>\n\
+         <manifest>\n<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n\
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n{manifest}</manifest>\n\
+         <spine toc=\"ncx\">\n{spine}</spine>\n</package>\n",
+        language = encode_text(language), source = encode_text(prefix));
+    add("OEBPS/content.opf", deflated, package.as_bytes());
+    epub.finish().expect("Failed to write EPUB file");
+}
+
+// Bundles articles into a book for offline reading, an EPUB (the default) or a PDF with --format pdf, written to
+// --output (data_path/book.epub by default). The articles are the titles in --titles FILE, one per line and in that
+// order, or the articles in --category NAME and its subcategories down to --depth N levels (0 by default) sorted by
+// title, at most --max-articles of them. Both formats start with a table of contents. The EPUB's chapters are
+// rendered like build-site's pages, linking to each other, and the PDF has the articles' plain text with their
+// sections, in the standard Helvetica fonts so characters outside Latin-1 come out as question marks.
+pub fn export_book(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let format = args.value("format").unwrap_or("epub");
+    if !["epub", "pdf"].contains(&format) {
+        eprintln!("Error: Unknown book format {} (expected epub or pdf)", format);
+        std::process::exit(1);
+    }
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let lookup = ArticleLookup::new(&index_path, &articles_path, DEFAULT_CACHE_SIZE);
+
+    let (mut ids, default_title) = match (args.value("titles"), args.value("category")) {
+        (Some(path), None) => {
+            let stem = Path::new(path).file_stem().unwrap().to_str().unwrap().to_string();
+            (titles_from_file(path, &lookup), stem)
+        }
+        (None, Some(category)) => {
+            let ids = category_articles(data_path, &lookup, category, args.parse_value("depth").unwrap_or(0));
+            (ids, category.strip_prefix(CATEGORY_PREFIX).unwrap_or(category).replace('_', " "))
+        }
+        _ => {
+            eprintln!("Error: Expected either --titles FILE or --category NAME");
+            std::process::exit(1);
+        }
+    };
+    let max_articles = args.parse_value("max-articles").unwrap_or(DEFAULT_MAX_ARTICLES);
+    if ids.len() > max_articles {
+        println!("Keeping the first {} of {} articles (--max-articles)", max_articles, ids.len());
+        ids.truncate(max_articles);
+    }
+    let (chapters, redirects) = load_chapters(&lookup, &ids);
+    if chapters.is_empty() {
+        eprintln!("Error: None of the articles are in the dump");
+        std::process::exit(1);
+    }
+
+    let title = args.value("title").map_or(default_title, str::to_string);
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join(format!("book.{}", format)));
+    if format == "pdf" {
+        let pages: Vec<(String, String)> = chapters.iter().map(|(_, title, text)| (title.clone(), text.clone())).collect();
+        write_pdf(&output_path, &title, &pages);
+    } else {
+        write_epub(&output_path, &title, &language(args), &chapters, &redirects, &lookup);
+    }
+    println!("Wrote {} articles to {}", chapters.len(), output_path.to_str().unwrap());
+}
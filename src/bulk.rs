@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk, load_index, locate_dump_files};
+use crate::config::config;
+use tracing::warn;
+
+// Returns the names of the categories an article is in, from its [[Category:Name|sort key]] links
+fn extract_categories(text: &str) -> Vec<String> {
+    text.match_indices("[[Category:")
+        .filter_map(|(start, prefix)| {
+            let rest = &text[start + prefix.len()..];
+            let name = &rest[..rest.find("]]")?];
+            Some(name.split('|').next().unwrap().trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Flavor { Elasticsearch, Meilisearch }
+
+// Collects documents into batches, writing each full batch to its own file or POSTing it to the endpoint
+struct BatchSink {
+    flavor: Flavor,
+    index_name: String,
+    batch_size: usize,
+    output_dir: PathBuf,
+    endpoint: Option<String>,
+    api_key: Option<String>,
+    buffer: Vec<u8>,
+    buffered: usize,
+    batches: usize,
+    documents: usize,
+    failed_documents: usize,
+}
+
+impl BatchSink {
+    fn add(&mut self, id: PageId, title: &str, text: &str) {
+        if self.flavor == Flavor::Elasticsearch {
+            let action = json!({ "index": { "_index": self.index_name, "_id": id.to_string() } });
+            writeln!(self.buffer, "{}", action).expect("Failed to write document");
+        }
+        let document = json!({ "id": id, "title": title, "text": text, "categories": extract_categories(text) });
+        writeln!(self.buffer, "{}", document).expect("Failed to write document");
+        self.buffered += 1;
+        self.documents += 1;
+        if self.buffered >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffered == 0 { return; }
+        match self.endpoint.clone() {
+            Some(endpoint) => self.post(&endpoint),
+            None => {
+                let batch_path = self.output_dir.join(format!("bulk-{:0>6}.ndjson", self.batches));
+                let mut batch_file = BufWriter::new(File::create(batch_path).expect("Failed to create output file"));
+                batch_file.write_all(&self.buffer).expect("Failed to write output file");
+                batch_file.flush().expect("Failed to flush output file");
+            }
+        }
+        self.buffer.clear();
+        self.buffered = 0;
+        self.batches += 1;
+    }
+
+    fn post(&mut self, endpoint: &str) {
+        let mut request = ureq::post(endpoint).header("Content-Type", "application/x-ndjson");
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", &format!("Bearer {}", api_key));
+        }
+        let mut response = request.send(&self.buffer[..]).unwrap_or_else(|err| {
+            eprintln!("Error: Failed to POST batch {} to {}: {}", self.batches, endpoint, err);
+            std::process::exit(1);
+        });
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+
+        // Elasticsearch answers 200 even when individual documents fail, and reports them per item
+        if self.flavor == Flavor::Elasticsearch {
+            let response: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+            if response["errors"].as_bool() == Some(true) {
+                let failed = response["items"].as_array().map_or(0, |items| items.iter().filter(|item| item["index"]["error"].is_object()).count());
+                warn!("{} documents in batch {} failed to index", failed, self.batches);
+                self.failed_documents += failed;
+            }
+        }
+    }
+}
+
+pub fn export_bulk(args: &Args, data_path: &Path, format: &str, output_dir: &Path) {
+    let flavor = if format == "elasticsearch" { Flavor::Elasticsearch } else { Flavor::Meilisearch };
+    let endpoint = args.value("endpoint").map(str::to_string);
+    if endpoint.is_none() {
+        create_dir_all(output_dir).expect("Failed to create output directory");
+    }
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let sink = Arc::new(Mutex::new(BatchSink {
+        flavor,
+        index_name: args.value("index").unwrap_or("wikipedia").to_string(),
+        batch_size: args.parse_value("batch-size").unwrap_or(1000),
+        output_dir: output_dir.to_path_buf(),
+        endpoint,
+        api_key: args.value("api-key").map(str::to_string),
+        buffer: Vec::new(),
+        buffered: 0,
+        batches: 0,
+        documents: 0,
+        failed_documents: 0,
+    }));
+
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Exporting documents"));
+
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let progress_bar = Arc::clone(&progress_bar);
+        let sink = Arc::clone(&sink);
+
+        pool.execute(move || {
+            let articles = load_chunk(&articles_path, start_position, end_position);
+            let mut sink = sink.lock().unwrap();
+            for (article_id, (title, content)) in &articles {
+                sink.add(*article_id, title, content);
+            }
+            progress_bar.inc(1);
+        })
+    }
+
+    pool.join();
+    progress_bar.finish_and_clear();
+
+    let mut sink = sink.lock().unwrap();
+    sink.flush();
+    let destination = sink.endpoint.clone().unwrap_or_else(|| output_dir.to_str().unwrap().to_string());
+    println!("Exported {} documents in {} batches to {}", sink.documents, sink.batches, destination);
+    if sink.failed_documents > 0 {
+        println!("{} documents failed to index", sink.failed_documents);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use html_escape::decode_html_entities;
+use indicatif::ProgressIterator;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::index::normalize_link;
+use crate::titles::TitleTable;
+
+// Where a surface form's candidate came from, as bit flags
+const ANCHOR: u8 = 1;
+const REDIRECT: u8 = 2;
+const TITLE: u8 = 4;
+const DISAMBIGUATION: u8 = 8;
+const SOURCES: [(u8, &str); 4] = [(ANCHOR, "anchor"), (REDIRECT, "redirect"), (TITLE, "title"), (DISAMBIGUATION, "disambiguation")];
+const MAX_SURFACE_LENGTH: usize = 100;
+const MAX_HOPS: usize = 10;
+
+// The templates that mark disambiguation pages, like {{Disambiguation}} and {{Hndis}}
+static DISAMBIGUATION_TEMPLATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\{\{\s*(disambiguation|disambig|disamb|dab|hndis|geodis|numberdis|school disambiguation|given name|surname)\s*(\||\}\})").unwrap()
+});
+
+// (anchor links, sources) of each candidate
+type Candidates = FxHashMap<PageId, (u64, u8)>;
+
+// Lowercases a surface form and collapses its whitespace, or returns None for one too long to be a name
+fn normalize_surface(text: &str) -> Option<String> {
+    let surface = decode_html_entities(text).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (!surface.is_empty() && surface.len() <= MAX_SURFACE_LENGTH && !surface.contains(['[', ']', '{', '}', '<', '>'])).then_some(surface)
+}
+
+// Returns each link's lowercase target and its anchor text, the text after the last pipe or else the target itself
+fn anchored_links(text: &str) -> Vec<(String, &str)> {
+    let mut links = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start + 2..].find("]]") else { break };
+        let inner = &rest[start + 2..start + 2 + length];
+        if let Some(target) = normalize_link(inner) {
+            let anchor = inner.rsplit('|').next().unwrap();
+            let anchor = if inner.contains('|') { anchor } else { anchor.split('#').next().unwrap() };
+            links.push((target.trim().to_string(), anchor));
+        }
+        rest = &rest[start + 2 + length + 2..];
+    }
+    links
+}
+
+#[derive(Default)]
+struct ChunkCandidates {
+    surfaces: FxHashMap<String, Candidates>,
+    redirects: Vec<(PageId, PageId)>,  // redirect ID, target ID
+}
+
+impl ChunkCandidates {
+    fn add(&mut self, surface: &str, id: PageId, links: u64, source: u8) {
+        let Some(surface) = normalize_surface(surface) else { return };
+        let entry = self.surfaces.entry(surface).or_default().entry(id).or_default();
+        entry.0 += links;
+        entry.1 |= source;
+    }
+}
+
+// Collects the surface forms of a chunk's articles: the anchor text of their links, the titles of redirects, the
+// titles of articles with and without a parenthetical qualifier, and the entries of disambiguation pages
+fn chunk_candidates(articles_path: &str, start_position: u64, end_position: u64, titles: &TitleTable) -> ChunkCandidates {
+    let mut chunk = ChunkCandidates::default();
+    for (id, page) in load_chunk_pages(articles_path, start_position, end_position) {
+        if page.namespace != 0 { continue; }
+        if page.redirect {
+            let target = page.redirect_target.as_deref().and_then(normalize_link).and_then(|target| titles.find(target.trim()));
+            if let Some(target) = target {
+                chunk.redirects.push((id, target));
+                chunk.add(&page.title, target, 0, REDIRECT);
+            }
+            continue;
+        }
+
+        let name = page.title.strip_suffix(" (disambiguation)");
+        if name.is_some() || DISAMBIGUATION_TEMPLATE.is_match(&page.text) {
+            // Each entry is a bulleted line whose first link is the article it refers to
+            let name = name.unwrap_or_else(|| page.title.split(" (").next().unwrap());
+            for line in page.text.lines().filter(|line| line.starts_with('*')) {
+                let target = anchored_links(line).into_iter().next().and_then(|(target, _)| titles.find(&target));
+                if let Some(target) = target {
+                    chunk.add(name, target, 0, DISAMBIGUATION);
+                }
+            }
+            continue;
+        }
+
+        chunk.add(&page.title, id, 0, TITLE);
+        if let Some((name, _)) = page.title.split_once(" (").filter(|_| page.title.ends_with(')')) {
+            chunk.add(name, id, 0, TITLE);
+        }
+        for (target, anchor) in anchored_links(&page.text) {
+            if let Some(target) = titles.find(&target) {
+                chunk.add(anchor, target, 1, ANCHOR);
+            }
+        }
+    }
+    chunk
+}
+
+// Writes an entity-linking candidate table to candidates.tsv, mapping each lowercase surface form to the articles it
+// may refer to, ranked by prior probability. Surface forms come from link anchor text, redirect titles, article titles
+// with and without their parenthetical qualifier, and the entries of disambiguation pages, with candidates that are
+// redirects resolved to their final target. The prior of a candidate is its share of the surface form's mentions,
+// where each anchor link counts as a mention and each other source counts as one more, so that candidates never
+// linked with that text still get a small prior. Surface forms with fewer than --min-count mentions are dropped, and
+// each keeps at most --max-candidates candidates.
+pub fn export_candidates(args: &Args, data_path: &Path, output_dir: &Path) {
+    let min_count: u64 = args.parse_value("min-count").unwrap_or(1);
+    let max_candidates: usize = args.parse_value("max-candidates").unwrap_or(20);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let titles = Arc::new(TitleTable::new(seek_position_map.values()
+        .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating title index"))
+        .flatten()));
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let surfaces: Arc<Mutex<FxHashMap<String, Candidates>>> = Arc::new(Mutex::new(FxHashMap::default()));
+    let redirects = Arc::new(Mutex::new(FxHashMap::default()));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Collecting surface forms"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let titles = Arc::clone(&titles);
+        let surfaces = Arc::clone(&surfaces);
+        let redirects = Arc::clone(&redirects);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let chunk = chunk_candidates(&articles_path, start_position, end_position, &titles);
+            redirects.lock().unwrap().extend(chunk.redirects);
+            let mut surfaces = surfaces.lock().unwrap();
+            for (surface, candidates) in chunk.surfaces {
+                let merged = surfaces.entry(surface).or_default();
+                for (id, (links, sources)) in candidates {
+                    let entry = merged.entry(id).or_default();
+                    entry.0 += links;
+                    entry.1 |= sources;
+                }
+            }
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    let surfaces = Arc::try_unwrap(surfaces).ok().unwrap().into_inner().unwrap();
+    let redirects: FxHashMap<PageId, PageId> = Arc::try_unwrap(redirects).ok().unwrap().into_inner().unwrap();
+
+    // Links and disambiguation entries often point at redirects, which stand for the article they lead to
+    let resolve = |mut id: PageId| {
+        for _ in 0..MAX_HOPS {
+            match redirects.get(&id) {
+                Some(&target) => id = target,
+                None => break,
+            }
+        }
+        id
+    };
+    let mut surfaces: Vec<_> = surfaces.into_iter().map(|(surface, candidates)| {
+        let mut resolved: Candidates = FxHashMap::default();
+        for (id, (links, sources)) in candidates {
+            let entry = resolved.entry(resolve(id)).or_default();
+            entry.0 += links;
+            entry.1 |= sources;
+        }
+        let candidates: Vec<(PageId, u64, u8)> = resolved.into_iter()
+            .filter(|(id, _)| !redirects.contains_key(id))
+            .map(|(id, (links, sources))| (id, links + (sources & !ANCHOR).count_ones() as u64, sources))
+            .collect();
+        (surface, candidates)
+    }).collect();
+    surfaces.retain(|(_, candidates)| candidates.iter().map(|(_, mentions, _)| mentions).sum::<u64>() >= min_count.max(1));
+    surfaces.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    create_dir_all(output_dir).expect("Failed to create output directory");
+    let output_path = output_dir.join("candidates.tsv");
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    writeln!(output_file, "surface\trank\tarticle_id\ttitle\tprior\tlinks\tsources").expect("Failed to write output file");
+    let mut rows = 0;
+    for (surface, candidates) in &mut surfaces {
+        let total: u64 = candidates.iter().map(|(_, mentions, _)| mentions).sum();
+        candidates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (rank, (id, mentions, sources)) in candidates.iter().take(max_candidates).enumerate() {
+            let links = mentions - (sources & !ANCHOR).count_ones() as u64;
+            let sources: Vec<&str> = SOURCES.iter().filter(|(flag, _)| sources & flag != 0).map(|(_, name)| *name).collect();
+            writeln!(output_file, "{}\t{}\t{}\t{}\t{:.6}\t{}\t{}", surface, rank + 1, id, titles.title(*id).unwrap_or(""),
+                *mentions as f64 / total as f64, links, sources.join(",")).expect("Failed to write output file");
+            rows += 1;
+        }
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    println!("Wrote {} candidates for {} surface forms to {}", rows, surfaces.len(), output_path.to_str().unwrap());
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use rustc_hash::FxHashMap;
+use crate::corpus::categories;
+use crate::files::read_names;
+use crate::helpers::PageId;
+use crate::links::PageInfo;
+
+// categories.bin lists the categories each page is in, with the same records as files.bin. Category pages are only
+// indexed when the Category namespace isn't ignored (--include-namespaces Category), and their records then give the
+// category hierarchy.
+pub const CATEGORIES_FILE: &str = "categories.bin";
+const MAGIC: &[u8; 4] = b"WKCA";
+const VERSION: u32 = 1;
+const CATEGORY_NAMESPACE: u32 = 14;
+// The category whose subcategories are the top-level topics, like Culture, Geography and History
+pub const DEFAULT_ROOT: &str = "Main topic classifications";
+// Without a hierarchy, articles are grouped by this many of the largest categories
+const LARGEST_CATEGORIES: usize = 20;
+
+pub fn get_header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+// Returns the distinct categories a page is in, with the first letter uppercase as in category titles
+pub fn extract_categories(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in categories(text) {
+        let mut chars = name.chars();
+        let name: String = chars.next().unwrap().to_uppercase().chain(chars).collect();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+pub fn read_categories(path: &Path) -> FxHashMap<PageId, Vec<String>> {
+    read_names(path, MAGIC, VERSION, "categories")
+}
+
+// Groups the articles in the main namespace by top-level category, largest group first, along with a description of
+// how the groups were chosen. When the category pages were indexed, the top-level categories are the subcategories of
+// `root`, and each article goes to the one nearest to any of its categories in the hierarchy (ties go to the first
+// alphabetically). Otherwise each article goes to every one of its own categories that's among the largest.
+pub fn top_level_groups(categories: &FxHashMap<PageId, Vec<String>>, pages: &FxHashMap<PageId, PageInfo>, root: &str, title: impl Fn(&PageId) -> Option<String>) -> (String, Vec<(String, Vec<PageId>)>) {
+    let is_article = |id: &PageId| pages.get(id).is_some_and(|info| info.namespace == 0 && !info.redirect);
+    let mut subcategories: FxHashMap<&str, Vec<String>> = FxHashMap::default();
+    for (id, parents) in categories.iter().filter(|(id, _)| pages.get(id).is_some_and(|info| info.namespace == CATEGORY_NAMESPACE)) {
+        let Some(name) = title(id) else { continue };
+        let name = name.split_once(':').map_or(name.as_str(), |(_, name)| name).to_string();
+        for parent in parents {
+            subcategories.entry(parent.as_str()).or_default().push(name.clone());
+        }
+    }
+
+    let mut groups: FxHashMap<String, Vec<PageId>> = FxHashMap::default();
+    let description = match subcategories.get(root).filter(|top_level| !top_level.is_empty()) {
+        Some(top_level) => {
+            // Breadth-first search down from all the top-level categories at once, so each category is reached first
+            // from the top-level category nearest to it
+            let mut top_level = top_level.clone();
+            top_level.sort_unstable();
+            top_level.dedup();
+            let mut nearest: FxHashMap<&str, (usize, usize)> = FxHashMap::default();  // category -> (distance, top-level index)
+            let mut queue = VecDeque::new();
+            for (index, name) in top_level.iter().enumerate() {
+                nearest.entry(name).or_insert_with(|| {
+                    queue.push_back(name.as_str());
+                    (0, index)
+                });
+            }
+            while let Some(name) = queue.pop_front() {
+                let (distance, index) = nearest[name];
+                for child in subcategories.get(name).into_iter().flatten() {
+                    nearest.entry(child).or_insert_with(|| {
+                        queue.push_back(child.as_str());
+                        (distance + 1, index)
+                    });
+                }
+            }
+            for (id, article_categories) in categories.iter().filter(|(id, _)| is_article(id)) {
+                if let Some((_, index)) = article_categories.iter().filter_map(|name| nearest.get(name.as_str())).min() {
+                    groups.entry(top_level[*index].clone()).or_default().push(*id);
+                }
+            }
+            format!("nearest subcategory of {}", root)
+        }
+        None => {
+            for (id, article_categories) in categories.iter().filter(|(id, _)| is_article(id)) {
+                for name in article_categories {
+                    groups.entry(name.clone()).or_default().push(*id);
+                }
+            }
+            let reason = if subcategories.is_empty() { "category pages weren't indexed".to_string() } else { format!("{} has no subcategories", root) };
+            format!("{} largest categories, {}", LARGEST_CATEGORIES, reason)
+        }
+    };
+
+    let mut groups: Vec<(String, Vec<PageId>)> = groups.into_iter().collect();
+    groups.sort_unstable_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    groups.truncate(LARGEST_CATEGORIES.max(subcategories.get(root).map_or(0, Vec::len)));
+    (description, groups)
+}
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::preflight::format_size;
+use crate::stats::{format_duration, percentile, print_size_histogram};
+
+pub const CHUNK_TIMES_FILE: &str = "chunk-times.tsv";
+
+// A chunk is hot when it takes this many times longer than the median chunk, or gets through each byte this many
+// times slower than the median rate
+const HOT_CHUNK_FACTOR: u64 = 5;
+const HOT_CHUNKS_SHOWN: usize = 10;
+
+pub struct ChunkTiming {
+    pub chunk_index: usize,
+    pub start_position: u64,
+    pub bytes: u64,
+    pub articles: usize,
+    pub duration: Duration,
+    pub finished: Duration,  // since the start of the run
+}
+
+impl ChunkTiming {
+    fn nanos_per_byte(&self) -> u64 {
+        self.duration.as_nanos() as u64 / self.bytes.max(1)
+    }
+}
+
+// Keeps fractions of a second for the short times most chunks take
+fn format_time(duration: Duration) -> String {
+    if duration.as_secs() < 60 { format!("{:.2}s", duration.as_secs_f64()) } else { format_duration(duration) }
+}
+
+// Returns the chunks that took far longer than the typical one, slowest first. Small chunks are only flagged for a
+// slow rate when they also took longer than the median, since a fixed cost per chunk makes them look slow per byte
+pub fn hot_chunks(timings: &[ChunkTiming]) -> Vec<&ChunkTiming> {
+    if timings.is_empty() { return Vec::new(); }
+    let mut times: Vec<u64> = timings.iter().map(|timing| timing.duration.as_micros() as u64).collect();
+    times.sort_unstable();
+    let mut rates: Vec<u64> = timings.iter().map(ChunkTiming::nanos_per_byte).collect();
+    rates.sort_unstable();
+    let (median_time, median_rate) = (percentile(&times, 0.5).max(1), percentile(&rates, 0.5).max(1));
+
+    let mut hot: Vec<&ChunkTiming> = timings.iter()
+        .filter(|timing| {
+            let time = timing.duration.as_micros() as u64;
+            time > HOT_CHUNK_FACTOR * median_time || (time > median_time && timing.nanos_per_byte() > HOT_CHUNK_FACTOR * median_rate)
+        })
+        .collect();
+    hot.sort_unstable_by_key(|timing| std::cmp::Reverse(timing.duration));
+    hot
+}
+
+// Prints the distribution of chunk sizes and processing times for an index run, how busy the threads were and how
+// long the run waited on its last few chunks, and the hot chunks, then writes every chunk's timing to a TSV file
+pub fn report(timings: &[ChunkTiming], threads: usize, elapsed: Duration, data_path: &Path) {
+    if timings.is_empty() { return; }
+    let mut sizes: Vec<u64> = timings.iter().map(|timing| timing.bytes).collect();
+    sizes.sort_unstable();
+    let mut times: Vec<u64> = timings.iter().map(|timing| timing.duration.as_micros() as u64).collect();
+    times.sort_unstable();
+    let time = |micros: u64| format_time(Duration::from_micros(micros));
+
+    println!("\nChunks processed: {}", timings.len());
+    println!("  Size: min {}, median {}, p90 {}, p99 {}, max {}",
+        format_size(sizes[0]), format_size(percentile(&sizes, 0.5)), format_size(percentile(&sizes, 0.9)),
+        format_size(percentile(&sizes, 0.99)), format_size(sizes[sizes.len() - 1]));
+    println!("  Size distribution:");
+    print_size_histogram(&sizes);
+    println!("  Time: min {}, median {}, p90 {}, p99 {}, max {}",
+        time(times[0]), time(percentile(&times, 0.5)), time(percentile(&times, 0.9)), time(percentile(&times, 0.99)), time(times[times.len() - 1]));
+
+    let busy: Duration = timings.iter().map(|timing| timing.duration).sum();
+    let bytes: u64 = sizes.iter().sum();
+    println!("  Throughput: {}/s per thread, {}/s overall",
+        format_size((bytes as f64 / busy.as_secs_f64().max(1e-9)) as u64), format_size((bytes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64));
+    let utilisation = busy.as_secs_f64() / (elapsed.as_secs_f64() * threads as f64).max(1e-9);
+    println!("  Threads busy: {:.0}% of {} threads over {}", 100.0 * utilisation.min(1.0), threads, format_time(elapsed));
+
+    // Once fewer chunks are left than there are threads, the idle threads can only wait for the slowest of the rest
+    let mut finished: Vec<Duration> = timings.iter().map(|timing| timing.finished).collect();
+    finished.sort_unstable();
+    if finished.len() > threads && threads > 1 {
+        let tail = finished[finished.len() - 1] - finished[finished.len() - threads];
+        let fraction = tail.as_secs_f64() / elapsed.as_secs_f64().max(1e-9);
+        println!("  Tail: {} ({:.0}% of the run) between the first thread going idle and the last chunk finishing", format_time(tail), 100.0 * fraction);
+        if fraction > 0.2 {
+            println!("  The run spent much of its time waiting on its slowest chunks, so more threads won't make it much faster");
+        } else if utilisation > 0.9 {
+            println!("  The threads were busy nearly the whole run, so more threads may help if there are spare cores");
+        }
+    }
+
+    let hot = hot_chunks(timings);
+    let median_time = percentile(&times, 0.5).max(1);
+    if hot.is_empty() {
+        println!("  No hot chunks (over {}x the median time or time per byte)", HOT_CHUNK_FACTOR);
+    } else {
+        println!("  Hot chunks (over {}x the median time or time per byte): {}", HOT_CHUNK_FACTOR, hot.len());
+        for timing in hot.iter().take(HOT_CHUNKS_SHOWN) {
+            println!("    Chunk {} at byte {}: {}, {} articles, {} ({:.1}x median, {}/s)",
+                timing.chunk_index, timing.start_position, format_size(timing.bytes), timing.articles, format_time(timing.duration),
+                timing.duration.as_micros() as f64 / median_time as f64,
+                format_size((timing.bytes as f64 / timing.duration.as_secs_f64().max(1e-9)) as u64));
+        }
+        if hot.len() > HOT_CHUNKS_SHOWN {
+            println!("    ... and {} more", hot.len() - HOT_CHUNKS_SHOWN);
+        }
+    }
+
+    let path = data_path.join(CHUNK_TIMES_FILE);
+    let mut writer = BufWriter::new(File::create(&path).expect("Failed to create chunk times file"));
+    writeln!(writer, "chunk\tstart\tbytes\tarticles\tmillis\tfinished_millis").expect("Failed to write chunk times file");
+    let mut sorted: Vec<&ChunkTiming> = timings.iter().collect();
+    sorted.sort_unstable_by_key(|timing| timing.chunk_index);
+    for timing in sorted {
+        writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}", timing.chunk_index, timing.start_position, timing.bytes, timing.articles,
+            timing.duration.as_millis(), timing.finished.as_millis()).expect("Failed to write chunk times file");
+    }
+    writer.flush().expect("Failed to write chunk times file");
+    println!("  Per-chunk timings written to {}", path.display());
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tracing::debug;
+use crate::graph::{UndirectedGraph, build_dense_graph, build_undirected_graph};
+use crate::helpers::Args;
+use crate::split::{load_graph, load_titles};
+
+// Stop moving nodes once a pass improves modularity by less than this
+const MIN_GAIN: f64 = 1e-6;
+
+// A level of the Louvain hierarchy. Each node's row can include a self-loop holding the weight inside it, counted
+// from both ends, so a node's degree is always the sum of its row.
+struct Level {
+    offsets: Vec<usize>,
+    neighbors: Vec<u32>,
+    weights: Vec<f64>,
+}
+
+impl Level {
+    fn node_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    fn row(&self, node: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.offsets[node]..self.offsets[node + 1];
+        self.neighbors[range.clone()].iter().map(|&neighbor| neighbor as usize).zip(self.weights[range].iter().copied())
+    }
+
+    fn degrees(&self) -> Vec<f64> {
+        (0..self.node_count()).map(|node| self.row(node).map(|(_, weight)| weight).sum()).collect()
+    }
+}
+
+fn modularity(level: &Level, communities: &[u32], degrees: &[f64], total_weight: f64, resolution: f64) -> f64 {
+    let mut inside = vec![0.0; level.node_count()];
+    let mut totals = vec![0.0; level.node_count()];
+    for node in 0..level.node_count() {
+        let community = communities[node] as usize;
+        totals[community] += degrees[node];
+        inside[community] += level.row(node).filter(|&(neighbor, _)| communities[neighbor] as usize == community).map(|(_, weight)| weight).sum::<f64>();
+    }
+    inside.iter().zip(&totals).map(|(inside, total)| inside / total_weight - resolution * (total / total_weight).powi(2)).sum()
+}
+
+// Moves nodes one at a time into whichever neighboring community gains the most modularity, until a pass gains too
+// little. Returns each node's community, numbered densely.
+fn move_nodes(level: &Level, total_weight: f64, resolution: f64) -> Vec<u32> {
+    let node_count = level.node_count();
+    let degrees = level.degrees();
+    let mut communities: Vec<u32> = (0..node_count as u32).collect();
+    let mut totals = degrees.clone();
+    let mut community_weights = vec![0.0; node_count];
+    let mut touched = Vec::new();
+    let mut quality = modularity(level, &communities, &degrees, total_weight, resolution);
+    loop {
+        let mut moves = 0;
+        for node in 0..node_count {
+            let current = communities[node] as usize;
+            totals[current] -= degrees[node];
+            for (neighbor, weight) in level.row(node).filter(|&(neighbor, _)| neighbor != node) {
+                let community = communities[neighbor] as usize;
+                if community_weights[community] == 0.0 { touched.push(community); }
+                community_weights[community] += weight;
+            }
+
+            let gain = |community: usize| community_weights[community] - resolution * totals[community] * degrees[node] / total_weight;
+            let mut best = current;
+            let mut best_gain = gain(current);
+            for &community in &touched {
+                let community_gain = gain(community);
+                if community_gain > best_gain || (community_gain == best_gain && community < best) {
+                    best = community;
+                    best_gain = community_gain;
+                }
+            }
+            for community in touched.drain(..) {
+                community_weights[community] = 0.0;
+            }
+
+            totals[best] += degrees[node];
+            if best != current {
+                communities[node] = best as u32;
+                moves += 1;
+            }
+        }
+        let new_quality = modularity(level, &communities, &degrees, total_weight, resolution);
+        let gain = new_quality - quality;
+        quality = new_quality;
+        if moves == 0 || gain < MIN_GAIN { break; }
+    }
+
+    // Renumber the communities that are still in use
+    let mut numbering = vec![u32::MAX; node_count];
+    let mut next = 0;
+    for community in &mut communities {
+        if numbering[*community as usize] == u32::MAX {
+            numbering[*community as usize] = next;
+            next += 1;
+        }
+        *community = numbering[*community as usize];
+    }
+    communities
+}
+
+// Collapses each community into a single node, summing the weights between communities and inside them
+fn aggregate(level: &Level, communities: &[u32]) -> Level {
+    let community_count = communities.iter().max().map_or(0, |&max| max as usize + 1);
+    let mut members = vec![Vec::new(); community_count];
+    for (node, &community) in communities.iter().enumerate() {
+        members[community as usize].push(node);
+    }
+
+    let mut offsets = vec![0];
+    let mut neighbors = Vec::new();
+    let mut weights = Vec::new();
+    let mut row_weights = vec![0.0; community_count];
+    let mut touched = Vec::new();
+    for nodes in &members {
+        for &node in nodes {
+            for (neighbor, weight) in level.row(node) {
+                let community = communities[neighbor] as usize;
+                if row_weights[community] == 0.0 { touched.push(community); }
+                row_weights[community] += weight;
+            }
+        }
+        touched.sort_unstable();
+        for community in touched.drain(..) {
+            neighbors.push(community as u32);
+            weights.push(row_weights[community]);
+            row_weights[community] = 0.0;
+        }
+        offsets.push(neighbors.len());
+    }
+    Level { offsets, neighbors, weights }
+}
+
+// Runs Louvain until a level stops merging nodes, and returns each node's community along with the final modularity
+fn louvain(graph: &UndirectedGraph, resolution: f64) -> (Vec<u32>, f64) {
+    let mut level = Level {
+        offsets: graph.offsets.iter().map(|&offset| offset as usize).collect(),
+        neighbors: graph.neighbors.clone(),
+        weights: graph.weights.iter().map(|&weight| weight as f64).collect(),
+    };
+    let total_weight: f64 = level.weights.iter().sum();
+    let mut assignments: Vec<u32> = (0..graph.node_count() as u32).collect();
+    if total_weight == 0.0 { return (assignments, 0.0); }
+    loop {
+        let communities = move_nodes(&level, total_weight, resolution);
+        let community_count = communities.iter().max().map_or(0, |&max| max as usize + 1);
+        for assignment in &mut assignments {
+            *assignment = communities[*assignment as usize];
+        }
+        debug!("Louvain level merged {} nodes into {} communities", level.node_count(), community_count);
+        if community_count == level.node_count() { break; }
+        level = aggregate(&level, &communities);
+    }
+
+    let degrees = level.degrees();
+    let identity: Vec<u32> = (0..level.node_count() as u32).collect();
+    let quality = modularity(&level, &identity, &degrees, total_weight, resolution);
+    (assignments, quality)
+}
+
+pub fn communities(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let resolution: f64 = args.parse_value("resolution").unwrap_or(1.0);
+    if resolution.is_nan() || resolution <= 0.0 {
+        eprintln!("Error: --resolution must be positive");
+        std::process::exit(1);
+    }
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("communities.tsv"));
+
+    let Some(links) = load_graph(data_path) else {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let graph = build_dense_graph(&links);
+    drop(links);
+    let undirected = build_undirected_graph(&graph);
+    let (assignments, quality) = louvain(&undirected, resolution);
+
+    // Number the communities from largest to smallest
+    let community_count = assignments.iter().max().map_or(0, |&max| max as usize + 1);
+    let mut sizes = vec![0; community_count];
+    for &community in &assignments {
+        sizes[community as usize] += 1;
+    }
+    let mut order: Vec<usize> = (0..community_count).collect();
+    order.sort_by_key(|&community| (std::cmp::Reverse(sizes[community]), community));
+    let mut ranks = vec![0; community_count];
+    for (rank, &community) in order.iter().enumerate() {
+        ranks[community] = rank;
+    }
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    writeln!(output_file, "article_id\tcommunity").expect("Failed to write output file");
+    for (node, &community) in assignments.iter().enumerate() {
+        writeln!(output_file, "{}\t{}", graph.ids[node], ranks[community as usize]).expect("Failed to write output file");
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    let degree = |node: usize| undirected.weights(node).iter().map(|&weight| weight as u64).sum::<u64>();
+    let mut members = vec![Vec::new(); community_count];
+    for (node, &community) in assignments.iter().enumerate() {
+        members[ranks[community as usize]].push(node);
+    }
+    let titles = load_titles(data_path).unwrap();
+    let title = |node: &usize| titles.get(&graph.ids[*node]).cloned().unwrap_or_else(|| format!("Unknown (ID: {})", graph.ids[*node]));
+
+    println!("Modularity: {:.4}", quality);
+    println!("Communities: {} ({} with more than one article)", community_count, sizes.iter().filter(|&&size| size > 1).count());
+    println!("Wrote community assignments to {}", output_path.to_str().unwrap());
+    println!("\nTop 10 largest communities:");
+    for (rank, nodes) in members.iter_mut().take(10).enumerate() {
+        nodes.sort_by_key(|&node| (std::cmp::Reverse(degree(node)), node));
+        let top: Vec<String> = nodes.iter().take(5).map(title).collect();
+        println!("{:>2}) {} articles: {}", rank + 1, nodes.len(), top.join(", "));
+    }
+}
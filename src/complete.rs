@@ -0,0 +1,95 @@
+use std::path::Path;
+use std::fs::{File, read};
+use std::io::BufWriter;
+use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer};
+use fst::automaton::{Levenshtein, Str};
+use crate::helpers::{Args, PageId};
+use crate::split::{RecordIndex, split_files_exist};
+
+// Prefix searches can match a huge number of titles, so only this many are collected before ranking
+const MAX_CANDIDATES: usize = 10000;
+
+// Writes a finite-state transducer mapping lowercased titles to article IDs. Titles that collide after
+// lowercasing map to the lowest ID.
+pub fn write_title_fst<'a>(path: &Path, titles: impl Iterator<Item = &'a (PageId, String)>) {
+    let mut entries: Vec<(String, PageId)> = titles.map(|(id, title)| (title.to_lowercase(), *id)).collect();
+    entries.sort_unstable();
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let file = BufWriter::new(File::create(path).expect("Failed to create title FST"));
+    let mut builder = MapBuilder::new(file).expect("Failed to create title FST");
+    for (title, id) in entries {
+        builder.insert(title, id as u64).expect("Failed to write title FST");
+    }
+    builder.finish().expect("Failed to finish title FST");
+}
+
+pub struct TitleCompleter {
+    map: Map<Vec<u8>>,
+}
+
+impl TitleCompleter {
+    pub fn open(data_path: &Path) -> Option<Self> {
+        let bytes = read(data_path.join("titles.fst")).ok()?;
+        Some(TitleCompleter { map: Map::new(bytes).expect("titles.fst is corrupt, re-run the index command") })
+    }
+
+    // The article ID for an exact title, ignoring case
+    pub fn find(&self, title: &str) -> Option<PageId> {
+        self.map.get(title.trim().to_lowercase()).map(|id| id as PageId)
+    }
+
+    fn search<A: Automaton>(&self, automaton: A) -> Vec<(String, PageId)> {
+        let mut stream = self.map.search(automaton).into_stream();
+        let mut matches = Vec::new();
+        while let Some((title, id)) = stream.next() {
+            matches.push((String::from_utf8_lossy(title).into_owned(), id as PageId));
+            if matches.len() >= MAX_CANDIDATES { break; }
+        }
+        matches
+    }
+
+    // Returns (lowercased title, article ID) pairs for titles starting with `prefix`, or within `fuzzy` edits of
+    // a title prefix. Exact prefix matches come first, then shorter titles.
+    pub fn complete(&self, prefix: &str, fuzzy: u32, limit: usize) -> Result<Vec<(String, PageId)>, String> {
+        let prefix = prefix.trim().to_lowercase();
+        let mut matches = if fuzzy == 0 {
+            self.search(Str::new(&prefix).starts_with())
+        } else {
+            let automaton = Levenshtein::new(&prefix, fuzzy).map_err(|err| format!("Query too complex for fuzzy matching: {}", err))?;
+            self.search(automaton.starts_with())
+        };
+
+        let rank = |(title, id): &(String, PageId)| (!title.starts_with(&prefix), title.len(), title.clone(), *id);
+        matches.sort_by_cached_key(rank);
+        matches.truncate(limit);
+        Ok(matches)
+    }
+}
+
+pub fn complete(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let prefix = args.positional[1..].join(" ");
+    if prefix.is_empty() {
+        eprintln!("Usage: complete <data_path> <prefix> [--limit N] [--fuzzy N]");
+        std::process::exit(1);
+    }
+    let Some(completer) = TitleCompleter::open(data_path) else {
+        eprintln!("Error: Unable to locate titles.fst in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let record_index = split_files_exist(data_path).then(|| RecordIndex::open(data_path));
+
+    let matches = completer.complete(&prefix, args.parse_value("fuzzy").unwrap_or(0), args.parse_value("limit").unwrap_or(10))
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        });
+    for (title, id) in &matches {
+        let title = record_index.as_ref().and_then(|record_index| record_index.title(*id)).unwrap_or(title.clone());
+        println!("{} (ID: {})", title, id);
+    }
+    if matches.is_empty() {
+        println!("No matches");
+    }
+}
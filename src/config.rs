@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::fs::read_to_string;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+use tracing::warn;
+use crate::helpers::Args;
+use crate::namespaces;
+
+// Settings shared by every command, read from wikipedia.toml in the working directory or the file given with
+// --config. For example:
+//
+//   data_path = "/data/enwiki"
+//   dump_prefix = "enwiki-20240801"
+//   dump_url = "https://dumps.wikimedia.org/enwiki/20240801"
+//   threads = 16
+//   ignore_namespaces = ["Category", "Wikipedia", "File", "Template", "Draft", "Portal", "Module"]
+//   include_namespaces = ["Portal"]
+//
+//   [output]
+//   dump = "/scratch/enwiki-chunks"
+//   export = "/scratch/enwiki-exports"
+//
+// ignore_namespaces replaces the default list of namespaces to skip, and include_namespaces takes names out of it. The
+// default list depends on the project the dump prefix names, so enwiktionary-20240801 skips Wiktionary: and Appendix:
+// pages instead of Wikipedia: pages (see namespaces.rs). --threads, --dump-prefix and --dump-url override the file, and
+// --ignore-namespaces and --include-namespaces take comma-separated names that are applied on top of it.
+//
+// With dump_url, an http(s)://, s3:// or gs:// location (see storage.rs), the dump is read remotely with range requests
+// instead of from data_path: the index is downloaded into data_path once, and after that only the chunks a command
+// needs are fetched.
+const CONFIG_FILE: &str = "wikipedia.toml";
+const DEFAULT_DUMP_PREFIX: &str = "enwiki-20240801";
+const DEFAULT_THREADS: usize = 8;
+
+pub struct Config {
+    pub data_path: Option<PathBuf>,
+    pub dump_prefix: String,
+    pub dump_url: Option<String>,
+    pub threads: usize,
+    threads_configured: bool,  // whether the file or --threads set threads, rather than it being the default
+    pub ignore_prefixes: Vec<String>,  // namespace names with the trailing colon, as they appear in titles
+    pub dump_output: Option<PathBuf>,
+    pub export_output: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_path: None,
+            dump_prefix: DEFAULT_DUMP_PREFIX.to_string(),
+            dump_url: None,
+            threads: DEFAULT_THREADS,
+            threads_configured: false,
+            ignore_prefixes: namespaces::to_prefixes(namespaces::default_ignored(DEFAULT_DUMP_PREFIX).into_iter()),
+            dump_output: None,
+            export_output: None,
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+fn fail(config_path: &Path, message: &str) -> ! {
+    eprintln!("Error: {}: {}", config_path.to_str().unwrap(), message);
+    std::process::exit(1);
+}
+
+fn get_strings<'a>(table: &'a Table, key: &str, config_path: &Path) -> Option<Vec<&'a str>> {
+    table.get(key).map(|value| value.as_array()
+        .and_then(|values| values.iter().map(Value::as_str).collect())
+        .unwrap_or_else(|| fail(config_path, &format!("{} must be a list of strings", key))))
+}
+
+fn get_string(table: &Table, key: &str, config_path: &Path) -> Option<String> {
+    table.get(key).map(|value| match value {
+        Value::String(string) => string.clone(),
+        _ => fail(config_path, &format!("{} must be a string", key)),
+    })
+}
+
+// Namespace lists from the config file, which are applied once the dump prefix is final
+#[derive(Default)]
+struct NamespaceSettings {
+    ignore: Option<Vec<String>>,
+    include: Vec<String>,
+}
+
+fn parse_config(config_path: &Path) -> (Config, NamespaceSettings) {
+    let contents = read_to_string(config_path).unwrap_or_else(|err| fail(config_path, &err.to_string()));
+    let table: Table = contents.parse().unwrap_or_else(|err: toml::de::Error| fail(config_path, err.message()));
+    let mut config = Config::default();
+
+    for key in table.keys() {
+        if !["data_path", "dump_prefix", "dump_url", "threads", "ignore_namespaces", "include_namespaces", "output"].contains(&key.as_str()) {
+            warn!("{}: ignoring unknown setting {}", config_path.to_str().unwrap(), key);
+        }
+    }
+    config.data_path = get_string(&table, "data_path", config_path).map(PathBuf::from);
+    if let Some(dump_prefix) = get_string(&table, "dump_prefix", config_path) {
+        config.dump_prefix = dump_prefix;
+    }
+    config.dump_url = get_string(&table, "dump_url", config_path);
+    if let Some(threads) = table.get("threads") {
+        config.threads = threads.as_integer().filter(|&threads| threads > 0)
+            .unwrap_or_else(|| fail(config_path, "threads must be a positive integer")) as usize;
+        config.threads_configured = true;
+    }
+    let settings = NamespaceSettings {
+        ignore: get_strings(&table, "ignore_namespaces", config_path).map(|ignore| namespaces::to_prefixes(ignore.into_iter())),
+        include: get_strings(&table, "include_namespaces", config_path).map(|include| namespaces::to_prefixes(include.into_iter())).unwrap_or_default(),
+    };
+    if let Some(output) = table.get("output") {
+        let output = output.as_table().unwrap_or_else(|| fail(config_path, "output must be a table"));
+        config.dump_output = get_string(output, "dump", config_path).map(PathBuf::from);
+        config.export_output = get_string(output, "export", config_path).map(PathBuf::from);
+    }
+    (config, settings)
+}
+
+// Reads the config file, if there is one, and applies the command line overrides. Must run before anything
+// calls config().
+pub fn load(args: &Args) {
+    let (mut config, settings) = match args.value("config") {
+        Some(config_path) => parse_config(Path::new(config_path)),
+        None if Path::new(CONFIG_FILE).exists() => parse_config(Path::new(CONFIG_FILE)),
+        None => (Config::default(), NamespaceSettings::default()),
+    };
+    if let Some(threads) = args.parse_value("threads") {
+        if threads == 0 {
+            eprintln!("Error: --threads must be at least 1");
+            std::process::exit(1);
+        }
+        config.threads = threads;
+        config.threads_configured = true;
+    }
+    if let Some(dump_prefix) = args.value("dump-prefix") {
+        config.dump_prefix = dump_prefix.to_string();
+    }
+    if let Some(dump_url) = args.value("dump-url") {
+        config.dump_url = Some(dump_url.to_string());
+    }
+    config.ignore_prefixes = settings.ignore
+        .unwrap_or_else(|| namespaces::to_prefixes(namespaces::default_ignored(&config.dump_prefix).into_iter()));
+    namespaces::apply_overrides(&mut config.ignore_prefixes, &[], &settings.include);
+    let list = |name| args.value(name).map(|names: &str| namespaces::to_prefixes(names.split(','))).unwrap_or_default();
+    namespaces::apply_overrides(&mut config.ignore_prefixes, &list("ignore-namespaces"), &list("include-namespaces"));
+    // The rayon pool used by the analyses otherwise sizes itself to the machine, even when asked for 8 threads
+    if config.threads_configured {
+        rayon::ThreadPoolBuilder::new().num_threads(config.threads).build_global().expect("Failed to configure thread pool");
+    }
+    CONFIG.set(config).ok().expect("Config loaded twice");
+}
@@ -0,0 +1,329 @@
+use std::path::Path;
+use std::fs::{create_dir_all, read_to_string};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use html_escape::decode_html_entities;
+use rustc_hash::FxHashMap;
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::helpers::{Args, OutputCompression, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files, skip_nested};
+use crate::config::config;
+use crate::namespaces::is_ignored;
+use crate::quality::load_quality_filter;
+
+// Sections that are mostly citations and link lists rather than prose
+pub const SKIPPED_SECTIONS: [&str; 8] = ["references", "notes", "citations", "sources", "bibliography", "further reading", "external links", "see also"];
+// Tags whose contents aren't part of the article's prose
+pub const DROPPED_TAGS: [&str; 4] = ["ref", "gallery", "math", "timeline"];
+
+// Counts tokens either as whitespace-separated words or with a byte-pair encoding loaded from a tiktoken vocabulary
+// file, where each line is a base64 token and its rank
+pub enum Tokenizer {
+    Words,
+    Bpe(FxHashMap<Vec<u8>, u32>),
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+impl Tokenizer {
+    pub fn from_args(args: &Args) -> Self {
+        let Some(vocabulary_path) = args.value("tokenizer").filter(|&tokenizer| tokenizer != "words") else { return Tokenizer::Words };
+        let contents = read_to_string(vocabulary_path).unwrap_or_else(|err| {
+            eprintln!("Error: Failed to read tokenizer vocabulary {}: {}", vocabulary_path, err);
+            std::process::exit(1);
+        });
+        let ranks: Option<FxHashMap<Vec<u8>, u32>> = contents.lines().filter(|line| !line.is_empty()).map(|line| {
+            let (token, rank) = line.split_once(' ')?;
+            Some((decode_base64(token)?, rank.parse().ok()?))
+        }).collect();
+        let Some(ranks) = ranks else {
+            eprintln!("Error: {} is not a tiktoken vocabulary file", vocabulary_path);
+            std::process::exit(1);
+        };
+        Tokenizer::Bpe(ranks)
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Tokenizer::Words => text.split_whitespace().count(),
+            Tokenizer::Bpe(ranks) => pre_tokenize(text).iter().map(|piece| bpe_count(ranks, piece.as_bytes())).sum(),
+        }
+    }
+}
+
+// Splits text into runs of letters, digits (at most three at a time), whitespace and other symbols, with a single
+// leading space kept on the following run, which approximates the splitting tiktoken does before merging
+fn pre_tokenize(text: &str) -> Vec<&str> {
+    let class = |c: char| if c.is_alphabetic() { 0 } else if c.is_numeric() { 1 } else if c.is_whitespace() { 2 } else { 3 };
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let piece_class = match chars.peek() {
+            Some(&(_, next)) if c == ' ' && class(next) != 2 => class(next),
+            _ => class(c),
+        };
+        let mut digits = usize::from(piece_class == 1 && c != ' ');
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if class(next) != piece_class || (piece_class == 1 && digits == 3) { break; }
+            digits += usize::from(piece_class == 1);
+            end = j + next.len_utf8();
+            chars.next();
+        }
+        pieces.push(&text[start..end]);
+        start = end;
+    }
+    pieces
+}
+
+// Applies the lowest ranked merge until none apply, and returns how many tokens are left
+fn bpe_count(ranks: &FxHashMap<Vec<u8>, u32>, piece: &[u8]) -> usize {
+    if ranks.contains_key(piece) { return 1; }
+    let mut parts: Vec<&[u8]> = piece.chunks(1).collect();
+    loop {
+        let best = (0..parts.len().saturating_sub(1))
+            .filter_map(|i| ranks.get(&[parts[i], parts[i + 1]].concat()).map(|&rank| (rank, i)))
+            .min();
+        let Some((_, i)) = best else { return parts.len() };
+        let merged = &piece[parts[..i].iter().map(|part| part.len()).sum::<usize>()..][..parts[i].len() + parts[i + 1].len()];
+        parts[i] = merged;
+        parts.remove(i + 1);
+    }
+}
+
+// Converts wikitext to plain text, dropping templates, tables, comments, references, files and categories, and
+// keeping the displayed text of links and formatting
+pub fn plain_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |end| end + 3);
+        } else if rest.starts_with("{{") {
+            i += skip_nested(rest, "{{", "}}");
+        } else if rest.starts_with("{|") {
+            i += skip_nested(rest, "{|", "|}");
+        } else if rest.starts_with("[[") {
+            let length = skip_nested(rest, "[[", "]]");
+            let inner = rest[2..length].strip_suffix("]]").unwrap_or(&rest[2..length]);
+            // Links into ignored namespaces (files, categories and so on) and interlanguage links aren't prose
+            let target = inner.split('|').next().unwrap().trim();
+            let language = target.split_once(':').is_some_and(|(prefix, _)| (2..=3).contains(&prefix.len()) && prefix.bytes().all(|c| c.is_ascii_lowercase()));
+            if !is_ignored(target) && !target.starts_with("Image:") && !language {
+                output.push_str(&plain_text(inner.rsplit('|').next().unwrap()));
+            }
+            i += length;
+        } else if rest.starts_with("[http") || rest.starts_with("[//") {
+            let end = rest.find(']').unwrap_or(rest.len());
+            if let Some((_, label)) = rest[1..end].split_once(' ') {
+                output.push_str(label);
+            }
+            i += (end + 1).min(rest.len());
+        } else if rest.starts_with("''") {
+            i += rest.len() - rest.trim_start_matches('\'').len();
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') {
+            let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let name = rest[1..].split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap().to_lowercase();
+            i += tag_end;
+            if DROPPED_TAGS.contains(&name.as_str()) && !rest[..tag_end].ends_with("/>") {
+                let closing = format!("</{}>", name);
+                i += text[i..].find(&closing).map_or(text.len() - i, |end| end + closing.len());
+            }
+        } else {
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            i += c.len_utf8();
+        }
+    }
+
+    // Drop list markers and behavior switches like __NOTOC__, then squeeze runs of blank lines
+    let mut cleaned = String::with_capacity(output.len());
+    for line in decode_html_entities(&output).lines() {
+        let line = line.trim_start_matches(['*', '#', ':', ';']).split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.starts_with("__") && line.ends_with("__") { continue; }
+        if line.is_empty() && (cleaned.is_empty() || cleaned.ends_with("\n\n")) { continue; }
+        cleaned.push_str(&line);
+        cleaned.push('\n');
+    }
+    cleaned.trim().to_string()
+}
+
+// Returns the categories an article's wikitext puts it in, without sort keys
+pub fn categories(text: &str) -> Vec<String> {
+    text.match_indices("[[Category:")
+        .filter_map(|(start, prefix)| {
+            let rest = &text[start + prefix.len()..];
+            let name = &rest[..rest.find("]]")?];
+            Some(name.split('|').next().unwrap().trim().replace('_', " "))
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+// Splits wikitext at its headings into (section title, wikitext) pairs, with the lead section titled "Introduction"
+pub fn split_sections(text: &str) -> Vec<(String, &str)> {
+    let mut sections = Vec::new();
+    let mut title = "Introduction".to_string();
+    let mut start = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let heading = line.trim();
+        if heading.len() > 4 && heading.starts_with("==") && heading.ends_with("==") {
+            sections.push((title, &text[start..offset]));
+            title = plain_text(heading.trim_matches('=').trim());
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    sections.push((title, &text[start..]));
+    sections
+}
+
+// Groups paragraphs into chunks of at most `max_tokens` tokens, splitting paragraphs that are too long on their own
+// between words
+fn chunk_text(text: &str, max_tokens: usize, tokenizer: &Tokenizer) -> Vec<(String, usize)> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    let mut push = |piece: &str, tokens: usize, chunks: &mut Vec<(String, usize)>| {
+        if current_tokens > 0 && current_tokens + tokens > max_tokens {
+            chunks.push((std::mem::take(&mut current), current_tokens));
+            current_tokens = 0;
+        }
+        if current.is_empty() {
+            current.push_str(piece.trim_start());
+        } else {
+            current.push_str(if piece.starts_with(' ') { "" } else { "\n\n" });
+            current.push_str(piece);
+        }
+        current_tokens += tokens;
+    };
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        let tokens = tokenizer.count(paragraph);
+        if tokens <= max_tokens {
+            push(paragraph, tokens, &mut chunks);
+            continue;
+        }
+        for (index, word) in paragraph.split(' ').filter(|word| !word.is_empty()).enumerate() {
+            let word = if index == 0 { word.to_string() } else { format!(" {}", word) };
+            push(&word, tokenizer.count(&word).max(1), &mut chunks);
+        }
+    }
+    if current_tokens > 0 {
+        chunks.push((current, current_tokens));
+    }
+    chunks
+}
+
+// A chunk of an article's plain text, numbered in order across the whole article
+pub struct Passage {
+    pub section: String,
+    pub chunk: usize,
+    pub tokens: usize,
+    pub text: String,
+}
+
+// Splits an article into passages of at most `max_tokens` tokens that never cross a section boundary
+pub fn article_passages(text: &str, max_tokens: usize, tokenizer: &Tokenizer) -> Vec<Passage> {
+    let mut passages = Vec::new();
+    for (section, wikitext) in split_sections(text) {
+        if SKIPPED_SECTIONS.contains(&section.to_lowercase().as_str()) { continue; }
+        for (text, tokens) in chunk_text(&plain_text(wikitext), max_tokens, tokenizer) {
+            passages.push(Passage { section: section.clone(), chunk: passages.len(), tokens, text });
+        }
+    }
+    passages
+}
+
+// Writes the article's chunks as JSON lines and returns how many there were
+fn write_article_chunks(output: &mut Vec<u8>, id: PageId, title: &str, text: &str, max_tokens: usize, tokenizer: &Tokenizer) -> usize {
+    let passages = article_passages(text, max_tokens, tokenizer);
+    for passage in &passages {
+        let record = json!({ "id": id, "title": title, "section": passage.section, "chunk": passage.chunk, "tokens": passage.tokens, "text": passage.text });
+        writeln!(output, "{}", record).expect("Failed to write chunk");
+    }
+    passages.len()
+}
+
+// Exports article text as plain text chunks of about --chunk-tokens tokens to chunks.jsonl, for training and
+// retrieval pipelines. Only articles in the main namespace are included, and redirects are skipped. --quality FA,GA
+// keeps just the articles quality.tsv puts in those tiers.
+pub fn export_corpus(args: &Args, data_path: &Path, output_dir: &Path) {
+    let max_tokens: usize = args.parse_value("chunk-tokens").unwrap_or(512);
+    if max_tokens == 0 {
+        eprintln!("Error: --chunk-tokens must be positive");
+        std::process::exit(1);
+    }
+    let tokenizer = Arc::new(Tokenizer::from_args(args));
+    let quality = Arc::new(load_quality_filter(args, data_path));
+    create_dir_all(output_dir).expect("Failed to create output directory");
+    let compression = OutputCompression::from_args(args);
+    let output_path = output_dir.join("chunks.jsonl");
+    let output_file = Arc::new(Mutex::new(compression.create(&output_path)));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let totals = Arc::new(Mutex::new((0, 0)));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Exporting text chunks"));
+
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let tokenizer = Arc::clone(&tokenizer);
+        let quality = Arc::clone(&quality);
+        let output_file = Arc::clone(&output_file);
+        let totals = Arc::clone(&totals);
+        let progress_bar = Arc::clone(&progress_bar);
+
+        pool.execute(move || {
+            let mut pages: Vec<_> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(id, page)| page.namespace == 0 && !page.redirect && quality.as_ref().as_ref().is_none_or(|quality| quality.contains(id)))
+                .collect();
+            pages.sort_unstable_by_key(|(id, _)| *id);
+            let mut output = Vec::new();
+            let mut chunks = 0;
+            for (id, page) in &pages {
+                chunks += write_article_chunks(&mut output, *id, &page.title, &page.text, max_tokens, &tokenizer);
+            }
+            output_file.lock().unwrap().write_all(&output).expect("Failed to write output file");
+            let mut totals = totals.lock().unwrap();
+            totals.0 += pages.len();
+            totals.1 += chunks;
+            progress_bar.inc(1);
+        })
+    }
+
+    pool.join();
+    progress_bar.finish_and_clear();
+    Arc::try_unwrap(output_file).ok().unwrap().into_inner().unwrap().flush().expect("Failed to flush output file");
+    let (articles, chunks) = *totals.lock().unwrap();
+    println!("Exported {} chunks from {} articles to {}{}", chunks, articles, output_path.to_str().unwrap(), compression.extension());
+}
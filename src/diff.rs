@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::fs::File;
+use rustc_hash::FxHashMap;
+use serde_json::{Value, json};
+use crate::helpers::{Args, PageId};
+use crate::links::load_links;
+
+fn count_incoming_links(links: &FxHashMap<PageId, Vec<PageId>>) -> FxHashMap<PageId, i64> {
+    let mut incoming_links = FxHashMap::default();
+    for article_links in links.values() {
+        for &link in article_links {
+            *incoming_links.entry(link).or_insert(0) += 1;
+        }
+    }
+    incoming_links
+}
+
+fn article_list(ids: &[PageId], titles: &FxHashMap<PageId, String>) -> Value {
+    ids.iter().map(|id| json!({ "id": id, "title": titles.get(id) })).collect()
+}
+
+pub fn diff(args: &Args) {
+    let (old_path, new_path) = match &args.positional[..] {
+        [old_path, new_path, ..] => (Path::new(old_path), Path::new(new_path)),
+        _ => {
+            eprintln!("Usage: diff <old_links.bin> <new_links.bin> [--output report.json] [--top N] [--report-top N]");
+            std::process::exit(1);
+        }
+    };
+    let output_path = args.value("output").unwrap_or("diff-report.json");
+    let top = args.parse_value("top").unwrap_or(10);
+    let report_top = args.parse_value("report-top").unwrap_or(1000);
+
+    let old = load_links(old_path);
+    let new = load_links(new_path);
+
+    // Articles added and removed
+    let mut added: Vec<PageId> = new.links.keys().filter(|id| !old.links.contains_key(id)).copied().collect();
+    let mut removed: Vec<PageId> = old.links.keys().filter(|id| !new.links.contains_key(id)).copied().collect();
+    added.sort_unstable();
+    removed.sort_unstable();
+
+    // Links added and removed, per article present in both snapshots
+    let mut links_added = 0;
+    let mut links_removed = 0;
+    let mut link_changes = Vec::new();
+    for (article_id, new_links) in &new.links {
+        let Some(old_links) = old.links.get(article_id) else { continue };
+        let old_set: HashSet<&PageId> = old_links.iter().collect();
+        let new_set: HashSet<&PageId> = new_links.iter().collect();
+        let gained = new_set.difference(&old_set).count();
+        let lost = old_set.difference(&new_set).count();
+        links_added += gained;
+        links_removed += lost;
+        if gained + lost > 0 {
+            link_changes.push((*article_id, gained, lost));
+        }
+    }
+    link_changes.sort_by_key(|&(article_id, gained, lost)| (std::cmp::Reverse(gained + lost), article_id));
+
+    // Biggest movers in in-degree
+    let old_incoming = count_incoming_links(&old.links);
+    let new_incoming = count_incoming_links(&new.links);
+    let mut movers: Vec<(PageId, i64, i64)> = old_incoming.keys().chain(new_incoming.keys())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|id| (*id, *old_incoming.get(id).unwrap_or(&0), *new_incoming.get(id).unwrap_or(&0)))
+        .filter(|(_, before, after)| before != after)
+        .collect();
+    movers.sort_by_key(|&(article_id, before, after)| (std::cmp::Reverse((after - before).abs()), article_id));
+
+    let (old_titles, new_titles) = (&old.titles, &new.titles);
+    let title = |id: &PageId| new_titles.get(id).or_else(|| old_titles.get(id)).cloned().unwrap_or_else(|| format!("Unknown (ID: {})", id));
+
+    let report = json!({
+        "old": old_path.to_str(),
+        "new": new_path.to_str(),
+        "articles": { "old": old.links.len(), "new": new.links.len(), "added": article_list(&added, new_titles), "removed": article_list(&removed, old_titles) },
+        "links": { "added": links_added, "removed": links_removed },
+        "link_changes": link_changes.iter().take(report_top).map(|(id, gained, lost)| json!({ "id": id, "title": title(id), "added": gained, "removed": lost })).collect::<Value>(),
+        "in_degree_movers": movers.iter().take(report_top).map(|(id, before, after)| json!({ "id": id, "title": title(id), "old": before, "new": after })).collect::<Value>(),
+    });
+    let output_file = File::create(output_path).expect("Failed to create report file");
+    serde_json::to_writer_pretty(output_file, &report).expect("Failed to write report file");
+
+    // Print a summary of the changes
+    println!("Articles: {} -> {} (+{} / -{})", old.links.len(), new.links.len(), added.len(), removed.len());
+    println!("Links added: {}", links_added);
+    println!("Links removed: {}", links_removed);
+
+    println!("\nTop {} articles with most changed links:", top);
+    for (rank, (article_id, gained, lost)) in link_changes.iter().take(top).enumerate() {
+        println!("{:>2}) {} (+{} / -{})", rank + 1, title(article_id), gained, lost);
+    }
+
+    println!("\nTop {} biggest movers in incoming links:", top);
+    for (rank, (article_id, before, after)) in movers.iter().take(top).enumerate() {
+        println!("{:>2}) {} ({} -> {}, {:+})", rank + 1, title(article_id), before, after, after - before);
+    }
+
+    println!("\nChange report written to {}", output_path);
+}
@@ -1,69 +1,273 @@
 use std::io::prelude::*;
-use std::path::Path;
-use std::fs::{File, create_dir_all};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, create_dir_all, read_to_string};
 use std::sync::{Arc, Mutex};
-use threadpool::ThreadPool;
-use crate::helpers::{create_progress_bar, load_index, load_chunk};
+use serde_json::json;
+use crate::helpers::{Args, OutputCompression, Page, PageId, get_chunk_ranges, load_index, locate_dump_files};
+use crate::pipeline::{self, PageSink, SinkChunk};
+use crate::preflight;
+use crate::quality::load_quality_filter;
+use crate::summary::RunSummary;
+use crate::config::config;
+use tracing::info;
 
-fn process_chunk(articles_path: &str, start_position: u64, end_position: u64, output_dir: &Path, chunk_index: usize) -> usize {
-    let articles = load_chunk(articles_path, start_position, end_position);
-    let file_name = format!("{:0>6}.txt", chunk_index);
-    let file_path = output_dir.join(file_name);
-    let mut file = File::create(file_path).expect("Failed to create chunk file");
+// Replaces characters that aren't safe in file names on common filesystems and caps the length at 200 bytes
+fn sanitize_title(title: &str) -> String {
+    let mut name: String = title.chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    while name.len() > 200 {
+        name.pop();
+    }
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() { "_".to_string() } else { name.to_string() }
+}
+
+// Assigns each article a file name based on its title. Names are compared case-insensitively, and when several
+// articles map to the same name the lowest ID keeps it while the rest get their ID appended.
+fn get_file_names(seek_position_map: &HashMap<u64, Vec<(PageId, String)>>) -> HashMap<PageId, String> {
+    let mut articles: Vec<(PageId, String)> = seek_position_map.values().flatten()
+        .map(|(id, title)| (*id, sanitize_title(title)))
+        .collect();
+    articles.sort_unstable();
+
+    let mut used_names = HashSet::new();
+    let mut file_names = HashMap::new();
+    for (id, name) in articles {
+        let file_name = if used_names.insert(name.to_lowercase()) { name } else { format!("{} ({})", name, id) };
+        file_names.insert(id, format!("{}.txt", file_name));
+    }
+    file_names
+}
+
+// Dumped files either go into the output directory or are streamed into a single tar archive
+enum Output {
+    Directory(PathBuf),
+    Archive(Mutex<Option<tar::Builder<Box<dyn Write + Send>>>>, &'static str),  // entries are prefixed with the directory name, taken by finish
+}
+impl Output {
+    // Compression only applies to directory output, since archives are compressed as a whole
+    fn write(&self, file_name: &str, contents: &[u8], compression: OutputCompression) {
+        match self {
+            Output::Directory(output_dir) => {
+                let mut file = compression.create(&output_dir.join(file_name));
+                file.write_all(contents).expect("Failed to write output file");
+                file.flush().expect("Failed to flush output file");
+            }
+            Output::Archive(builder, prefix) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                let entry_path = format!("{}/{}", prefix, file_name);
+                builder.lock().unwrap().as_mut().unwrap().append_data(&mut header, entry_path, contents).expect("Failed to write archive entry");
+            }
+        }
+    }
+}
+
+// With --metadata, each article starts with a header giving its ID, title, namespace, latest revision timestamp and
+// redirect target in place of the title line: YAML front matter between `---` lines, or a single line of JSON
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metadata { None, Yaml, Json }
+impl Metadata {
+    pub fn from_args(args: &Args) -> Self {
+        match args.value("metadata") {
+            None => Metadata::None,
+            Some("yaml") => Metadata::Yaml,
+            Some("json") => Metadata::Json,
+            Some(other) => {
+                eprintln!("Error: Unknown metadata format {} (expected yaml or json)", other);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn header(&self, article_id: PageId, page: &Page) -> String {
+        let fields = json!({
+            "id": article_id, "title": page.title, "namespace": page.namespace, "timestamp": page.timestamp, "redirect": page.redirect_target,
+        });
+        match self {
+            Metadata::None => page.title.clone(),
+            Metadata::Json => fields.to_string(),
+            // JSON values are also valid YAML scalars, so they take care of quoting titles
+            Metadata::Yaml => {
+                let mut header = "---\n".to_string();
+                for key in ["id", "title", "namespace", "timestamp", "redirect"] {
+                    header.push_str(&format!("{}: {}\n", key, fields[key]));
+                }
+                header.push_str("---");
+                header
+            }
+        }
+    }
+}
 
-    for (_, (title, content)) in &articles {
-        write!(file, "{}\n{}\n\n", title, content).expect("Failed to write article");
+// Writes each chunk's articles to a file of its own, or each article to its own file with --name-by-title, and
+// appends the chunk to the manifest once it's been written. Files are written from the worker threads as each chunk
+// is extracted, so only the manifest waits for the writer.
+pub struct DumpSink {
+    output: Output,
+    file_names: Option<HashMap<PageId, String>>,
+    compression: OutputCompression,
+    metadata: Metadata,
+    quality: Option<HashSet<PageId>>,
+    manifest_file: Option<Mutex<File>>,
+    articles: Mutex<usize>,
+}
+
+impl PageSink for DumpSink {
+    fn extract(&self, chunk_index: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        let articles: Vec<(&PageId, &Page)> = pages.iter()
+            .filter(|(article_id, _)| self.quality.as_ref().is_none_or(|quality| quality.contains(article_id)))
+            .collect();
+        match &self.file_names {
+            Some(file_names) => {
+                for (article_id, page) in &articles {
+                    let contents = format!("{}\n{}\n", self.metadata.header(**article_id, page), page.text);
+                    self.output.write(&file_names[article_id], contents.as_bytes(), self.compression);
+                }
+            }
+            None => {
+                let mut contents = Vec::new();
+                for (article_id, page) in &articles {
+                    write!(contents, "{}\n{}\n\n", self.metadata.header(**article_id, page), page.text).expect("Failed to write article");
+                }
+                self.output.write(&format!("{:0>6}.txt", chunk_index), &contents, self.compression);
+            }
+        }
+        Box::new((chunk_index, articles.len()))
+    }
+
+    fn write(&self, chunk: SinkChunk) {
+        let (chunk_index, articles) = *chunk.downcast::<(usize, usize)>().unwrap();
+        if let Some(manifest_file) = &self.manifest_file {
+            writeln!(manifest_file.lock().unwrap(), "{}:{}", chunk_index, articles).expect("Failed to write manifest");
+        }
+        *self.articles.lock().unwrap() += articles;
     }
 
-    articles.len()
+    // Writes the tar footer and finishes the compressed stream
+    fn finish(&self) {
+        if let Output::Archive(builder, _) = &self.output {
+            if let Some(builder) = builder.lock().unwrap().take() {
+                let mut archive_file = builder.into_inner().expect("Failed to finish archive");
+                archive_file.flush().expect("Failed to flush archive");
+            }
+        }
+    }
 }
 
-pub fn dump(data_path: &Path) {
-    let index_path = data_path.join("enwiki-20240801-pages-articles-multistream-index.txt.bz2");
-    let articles_path = data_path.join("enwiki-20240801-pages-articles-multistream.xml.bz2");
-    if !index_path.exists() || !articles_path.exists() {
-        eprintln!("Error: Unable to locate data files in {}", data_path.to_str().unwrap());
-        std::process::exit(1);
+impl DumpSink {
+    // Sets up the output that --archive, --name-by-title, --compress, --metadata and --quality ask for. With --resume,
+    // the chunks that the manifest lists as complete are dropped from `chunk_ranges`, and the number of articles they
+    // held is returned alongside the sink.
+    pub fn from_args(args: &Args, data_path: &Path, seek_position_map: &HashMap<u64, Vec<(PageId, String)>>, chunk_ranges: &mut Vec<(usize, u64, u64)>) -> (Self, usize) {
+        let output_name = if args.flag("name-by-title") { "articles" } else { "chunks" };
+        let compression = OutputCompression::from_args(args);
+        let mut skipped_articles = 0;
+        let (output, manifest_file) = match args.value("archive") {
+            Some(archive_path) => {
+                if args.flag("resume") || args.value("compress").is_some() {
+                    eprintln!("Error: --archive can't be combined with --resume or --compress");
+                    std::process::exit(1);
+                }
+                let archive_path = Path::new(archive_path);
+                let archive_file = BufWriter::new(File::create(archive_path).expect("Failed to create archive"));
+                let mut builder = tar::Builder::new(OutputCompression::from_path(archive_path).wrap(archive_file));
+                builder.mode(tar::HeaderMode::Deterministic);
+                (Output::Archive(Mutex::new(Some(builder)), output_name), None)
+            }
+            None => {
+                let output_dir = config().dump_output.clone().unwrap_or_else(|| data_path.to_path_buf()).join(output_name);
+                create_dir_all(&output_dir).expect("Failed to create output directory");
+
+                // When resuming, skip the chunks that the manifest says are already complete
+                let manifest_path = output_dir.join("manifest.txt");
+                if args.flag("resume") {
+                    let manifest = load_manifest(&manifest_path);
+                    let total_chunks = chunk_ranges.len();
+                    skipped_articles = chunk_ranges.iter().filter_map(|(chunk_index, _, _)| manifest.get(chunk_index)).sum();
+                    chunk_ranges.retain(|(chunk_index, _, _)| !manifest.contains_key(chunk_index));
+                    info!("Skipping {} chunks already dumped", total_chunks - chunk_ranges.len());
+                }
+                let manifest_file = OpenOptions::new().create(true).append(true).truncate(false).open(&manifest_path).expect("Failed to open manifest");
+                if !args.flag("resume") {
+                    manifest_file.set_len(0).expect("Failed to reset manifest");
+                }
+                (Output::Directory(output_dir), Some(Mutex::new(manifest_file)))
+            }
+        };
+        // With --name-by-title, each article gets its own file plus a titles.tsv manifest mapping IDs to file names
+        let file_names = args.flag("name-by-title").then(|| {
+            let file_names = get_file_names(seek_position_map);
+            let mut titles: Vec<&(PageId, String)> = seek_position_map.values().flatten().collect();
+            titles.sort_unstable();
+            let mut contents = Vec::new();
+            for (id, title) in titles {
+                writeln!(contents, "{}\t{}\t{}{}", id, title, file_names[id], compression.extension()).expect("Failed to write titles manifest");
+            }
+            output.write("titles.tsv", &contents, OutputCompression::None);
+            file_names
+        });
+        let sink = DumpSink {
+            output, file_names, compression, metadata: Metadata::from_args(args), quality: load_quality_filter(args, data_path),
+            manifest_file, articles: Mutex::new(0),
+        };
+        (sink, skipped_articles)
     }
 
-    let output_dir = data_path.join("chunks");
-    create_dir_all(&output_dir).expect("Failed to create output directory");
+    // Number of articles dumped so far in this run
+    pub fn articles(&self) -> usize {
+        *self.articles.lock().unwrap()
+    }
+}
 
+// The manifest lists `chunk_index:article_count` for every chunk file that was completely written
+fn load_manifest(manifest_path: &Path) -> HashMap<usize, usize> {
+    if !manifest_path.exists() { return HashMap::new(); }
+    read_to_string(manifest_path).expect("Unable to read manifest")
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(chunk_index, article_count)| Some((chunk_index.parse().ok()?, article_count.parse().ok()?)))
+        .collect()
+}
+
+pub fn dump(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let mut summary = RunSummary::new("dump", args);
+    summary.stage("hash inputs");
+    summary.input(&index_path);
+    summary.input(&articles_path);
+
+    summary.stage("load index");
     let seek_position_map = load_index(index_path.to_str().unwrap());
-    println!("Total number of chunks: {}", seek_position_map.len());
-
-    let mut positions: Vec<&u64> = seek_position_map.keys().collect();
-    let file = File::open(&articles_path).expect("Unable to open articles file");
-    let file_size = file.metadata().expect("Failed to get file metadata").len();
-    positions.push(&file_size);
-    positions.sort_unstable();
-
-    let num_threads = 8;
-    let pool = ThreadPool::new(num_threads);
-    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
-    let total_articles = Arc::new(Mutex::new(0));
-    let progress_bar = Arc::new(create_progress_bar((positions.len()-1) as u64, "Dumping chunks"));
-    let output_dir = Arc::new(output_dir);
-
-    // Process chunks using the thread pool
-    for chunk_index in 0..positions.len()-1 {
-        let start_position = *positions[chunk_index];
-        let end_position = *positions[chunk_index + 1];
-
-        let total_articles = Arc::clone(&total_articles);
-        let articles_path = Arc::clone(&articles_path);
-        let progress_bar = Arc::clone(&progress_bar);
-        let output_dir = Arc::clone(&output_dir);
-
-        pool.execute(move || {
-            let chunk_article_count = process_chunk(&articles_path, start_position, end_position, &output_dir, chunk_index);
-            *(total_articles.lock().unwrap()) += chunk_article_count;
-            progress_bar.inc(1);
-        })
+    info!("Total number of chunks: {}", seek_position_map.len());
+
+    let mut chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    if chunk_ranges.len() < seek_position_map.len() {
+        info!("Processing {} of {} chunks", chunk_ranges.len(), seek_position_map.len());
     }
+    let output_volume = args.value("archive").and_then(|archive| Path::new(archive).parent()).filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(data_path);
+    preflight::check(args, output_volume, &preflight::estimate_dump(&seek_position_map, &chunk_ranges, args));
 
-    pool.join();
-    progress_bar.finish_and_clear();
+    let (sink, skipped_articles) = DumpSink::from_args(args, data_path, &seek_position_map, &mut chunk_ranges);
+    let sink = Arc::new(sink);
+
+    summary.stage("dump articles");
+    pipeline::run(&articles_path, chunk_ranges, &[sink.clone() as Arc<dyn PageSink>], Arc::default(), false, "Dumping chunks");
+    sink.finish();
+
+    let total_articles = sink.articles();
+    println!("Total articles dumped: {}", total_articles);
+    if skipped_articles > 0 {
+        println!("Articles from previous runs: {}", skipped_articles);
+    }
 
-    println!("Total articles dumped: {}", *total_articles.lock().unwrap());
+    summary.count("chunks", seek_position_map.len());
+    summary.count("articles", total_articles);
+    summary.count("skipped_articles", skipped_articles);
+    summary.write(data_path);
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::{debug, warn};
+use crate::helpers::{PageId, dump_size, load_chunk_pages};
+use crate::titles::TitleTable;
+
+// Pages to leave out while indexing, as (chunk start position, article ID)
+pub type DroppedPages = HashSet<(u64, PageId)>;
+
+// Keeps only the first entry, in dump order, for article IDs that the index lists more than once, and returns the
+// number of duplicated IDs along with the pages that were dropped
+pub fn dedupe_ids(seek_position_map: &mut HashMap<u64, Vec<(PageId, String)>>) -> (usize, DroppedPages) {
+    let mut positions: Vec<u64> = seek_position_map.keys().copied().collect();
+    positions.sort_unstable();
+    let mut seen = HashSet::new();
+    let mut dropped = DroppedPages::new();
+    for position in positions {
+        let entries = seek_position_map.get_mut(&position).unwrap();
+        entries.retain(|(id, title)| {
+            if seen.insert(*id) { return true; }
+            debug!(id, title, position, "dropping duplicate article ID");
+            dropped.insert((position, *id));
+            false
+        });
+    }
+    let duplicate_ids = dropped.iter().map(|(_, id)| id).collect::<HashSet<_>>().len();
+    if duplicate_ids > 0 {
+        warn!("{} article IDs appear more than once in the index, keeping the first page for each", duplicate_ids);
+    }
+    (duplicate_ids, dropped)
+}
+
+// Loads the chunks holding the given IDs and returns the ones that are redirects
+fn find_redirects(articles_path: &str, seek_position_map: &HashMap<u64, Vec<(PageId, String)>>, ids: &HashSet<PageId>) -> HashSet<PageId> {
+    let mut positions: Vec<u64> = seek_position_map.keys().copied().collect();
+    positions.push(dump_size(Path::new(articles_path)));
+    positions.sort_unstable();
+    let mut redirects = HashSet::new();
+    for window in positions.windows(2) {
+        if !seek_position_map[&window[0]].iter().any(|(id, _)| ids.contains(id)) { continue; }
+        let pages = load_chunk_pages(articles_path, window[0], window[1]);
+        redirects.extend(pages.iter().filter(|(id, page)| ids.contains(id) && page.redirect).map(|(id, _)| *id));
+    }
+    redirects
+}
+
+// Titles that only differ in case map to a single ID. The title table keeps the lowest ID, so this looks up which
+// of the colliding pages are redirects and switches to the lowest non-redirect where there is one. Returns the
+// number of colliding titles.
+pub fn resolve_title_collisions(titles: &mut TitleTable, articles_path: &str, seek_position_map: &HashMap<u64, Vec<(PageId, String)>>) -> usize {
+    let mut groups: HashMap<PageId, Vec<PageId>> = HashMap::new();
+    for &(kept, other) in titles.collisions() {
+        groups.entry(kept).or_insert_with(|| vec![kept]).push(other);
+    }
+    if groups.is_empty() { return 0; }
+
+    let ids: HashSet<PageId> = groups.values().flatten().copied().collect();
+    let redirects = find_redirects(articles_path, seek_position_map, &ids);
+    let mut groups: Vec<Vec<PageId>> = groups.into_values().collect();
+    groups.sort_unstable();
+    for candidates in &mut groups {
+        candidates.sort_unstable();
+        let preferred = candidates.iter().find(|id| !redirects.contains(id)).unwrap_or(&candidates[0]);
+        debug!(title = titles.title(*preferred), ?candidates, preferred, "resolved title collision");
+        if *preferred != candidates[0] {
+            titles.prefer(*preferred);
+        }
+    }
+    warn!("{} titles are shared by more than one page after lowercasing, preferring non-redirects and then the lowest ID", groups.len());
+    groups.len()
+}
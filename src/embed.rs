@@ -0,0 +1,247 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::{Passage, Tokenizer, article_passages};
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::links::{ID_WIDTH, check_id_width, read_fixed_id, read_links_file};
+#[cfg(feature = "onnx")]
+use crate::onnx::OnnxModel;
+
+// embeddings.bin holds one unit-length vector per passage. It starts with MAGIC, a little-endian u32 version, the
+// u32 vector dimensions and the u32 width of a page ID in bytes as in links.bin, followed by fixed-size records of
+// article_id (4 or 8 bytes), chunk (u32) and the vector as f32s, all little-endian. Version 1 files had no width and
+// 4-byte IDs. Line i of passages.jsonl holds the text and metadata of record i.
+pub const EMBEDDINGS_FILE: &str = "embeddings.bin";
+pub const PASSAGES_FILE: &str = "passages.jsonl";
+const MAGIC: &[u8; 4] = b"WKEM";
+const VERSION: u32 = 2;
+
+// Computes embeddings with a local ONNX model given by --onnx-model, or requests them from the OpenAI-compatible
+// /v1/embeddings endpoint given by --endpoint, such as an inference server running a sentence-embedding model
+pub enum EmbeddingClient {
+    Http { endpoint: String, model: Option<String>, api_key: Option<String> },
+    #[cfg(feature = "onnx")]
+    Onnx(Box<OnnxModel>),
+}
+
+#[cfg(feature = "onnx")]
+fn load_onnx_model(model_dir: &Path) -> EmbeddingClient {
+    EmbeddingClient::Onnx(Box::new(OnnxModel::load(model_dir).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    })))
+}
+
+#[cfg(not(feature = "onnx"))]
+fn load_onnx_model(_model_dir: &Path) -> EmbeddingClient {
+    eprintln!("Error: --onnx-model requires building with --features onnx");
+    std::process::exit(1);
+}
+
+impl EmbeddingClient {
+    pub fn from_args(args: &Args, usage: &str) -> Self {
+        if let Some(model_dir) = args.value("onnx-model") {
+            return load_onnx_model(Path::new(model_dir));
+        }
+        let Some(endpoint) = args.value("endpoint") else {
+            eprintln!("Error: --endpoint or --onnx-model is required to compute embeddings ({})", usage);
+            std::process::exit(1);
+        };
+        EmbeddingClient::Http { endpoint: endpoint.to_string(), model: args.value("model").map(str::to_string), api_key: args.value("api-key").map(str::to_string) }
+    }
+
+    // Where the embeddings come from, for error messages
+    fn source(&self) -> String {
+        match self {
+            EmbeddingClient::Http { endpoint, .. } => endpoint.clone(),
+            #[cfg(feature = "onnx")]
+            EmbeddingClient::Onnx(model) => model.path.display().to_string(),
+        }
+    }
+
+    fn request(endpoint: &str, model: &Option<String>, api_key: &Option<String>, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let mut request = ureq::post(endpoint).header("Content-Type", "application/json");
+        if let Some(api_key) = api_key {
+            request = request.header("Authorization", &format!("Bearer {}", api_key));
+        }
+        let body = json!({ "input": texts, "model": model });
+        let mut response = request.send(body.to_string()).map_err(|err| format!("Failed to request embeddings from {}: {}", endpoint, err))?;
+        let response: serde_json::Value = serde_json::from_str(&response.body_mut().read_to_string().unwrap_or_default()).unwrap_or_default();
+        let Some(data) = response["data"].as_array().filter(|data| data.len() == texts.len()) else {
+            return Err(format!("Expected {} embeddings from {} but the response didn't have them", texts.len(), endpoint));
+        };
+        let mut vectors = vec![Vec::new(); texts.len()];
+        for (position, item) in data.iter().enumerate() {
+            let index = item["index"].as_u64().map_or(position, |index| index as usize);
+            if let Some(slot) = vectors.get_mut(index) {
+                *slot = item["embedding"].as_array().into_iter().flatten().filter_map(|value| value.as_f64()).map(|value| value as f32).collect();
+            }
+        }
+        Ok(vectors)
+    }
+
+    // Returns one unit-length vector per text, in order. Failures are returned rather than exiting, since the server
+    // embeds queries with this too.
+    pub fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let mut vectors = match self {
+            EmbeddingClient::Http { endpoint, model, api_key } => Self::request(endpoint, model, api_key, texts)?,
+            #[cfg(feature = "onnx")]
+            EmbeddingClient::Onnx(model) => model.embed(texts)?,
+        };
+        if vectors.len() != texts.len() || vectors.iter().any(|vector| vector.is_empty() || vector.len() != vectors[0].len()) {
+            return Err(format!("{} returned empty or mismatched embeddings", self.source()));
+        }
+        for vector in &mut vectors {
+            let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt().max(f32::MIN_POSITIVE);
+            vector.iter_mut().for_each(|value| *value /= norm);
+        }
+        Ok(vectors)
+    }
+}
+
+pub struct Embeddings {
+    pub dimensions: usize,
+    pub keys: Vec<(PageId, u32)>,  // (article_id, chunk) per record
+    pub vectors: Vec<f32>,  // record i is vectors[i * dimensions..(i + 1) * dimensions]
+}
+
+impl Embeddings {
+    pub fn count(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn vector(&self, index: usize) -> &[f32] {
+        &self.vectors[index * self.dimensions..(index + 1) * self.dimensions]
+    }
+}
+
+pub fn read_embeddings(path: &Path) -> Embeddings {
+    let buffer = read_links_file(path);
+    if !buffer.starts_with(MAGIC) || buffer.get(4..8) != Some(&VERSION.to_le_bytes()[..]) || buffer.len() < 16 {
+        eprintln!("Error: {} is not a valid embeddings file, re-run the embed command", path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let dimensions = u32::from_le_bytes(buffer[8..12].try_into().unwrap()) as usize;
+    let width = u32::from_le_bytes(buffer[12..16].try_into().unwrap());
+    if let Err(err) = check_id_width(width) {
+        eprintln!("Error: {}: {}", path.to_str().unwrap(), err);
+        std::process::exit(1);
+    }
+    let width = width as usize;
+    let record_size = width + 4 + 4 * dimensions;
+    if dimensions == 0 || !(buffer.len() - 16).is_multiple_of(record_size) {
+        eprintln!("Error: {} is truncated", path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let mut keys = Vec::new();
+    let mut vectors = Vec::with_capacity((buffer.len() - 16) / 4);
+    for record in buffer[16..].chunks_exact(record_size) {
+        keys.push((read_fixed_id(&record[..width]), u32::from_le_bytes(record[width..width + 4].try_into().unwrap())));
+        vectors.extend(record[width + 4..].chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())));
+    }
+    Embeddings { dimensions, keys, vectors }
+}
+
+// Appends records to embeddings.bin and passages.jsonl together, writing the header once the dimensions are known
+struct EmbeddingWriter {
+    embeddings_file: BufWriter<File>,
+    passages_file: BufWriter<File>,
+    dimensions: Option<usize>,
+    records: usize,
+}
+
+impl EmbeddingWriter {
+    fn write(&mut self, id: PageId, title: &str, passage: &Passage, vector: &[f32]) {
+        let dimensions = *self.dimensions.get_or_insert_with(|| {
+            self.embeddings_file.write_all(MAGIC).expect("Failed to write embeddings file");
+            self.embeddings_file.write_all(&VERSION.to_le_bytes()).expect("Failed to write embeddings file");
+            self.embeddings_file.write_all(&(vector.len() as u32).to_le_bytes()).expect("Failed to write embeddings file");
+            self.embeddings_file.write_all(&ID_WIDTH.to_le_bytes()).expect("Failed to write embeddings file");
+            vector.len()
+        });
+        if vector.len() != dimensions {
+            eprintln!("Error: The model returned {} dimensions after returning {}", vector.len(), dimensions);
+            std::process::exit(1);
+        }
+        self.embeddings_file.write_all(&id.to_le_bytes()).expect("Failed to write embeddings file");
+        self.embeddings_file.write_all(&(passage.chunk as u32).to_le_bytes()).expect("Failed to write embeddings file");
+        for value in vector {
+            self.embeddings_file.write_all(&value.to_le_bytes()).expect("Failed to write embeddings file");
+        }
+        let record = json!({ "id": id, "title": title, "section": passage.section, "chunk": passage.chunk, "text": passage.text });
+        writeln!(self.passages_file, "{}", record).expect("Failed to write passages file");
+        self.records += 1;
+    }
+}
+
+// Embeds every article's passages, or with --abstracts just the first passage of each, and writes the vectors to
+// embeddings.bin and the passages to passages.jsonl
+pub fn embed(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let client = Arc::new(EmbeddingClient::from_args(args, "embed <data_path> --endpoint URL [--model NAME] or --onnx-model DIR"));
+    let batch_size: usize = args.parse_value("batch-size").unwrap_or(32);
+    let max_tokens: usize = args.parse_value("chunk-tokens").unwrap_or(256);
+    let abstracts_only = args.flag("abstracts");
+    if batch_size == 0 || max_tokens == 0 {
+        eprintln!("Error: --batch-size and --chunk-tokens must be positive");
+        std::process::exit(1);
+    }
+    let tokenizer = Arc::new(Tokenizer::from_args(args));
+    let writer = Arc::new(Mutex::new(EmbeddingWriter {
+        embeddings_file: BufWriter::new(File::create(data_path.join(EMBEDDINGS_FILE)).expect("Failed to create embeddings file")),
+        passages_file: BufWriter::new(File::create(data_path.join(PASSAGES_FILE)).expect("Failed to create passages file")),
+        dimensions: None,
+        records: 0,
+    }));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Embedding passages"));
+
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let client = Arc::clone(&client);
+        let tokenizer = Arc::clone(&tokenizer);
+        let writer = Arc::clone(&writer);
+        let progress_bar = Arc::clone(&progress_bar);
+
+        pool.execute(move || {
+            let mut pages: Vec<_> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(_, page)| page.namespace == 0 && !page.redirect)
+                .collect();
+            pages.sort_unstable_by_key(|(id, _)| *id);
+            let mut passages = Vec::new();
+            for (id, page) in &pages {
+                let mut article_passages = article_passages(&page.text, max_tokens, &tokenizer);
+                article_passages.truncate(if abstracts_only { 1 } else { usize::MAX });
+                passages.extend(article_passages.into_iter().map(|passage| (*id, page.title.as_str(), passage)));
+            }
+            for batch in passages.chunks(batch_size) {
+                let texts: Vec<&str> = batch.iter().map(|(_, _, passage)| passage.text.as_str()).collect();
+                let vectors = client.embed(&texts).unwrap_or_else(|err| {
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                });
+                let mut writer = writer.lock().unwrap();
+                for ((id, title, passage), vector) in batch.iter().zip(&vectors) {
+                    writer.write(*id, title, passage, vector);
+                }
+            }
+            progress_bar.inc(1);
+        })
+    }
+
+    pool.join();
+    progress_bar.finish_and_clear();
+    let mut writer = writer.lock().unwrap();
+    writer.embeddings_file.flush().expect("Failed to flush embeddings file");
+    writer.passages_file.flush().expect("Failed to flush passages file");
+    println!("Wrote {} embeddings with {} dimensions to {}", writer.records, writer.dimensions.unwrap_or(0), data_path.join(EMBEDDINGS_FILE).to_str().unwrap());
+}
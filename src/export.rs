@@ -0,0 +1,483 @@
+use std::path::{Path, PathBuf};
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use rustc_hash::FxHashMap;
+use serde_json::json;
+use crate::bulk::export_bulk;
+use crate::candidates::export_candidates;
+use crate::corpus::export_corpus;
+use crate::graph::{DenseGraph, build_dense_graph};
+use crate::helpers::{Args, PageId, create_progress_bar};
+use crate::ngrams::export_ngrams;
+use crate::sentences::export_sentences;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::export_sqlite_fts;
+use crate::zim::export_zim;
+use crate::split::{load_graph, load_titles};
+use crate::storage::upload_dir;
+use crate::config::config;
+
+fn write_array<const N: usize>(path: &Path, values: impl Iterator<Item = [u8; N]>) {
+    let mut file = BufWriter::new(File::create(path).expect("Failed to create output file"));
+    for value in values {
+        file.write_all(&value).expect("Failed to write output file");
+    }
+    file.flush().expect("Failed to flush output file");
+}
+
+// The article IDs of the dense indices are stored at the width of a page ID
+const IDS_FILE: &str = if size_of::<PageId>() == 8 { "ids.u64" } else { "ids.u32" };
+
+// Raw little-endian arrays that can be mmapped as-is, plus a JSON file describing them
+fn export_csr(graph: &DenseGraph, output_dir: &Path) {
+    write_array(&output_dir.join("offsets.u64"), graph.offsets.iter().map(|value| value.to_le_bytes()));
+    write_array(&output_dir.join("edges.u32"), graph.edges.iter().map(|value| value.to_le_bytes()));
+    write_array(&output_dir.join(IDS_FILE), graph.ids.iter().map(|value| value.to_le_bytes()));
+
+    let metadata = json!({
+        "format": "csr",
+        "nodes": graph.ids.len(),
+        "edges": graph.edges.len(),
+        "byte_order": "little",
+        "files": {
+            "offsets.u64": "u64[nodes + 1], row i's edges are edges[offsets[i]..offsets[i + 1]]",
+            "edges.u32": "u32[edges], dense target indices sorted within each row",
+            IDS_FILE: format!("{}[nodes], the article ID of each dense index in ascending order", &IDS_FILE[4..]),
+        },
+    });
+    let metadata_file = File::create(output_dir.join("metadata.json")).expect("Failed to create metadata file");
+    serde_json::to_writer_pretty(metadata_file, &metadata).expect("Failed to write metadata file");
+}
+
+// Writes bits most significant first, padding the last byte with zeros, as WebGraph's OutputBitStream does
+struct BitWriter<W: Write> {
+    writer: W,
+    buffer: u64,
+    filled: u32,  // bits in `buffer` not yet written, always less than 8 between calls
+    written: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn new(writer: W) -> Self {
+        BitWriter { writer, buffer: 0, filled: 0, written: 0 }
+    }
+
+    // Writes the lowest `length` bits of `value`, for lengths up to 56
+    fn write_int(&mut self, value: u64, length: u32) {
+        if length == 0 { return; }
+        self.buffer = (self.buffer << length) | (value & (u64::MAX >> (64 - length)));
+        self.filled += length;
+        self.written += length as u64;
+        while self.filled >= 8 {
+            self.filled -= 8;
+            self.writer.write_all(&[(self.buffer >> self.filled) as u8]).expect("Failed to write output file");
+        }
+        self.buffer &= (1 << self.filled) - 1;
+    }
+
+    // x zeros followed by a one
+    fn write_unary(&mut self, mut x: u64) {
+        while x > 32 {
+            self.write_int(0, 32);
+            x -= 32;
+        }
+        self.write_int(1, x as u32 + 1);
+    }
+
+    fn write_gamma(&mut self, x: u64) {
+        let x = x + 1;
+        let msb = 63 - x.leading_zeros();
+        self.write_unary(msb as u64);
+        self.write_int(x, msb);
+    }
+
+    fn write_zeta(&mut self, x: u64, k: u32) {
+        let x = x + 1;
+        let h = (63 - x.leading_zeros()) / k;
+        self.write_unary(h as u64);
+        let left = 1 << (h * k);
+        if x - left < left {
+            self.write_int(x - left, h * k + k - 1);
+        } else {
+            self.write_int(x, h * k + k);
+        }
+    }
+
+    fn finish(mut self) {
+        if self.filled > 0 {
+            self.write_int(0, 8 - self.filled);
+        }
+        self.writer.flush().expect("Failed to flush output file");
+    }
+}
+
+// WebGraph's BVGraph format with no reference compression (window size 0) and no interval coding, so each
+// successor list is its outdegree in gamma code followed by the gaps between successors in zeta code. The
+// result loads with BVGraph in Java and with webgraph-rs.
+const ZETA_K: u32 = 3;
+
+fn export_bv(graph: &DenseGraph, output_dir: &Path) {
+    let mut graph_stream = BitWriter::new(BufWriter::new(File::create(output_dir.join("graph.graph")).expect("Failed to create output file")));
+    let mut offsets_stream = BitWriter::new(BufWriter::new(File::create(output_dir.join("graph.offsets")).expect("Failed to create output file")));
+
+    // BV successor lists must be strictly increasing, so duplicate links are collapsed
+    let progress_bar = create_progress_bar(graph.ids.len() as u64, "Writing BV graph");
+    let mut arcs = 0;
+    let mut last_offset = 0;
+    for node in 0..graph.ids.len() {
+        offsets_stream.write_gamma(graph_stream.written - last_offset);
+        last_offset = graph_stream.written;
+
+        let successors = graph.unique_successors(node);
+        arcs += successors.len();
+        graph_stream.write_gamma(successors.len() as u64);
+        let mut previous = None;
+        for &successor in &successors {
+            match previous {
+                // The first successor is coded relative to the node itself, mapping negatives to odd numbers
+                None => {
+                    let difference = successor as i64 - node as i64;
+                    graph_stream.write_zeta(if difference >= 0 { 2 * difference } else { -2 * difference - 1 } as u64, ZETA_K);
+                }
+                Some(previous) => graph_stream.write_zeta((successor - previous - 1) as u64, ZETA_K),
+            }
+            previous = Some(successor);
+        }
+        progress_bar.inc(1);
+    }
+    offsets_stream.write_gamma(graph_stream.written - last_offset);
+    let total_bits = graph_stream.written;
+    graph_stream.finish();
+    offsets_stream.finish();
+    progress_bar.finish_and_clear();
+
+    let mut properties = File::create(output_dir.join("graph.properties")).expect("Failed to create output file");
+    write!(properties, "\
+        graphclass=it.unimi.dsi.webgraph.BVGraph\n\
+        version=0\n\
+        nodes={}\n\
+        arcs={}\n\
+        windowsize=0\n\
+        maxrefcount=0\n\
+        minintervallength=0\n\
+        zetak={}\n\
+        compressionflags=\n\
+        bitsperlink={}\n",
+        graph.ids.len(), arcs, ZETA_K, if arcs > 0 { total_bits as f64 / arcs as f64 } else { 0.0 }).expect("Failed to write properties file");
+
+    // Node numbers are dense indices, so keep the mapping back to article IDs
+    write_array(&output_dir.join(IDS_FILE), graph.ids.iter().map(|value| value.to_le_bytes()));
+}
+
+// Writes a .npy array header, padded so the data starts on a 64-byte boundary
+fn write_npy_header(writer: &mut impl Write, descr: &str, shape: &[usize]) {
+    let shape = match shape {
+        [length] => format!("({},)", length),
+        _ => format!("({})", shape.iter().map(|length| length.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    writer.write_all(b"\x93NUMPY\x01\x00").expect("Failed to write output file");
+    writer.write_all(&(header.len() as u16).to_le_bytes()).expect("Failed to write output file");
+    writer.write_all(header.as_bytes()).expect("Failed to write output file");
+}
+
+// A scipy.sparse CSR matrix saved the way save_npz does it, so load_npz reads it back directly. Duplicate links
+// are collapsed and every entry is True. Dense indices map to articles through nodes.tsv.
+fn export_npz(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, output_dir: &Path) {
+    let mut indptr = Vec::with_capacity(graph.offsets.len());
+    let mut indices = Vec::with_capacity(graph.edges.len());
+    indptr.push(0);
+    for node in 0..graph.ids.len() {
+        indices.extend(graph.unique_successors(node));
+        indptr.push(indices.len() as u64);
+    }
+
+    // scipy uses int32 indices unless the matrix is too big for them
+    let wide = indices.len() > i32::MAX as usize;
+    let index_descr = if wide { "<i8" } else { "<i4" };
+    let write_index = |file: &mut zip::ZipWriter<BufWriter<File>>, value: u64| {
+        let result = if wide { file.write_all(&value.to_le_bytes()) } else { file.write_all(&(value as u32).to_le_bytes()) };
+        result.expect("Failed to write output file");
+    };
+
+    let progress_bar = create_progress_bar(4, "Writing npz arrays");
+    let npz_file = BufWriter::new(File::create(output_dir.join("graph.npz")).expect("Failed to create output file"));
+    let mut npz = zip::ZipWriter::new(npz_file);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored).large_file(true);
+
+    npz.start_file("indices.npy", options).expect("Failed to write npz entry");
+    write_npy_header(&mut npz, index_descr, &[indices.len()]);
+    for &index in &indices {
+        write_index(&mut npz, index as u64);
+    }
+    progress_bar.inc(1);
+
+    npz.start_file("indptr.npy", options).expect("Failed to write npz entry");
+    write_npy_header(&mut npz, index_descr, &[indptr.len()]);
+    for &offset in &indptr {
+        write_index(&mut npz, offset);
+    }
+    progress_bar.inc(1);
+
+    npz.start_file("format.npy", options).expect("Failed to write npz entry");
+    write_npy_header(&mut npz, "<U3", &[]);
+    for c in "csr".chars() {
+        npz.write_all(&(c as u32).to_le_bytes()).expect("Failed to write output file");
+    }
+
+    npz.start_file("shape.npy", options).expect("Failed to write npz entry");
+    write_npy_header(&mut npz, "<i8", &[2]);
+    for _ in 0..2 {
+        npz.write_all(&(graph.ids.len() as u64).to_le_bytes()).expect("Failed to write output file");
+    }
+    progress_bar.inc(1);
+
+    npz.start_file("data.npy", options).expect("Failed to write npz entry");
+    write_npy_header(&mut npz, "|b1", &[indices.len()]);
+    for _ in 0..indices.len() {
+        npz.write_all(&[1]).expect("Failed to write output file");
+    }
+    npz.finish().expect("Failed to finish npz file").flush().expect("Failed to flush output file");
+    progress_bar.inc(1);
+    progress_bar.finish_and_clear();
+
+    let mut nodes_file = BufWriter::new(File::create(output_dir.join("nodes.tsv")).expect("Failed to create output file"));
+    writeln!(nodes_file, "index\tid\ttitle").expect("Failed to write nodes file");
+    for (index, id) in graph.ids.iter().enumerate() {
+        writeln!(nodes_file, "{}\t{}\t{}", index, id, titles.get(id).map_or("", String::as_str)).expect("Failed to write nodes file");
+    }
+    nodes_file.flush().expect("Failed to flush nodes file");
+}
+
+fn csv_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+// Node and relationship CSVs with the header conventions of neo4j-admin database import, keyed by article ID
+fn export_neo4j(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, output_dir: &Path) {
+    let progress_bar = create_progress_bar(graph.ids.len() as u64 * 2, "Writing Neo4j CSVs");
+    let mut articles_file = BufWriter::new(File::create(output_dir.join("articles.csv")).expect("Failed to create output file"));
+    writeln!(articles_file, "articleId:ID(Article),title,outDegree:int,:LABEL").expect("Failed to write articles file");
+    for (node, id) in graph.ids.iter().enumerate() {
+        let title = titles.get(id).map_or("", String::as_str);
+        writeln!(articles_file, "{},{},{},Article", id, csv_quote(title), graph.unique_successors(node).len()).expect("Failed to write articles file");
+        progress_bar.inc(1);
+    }
+    articles_file.flush().expect("Failed to flush articles file");
+
+    let mut links_file = BufWriter::new(File::create(output_dir.join("links.csv")).expect("Failed to create output file"));
+    writeln!(links_file, ":START_ID(Article),:END_ID(Article),:TYPE").expect("Failed to write links file");
+    for (node, id) in graph.ids.iter().enumerate() {
+        for successor in graph.unique_successors(node) {
+            writeln!(links_file, "{},{},LINKS_TO", id, graph.ids[successor as usize]).expect("Failed to write links file");
+        }
+        progress_bar.inc(1);
+    }
+    links_file.flush().expect("Failed to flush links file");
+    progress_bar.finish_and_clear();
+
+    println!("Import with: neo4j-admin database import full --nodes={} --relationships={} <database>",
+        output_dir.join("articles.csv").to_str().unwrap(), output_dir.join("links.csv").to_str().unwrap());
+}
+
+const POSTGRES_TABLES: &str = "\
+CREATE TABLE articles (
+    id integer NOT NULL,
+    title text NOT NULL
+);
+CREATE TABLE links (
+    source_id integer NOT NULL,
+    target_id integer NOT NULL
+);
+";
+
+// Indexes are created after loading, which is much faster than maintaining them during COPY
+const POSTGRES_INDEXES: &str = "\
+ALTER TABLE articles ADD PRIMARY KEY (id);
+CREATE INDEX articles_lower_title ON articles (lower(title));
+ALTER TABLE links ADD PRIMARY KEY (source_id, target_id);
+CREATE INDEX links_target_id ON links (target_id);
+";
+
+// Escapes a value for COPY's text format
+fn copy_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn write_article_rows(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, writer: &mut impl Write) {
+    for id in &graph.ids {
+        writeln!(writer, "{}\t{}", id, copy_escape(titles.get(id).map_or("", String::as_str))).expect("Failed to write articles");
+    }
+}
+
+fn write_link_rows(graph: &DenseGraph, writer: &mut impl Write) {
+    let progress_bar = create_progress_bar(graph.ids.len() as u64, "Writing links");
+    for (node, id) in graph.ids.iter().enumerate() {
+        for successor in graph.unique_successors(node) {
+            writeln!(writer, "{}\t{}", id, graph.ids[successor as usize]).expect("Failed to write links");
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+}
+
+// COPY-ready TSV files plus a psql script that creates the tables, loads the files, and builds the indexes
+fn export_postgres(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, output_dir: &Path) {
+    let mut articles_file = BufWriter::new(File::create(output_dir.join("articles.tsv")).expect("Failed to create output file"));
+    write_article_rows(graph, titles, &mut articles_file);
+    articles_file.flush().expect("Failed to flush articles file");
+    let mut links_file = BufWriter::new(File::create(output_dir.join("links.tsv")).expect("Failed to create output file"));
+    write_link_rows(graph, &mut links_file);
+    links_file.flush().expect("Failed to flush links file");
+
+    let mut schema_file = File::create(output_dir.join("schema.sql")).expect("Failed to create output file");
+    write!(schema_file, "-- Run from this directory with: psql <database> -f schema.sql\n{}\\copy articles FROM 'articles.tsv'\n\\copy links FROM 'links.tsv'\n{}",
+        POSTGRES_TABLES, POSTGRES_INDEXES).expect("Failed to write schema file");
+    println!("Load with: cd {} && psql <database> -f schema.sql", output_dir.to_str().unwrap());
+}
+
+// Creates the tables in a running database and streams the rows straight into them with COPY
+#[cfg(feature = "postgres")]
+fn stream_postgres(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, connection_string: &str) {
+    let mut client = postgres::Client::connect(connection_string, postgres::NoTls).unwrap_or_else(|err| {
+        eprintln!("Error: Unable to connect to Postgres: {}", err);
+        std::process::exit(1);
+    });
+    client.batch_execute(POSTGRES_TABLES).unwrap_or_else(|err| {
+        eprintln!("Error: Unable to create tables: {}", err);
+        std::process::exit(1);
+    });
+
+    let mut writer = client.copy_in("COPY articles FROM STDIN").expect("Failed to start COPY");
+    write_article_rows(graph, titles, &mut writer);
+    writer.finish().expect("Failed to copy articles");
+    let mut writer = client.copy_in("COPY links FROM STDIN").expect("Failed to start COPY");
+    write_link_rows(graph, &mut writer);
+    writer.finish().expect("Failed to copy links");
+
+    tracing::info!("Creating indexes...");
+    client.batch_execute(POSTGRES_INDEXES).expect("Failed to create indexes");
+}
+
+#[cfg(not(feature = "postgres"))]
+fn stream_postgres(_graph: &DenseGraph, _titles: &FxHashMap<PageId, String>, _connection_string: &str) {
+    eprintln!("Error: --connection requires building with --features postgres");
+    std::process::exit(1);
+}
+
+// A single-file DuckDB database with the same articles and links tables as the Postgres export, filled with the
+// appender API rather than row-by-row inserts
+#[cfg(feature = "duckdb")]
+fn export_duckdb(graph: &DenseGraph, titles: &FxHashMap<PageId, String>, output_dir: &Path) {
+    let database_path = output_dir.join("wikipedia.duckdb");
+    if database_path.exists() {
+        std::fs::remove_file(&database_path).expect("Failed to remove existing database");
+    }
+    let connection = duckdb::Connection::open(&database_path).expect("Failed to create DuckDB database");
+    connection.execute_batch(POSTGRES_TABLES).expect("Failed to create tables");
+
+    let mut appender = connection.appender("articles").expect("Failed to create appender");
+    for id in &graph.ids {
+        appender.append_row(duckdb::params![*id as i32, titles.get(id).map_or("", String::as_str)]).expect("Failed to append article");
+    }
+    appender.flush().expect("Failed to write articles");
+
+    let progress_bar = create_progress_bar(graph.ids.len() as u64, "Writing links");
+    let mut appender = connection.appender("links").expect("Failed to create appender");
+    for (node, id) in graph.ids.iter().enumerate() {
+        for successor in graph.unique_successors(node) {
+            appender.append_row(duckdb::params![*id as i32, graph.ids[successor as usize] as i32]).expect("Failed to append link");
+        }
+        progress_bar.inc(1);
+    }
+    appender.flush().expect("Failed to write links");
+    progress_bar.finish_and_clear();
+    println!("Open with: duckdb {}", database_path.to_str().unwrap());
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite_fts(_args: &Args, _data_path: &Path, _output_dir: &Path) {
+    eprintln!("Error: --format sqlite-fts requires building with --features sqlite");
+    std::process::exit(1);
+}
+
+#[cfg(not(feature = "duckdb"))]
+fn export_duckdb(_graph: &DenseGraph, _titles: &FxHashMap<PageId, String>, _output_dir: &Path) {
+    eprintln!("Error: --format duckdb requires building with --features duckdb");
+    std::process::exit(1);
+}
+
+// Formats that write the link graph, as opposed to article text
+pub const GRAPH_FORMATS: [&str; 6] = ["csr", "bv", "npz", "neo4j", "postgres", "duckdb"];
+
+// Writes the graph in one of GRAPH_FORMATS to `output_dir`, or streams it to the database given by --connection
+pub fn export_graph(args: &Args, data_path: &Path, format: &str, graph: &DenseGraph, output_dir: &Path) {
+    let streaming = format == "postgres" && args.value("connection").is_some();
+    if !streaming {
+        create_dir_all(output_dir).expect("Failed to create output directory");
+    }
+
+    match format {
+        "csr" => export_csr(graph, output_dir),
+        "bv" => export_bv(graph, output_dir),
+        "npz" => export_npz(graph, &load_titles(data_path).unwrap(), output_dir),
+        "neo4j" => export_neo4j(graph, &load_titles(data_path).unwrap(), output_dir),
+        "postgres" => match args.value("connection") {
+            Some(connection_string) => stream_postgres(graph, &load_titles(data_path).unwrap(), connection_string),
+            None => export_postgres(graph, &load_titles(data_path).unwrap(), output_dir),
+        },
+        "duckdb" => export_duckdb(graph, &load_titles(data_path).unwrap(), output_dir),
+        _ => unreachable!(),
+    }
+
+    let destination = if streaming { "the database" } else { output_dir.to_str().unwrap() };
+    println!("Exported {} nodes and {} edges to {}", graph.ids.len(), graph.edges.len(), destination);
+    if graph.dropped_links > 0 {
+        println!("Dropped {} links to articles without a record", graph.dropped_links);
+    }
+}
+
+pub fn export(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let format = args.value("format").unwrap_or("csr");
+    if !GRAPH_FORMATS.contains(&format) && !["elasticsearch", "meilisearch", "llm-jsonl", "ngrams", "candidates", "sentences", "zim", "sqlite-fts"].contains(&format) {
+        eprintln!("Error: Unknown export format {} (expected csr, bv, npz, neo4j, postgres, duckdb, elasticsearch, meilisearch, llm-jsonl, ngrams, candidates, sentences, zim or sqlite-fts)", format);
+        std::process::exit(1);
+    }
+    let output_dir = args.value("output").map(PathBuf::from)
+        .unwrap_or_else(|| config().export_output.as_deref().unwrap_or(data_path).join(format));
+
+    // The search engine formats export article text from the dump rather than the link graph
+    if format == "elasticsearch" || format == "meilisearch" {
+        export_bulk(args, data_path, format, &output_dir);
+    } else if format == "llm-jsonl" {
+        export_corpus(args, data_path, &output_dir);
+    } else if format == "ngrams" {
+        export_ngrams(args, data_path, &output_dir);
+    } else if format == "candidates" {
+        export_candidates(args, data_path, &output_dir);
+    } else if format == "sentences" {
+        export_sentences(args, data_path, &output_dir);
+    } else if format == "zim" {
+        export_zim(args, data_path, &output_dir);
+    } else if format == "sqlite-fts" {
+        export_sqlite_fts(args, data_path, &output_dir);
+    } else {
+        let Some(links) = load_graph(data_path) else {
+            eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+            std::process::exit(1);
+        };
+        let graph = build_dense_graph(&links);
+        drop(links);
+        export_graph(args, data_path, format, &graph, &output_dir);
+    }
+
+    // Formats that send their output straight to a server or database leave nothing on disk to upload
+    if let Some(location) = args.value("upload").filter(|_| output_dir.is_dir()) {
+        upload_dir(location, &output_dir);
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use threadpool::ThreadPool;
+use tracing::info;
+use crate::config::config;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::stubs::{STUB_DUMP, for_each_stub, get_dump_path};
+
+struct Entry {
+    title: String,
+    namespace: u32,
+    redirect: Option<String>,  // target title
+}
+
+fn csv_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+// Collects the namespace and redirect target of every page in the chosen chunks, decompressing them in parallel
+fn load_entries_from_chunks(articles_path: &Path, chunk_ranges: Vec<(usize, u64, u64)>) -> HashMap<PageId, Entry> {
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let entries = Arc::new(Mutex::new(HashMap::new()));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Reading pages"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let entries = Arc::clone(&entries);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let pages = load_chunk_pages(&articles_path, start_position, end_position);
+            let chunk_entries: Vec<(PageId, Entry)> = pages.into_iter()
+                .map(|(id, page)| (id, Entry { title: page.title, namespace: page.namespace, redirect: page.redirect_target.filter(|_| page.redirect) }))
+                .collect();
+            entries.lock().unwrap().extend(chunk_entries);
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    Arc::try_unwrap(entries).ok().unwrap().into_inner().unwrap()
+}
+
+// Writes the page inventory, sorted by ID, without building links.bin. IDs and titles come straight from the
+// multistream index. --namespaces and --redirects also need each page's metadata, which is read from the stub dump
+// when it's been downloaded, since that's a fraction of the size, and otherwise from the articles dump.
+pub fn export_titles(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let format = args.value("format").unwrap_or("tsv");
+    if !["tsv", "csv"].contains(&format) {
+        eprintln!("Error: Unknown format {} (expected tsv or csv)", format);
+        std::process::exit(1);
+    }
+    let with_namespaces = args.flag("namespaces");
+    let with_redirects = args.flag("redirects");
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join(format!("titles.{}", format)));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let stub_path = get_dump_path(data_path, STUB_DUMP);
+    let mut entries: Vec<(PageId, Entry)> = if !(with_namespaces || with_redirects) {
+        chunk_ranges.iter()
+            .flat_map(|(_, start_position, _)| &seek_position_map[start_position])
+            .map(|(id, title)| (*id, Entry { title: title.clone(), namespace: 0, redirect: None }))
+            .collect()
+    } else if stub_path.exists() && chunk_ranges.len() == seek_position_map.len() {
+        info!("Reading page metadata from {}", stub_path.to_str().unwrap());
+        let mut entries = Vec::new();
+        for_each_stub(&stub_path, |page| entries.push((page.id, Entry { title: page.title.clone(), namespace: page.namespace, redirect: page.redirect.clone() })));
+        entries
+    } else {
+        load_entries_from_chunks(&articles_path, chunk_ranges).into_iter().collect()
+    };
+    entries.sort_unstable_by_key(|(id, _)| *id);
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    let mut columns = vec!["id", "title"];
+    if with_namespaces { columns.push("namespace"); }
+    if with_redirects { columns.push("redirect_target"); }
+    if format == "csv" {
+        writeln!(output_file, "{}", columns.join(",")).expect("Failed to write output file");
+    }
+    let (separator, quote): (&str, fn(&str) -> String) = if format == "csv" { (",", csv_quote) } else { ("\t", str::to_string) };
+    for (id, entry) in &entries {
+        let mut fields = vec![id.to_string(), quote(&entry.title)];
+        if with_namespaces { fields.push(entry.namespace.to_string()); }
+        if with_redirects { fields.push(quote(entry.redirect.as_deref().unwrap_or(""))); }
+        writeln!(output_file, "{}", fields.join(separator)).expect("Failed to write output file");
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    println!("Wrote {} titles to {}", entries.len(), output_path.to_str().unwrap());
+}
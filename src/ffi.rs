@@ -0,0 +1,192 @@
+// A C API for opening a dump, fetching article text and querying the link graph, so the crate can be embedded in C,
+// C++ or Go programs and other language bindings can be built on it. Build the shared library with
+//
+//   cargo rustc --release --lib --features cdylib --crate-type cdylib
+//
+// and include include/wikipedia.h, which documents each function. Page IDs are always uint64_t here, whatever width
+// the crate was built with. Functions that fail return NULL or -1 and leave a message for wiki_last_error, and
+// strings and ID arrays returned to the caller must be released with wiki_string_free and wiki_ids_free. The safety
+// requirements of the pointer arguments are spelled out in the header.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::PathBuf;
+use std::ptr;
+use crate::helpers::{PageId, find_dump_files};
+use crate::lookup::{ArticleLookup, DEFAULT_CACHE_SIZE};
+use crate::split::LinkStore;
+
+// Bumped whenever a function's signature or behaviour changes incompatibly
+const ABI_VERSION: u32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+pub struct WikiHandle {
+    lookup: ArticleLookup,
+    links: LinkStore,
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+// Runs the body of an exported function, turning a panic into `default` so it never unwinds into the caller
+fn guard<T>(default: T, body: impl FnOnce() -> Result<T, String>) -> T {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_last_error(message);
+            default
+        }
+        Err(panic) => {
+            let message = panic.downcast_ref::<String>().map(String::as_str).or_else(|| panic.downcast_ref::<&str>().copied());
+            set_last_error(format!("Internal error: {}", message.unwrap_or("panic")));
+            default
+        }
+    }
+}
+
+unsafe fn to_str<'a>(string: *const c_char) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err("Unexpected null string".to_string());
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| "String isn't valid UTF-8".to_string())
+}
+
+unsafe fn to_handle<'a>(handle: *const WikiHandle) -> Result<&'a WikiHandle, String> {
+    handle.as_ref().ok_or_else(|| "Unexpected null handle".to_string())
+}
+
+fn to_page_id(id: u64) -> Option<PageId> {
+    #[allow(clippy::useless_conversion, clippy::unnecessary_fallible_conversions)]
+    PageId::try_from(id).ok()
+}
+
+fn into_c_string(string: &str) -> *mut c_char {
+    CString::new(string.replace('\0', "")).unwrap().into_raw()
+}
+
+// Hands an array of IDs to the caller, storing its length in `length`
+unsafe fn into_id_array(ids: &[PageId], length: *mut usize) -> *mut u64 {
+    let ids: Box<[u64]> = ids.iter().map(|&id| id as u64).collect();
+    if !length.is_null() {
+        *length = ids.len();
+    }
+    Box::into_raw(ids) as *mut u64
+}
+
+#[no_mangle]
+pub extern "C" fn wiki_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+// The message of the last error on this thread, or NULL if nothing has failed yet. It stays valid until the next
+// failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn wiki_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_open(data_path: *const c_char, cache_size: usize) -> *mut WikiHandle {
+    guard(ptr::null_mut(), || {
+        let data_path = PathBuf::from(to_str(data_path)?);
+        if !data_path.is_dir() {
+            return Err(format!("No data directory at {}", data_path.display()));
+        }
+        // Nothing on this path may exit, since that would take the host process down with it
+        let (index_path, articles_path) = find_dump_files(&data_path)?;
+        let links = LinkStore::try_open(&data_path)?;
+        let cache_size = if cache_size == 0 { DEFAULT_CACHE_SIZE } else { cache_size };
+        let lookup = ArticleLookup::new(&index_path, &articles_path, cache_size);
+        Ok(Box::into_raw(Box::new(WikiHandle { lookup, links })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_close(handle: *mut WikiHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_article_count(handle: *const WikiHandle) -> u64 {
+    guard(0, || Ok(to_handle(handle)?.lookup.len() as u64))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_find(handle: *const WikiHandle, title: *const c_char, id: *mut u64) -> i32 {
+    guard(-1, || {
+        match to_handle(handle)?.lookup.find(to_str(title)?) {
+            Some(found) => {
+                if !id.is_null() {
+                    *id = found as u64;
+                }
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_title(handle: *const WikiHandle, id: u64) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let title = to_page_id(id).and_then(|id| handle.lookup.title(id)).ok_or_else(|| format!("No article with ID {}", id))?;
+        Ok(into_c_string(title))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_article_text(handle: *const WikiHandle, id: u64) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let text = to_page_id(id).and_then(|id| handle.lookup.get(id)).ok_or_else(|| format!("No article with ID {}", id))?;
+        Ok(into_c_string(&text))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_links(handle: *const WikiHandle, id: u64, length: *mut usize) -> *mut u64 {
+    guard(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let links = match to_page_id(id) {
+            Some(id) => handle.links.links(id)?,
+            None => Vec::new(),
+        };
+        Ok(into_id_array(&links, length))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_backlinks(handle: *const WikiHandle, id: u64, length: *mut usize) -> *mut u64 {
+    guard(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let backlinks = match to_page_id(id) {
+            Some(id) => handle.links.backlinks(id)?.to_vec(),
+            None => Vec::new(),
+        };
+        Ok(into_id_array(&backlinks, length))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wiki_ids_free(ids: *mut u64, length: usize) {
+    if !ids.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ids, length)));
+    }
+}
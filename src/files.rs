@@ -0,0 +1,131 @@
+use std::path::Path;
+use html_escape::decode_html_entities;
+use rustc_hash::FxHashMap;
+use crate::helpers::PageId;
+use crate::links::{read_id, read_links_file, read_varint, write_id, write_varint};
+
+// files.bin lists the images and other files each article embeds with [[File:...]] or [[Image:...]], which the link
+// graph leaves out. It starts with MAGIC and a little-endian u32 version, and each record is: body_length, then a body
+// of article_id, file_count, and a (name_length, name) pair per file in text order. Every integer is a LEB128 varint.
+// categories.bin uses the same records for the categories of each page.
+pub const FILES_FILE: &str = "files.bin";
+const MAGIC: &[u8; 4] = b"WKFI";
+const VERSION: u32 = 1;
+
+pub fn get_header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+// Returns the names of the files an article embeds, normalized the way MediaWiki does: underscores become spaces and
+// the first letter is uppercase
+pub fn extract_files(text: &str) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut start = 0;
+    while let Some(open_bracket) = text[start..].find("[[") {
+        start += open_bracket + 2;
+        let rest = text[start..].trim_start();
+        let Some((namespace, name)) = rest.split_once(':') else { continue };
+        if !namespace.eq_ignore_ascii_case("file") && !namespace.eq_ignore_ascii_case("image") { continue; }
+        let end = name.find(['|', ']']).unwrap_or(name.len());
+        let name = decode_html_entities(&name[..end]).replace('_', " ");
+        let name = name.trim();
+        let mut chars = name.chars();
+        if let Some(first) = chars.next() {
+            files.push(first.to_uppercase().chain(chars).collect());
+        }
+    }
+    files
+}
+
+pub fn get_names_byte_string(article_id: PageId, names: &[String]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_id(&mut body, article_id);
+    write_varint(&mut body, names.len() as u32);
+    for name in names {
+        write_varint(&mut body, name.len() as u32);
+        body.extend_from_slice(name.as_bytes());
+    }
+
+    let mut output_buffer = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut output_buffer, body.len() as u32);
+    output_buffer.extend_from_slice(&body);
+    output_buffer
+}
+
+fn parse_record(buffer: &[u8], offset: &mut usize) -> Result<(PageId, Vec<String>), String> {
+    let body_length = read_varint(buffer, offset)? as usize;
+    let body_end = offset.checked_add(body_length).filter(|&end| end <= buffer.len()).ok_or("record runs past end of file")?;
+    let body = &buffer[..body_end];
+    let article_id = read_id(body, offset)?;
+    let count = read_varint(body, offset)? as usize;
+    if count > body_end - *offset {
+        return Err(format!("name count {} runs past end of record", count));
+    }
+    let mut names = Vec::with_capacity(count);
+    for _ in 0..count {
+        let name_length = read_varint(body, offset)? as usize;
+        let name_bytes = body.get(*offset..*offset + name_length).ok_or_else(|| format!("name length {} runs past end of record", name_length))?;
+        names.push(String::from_utf8(name_bytes.to_vec()).map_err(|_| "name is not valid UTF-8".to_string())?);
+        *offset += name_length;
+    }
+    *offset = body_end;
+    Ok((article_id, names))
+}
+
+// Reads a file of name records like files.bin, given its magic number and version and what kind of file it is for errors
+pub fn read_names(path: &Path, magic: &[u8; 4], version: u32, kind: &str) -> FxHashMap<PageId, Vec<String>> {
+    let buffer = read_links_file(path);
+    if !buffer.starts_with(magic) || buffer.get(4..8) != Some(&version.to_le_bytes()[..]) {
+        eprintln!("Error: {} is not a valid {} file", path.to_str().unwrap(), kind);
+        std::process::exit(1);
+    }
+    let mut names = FxHashMap::default();
+    let mut i = 8;
+    while i < buffer.len() {
+        let offset = i;
+        let (article_id, article_names) = parse_record(&buffer, &mut i).unwrap_or_else(|err| panic!("Corrupt {} record at byte {}: {}", kind, offset, err));
+        names.insert(article_id, article_names);
+    }
+    names
+}
+
+pub fn read_files(path: &Path) -> FxHashMap<PageId, Vec<String>> {
+    read_names(path, MAGIC, VERSION, "files")
+}
+
+// Reports the most widely used files and the articles that embed the most of them
+pub fn print_file_stats(path: &Path, title: impl Fn(&PageId) -> String) {
+    let files = read_files(path);
+    let mut usage: FxHashMap<&str, usize> = FxHashMap::default();
+    let mut references = 0;
+    for article_files in files.values() {
+        references += article_files.len();
+        let mut unique: Vec<&str> = article_files.iter().map(String::as_str).collect();
+        unique.sort_unstable();
+        unique.dedup();
+        for name in unique {
+            *usage.entry(name).or_insert(0) += 1;
+        }
+    }
+
+    println!("\nFiles:");
+    println!("  File references: {}", references);
+    println!("  Distinct files: {}", usage.len());
+    println!("  Articles with files: {}", files.values().filter(|article_files| !article_files.is_empty()).count());
+
+    let mut most_used: Vec<(&str, usize)> = usage.into_iter().collect();
+    most_used.sort_unstable_by_key(|&(name, count)| (std::cmp::Reverse(count), name));
+    println!("\nTop 10 most used files:");
+    for (rank, (name, count)) in most_used.iter().take(10).enumerate() {
+        println!("{:>2}) {} ({} articles)", rank + 1, name, count);
+    }
+
+    let mut most_media: Vec<(PageId, usize)> = files.iter().map(|(id, article_files)| (*id, article_files.len())).collect();
+    most_media.sort_unstable_by_key(|&(id, count)| (std::cmp::Reverse(count), id));
+    println!("\nTop 10 articles with the most files:");
+    for (rank, (id, count)) in most_media.iter().take(10).enumerate() {
+        println!("{:>2}) {} ({})", rank + 1, title(id), count);
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::fs::{File, create_dir_all};
+use std::io::Write;
+use bzip2::Compression;
+use bzip2::write::BzEncoder;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::helpers::{Args, get_dump_paths};
+
+const WORDS: [&str; 16] = ["the", "history", "of", "city", "river", "was", "founded", "in", "and", "a", "notable", "family", "known", "for", "its", "music"];
+const SITEINFO: &str = "<mediawiki xmlns=\"http://www.mediawiki.org/xml/export-0.11/\" xml:lang=\"en\">\n  <siteinfo>\n    <sitename>Wikipedia</sitename>\n    <dbname>enwiki</dbname>\n  </siteinfo>\n";
+
+fn compress(data: &str) -> Vec<u8> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data.as_bytes()).expect("Failed to compress data");
+    encoder.finish().expect("Failed to compress data")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn article_title(id: u32) -> String {
+    format!("Article {}", id)
+}
+
+// Every tenth page is a non-article namespace page, so the namespace filtering gets exercised too
+fn page_title(id: u32) -> (String, u32) {  // (title, namespace)
+    match id % 10 {
+        0 => (format!("Category:Topic {}", id), 14),
+        5 => (format!("Template:Box {}", id), 10),
+        // Titles that only differ in case from the previous page's, where the previous page is sometimes a redirect
+        _ if id % 100 == 47 || id % 100 == 64 => (article_title(id - 1).to_uppercase(), 0),
+        _ => (article_title(id), 0),
+    }
+}
+
+fn article_text(rng: &mut StdRng, num_articles: u32) -> String {
+    let mut text = String::new();
+    for _ in 0..rng.gen_range(2..6) {
+        for _ in 0..rng.gen_range(10..40) {
+            text.push_str(WORDS[rng.gen_range(0..WORDS.len())]);
+            text.push(' ');
+            match rng.gen_range(0..20) {
+                0 => text.push_str(&format!("[[{}]] ", article_title(rng.gen_range(1..=num_articles)))),
+                1 => text.push_str(&format!("[[{}|the article]] ", article_title(rng.gen_range(1..=num_articles)))),
+                2 => text.push_str(&format!("[[{}#History]] ", article_title(rng.gen_range(1..=num_articles)))),
+                3 => text.push_str(&format!("[[Missing page {}]] ", rng.gen_range(0..1000))),
+                4 => text.push_str("[[File:Example.jpg|thumb|A & B <caption>]] "),
+                _ => {}
+            }
+        }
+        text.push_str("\n\n== Section ==\n");
+    }
+    text.push_str(&format!("[[Category:Topic {}]]", rng.gen_range(1..=num_articles / 10 + 1) * 10));
+    text
+}
+
+fn page_xml(id: u32, title: &str, namespace: u32, redirect: Option<&str>, text: &str) -> String {
+    let redirect = redirect.map(|target| format!("    <redirect title=\"{}\" />\n", escape_xml(target))).unwrap_or_default();
+    format!(
+        "  <page>\n    <title>{}</title>\n    <ns>{}</ns>\n    <id>{}</id>\n{}    <revision>\n      <id>{}</id>\n      <timestamp>2024-08-01T00:00:00Z</timestamp>\n      <text bytes=\"{}\" xml:space=\"preserve\">{}</text>\n    </revision>\n  </page>\n",
+        escape_xml(title), namespace, id, redirect, id + 1_000_000, text.len(), escape_xml(text))
+}
+
+// Writes a small but valid multistream dump and index to `data_path`, using the same file names as the real dump.
+// Like the real dump, the siteinfo header and footer get their own bz2 streams and each chunk holds `chunk_size` pages.
+pub fn generate_test_dump(data_path: &Path, num_articles: u32, chunk_size: u32, seed: u64) {
+    create_dir_all(data_path).expect("Failed to create output directory");
+    let (index_path, articles_path) = get_dump_paths(data_path);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut articles_file = File::create(articles_path).expect("Failed to create articles file");
+    let mut index = String::new();
+    let header = compress(SITEINFO);
+    articles_file.write_all(&header).expect("Failed to write articles file");
+    let mut position = header.len() as u64;
+
+    let ids: Vec<u32> = (1..=num_articles).collect();
+    for chunk in ids.chunks(chunk_size as usize) {
+        let mut chunk_xml = String::new();
+        for &id in chunk {
+            let (title, namespace) = page_title(id);
+            // Some articles are redirects, like the millions in the real dump
+            if namespace == 0 && id % 20 == 3 {
+                let target = article_title(rng.gen_range(1..=num_articles));
+                chunk_xml.push_str(&page_xml(id, &title, namespace, Some(&target), &format!("#REDIRECT [[{}]]", target)));
+            } else {
+                chunk_xml.push_str(&page_xml(id, &title, namespace, None, &article_text(&mut rng, num_articles)));
+            }
+            index.push_str(&format!("{}:{}:{}\n", position, id, escape_xml(&title)));
+        }
+        let compressed = compress(&chunk_xml);
+        articles_file.write_all(&compressed).expect("Failed to write articles file");
+        position += compressed.len() as u64;
+    }
+    articles_file.write_all(&compress("</mediawiki>\n")).expect("Failed to write articles file");
+
+    let mut index_file = File::create(index_path).expect("Failed to create index file");
+    index_file.write_all(&compress(&index)).expect("Failed to write index file");
+}
+
+pub fn gen_testdata(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let num_articles = args.parse_value("articles").unwrap_or(1000);
+    let chunk_size = args.parse_value("chunk-size").unwrap_or(100);
+    let seed = args.parse_value("seed").unwrap_or(0);
+    if num_articles == 0 || chunk_size == 0 {
+        eprintln!("Error: --articles and --chunk-size must be positive");
+        std::process::exit(1);
+    }
+
+    generate_test_dump(data_path, num_articles, chunk_size, seed);
+    println!("Wrote {} pages in {} chunks to {}", num_articles, num_articles.div_ceil(chunk_size), data_path.to_str().unwrap());
+}
@@ -0,0 +1,224 @@
+use rustc_hash::FxHashMap;
+use crate::helpers::{PageId, create_progress_bar};
+
+// The graph with article IDs remapped to dense indices 0..n in ascending ID order
+pub struct DenseGraph {
+    pub ids: Vec<PageId>,  // dense index -> article ID
+    pub offsets: Vec<u64>,  // row i's edges are edges[offsets[i]..offsets[i+1]]
+    pub edges: Vec<u32>,  // dense target indices, sorted within each row
+    pub dropped_links: usize,  // links to articles without a record
+}
+
+impl DenseGraph {
+    pub fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    // The dense index of an article ID
+    pub fn index_of(&self, article_id: PageId) -> Option<usize> {
+        self.ids.binary_search(&article_id).ok()
+    }
+
+    pub fn successors(&self, node: usize) -> &[u32] {
+        &self.edges[self.offsets[node] as usize..self.offsets[node + 1] as usize]
+    }
+
+    // The same graph with every link reversed, so successors become predecessors
+    pub fn transpose(&self) -> DenseGraph {
+        let mut offsets = vec![0u64; self.node_count() + 1];
+        for &target in &self.edges {
+            offsets[target as usize + 1] += 1;
+        }
+        for node in 0..self.node_count() {
+            offsets[node + 1] += offsets[node];
+        }
+        // Sources are visited in order, so each row comes out sorted
+        let mut cursors = offsets.clone();
+        let mut edges = vec![0u32; self.edges.len()];
+        for node in 0..self.node_count() {
+            for &target in self.successors(node) {
+                edges[cursors[target as usize] as usize] = node as u32;
+                cursors[target as usize] += 1;
+            }
+        }
+        DenseGraph { ids: self.ids.clone(), offsets, edges, dropped_links: 0 }
+    }
+
+    // The subgraph induced by the given nodes, keeping only links between them
+    pub fn subgraph(&self, nodes: &[usize]) -> DenseGraph {
+        let mut nodes = nodes.to_vec();
+        nodes.sort_unstable();
+        nodes.dedup();
+        let mut new_index = vec![u32::MAX; self.node_count()];
+        for (index, &node) in nodes.iter().enumerate() {
+            new_index[node] = index as u32;
+        }
+        let mut offsets = vec![0];
+        let mut edges = Vec::new();
+        for &node in &nodes {
+            // Remapping keeps the order, so rows stay sorted
+            edges.extend(self.successors(node).iter().map(|&target| new_index[target as usize]).filter(|&target| target != u32::MAX));
+            offsets.push(edges.len() as u64);
+        }
+        DenseGraph { ids: nodes.iter().map(|&node| self.ids[node]).collect(), offsets, edges, dropped_links: 0 }
+    }
+
+    // The node's successors with duplicate links collapsed
+    pub fn unique_successors(&self, node: usize) -> Vec<u32> {
+        let mut successors = self.successors(node).to_vec();
+        successors.dedup();
+        successors
+    }
+}
+
+pub fn build_dense_graph(links: &FxHashMap<PageId, Vec<PageId>>) -> DenseGraph {
+    let mut ids: Vec<PageId> = links.keys().copied().collect();
+    ids.sort_unstable();
+    let dense_ids: FxHashMap<PageId, u32> = ids.iter().enumerate().map(|(index, id)| (*id, index as u32)).collect();
+
+    let progress_bar = create_progress_bar(ids.len() as u64, "Building CSR arrays");
+    let mut offsets = Vec::with_capacity(ids.len() + 1);
+    let mut edges = Vec::new();
+    let mut dropped_links = 0;
+    offsets.push(0);
+    for id in &ids {
+        let row_start = edges.len();
+        for link in &links[id] {
+            match dense_ids.get(link) {
+                Some(&index) => edges.push(index),
+                None => dropped_links += 1,
+            }
+        }
+        edges[row_start..].sort_unstable();
+        offsets.push(edges.len() as u64);
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+
+    DenseGraph { ids, offsets, edges, dropped_links }
+}
+
+// An undirected view of a DenseGraph without self-links, where parallel links are merged into one edge whose weight
+// is the number of links between its endpoints in either direction
+pub struct UndirectedGraph {
+    pub offsets: Vec<u64>,
+    pub neighbors: Vec<u32>,  // sorted within each row
+    pub weights: Vec<u32>,
+}
+
+impl UndirectedGraph {
+    pub fn node_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn neighbors(&self, node: usize) -> &[u32] {
+        &self.neighbors[self.offsets[node] as usize..self.offsets[node + 1] as usize]
+    }
+
+    pub fn weights(&self, node: usize) -> &[u32] {
+        &self.weights[self.offsets[node] as usize..self.offsets[node + 1] as usize]
+    }
+}
+
+pub fn build_undirected_graph(graph: &DenseGraph) -> UndirectedGraph {
+    // Count each link at both ends, lay the rows out, then fill them in
+    let node_count = graph.node_count();
+    let mut row_lengths = vec![0u64; node_count];
+    for node in 0..node_count {
+        for &target in graph.successors(node).iter().filter(|&&target| target as usize != node) {
+            row_lengths[node] += 1;
+            row_lengths[target as usize] += 1;
+        }
+    }
+    let mut cursors = Vec::with_capacity(node_count);
+    let mut total = 0;
+    for length in &row_lengths {
+        cursors.push(total);
+        total += length;
+    }
+    let mut all_neighbors = vec![0u32; total as usize];
+    for node in 0..node_count {
+        for &target in graph.successors(node).iter().filter(|&&target| target as usize != node) {
+            all_neighbors[cursors[node] as usize] = target;
+            cursors[node] += 1;
+            all_neighbors[cursors[target as usize] as usize] = node as u32;
+            cursors[target as usize] += 1;
+        }
+    }
+
+    // Sort each row and merge repeated neighbors into weights
+    let progress_bar = create_progress_bar(node_count as u64, "Building undirected graph");
+    let mut offsets = Vec::with_capacity(node_count + 1);
+    let mut neighbors = Vec::new();
+    let mut weights = Vec::new();
+    let mut row_start = 0;
+    offsets.push(0);
+    for (node, length) in row_lengths.iter().enumerate() {
+        let row = &mut all_neighbors[row_start..row_start + *length as usize];
+        row.sort_unstable();
+        for &neighbor in row.iter() {
+            if neighbors.len() > offsets[node] as usize && neighbors.last() == Some(&neighbor) {
+                *weights.last_mut().unwrap() += 1;
+            } else {
+                neighbors.push(neighbor);
+                weights.push(1);
+            }
+        }
+        row_start += *length as usize;
+        offsets.push(neighbors.len() as u64);
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+
+    UndirectedGraph { offsets, neighbors, weights }
+}
+
+// Each node's core number, the largest k such that it belongs to a subgraph where every node has at least k
+// neighbors. Peels nodes in order of degree using bucket sort (Batagelj and Zaversnik), so it runs in linear time.
+pub fn core_numbers(graph: &UndirectedGraph) -> Vec<u32> {
+    let node_count = graph.node_count();
+    let mut degrees: Vec<u32> = (0..node_count).map(|node| graph.neighbors(node).len() as u32).collect();
+    let max_degree = degrees.iter().max().copied().unwrap_or(0) as usize;
+
+    // Sort the nodes by degree, where bucket_starts[d] is the position of the first node with degree d
+    let mut bucket_starts = vec![0; max_degree + 2];
+    for &degree in &degrees {
+        bucket_starts[degree as usize + 1] += 1;
+    }
+    for degree in 1..bucket_starts.len() {
+        bucket_starts[degree] += bucket_starts[degree - 1];
+    }
+    let mut order = vec![0u32; node_count];
+    let mut positions = vec![0; node_count];
+    let mut next_slot = bucket_starts.clone();
+    for (node, &degree) in degrees.iter().enumerate() {
+        positions[node] = next_slot[degree as usize];
+        order[positions[node]] = node as u32;
+        next_slot[degree as usize] += 1;
+    }
+
+    // Take nodes in order, moving each neighbor with a higher degree down a bucket by swapping it with the first node
+    // of its bucket
+    let progress_bar = create_progress_bar(node_count as u64, "Peeling cores");
+    for index in 0..node_count {
+        let node = order[index] as usize;
+        for &neighbor in graph.neighbors(node) {
+            let neighbor = neighbor as usize;
+            if degrees[neighbor] > degrees[node] {
+                let degree = degrees[neighbor] as usize;
+                let first = bucket_starts[degree];
+                let first_node = order[first] as usize;
+                if first_node != neighbor {
+                    order.swap(positions[neighbor], first);
+                    positions[first_node] = positions[neighbor];
+                    positions[neighbor] = first;
+                }
+                bucket_starts[degree] += 1;
+                degrees[neighbor] -= 1;
+            }
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+    degrees
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Error, Object, Request, Result, Schema};
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+use crate::categories::{CATEGORIES_FILE, extract_categories, read_categories};
+use crate::helpers::PageId;
+use crate::lookup::ArticleLookup;
+use crate::query::shortest_path;
+use crate::split::LinkStore;
+
+// Nested queries like article { links { links { links { ... } } } } fan out fast, so they're capped
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 2000;
+
+struct Store {
+    lookup: Arc<ArticleLookup>,
+    links: Arc<LinkStore>,
+    categories: Option<FxHashMap<PageId, Vec<String>>>,  // from categories.bin, if the index wrote it
+    members: HashMap<String, Vec<PageId>>,  // category -> the pages in it, sorted by ID
+}
+
+impl Store {
+    fn articles(&self, ids: impl IntoIterator<Item = PageId>, limit: usize) -> Vec<ArticleNode> {
+        ids.into_iter().filter(|id| self.lookup.title(*id).is_some()).take(limit).map(|id| ArticleNode { id }).collect()
+    }
+}
+
+struct ArticleNode {
+    id: PageId,
+}
+
+#[Object(name = "Article")]
+impl ArticleNode {
+    async fn id(&self) -> PageId {
+        self.id
+    }
+
+    async fn title(&self, context: &Context<'_>) -> String {
+        context.data_unchecked::<Store>().lookup.title(self.id).unwrap_or_default().to_string()
+    }
+
+    // The wikitext, which means decompressing the chunk the article is in unless it's cached
+    async fn text(&self, context: &Context<'_>) -> Option<String> {
+        context.data_unchecked::<Store>().lookup.get(self.id)
+    }
+
+    async fn links(&self, context: &Context<'_>, #[graphql(default = 100)] limit: usize) -> Result<Vec<ArticleNode>> {
+        let store = context.data_unchecked::<Store>();
+        Ok(store.articles(store.links.links(self.id).map_err(Error::new)?, limit))
+    }
+
+    async fn backlinks(&self, context: &Context<'_>, #[graphql(default = 100)] limit: usize) -> Result<Vec<ArticleNode>> {
+        let store = context.data_unchecked::<Store>();
+        Ok(store.articles(store.links.backlinks(self.id).map_err(Error::new)?.iter().copied(), limit))
+    }
+
+    // The categories from categories.bin, or else from the article's wikitext
+    async fn categories(&self, context: &Context<'_>) -> Vec<String> {
+        let store = context.data_unchecked::<Store>();
+        match &store.categories {
+            Some(categories) => categories.get(&self.id).cloned().unwrap_or_default(),
+            None => store.lookup.get(self.id).map(|text| extract_categories(&text)).unwrap_or_default(),
+        }
+    }
+}
+
+struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // An article by ID, or by title ignoring case
+    async fn article(&self, context: &Context<'_>, id: Option<PageId>, title: Option<String>) -> Result<Option<ArticleNode>> {
+        let store = context.data_unchecked::<Store>();
+        let id = match (id, title) {
+            (Some(id), _) => Some(id).filter(|id| store.lookup.title(*id).is_some()),
+            (None, Some(title)) => store.lookup.find(&title),
+            (None, None) => return Err(Error::new("Expected an id or a title")),
+        };
+        Ok(id.map(|id| ArticleNode { id }))
+    }
+
+    // Articles whose titles contain the query, shortest titles first
+    async fn search(&self, context: &Context<'_>, query: String, #[graphql(default = 20)] limit: usize) -> Vec<ArticleNode> {
+        let store = context.data_unchecked::<Store>();
+        store.articles(store.lookup.search(&query, limit), limit)
+    }
+
+    // The articles in a category, given without the Category: prefix. Needs categories.bin.
+    async fn category(&self, context: &Context<'_>, name: String, #[graphql(default = 100)] limit: usize) -> Result<Vec<ArticleNode>> {
+        let store = context.data_unchecked::<Store>();
+        if store.categories.is_none() {
+            return Err(Error::new(format!("{} not found, run index with --with-categories", CATEGORIES_FILE)));
+        }
+        let name = name.trim();
+        let name = name.strip_prefix("Category:").unwrap_or(name);
+        Ok(store.articles(store.members.get(name).into_iter().flatten().copied(), limit))
+    }
+
+    // The articles on a shortest path of links between two titles, both included, or null if there's no path
+    async fn shortest_path(&self, context: &Context<'_>, from: String, to: String) -> Result<Option<Vec<ArticleNode>>> {
+        let store = context.data_unchecked::<Store>();
+        let find = |title: &str| store.lookup.find(title).ok_or_else(|| Error::new(format!("No article titled {}", title.trim())));
+        let links = store.links.all_links().map_err(Error::new)?;
+        Ok(shortest_path(links, find(&from)?, find(&to)?).map(|path| path.into_iter().map(|id| ArticleNode { id }).collect()))
+    }
+}
+
+// The GraphQL schema over the article store, the link graph and the categories that serve exposes at /graphql
+pub struct GraphqlSchema(Schema<QueryRoot, EmptyMutation, EmptySubscription>);
+
+impl GraphqlSchema {
+    pub fn new(lookup: Arc<ArticleLookup>, links: Arc<LinkStore>, data_path: &Path) -> Self {
+        let categories_path = data_path.join(CATEGORIES_FILE);
+        let categories = categories_path.exists().then(|| read_categories(&categories_path));
+        let mut members: HashMap<String, Vec<PageId>> = HashMap::new();
+        for (id, names) in categories.iter().flatten() {
+            for name in names {
+                members.entry(name.clone()).or_default().push(*id);
+            }
+        }
+        for ids in members.values_mut() {
+            ids.sort_unstable();
+        }
+        let store = Store { lookup, links, categories, members };
+        GraphqlSchema(Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .data(store)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish())
+    }
+
+    // Runs a request in the JSON form of a GraphQL POST body, returning the JSON response with any errors in it
+    pub fn execute(&self, request: &str) -> Result<Value, String> {
+        let request: Request = serde_json::from_str(request).map_err(|err| format!("Invalid GraphQL request: {}", err))?;
+        let response = futures_executor::block_on(self.0.execute(request));
+        Ok(serde_json::to_value(&response).expect("Failed to serialize GraphQL response"))
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use regex::{Regex, RegexBuilder};
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::parse::exit_on_write_error;
+
+// Returns `title:line:offset:text` for every line of a chunk's pages that matches, in ID order. The line number
+// counts from 1 and the offset is the byte offset of the first match in the page's wikitext.
+fn search_chunk(articles_path: &str, start_position: u64, end_position: u64, regex: &Regex, namespaces: Option<&[u32]>) -> Vec<String> {
+    let mut pages: Vec<_> = load_chunk_pages(articles_path, start_position, end_position).into_iter()
+        .filter(|(_, page)| namespaces.is_none_or(|namespaces| namespaces.contains(&page.namespace)))
+        .collect();
+    pages.sort_unstable_by_key(|(id, _)| *id);
+
+    let mut matches = Vec::new();
+    for (_, page) in pages {
+        let mut line_start = 0;
+        for (line_number, line) in page.text.split('\n').enumerate() {
+            if let Some(found) = regex.find(line) {
+                matches.push(format!("{}:{}:{}:{}", page.title, line_number + 1, line_start + found.start(), line));
+            }
+            line_start += line.len() + 1;
+        }
+    }
+    matches
+}
+
+// Searches the wikitext of every page with a regex and prints each matching line, like grep. Chunks are decompressed
+// and searched in parallel but printed in dump order, and once --max-count matches have been printed the chunks that
+// haven't started yet are skipped.
+pub fn grep(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let Some(pattern) = args.positional.get(1) else {
+        eprintln!("Usage: grep <data_path> <pattern> [--namespace N,M] [--max-count N] [--ignore-case]");
+        std::process::exit(1);
+    };
+    let regex = RegexBuilder::new(pattern).case_insensitive(args.flag("ignore-case")).build().unwrap_or_else(|err| {
+        eprintln!("Error: Invalid pattern: {}", err);
+        std::process::exit(1);
+    });
+    let namespaces: Option<Vec<u32>> = args.value("namespace").map(|namespaces| namespaces.split(',').map(|namespace| {
+        namespace.trim().parse().unwrap_or_else(|_| {
+            eprintln!("Error: --namespace expects namespace numbers, got {}", namespace);
+            std::process::exit(1);
+        })
+    }).collect());
+    let max_count = args.parse_value("max-count").unwrap_or(usize::MAX);
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let regex = Arc::new(regex);
+    let namespaces = Arc::new(namespaces);
+    let stopped = Arc::new(AtomicBool::new(false));
+    let progress_bar = create_progress_bar(chunk_ranges.len() as u64, "Searching pages");
+    let (sender, receiver) = mpsc::sync_channel(num_threads);
+
+    for (sequence, (_, start_position, end_position)) in chunk_ranges.into_iter().enumerate() {
+        let articles_path = Arc::clone(&articles_path);
+        let regex = Arc::clone(&regex);
+        let namespaces = Arc::clone(&namespaces);
+        let stopped = Arc::clone(&stopped);
+        let sender = sender.clone();
+        pool.execute(move || {
+            if stopped.load(Ordering::Relaxed) { return; }
+            let _ = sender.send((sequence, search_chunk(&articles_path, start_position, end_position, &regex, namespaces.as_deref())));
+        });
+    }
+    drop(sender);
+
+    // Chunks finish out of order, so hold early ones back until everything before them has been printed
+    let mut output = BufWriter::new(std::io::stdout().lock());
+    let mut pending = BTreeMap::new();
+    let mut next_sequence = 0;
+    let mut printed = 0;
+    'receive: for (sequence, matches) in receiver.iter() {
+        pending.insert(sequence, matches);
+        while let Some(matches) = pending.remove(&next_sequence) {
+            for line in matches {
+                writeln!(output, "{}", line).unwrap_or_else(|err| exit_on_write_error(err));
+                printed += 1;
+                if printed == max_count {
+                    stopped.store(true, Ordering::Relaxed);
+                    break 'receive;
+                }
+            }
+            output.flush().unwrap_or_else(|err| exit_on_write_error(err));
+            next_sequence += 1;
+            progress_bar.inc(1);
+        }
+    }
+    output.flush().unwrap_or_else(|err| exit_on_write_error(err));
+    progress_bar.finish_and_clear();
+}
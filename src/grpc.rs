@@ -0,0 +1,125 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tracing::{info, warn};
+use crate::helpers::PageId;
+use crate::lookup::ArticleLookup;
+use crate::query::shortest_path;
+use crate::split::LinkStore;
+
+#[allow(clippy::all)]
+mod proto {
+    tonic::include_proto!("wikipedia.v1");
+}
+
+use proto::{Article, ArticleList, ArticleRef, ArticleRequest, SearchRequest, ShortestPathRequest};
+use proto::article_request::Article as ArticleKey;
+use proto::wikipedia_server::{Wikipedia, WikipediaServer};
+
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+struct State {
+    lookup: Arc<ArticleLookup>,
+    links: Arc<LinkStore>,
+}
+
+impl State {
+    fn find(&self, request: Option<&ArticleRequest>) -> Result<PageId, Status> {
+        match request.and_then(|request| request.article.as_ref()) {
+            Some(ArticleKey::Id(id)) => PageId::try_from(*id).ok()
+                .filter(|id| self.lookup.title(*id).is_some())
+                .ok_or_else(|| Status::not_found(format!("No article with ID {}", id))),
+            Some(ArticleKey::Title(title)) => self.lookup.find(title).ok_or_else(|| Status::not_found(format!("No article titled {}", title.trim()))),
+            None => Err(Status::invalid_argument("Expected an article ID or title")),
+        }
+    }
+
+    fn article_list(&self, ids: &[PageId]) -> ArticleList {
+        let articles = ids.iter().map(|&id| ArticleRef { id: id as u64, title: self.lookup.title(id).unwrap_or_default().to_string() }).collect();
+        ArticleList { articles }
+    }
+}
+
+struct WikipediaService(Arc<State>);
+
+impl WikipediaService {
+    // Runs a request on the blocking pool, since reading an article can mean decompressing a whole chunk and the first
+    // graph query loads the whole graph
+    async fn blocking<T: Send + 'static>(&self, handler: impl FnOnce(&State) -> Result<T, Status> + Send + 'static) -> Result<Response<T>, Status> {
+        let state = Arc::clone(&self.0);
+        tokio::task::spawn_blocking(move || handler(&state)).await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .map(Response::new)
+    }
+}
+
+#[tonic::async_trait]
+impl Wikipedia for WikipediaService {
+    async fn get_article(&self, request: Request<ArticleRequest>) -> Result<Response<Article>, Status> {
+        info!(request = ?request.get_ref(), "GetArticle");
+        self.blocking(move |state| {
+            let id = state.find(Some(request.get_ref()))?;
+            let text = state.lookup.get(id).ok_or_else(|| Status::not_found(format!("No article with ID {}", id)))?;
+            Ok(Article { id: id as u64, title: state.lookup.title(id).unwrap_or_default().to_string(), text })
+        }).await
+    }
+
+    async fn get_links(&self, request: Request<ArticleRequest>) -> Result<Response<ArticleList>, Status> {
+        info!(request = ?request.get_ref(), "GetLinks");
+        self.blocking(move |state| {
+            let id = state.find(Some(request.get_ref()))?;
+            Ok(state.article_list(&state.links.links(id).map_err(Status::failed_precondition)?))
+        }).await
+    }
+
+    async fn get_backlinks(&self, request: Request<ArticleRequest>) -> Result<Response<ArticleList>, Status> {
+        info!(request = ?request.get_ref(), "GetBacklinks");
+        self.blocking(move |state| {
+            let id = state.find(Some(request.get_ref()))?;
+            Ok(state.article_list(state.links.backlinks(id).map_err(Status::failed_precondition)?))
+        }).await
+    }
+
+    async fn search(&self, request: Request<SearchRequest>) -> Result<Response<ArticleList>, Status> {
+        info!(request = ?request.get_ref(), "Search");
+        let SearchRequest { query, limit } = request.into_inner();
+        if query.trim().is_empty() {
+            return Err(Status::invalid_argument("Expected a non-empty query"));
+        }
+        let limit = if limit == 0 { DEFAULT_SEARCH_LIMIT } else { limit as usize };
+        self.blocking(move |state| Ok(state.article_list(&state.lookup.search(&query, limit)))).await
+    }
+
+    async fn shortest_path(&self, request: Request<ShortestPathRequest>) -> Result<Response<ArticleList>, Status> {
+        info!(request = ?request.get_ref(), "ShortestPath");
+        self.blocking(move |state| {
+            let source = state.find(request.get_ref().source.as_ref())?;
+            let target = state.find(request.get_ref().target.as_ref())?;
+            let links = state.links.all_links().map_err(Status::failed_precondition)?;
+            Ok(state.article_list(&shortest_path(links, source, target).unwrap_or_default()))
+        }).await
+    }
+}
+
+// Serves the gRPC interface in proto/wikipedia.proto on a background thread with its own async runtime, sharing the
+// article lookup and link graph with the HTTP server
+pub fn spawn_grpc_server(lookup: Arc<ArticleLookup>, links: Arc<LinkStore>, port: u16) {
+    let state = State { lookup, links };
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().expect("Failed to start the async runtime");
+    let incoming = {
+        let _guard = runtime.enter();
+        TcpIncoming::bind(SocketAddr::from(([127, 0, 0, 1], port))).unwrap_or_else(|err| {
+            eprintln!("Error: Unable to listen on port {}: {}", port, err);
+            std::process::exit(1);
+        })
+    };
+    let service = WikipediaServer::new(WikipediaService(Arc::new(state)));
+    std::thread::spawn(move || {
+        if let Err(err) = runtime.block_on(Server::builder().add_service(service).serve_with_incoming(incoming)) {
+            warn!("The gRPC server stopped: {}", err);
+        }
+    });
+    println!("Serving gRPC on 127.0.0.1:{}", port);
+}
@@ -1,19 +1,132 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use bzip2::read::BzDecoder;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use bzip2::read::{BzDecoder, MultiBzDecoder};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
 use indicatif::{ProgressBar, ProgressStyle};
 use xml::reader::{EventReader, XmlEvent};
 use html_escape::decode_html_entities;
+use serde_json::json;
+use tracing::warn;
+use crate::config::config;
+use crate::namespaces::is_ignored;
+use crate::storage;
+pub use crate::links::PageId;
 
-pub const IGNORE: [&str; 7] = ["Category:", "Wikipedia:", "File:", "Template:", "Draft:", "Portal:", "Module:"];
 const PROGRESS_TEMPLATE_BYTES: &str = "{msg}: {percent}% {bar:40.cyan/blue} {bytes}/{total_bytes} [{elapsed_precise}>{eta_precise}]";
 const PROGRESS_TEMPLATE_RAW: &str = "{msg}: {percent}% {bar:40.cyan/blue} {pos}/{len} [{elapsed_precise}>{eta_precise}]";
 
-struct ProgressReader<R: Read> { inner: R, progress_bar: ProgressBar }
+// Command line arguments are positionals followed by `--flag` or `--flag value` options, plus repeatable
+// single-letter switches like `-v` or `-vv`
+pub struct Args { pub positional: Vec<String>, flags: HashMap<String, Option<String>>, switches: HashMap<char, usize> }
+impl Args {
+    pub fn parse(args: &[String]) -> Self {
+        let mut positional = Vec::new();
+        let mut flags = HashMap::new();
+        let mut switches = HashMap::new();
+        let mut iter = args.iter().peekable();
+        let is_switch = |arg: &str| arg.strip_prefix('-').is_some_and(|letters| !letters.is_empty() && letters.chars().all(|c| c.is_ascii_alphabetic()));
+        while let Some(arg) = iter.next() {
+            if is_switch(arg) {
+                let letters = &arg[1..];
+                for letter in letters.chars() {
+                    *switches.entry(letter).or_insert(0) += 1;
+                }
+            } else if let Some(flag) = arg.strip_prefix("--") {
+                match flag.split_once('=') {
+                    Some((name, value)) => { flags.insert(name.to_string(), Some(value.to_string())); }
+                    None => {
+                        let value = iter.next_if(|next| !next.starts_with("--") && !is_switch(next)).cloned();
+                        flags.insert(flag.to_string(), value);
+                    }
+                }
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Args { positional, flags, switches }
+    }
+
+    // Number of times a single-letter switch was given
+    pub fn count(&self, switch: char) -> usize {
+        self.switches.get(&switch).copied().unwrap_or(0)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).and_then(|value| value.as_deref())
+    }
+
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.value(name).map(|value| value.parse().unwrap_or_else(|_| {
+            eprintln!("Error: Invalid value for --{}: {}", name, value);
+            std::process::exit(1);
+        }))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum OutputCompression { None, Zstd, Gzip }
+impl OutputCompression {
+    pub fn from_args(args: &Args) -> Self {
+        match args.value("compress") {
+            None => OutputCompression::None,
+            Some("zstd") => OutputCompression::Zstd,
+            Some("gzip") => OutputCompression::Gzip,
+            Some(other) => {
+                eprintln!("Error: Unknown compression {} (expected zstd or gzip)", other);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn from_path(file_path: &Path) -> Self {
+        match file_path.extension().and_then(|ext| ext.to_str()) {
+            Some("zst" | "zstd") => OutputCompression::Zstd,
+            Some("gz") => OutputCompression::Gzip,
+            _ => OutputCompression::None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputCompression::None => "",
+            OutputCompression::Zstd => ".zst",
+            OutputCompression::Gzip => ".gz",
+        }
+    }
+
+    // The compressed stream is finalized when the returned writer is dropped
+    pub fn wrap<W: Write + Send + 'static>(&self, writer: W) -> Box<dyn Write + Send> {
+        match self {
+            OutputCompression::None => Box::new(writer),
+            OutputCompression::Zstd => Box::new(zstd::Encoder::new(writer, 0).expect("Failed to create zstd encoder").auto_finish()),
+            OutputCompression::Gzip => Box::new(GzEncoder::new(writer, flate2::Compression::default())),
+        }
+    }
+
+    // Creates `file_path` with the compression extension appended
+    pub fn create(&self, file_path: &Path) -> Box<dyn Write + Send> {
+        let mut file_name = file_path.file_name().unwrap().to_os_string();
+        file_name.push(self.extension());
+        self.wrap(BufWriter::new(File::create(file_path.with_file_name(file_name)).expect("Failed to create output file")))
+    }
+}
+
+pub struct ProgressReader<R: Read> { inner: R, progress_bar: ProgressBar }
 impl<R: Read> ProgressReader<R> {
-    fn new(inner: R, progress_bar: ProgressBar) -> Self {
+    pub fn new(inner: R, progress_bar: ProgressBar) -> Self {
         ProgressReader { inner, progress_bar: progress_bar.with_style(get_progress_style(PROGRESS_TEMPLATE_BYTES)) }
     }
 }
@@ -27,6 +140,46 @@ impl<R: Read> Read for ProgressReader<R> {
     }
 }
 
+// Opens a dump that's read from start to end, like a history, stub or abstract dump, decompressing it according to its
+// extension and showing progress through the file
+pub fn open_dump_stream(file_path: &Path, message: &str) -> Box<dyn Read> {
+    let file = File::open(file_path).unwrap_or_else(|err| {
+        eprintln!("Error: Unable to open {}: {}", file_path.to_str().unwrap(), err);
+        std::process::exit(1);
+    });
+    let file_size = file.metadata().expect("Unable to get file metadata").len();
+    let reader = ProgressReader::new(file, create_progress_bar(file_size, message));
+    match file_path.extension().and_then(|ext| ext.to_str()) {
+        Some("bz2") => Box::new(MultiBzDecoder::new(reader)),
+        Some("gz") => Box::new(MultiGzDecoder::new(reader)),
+        Some("zst") => Box::new(zstd::Decoder::new(reader).expect("Failed to create zstd decoder")),
+        _ => Box::new(reader),
+    }
+}
+
+// Set by the first Ctrl-C or SIGTERM once handle_interrupts has been called, so long-running commands can stop
+// taking new work and save their progress. A second signal exits immediately.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn handle_interrupts() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        warn!("Interrupted, finishing the chunks in progress (press Ctrl-C again to stop immediately)");
+    }).expect("Failed to install signal handler");
+}
+
+pub fn hash_file(file_path: &Path) -> String {
+    let file = File::open(file_path).expect("Unable to open file for hashing");
+    let file_size = file.metadata().expect("Unable to get file metadata").len();
+    let file_name = file_path.file_name().unwrap().to_str().unwrap();
+    let mut reader = ProgressReader::new(file, create_progress_bar(file_size, &format!("Hashing {}", file_name)));
+    let mut context = md5::Context::new();
+    std::io::copy(&mut reader, &mut context).expect("Failed to read file for hashing");
+    format!("{:x}", context.finalize())
+}
+
 fn get_progress_style(template: &str) -> ProgressStyle {
     ProgressStyle::default_bar()
         .progress_chars("##-")
@@ -34,15 +187,246 @@ fn get_progress_style(template: &str) -> ProgressStyle {
         .unwrap()
 }
 
+// Cleared by --no-progress and -q, and when stderr isn't a terminal
+pub static PROGRESS_ENABLED: AtomicBool = AtomicBool::new(true);
+// Set by --progress json, which replaces the bars with periodic JSON events on stderr
+pub static PROGRESS_JSON: AtomicBool = AtomicBool::new(false);
+// Running count of articles parsed by load_chunk, used for the articles/sec figure in JSON progress events
+pub static ARTICLES_LOADED: AtomicU64 = AtomicU64::new(0);
+const PROGRESS_JSON_INTERVAL: Duration = Duration::from_secs(1);
+static PROGRESS_REPORTERS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+static PROGRESS_REPORTERS_STOP: AtomicBool = AtomicBool::new(false);
+
+fn emit_progress_event(event: &str, progress_bar: &ProgressBar, articles: u64) {
+    let elapsed = progress_bar.elapsed().as_secs_f64();
+    let total = progress_bar.length();
+    let done = progress_bar.position();
+    let eta = total.filter(|&total| done < total && done > 0).map(|_| progress_bar.eta().as_secs_f64());
+    let event = json!({
+        "event": event,
+        "stage": progress_bar.message(),
+        "done": done,
+        "total": total,
+        "elapsed_secs": elapsed,
+        "articles": articles,
+        "articles_per_sec": if elapsed > 0.0 { articles as f64 / elapsed } else { 0.0 },
+        "eta_secs": eta,
+    });
+    eprintln!("{}", event);
+}
+
+// Watches a hidden progress bar from a background thread, reporting its state every interval and once more
+// when it finishes. The thread exits when the bar is finished or the command returns.
+fn report_json_progress(progress_bar: &ProgressBar) {
+    let progress_bar = progress_bar.clone();
+    let articles_at_start = ARTICLES_LOADED.load(Ordering::Relaxed);
+    let reporter = std::thread::spawn(move || {
+        let poll_interval = PROGRESS_JSON_INTERVAL / 10;
+        let mut since_last_event = Duration::ZERO;
+        loop {
+            std::thread::sleep(poll_interval);
+            since_last_event += poll_interval;
+            let articles = ARTICLES_LOADED.load(Ordering::Relaxed) - articles_at_start;
+            if progress_bar.is_finished() || PROGRESS_REPORTERS_STOP.load(Ordering::Relaxed) {
+                emit_progress_event("finished", &progress_bar, articles);
+                break;
+            }
+            if since_last_event >= PROGRESS_JSON_INTERVAL {
+                emit_progress_event("progress", &progress_bar, articles);
+                since_last_event = Duration::ZERO;
+            }
+        }
+    });
+    PROGRESS_REPORTERS.lock().unwrap().push(reporter);
+}
+
+// Lets the JSON progress reporters emit their final events before the process exits
+pub fn wait_for_progress_reporters() {
+    PROGRESS_REPORTERS_STOP.store(true, Ordering::Relaxed);
+    for reporter in PROGRESS_REPORTERS.lock().unwrap().drain(..) {
+        reporter.join().expect("Progress reporter panicked");
+    }
+}
+
 pub fn create_progress_bar(total: u64, message: &str) -> ProgressBar {
+    if PROGRESS_JSON.load(Ordering::Relaxed) {
+        let progress_bar = ProgressBar::hidden().with_message(message.to_owned());
+        progress_bar.set_length(total);
+        report_json_progress(&progress_bar);
+        return progress_bar;
+    }
+    if !PROGRESS_ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
     ProgressBar::new(total)
         .with_style(get_progress_style(PROGRESS_TEMPLATE_RAW))
         .with_message(message.to_owned())
 }
 
-pub fn load_index(file_path: &str) -> HashMap<u64, Vec<(u32, String)>> {
+// A progress bar counting bytes, for reads that don't go through a ProgressReader
+pub fn create_bytes_progress_bar(total: u64, message: &str) -> ProgressBar {
+    create_progress_bar(total, message).with_style(get_progress_style(PROGRESS_TEMPLATE_BYTES))
+}
+
+// Frame magic number of a zstd chunk, as written by `recompress`
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// Paths of the seekable zstd dump written by `recompress` and its index
+pub fn get_zstd_dump_paths(data_path: &Path) -> (PathBuf, PathBuf) {
+    let index_path = data_path.join(format!("{}-pages-articles-multistream-index-zstd.txt", config().dump_prefix));
+    let articles_path = data_path.join(format!("{}-pages-articles-multistream.xml.zst", config().dump_prefix));
+    (index_path, articles_path)
+}
+
+pub fn get_dump_paths(data_path: &Path) -> (PathBuf, PathBuf) {
+    let index_path = data_path.join(format!("{}-pages-articles-multistream-index.txt.bz2", config().dump_prefix));
+    let articles_path = data_path.join(format!("{}-pages-articles-multistream.xml.bz2", config().dump_prefix));
+    (index_path, articles_path)
+}
+
+// Returns the length of the `open`...`close` block at the start of `text`, accounting for nesting, or the length of
+// the whole text if it's never closed
+pub fn skip_nested(text: &str, open: &str, close: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else if text[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 { break; }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    i
+}
+
+// Pages per chunk when indexing an uncompressed dump, the same as in the multistream dump
+const XML_CHUNK_PAGES: usize = 100;
+
+// Writes an index in the multistream index format (offset:id:title) for an uncompressed XML dump, where each offset
+// is the byte position in the XML of the first page in a chunk. The dump has one tag per line and escapes markup in
+// page text, so the <page>, <title> and first <id> lines are enough to find each page.
+fn build_xml_index(xml_path: &Path, index_path: &Path) {
+    let file = File::open(xml_path).expect("Unable to open XML dump");
+    let file_size = file.metadata().expect("Unable to get file metadata").len();
+    let mut reader = BufReader::new(ProgressReader::new(file, create_progress_bar(file_size, "Indexing XML dump")));
+    let mut index_file = BufWriter::new(File::create(index_path).expect("Unable to create XML index"));
+    let mut line = String::new();
+    let mut offset = 0;
+    let mut pages = 0;
+    let mut chunk_offset = 0;
+    let mut title = None;
+    let mut in_revision = false;
+    loop {
+        line.clear();
+        let length = reader.read_line(&mut line).expect("Failed to read XML dump");
+        if length == 0 { break; }
+        let tag = line.trim();
+        if tag == "<page>" {
+            if pages % XML_CHUNK_PAGES == 0 {
+                chunk_offset = offset;
+            }
+            pages += 1;
+            title = None;
+            in_revision = false;
+        } else if tag == "<revision>" {
+            in_revision = true;
+        } else if let Some(page_title) = tag.strip_prefix("<title>").and_then(|tag| tag.strip_suffix("</title>")) {
+            title = Some(page_title.to_string());
+        } else if let Some(id) = tag.strip_prefix("<id>").and_then(|tag| tag.strip_suffix("</id>")).filter(|_| !in_revision) {
+            if let Some(title) = title.take() {
+                writeln!(index_file, "{}:{}:{}", chunk_offset, id, title).expect("Failed to write XML index");
+            }
+        }
+        offset += length as u64;
+    }
+    index_file.flush().expect("Failed to flush XML index");
+}
+
+// Whether the articles path is the URL of a remote dump, which is read with range requests
+pub fn is_remote(articles_path: &Path) -> bool {
+    articles_path.to_str().is_some_and(storage::is_url)
+}
+
+fn remote_error(url: &str, err: String) -> ! {
+    eprintln!("Error: Failed to fetch {}: {}", url, err);
+    std::process::exit(1);
+}
+
+// Downloads the index of the dump at `dump_url` into data_path, unless it's already there, and returns the index path
+// and the URL of the articles file
+fn locate_remote_dump(data_path: &Path, dump_url: &str) -> (PathBuf, PathBuf) {
+    let (index_path, articles_path) = get_dump_paths(data_path);
+    let dump_url = dump_url.trim_end_matches('/');
+    let file_name = |path: &Path| path.file_name().unwrap().to_str().unwrap().to_string();
+    if !index_path.exists() && !index_path.with_extension("").exists() {
+        let remote = storage::open(dump_url);
+        let (mut reader, length) = remote.reader(&file_name(&index_path)).unwrap_or_else(|err| remote_error(dump_url, err));
+        std::fs::create_dir_all(data_path).expect("Failed to create data directory");
+        let partial_path = index_path.with_extension("bz2.partial");
+        let mut index_file = File::create(&partial_path).expect("Failed to create index file");
+        let progress_bar = create_progress_bar(length.unwrap_or(0), "Downloading index");
+        std::io::copy(&mut ProgressReader::new(&mut reader, progress_bar), &mut index_file).unwrap_or_else(|err| remote_error(dump_url, err.to_string()));
+        std::fs::rename(&partial_path, &index_path).expect("Failed to rename index file");
+    }
+    (index_path, PathBuf::from(format!("{}/{}", dump_url, file_name(&articles_path))))
+}
+
+// Returns the size of the articles file, asking the server for it if the dump is remote
+pub fn dump_size(articles_path: &Path) -> u64 {
+    let location = articles_path.to_str().unwrap();
+    let (storage, name) = storage::open_object(location);
+    storage.size(&name).unwrap_or_else(|err| {
+        eprintln!("Error: Failed to get the size of {}: {}", location, err);
+        std::process::exit(1);
+    })
+}
+
+// Returns the index and articles paths, preferring the fastest dump to read: an uncompressed XML dump when one has
+// been extracted, then a zstd dump from `recompress`, then the bz2 dump. An XML dump's index is built on first use and
+// rebuilt whenever the XML is newer.
+pub fn locate_dump_files(data_path: &Path) -> (PathBuf, PathBuf) {
+    find_dump_files(data_path).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
+
+// Like locate_dump_files, but returns an error rather than exiting when a local dump is missing, for callers embedded in
+// another process
+pub fn find_dump_files(data_path: &Path) -> Result<(PathBuf, PathBuf), String> {
+    if let Some(dump_url) = &config().dump_url {
+        return Ok(locate_remote_dump(data_path, dump_url));
+    }
+    let xml_paths = ["pages-articles-multistream.xml", "pages-articles.xml"].map(|name| data_path.join(format!("{}-{}", config().dump_prefix, name)));
+    if let Some(xml_path) = xml_paths.into_iter().find(|xml_path| xml_path.exists()) {
+        let index_path = xml_path.with_extension("xml.index.txt");
+        let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified()).ok();
+        if !index_path.exists() || modified(&index_path) < modified(&xml_path) {
+            build_xml_index(&xml_path, &index_path);
+        }
+        return Ok((index_path, xml_path));
+    }
+    let (index_path, articles_path) = get_zstd_dump_paths(data_path);
+    if index_path.exists() && articles_path.exists() {
+        return Ok((index_path, articles_path));
+    }
+
+    let (index_path, articles_path) = get_dump_paths(data_path);
+    if !index_path.exists() || !articles_path.exists() {
+        return Err(format!("Unable to locate data files in {}", data_path.to_str().unwrap()));
+    }
+    Ok((index_path, articles_path))
+}
+
+// Returns the path of the index as text, decompressing a bz2 index next to it the first time
+pub fn decompress_index(file_path: &str) -> PathBuf {
     let bz2_path = Path::new(file_path);
-    let decompressed_path = bz2_path.with_extension("");
+    let decompressed_path = if bz2_path.extension().is_some_and(|extension| extension == "bz2") { bz2_path.with_extension("") } else { bz2_path.to_path_buf() };
 
     // Decompress the file if it doesn't exist
     if !decompressed_path.exists() {
@@ -54,6 +438,11 @@ pub fn load_index(file_path: &str) -> HashMap<u64, Vec<(u32, String)>> {
         let mut decompressed_file = File::create(&decompressed_path).expect("Unable to create decompressed file");
         std::io::copy(&mut BufReader::new(decoder), &mut decompressed_file).expect("Failed to decompress the file");
     }
+    decompressed_path
+}
+
+pub fn load_index(file_path: &str) -> HashMap<u64, Vec<(PageId, String)>> {
+    let decompressed_path = decompress_index(file_path);
 
     // Read from the decompressed file
     let file = File::open(&decompressed_path).expect("Unable to open decompressed file");
@@ -61,89 +450,250 @@ pub fn load_index(file_path: &str) -> HashMap<u64, Vec<(u32, String)>> {
     let progress_bar = create_progress_bar(file_size, "Loading index");
     let reader = BufReader::new(ProgressReader::new(file, progress_bar));
 
-    let mut seek_position_map: HashMap<u64, Vec<(u32, String)>> = HashMap::new();
-    for line in reader.lines().filter_map(Result::ok) {
+    let mut seek_position_map: HashMap<u64, Vec<(PageId, String)>> = HashMap::new();
+    for line in reader.lines().map_while(Result::ok) {
         let parts: Vec<&str> = line.splitn(3, ':').collect();
         if parts.len() != 3 { continue; }
 
         let seek_position = parts[0].parse::<u64>().unwrap();
-        let article_id = parts[1].parse::<u32>().unwrap();
+        let article_id = parts[1].parse::<PageId>().unwrap();
         let article_title = decode_html_entities(parts[2]).to_string();
-        if IGNORE.iter().any(|prefix| article_title.starts_with(prefix)) { continue; }
+        if is_ignored(&article_title) { continue; }
 
         seek_position_map
             .entry(seek_position)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push((article_id, article_title));
     }
 
     seek_position_map
 }
 
-pub fn load_chunk(file_path: &str, start_position: u64, end_position: u64) -> HashMap<u32, (String, String)> {  // id -> (title, content)
-    let chunk_size = (end_position - start_position) as usize;
-    let mut buffer = vec![0u8; chunk_size];
-    let mut file = File::open(file_path).expect("Unable to open file");
-    file.seek(SeekFrom::Start(start_position)).expect("Failed to seek to the position");
-    file.read_exact(&mut buffer).expect("Error reading from the file");
+// Returns (chunk_index, start_position, end_position) for each bz2 chunk in the articles file, restricted to
+// the chunks starting inside `--byte-range START-END` and then to the first `--limit N` of those
+pub fn get_chunk_ranges(seek_position_map: &HashMap<u64, Vec<(PageId, String)>>, articles_path: &Path, args: &Args) -> Vec<(usize, u64, u64)> {
+    let mut positions: Vec<u64> = seek_position_map.keys().copied().collect();
+    positions.push(dump_size(articles_path));
+    positions.sort_unstable();
+
+    let (range_start, range_end) = match args.value("byte-range") {
+        Some(range) => range.split_once('-')
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+            .unwrap_or_else(|| {
+                eprintln!("Error: Invalid value for --byte-range: {} (expected START-END)", range);
+                std::process::exit(1);
+            }),
+        None => (0, u64::MAX),
+    };
+    let limit = args.parse_value("limit").unwrap_or(usize::MAX);
+
+    positions.windows(2)
+        .enumerate()
+        .map(|(chunk_index, window)| (chunk_index, window[0], window[1]))
+        .filter(|&(_, start_position, _)| start_position >= range_start && start_position < range_end)
+        .take(limit)
+        .collect()
+}
+
+pub struct Page {
+    pub title: String,
+    pub text: String,
+    pub namespace: u32,
+    pub redirect: bool,
+    pub redirect_target: Option<String>,
+    pub timestamp: String,  // of the latest revision
+}
+
+pub fn load_chunk(file_path: &str, start_position: u64, end_position: u64) -> HashMap<PageId, (String, String)> {  // id -> (title, content)
+    load_chunk_pages(file_path, start_position, end_position).into_iter()
+        .map(|(id, page)| (id, (page.title, page.text)))
+        .collect()
+}
+
+pub fn read_chunk_bytes(file_path: &str, start_position: u64, end_position: u64) -> Vec<u8> {
+    let (storage, name) = storage::open_object(file_path);
+    let read = || storage.read_range(&name, start_position, end_position);
+    let result = if is_remote(Path::new(file_path)) {
+        storage::retry(&format!("Reading bytes {}-{} of {}", start_position, end_position, file_path), read)
+    } else {
+        read()
+    };
+    result.unwrap_or_else(|err| panic!("Error reading bytes {}-{} of {}: {}", start_position, end_position, file_path, err))
+}
+
+// Size of the decompressed blocks passed from the bz2 decoder to the XML parser, and how many can be waiting
+const PIPELINE_BLOCK_SIZE: usize = 64 * 1024;
+const PIPELINE_DEPTH: usize = 16;
+
+// Reads the blocks a decoder thread sends through a channel
+struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    block: Vec<u8>,
+    offset: usize,
+}
 
-    let mut decoder = BzDecoder::new(&buffer[..]);
-    let mut decompressed_data = Vec::new();
-    decoder.read_to_end(&mut decompressed_data).expect("Error during decompression");
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.offset == self.block.len() {
+            match self.receiver.recv() {
+                Ok(block) => { self.block = block; self.offset = 0; }
+                Err(_) => return Ok(0),
+            }
+        }
+        let length = buf.len().min(self.block.len() - self.offset);
+        buf[..length].copy_from_slice(&self.block[self.offset..self.offset + length]);
+        self.offset += length;
+        Ok(length)
+    }
+}
+
+// Decompresses a bz2 chunk on its own thread and returns a reader over the output, so the XML parser can start on the
+// first pages while the rest of the chunk is still being decompressed. The decoder thread's result is an error if the
+// chunk was corrupt.
+fn pipeline_bz2(buffer: Vec<u8>) -> (ChannelReader, JoinHandle<std::io::Result<()>>) {
+    let (sender, receiver) = mpsc::sync_channel(PIPELINE_DEPTH);
+    let decoder = std::thread::spawn(move || {
+        let mut decoder = BzDecoder::new(&buffer[..]);
+        loop {
+            let mut block = vec![0u8; PIPELINE_BLOCK_SIZE];
+            let length = decoder.read(&mut block)?;
+            if length == 0 { return Ok(()); }
+            block.truncate(length);
+            // The parser stops early on malformed XML, and then there's no one left to decompress for
+            if sender.send(block).is_err() { return Ok(()); }
+        }
+    });
+    (ChannelReader { receiver, block: Vec::new(), offset: 0 }, decoder)
+}
 
-    let xml_text = String::from_utf8(decompressed_data).expect("Failed to convert decompressed bytes to UTF-8");
-    let parser = EventReader::new(xml_text.as_bytes());
+// Returns the XML of a chunk, decompressing it if it's a bz2 stream or a zstd frame. Chunks of an uncompressed dump
+// are already XML.
+pub fn load_chunk_xml(file_path: &str, start_position: u64, end_position: u64) -> Vec<u8> {
+    decompress_chunk(read_chunk_bytes(file_path, start_position, end_position))
+}
+
+pub fn decompress_chunk(buffer: Vec<u8>) -> Vec<u8> {
+    if buffer.starts_with(b"BZh") {
+        let mut decompressed_data = Vec::new();
+        BzDecoder::new(&buffer[..]).read_to_end(&mut decompressed_data).expect("Error during decompression");
+        decompressed_data
+    } else if buffer.starts_with(&ZSTD_MAGIC) {
+        zstd::decode_all(&buffer[..]).expect("Error during decompression")
+    } else {
+        buffer
+    }
+}
+
+// Like load_chunk, but also returns each page's namespace, redirect target and latest revision timestamp
+pub fn load_chunk_pages(file_path: &str, start_position: u64, end_position: u64) -> HashMap<PageId, Page> {
+    parse_chunk_bytes(read_chunk_bytes(file_path, start_position, end_position), start_position)
+}
+
+// Decompresses and parses a chunk that's already been read, for pipelines that read chunks on a separate thread
+pub fn parse_chunk_bytes(buffer: Vec<u8>, start_position: u64) -> HashMap<PageId, Page> {
+    let xml_text = String::from_utf8(decompress_chunk(buffer)).expect("Failed to convert decompressed bytes to UTF-8");
+    let articles = parse_pages(xml_text.as_bytes(), start_position);
+    ARTICLES_LOADED.fetch_add(articles.len() as u64, Ordering::Relaxed);
+    articles
+}
+
+// Like load_chunk, but for loading a single chunk as fast as possible rather than many chunks at once: bz2 chunks are
+// decompressed on a separate thread while they're parsed. Streaming through a channel costs about a fifth more CPU
+// than decompressing up front, so commands that already keep every core busy with whole chunks should use load_chunk.
+pub fn load_chunk_pipelined(file_path: &str, start_position: u64, end_position: u64) -> HashMap<PageId, (String, String)> {
+    let buffer = read_chunk_bytes(file_path, start_position, end_position);
+    let articles = if buffer.starts_with(b"BZh") {
+        let (reader, decoder) = pipeline_bz2(buffer);
+        let articles = parse_pages(BufReader::new(reader), start_position);
+        decoder.join().expect("Decoder thread panicked").expect("Error during decompression");
+        articles
+    } else {
+        parse_pages(&decompress_chunk(buffer)[..], start_position)
+    };
+    ARTICLES_LOADED.fetch_add(articles.len() as u64, Ordering::Relaxed);
+    articles.into_iter().map(|(id, page)| (id, (page.title, page.text))).collect()
+}
+
+pub fn parse_pages<R: Read>(reader: R, start_position: u64) -> HashMap<PageId, Page> {
+    let parser = EventReader::new(reader);
     let mut articles = HashMap::new();
     let mut in_page = false;
     let mut in_title = false;
     let mut in_text = false;
     let mut in_id = false;
+    let mut in_ns = false;
+    let mut in_timestamp = false;
     let mut current_title = String::new();
     let mut current_text = String::new();
     let mut current_id = 0;
+    let mut current_namespace = 0;
+    let mut current_redirect = false;
+    let mut current_redirect_target = None;
+    let mut current_timestamp = String::new();
 
     for event in parser {
         match event {
-            Ok(XmlEvent::StartElement { name, .. }) => {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
                 match name.local_name.as_str() {
                     "page" => in_page = true,
                     "title" => in_title = true,
                     "text" => in_text = true,
                     "id" if in_page && current_id == 0 => in_id = true,
+                    "ns" => in_ns = true,
+                    "timestamp" => in_timestamp = true,
+                    "redirect" => {
+                        current_redirect = true;
+                        current_redirect_target = attributes.into_iter().find(|attribute| attribute.name.local_name == "title").map(|attribute| attribute.value);
+                    }
                     _ => {}
                 }
             }
             Ok(XmlEvent::EndElement { name, .. }) => {
                 match name.local_name.as_str() {
                     "page" => {
-                        if !IGNORE.iter().any(|prefix| current_title.starts_with(prefix)) {
-                            articles.insert(current_id, (current_title.clone(), current_text.clone()));
+                        if !is_ignored(&current_title) {
+                            let page = Page {
+                                title: current_title.clone(), text: current_text.clone(), namespace: current_namespace, redirect: current_redirect,
+                                redirect_target: current_redirect_target.take(), timestamp: current_timestamp.clone(),
+                            };
+                            articles.insert(current_id, page);
                         }
                         current_title.clear();
                         current_text.clear();
+                        current_timestamp.clear();
+                        current_redirect_target = None;
                         current_id = 0;
+                        current_namespace = 0;
+                        current_redirect = false;
                         in_page = false;
                     }
                     "title" => in_title = false,
                     "text" => in_text = false,
                     "id" => in_id = false,
+                    "ns" => in_ns = false,
+                    "timestamp" => in_timestamp = false,
                     _ => {}
                 }
             }
-            Ok(XmlEvent::Characters(text)) => {
-                if in_page {
-                    if in_title {
-                        current_title.push_str(&text);
-                    } else if in_text {
-                        current_text.push_str(&text);
-                    } else if in_id {
-                        current_id = text.parse().unwrap_or(0);
-                    }
+            Ok(XmlEvent::Characters(text)) if in_page => {
+                if in_title {
+                    current_title.push_str(&text);
+                } else if in_text {
+                    current_text.push_str(&text);
+                } else if in_id {
+                    current_id = text.parse().unwrap_or(0);
+                } else if in_ns {
+                    current_namespace = text.parse().unwrap_or(0);
+                } else if in_timestamp {
+                    current_timestamp.push_str(&text);
                 }
             }
+            Err(err) => {
+                warn!("XML parse error in chunk at byte {}, skipping the rest of the chunk: {}", start_position, err);
+                break;
+            }
             _ => {}
         }
     }
-
     articles
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::io::BufReader;
+use xml::reader::{EventReader, XmlEvent};
+use crate::namespaces::is_ignored;
+use crate::helpers::{Args, PageId, open_dump_stream};
+
+pub struct Revision {
+    pub id: u64,
+    pub timestamp: String,
+    pub contributor: Option<String>,  // username or IP, None if the contributor was deleted
+    pub text: String,
+}
+
+// Streams every revision of every page in a pages-meta-history dump, calling `visit(page_id, title, revision)`.
+// Text is only collected when `with_text` is set, since the full history of a page can run to gigabytes.
+pub fn for_each_revision<F: FnMut(PageId, &str, &Revision)>(file_path: &Path, with_text: bool, mut visit: F) {
+    let parser = EventReader::new(BufReader::new(open_dump_stream(file_path, "Reading revisions")));
+
+    let mut path: Vec<String> = Vec::new();
+    let mut page_id = 0;
+    let mut page_title = String::new();
+    let mut revision = Revision { id: 0, timestamp: String::new(), contributor: None, text: String::new() };
+
+    for event in parser {
+        match event {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                if name.local_name == "revision" {
+                    revision = Revision { id: 0, timestamp: String::new(), contributor: None, text: String::new() };
+                }
+                path.push(name.local_name);
+            }
+            Ok(XmlEvent::EndElement { name, .. }) => {
+                path.pop();
+                match name.local_name.as_str() {
+                    "revision" if !is_ignored(&page_title) => visit(page_id, &page_title, &revision),
+                    "page" => {
+                        page_id = 0;
+                        page_title.clear();
+                    }
+                    _ => {}
+                }
+            }
+            Ok(XmlEvent::Characters(text)) => {
+                let parent = path.len().checked_sub(2).map(|i| path[i].as_str());
+                match (parent, path.last().map(String::as_str)) {
+                    (Some("page"), Some("title")) => page_title.push_str(&text),
+                    (Some("page"), Some("id")) => page_id = text.parse().unwrap_or(0),
+                    (Some("revision"), Some("id")) => revision.id = text.parse().unwrap_or(0),
+                    (Some("revision"), Some("timestamp")) => revision.timestamp.push_str(&text),
+                    (Some("revision"), Some("text")) if with_text => revision.text.push_str(&text),
+                    (Some("contributor"), Some("username" | "ip")) => revision.contributor.get_or_insert_with(String::new).push_str(&text),
+                    _ => {}
+                }
+            }
+            Err(err) => {
+                eprintln!("Error: Failed to parse history dump: {}", err);
+                std::process::exit(1);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn edit_counts(file_path: &Path, top: usize) {
+    let mut edits: HashMap<PageId, (String, usize)> = HashMap::new();
+    for_each_revision(file_path, false, |page_id, title, _| {
+        edits.entry(page_id).or_insert_with(|| (title.to_string(), 0)).1 += 1;
+    });
+
+    let total_edits: usize = edits.values().map(|(_, count)| count).sum();
+    let mut edits = edits.into_values().collect::<Vec<_>>();
+    edits.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    println!("Total articles: {}", edits.len());
+    println!("Total revisions: {}", total_edits);
+    println!("Average revisions per article: {:.2}", total_edits as f64 / edits.len().max(1) as f64);
+    println!("\nTop {} most edited articles:", top);
+    for (rank, (title, count)) in edits.iter().take(top).enumerate() {
+        println!("{:>2}) {} ({})", rank + 1, title, count);
+    }
+}
+
+fn editor_stats(file_path: &Path, top: usize) {
+    let mut editors: HashMap<String, usize> = HashMap::new();
+    let mut deleted_contributor_edits = 0;
+    for_each_revision(file_path, false, |_, _, revision| {
+        match &revision.contributor {
+            Some(contributor) => *editors.entry(contributor.clone()).or_insert(0) += 1,
+            None => deleted_contributor_edits += 1,
+        }
+    });
+
+    let total_edits: usize = editors.values().sum::<usize>() + deleted_contributor_edits;
+    let mut editors = editors.into_iter().collect::<Vec<_>>();
+    editors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    println!("Total revisions: {}", total_edits);
+    println!("Distinct editors: {}", editors.len());
+    println!("Revisions with deleted contributors: {}", deleted_contributor_edits);
+    println!("\nTop {} most active editors:", top);
+    for (rank, (editor, count)) in editors.iter().take(top).enumerate() {
+        println!("{:>2}) {} ({})", rank + 1, editor, count);
+    }
+}
+
+// Prints the revision with the given ID, or the latest revision at or before the given ISO 8601 timestamp
+fn extract_revision(file_path: &Path, title: &str, at: &str) {
+    let revision_id = at.parse::<u64>().ok();
+    let mut found: Option<(u64, String, String)> = None;
+    for_each_revision(file_path, true, |_, page_title, revision| {
+        if !page_title.eq_ignore_ascii_case(title) { return; }
+        let matches = match revision_id {
+            Some(id) => revision.id == id,
+            None => revision.timestamp.as_str() <= at && found.as_ref().is_none_or(|(_, timestamp, _)| revision.timestamp >= *timestamp),
+        };
+        if matches {
+            found = Some((revision.id, revision.timestamp.clone(), revision.text.clone()));
+        }
+    });
+
+    match found {
+        Some((id, timestamp, text)) => println!("Revision {} ({})\n\n{}", id, timestamp, text),
+        None => {
+            eprintln!("Error: No revision of {} found matching {}", title, at);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn history(args: &Args) {
+    let (file_path, mode) = match &args.positional[..] {
+        [file_path, mode, ..] => (Path::new(file_path), mode.as_str()),
+        _ => {
+            eprintln!("Usage: history <dump_file> <edits|editors|revision <title> <revision_id|timestamp>> [--top N]");
+            std::process::exit(1);
+        }
+    };
+    if !file_path.exists() {
+        eprintln!("Error: Unable to locate history dump {}", file_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+
+    let top = args.parse_value("top").unwrap_or(10);
+    match (mode, &args.positional[2..]) {
+        ("edits", _) => edit_counts(file_path, top),
+        ("editors", _) => editor_stats(file_path, top),
+        ("revision", [title, at, ..]) => extract_revision(file_path, title, at),
+        _ => {
+            eprintln!("Error: Unknown history mode: {}", mode);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rustc_hash::FxHashSet;
+use crate::embed::{EMBEDDINGS_FILE, PASSAGES_FILE, EmbeddingClient, Embeddings, read_embeddings};
+use crate::helpers::{Args, create_progress_bar};
+use crate::links::{read_links_file, read_varint, write_varint};
+
+// hnsw.bin holds the HNSW graph over the vectors in embeddings.bin. It starts with MAGIC, a little-endian u32
+// version and the u32 entry point, followed by a record per vector of its top layer and, for each layer from 0 up,
+// its neighbor count and neighbors. Every integer after the header is a LEB128 varint.
+pub const HNSW_FILE: &str = "hnsw.bin";
+const MAGIC: &[u8; 4] = b"WKHN";
+const VERSION: u32 = 1;
+const MAX_NEIGHBORS: usize = 16;  // per node on the upper layers, and twice this on layer 0
+
+// A node and its distance from the query, ordered by distance
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate(f32, u32);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+// Vectors are unit length, so cosine distance is one minus the dot product
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0 - a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>()
+}
+
+// A hierarchical navigable small world graph (Malkov and Yashunin), where neighbors[node][layer] lists the node's
+// neighbors on each layer up to its top one
+pub struct Hnsw {
+    entry: u32,
+    neighbors: Vec<Vec<Vec<u32>>>,
+}
+
+impl Hnsw {
+    fn top_layer(&self) -> usize {
+        self.neighbors.get(self.entry as usize).map_or(0, |layers| layers.len() - 1)
+    }
+
+    // Best-first search of one layer, returning up to `ef` of the nearest nodes found, nearest first
+    fn search_layer(&self, embeddings: &Embeddings, query: &[f32], entry_points: &[Candidate], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: FxHashSet<u32> = entry_points.iter().map(|candidate| candidate.1).collect();
+        let mut frontier: BinaryHeap<std::cmp::Reverse<Candidate>> = entry_points.iter().map(|&candidate| std::cmp::Reverse(candidate)).collect();
+        let mut nearest: BinaryHeap<Candidate> = entry_points.iter().copied().collect();
+        while let Some(std::cmp::Reverse(candidate)) = frontier.pop() {
+            if nearest.len() >= ef && candidate.0 > nearest.peek().unwrap().0 { break; }
+            for &neighbor in &self.neighbors[candidate.1 as usize][layer] {
+                if !visited.insert(neighbor) { continue; }
+                let neighbor = Candidate(distance(query, embeddings.vector(neighbor as usize)), neighbor);
+                if nearest.len() < ef || neighbor.0 < nearest.peek().unwrap().0 {
+                    frontier.push(std::cmp::Reverse(neighbor));
+                    nearest.push(neighbor);
+                    if nearest.len() > ef { nearest.pop(); }
+                }
+            }
+        }
+        nearest.into_sorted_vec()
+    }
+
+    // Picks up to `limit` neighbors from candidates sorted nearest first, skipping any that are closer to an already
+    // picked neighbor than to the node, so links spread out in different directions. Skipped candidates fill any
+    // remaining slots.
+    fn select_neighbors(embeddings: &Embeddings, candidates: &[Candidate], limit: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(limit);
+        let mut skipped = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= limit { break; }
+            let vector = embeddings.vector(candidate.1 as usize);
+            if selected.iter().all(|&other| distance(vector, embeddings.vector(other as usize)) > candidate.0) {
+                selected.push(candidate.1);
+            } else {
+                skipped.push(candidate.1);
+            }
+        }
+        selected.extend(skipped.into_iter().take(limit - selected.len()));
+        selected
+    }
+
+    pub fn build(embeddings: &Embeddings, ef_construction: usize, seed: u64) -> Hnsw {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let level_multiplier = 1.0 / (MAX_NEIGHBORS as f64).ln();
+        let mut hnsw = Hnsw { entry: 0, neighbors: Vec::with_capacity(embeddings.count()) };
+        let progress_bar = create_progress_bar(embeddings.count() as u64, "Building HNSW graph");
+        for node in 0..embeddings.count() as u32 {
+            let top_layer = (-(1.0 - rng.gen::<f64>()).ln() * level_multiplier) as usize;
+            hnsw.neighbors.push(vec![Vec::new(); top_layer + 1]);
+            if node == 0 {
+                progress_bar.inc(1);
+                continue;
+            }
+
+            // Descend greedily to the node's top layer, then link it into every layer from there down
+            let query = embeddings.vector(node as usize);
+            let mut entry_points = vec![Candidate(distance(query, embeddings.vector(hnsw.entry as usize)), hnsw.entry)];
+            for layer in (top_layer + 1..=hnsw.top_layer()).rev() {
+                entry_points = hnsw.search_layer(embeddings, query, &entry_points, 1, layer);
+            }
+            for layer in (0..=top_layer.min(hnsw.top_layer())).rev() {
+                let limit = if layer == 0 { 2 * MAX_NEIGHBORS } else { MAX_NEIGHBORS };
+                let candidates = hnsw.search_layer(embeddings, query, &entry_points, ef_construction, layer);
+                let selected = Hnsw::select_neighbors(embeddings, &candidates, limit);
+                for &neighbor in &selected {
+                    let neighbor_links = &mut hnsw.neighbors[neighbor as usize][layer];
+                    neighbor_links.push(node);
+                    if neighbor_links.len() > limit {
+                        let vector = embeddings.vector(neighbor as usize);
+                        let mut pruned: Vec<Candidate> = neighbor_links.iter().map(|&other| Candidate(distance(vector, embeddings.vector(other as usize)), other)).collect();
+                        pruned.sort_unstable();
+                        hnsw.neighbors[neighbor as usize][layer] = Hnsw::select_neighbors(embeddings, &pruned, limit);
+                    }
+                }
+                hnsw.neighbors[node as usize][layer] = selected;
+                entry_points = candidates;
+            }
+            if top_layer > hnsw.top_layer() {
+                hnsw.entry = node;
+            }
+            progress_bar.inc(1);
+        }
+        progress_bar.finish_and_clear();
+        hnsw
+    }
+
+    // The `limit` nearest vectors to the query as (record index, cosine similarity), nearest first
+    pub fn search(&self, embeddings: &Embeddings, query: &[f32], limit: usize, ef: usize) -> Vec<(usize, f32)> {
+        if self.neighbors.is_empty() { return Vec::new(); }
+        let mut entry_points = vec![Candidate(distance(query, embeddings.vector(self.entry as usize)), self.entry)];
+        for layer in (1..=self.top_layer()).rev() {
+            entry_points = self.search_layer(embeddings, query, &entry_points, 1, layer);
+        }
+        self.search_layer(embeddings, query, &entry_points, ef.max(limit), 0).into_iter()
+            .take(limit)
+            .map(|candidate| (candidate.1 as usize, 1.0 - candidate.0))
+            .collect()
+    }
+
+    pub fn write(&self, path: &Path) {
+        let mut output_file = BufWriter::new(File::create(path).expect("Failed to create HNSW file"));
+        output_file.write_all(MAGIC).expect("Failed to write HNSW file");
+        output_file.write_all(&VERSION.to_le_bytes()).expect("Failed to write HNSW file");
+        output_file.write_all(&self.entry.to_le_bytes()).expect("Failed to write HNSW file");
+        let mut buffer = Vec::new();
+        for layers in &self.neighbors {
+            buffer.clear();
+            write_varint(&mut buffer, layers.len() as u32 - 1);
+            for layer in layers {
+                write_varint(&mut buffer, layer.len() as u32);
+                for &neighbor in layer {
+                    write_varint(&mut buffer, neighbor);
+                }
+            }
+            output_file.write_all(&buffer).expect("Failed to write HNSW file");
+        }
+        output_file.flush().expect("Failed to flush HNSW file");
+    }
+
+    pub fn read(path: &Path, count: usize) -> Hnsw {
+        let buffer = read_links_file(path);
+        if !buffer.starts_with(MAGIC) || buffer.get(4..8) != Some(&VERSION.to_le_bytes()[..]) || buffer.len() < 12 {
+            eprintln!("Error: {} is not a valid HNSW file", path.to_str().unwrap());
+            std::process::exit(1);
+        }
+        let entry = u32::from_le_bytes(buffer[8..12].try_into().unwrap());
+        let mut offset = 12;
+        let mut neighbors = Vec::with_capacity(count);
+        let corrupt = |err: String| -> ! { panic!("Corrupt HNSW record: {}", err) };
+        while offset < buffer.len() {
+            let top_layer = read_varint(&buffer, &mut offset).unwrap_or_else(|err| corrupt(err));
+            let mut layers = Vec::with_capacity(top_layer as usize + 1);
+            for _ in 0..=top_layer {
+                let length = read_varint(&buffer, &mut offset).unwrap_or_else(|err| corrupt(err));
+                layers.push((0..length).map(|_| read_varint(&buffer, &mut offset).unwrap_or_else(|err| corrupt(err))).collect());
+            }
+            neighbors.push(layers);
+        }
+        if neighbors.len() != count {
+            eprintln!("Error: {} has {} nodes but there are {} embeddings, run the index-semantic command again", path.to_str().unwrap(), neighbors.len(), count);
+            std::process::exit(1);
+        }
+        Hnsw { entry, neighbors }
+    }
+}
+
+pub fn load_embeddings(data_path: &Path) -> Embeddings {
+    let embeddings_path = data_path.join(EMBEDDINGS_FILE);
+    if !embeddings_path.exists() {
+        eprintln!("Error: Unable to locate {} in {}, run the embed command first", EMBEDDINGS_FILE, data_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    read_embeddings(&embeddings_path)
+}
+
+pub fn index_semantic(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let ef_construction: usize = args.parse_value("ef-construction").unwrap_or(100);
+    let embeddings = load_embeddings(data_path);
+    let hnsw = Hnsw::build(&embeddings, ef_construction.max(1), args.parse_value("seed").unwrap_or(0));
+    hnsw.write(&data_path.join(HNSW_FILE));
+    println!("Indexed {} embeddings with {} layers in {}", embeddings.count(), hnsw.top_layer() + 1, data_path.join(HNSW_FILE).to_str().unwrap());
+}
+
+// Returns the lines of passages.jsonl at the given record indices, parsed
+pub fn load_passages(data_path: &Path, indices: &[usize]) -> Vec<serde_json::Value> {
+    let passages_file = File::open(data_path.join(PASSAGES_FILE)).expect("Failed to open passages file");
+    let wanted: FxHashSet<usize> = indices.iter().copied().collect();
+    let last = indices.iter().max().copied().unwrap_or(0);
+    let mut found = rustc_hash::FxHashMap::default();
+    for (index, line) in BufReader::new(passages_file).lines().enumerate().take(last + 1) {
+        if wanted.contains(&index) {
+            found.insert(index, serde_json::from_str(&line.expect("Failed to read passages file")).unwrap_or_default());
+        }
+    }
+    indices.iter().map(|index| found.remove(index).unwrap_or_default()).collect()
+}
+
+pub fn search_semantic(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let usage = "search-semantic <data_path> <query> --endpoint URL [--model NAME] or --onnx-model DIR [--limit N] [--ef N]";
+    let query_text = args.positional[1..].join(" ");
+    if query_text.is_empty() {
+        eprintln!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let limit: usize = args.parse_value("limit").unwrap_or(10);
+    let ef: usize = args.parse_value("ef").unwrap_or(64);
+    let client = EmbeddingClient::from_args(args, usage);
+
+    let hnsw_path = data_path.join(HNSW_FILE);
+    if !hnsw_path.exists() {
+        eprintln!("Error: Unable to locate {} in {}, run the index-semantic command first", HNSW_FILE, data_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let embeddings = load_embeddings(data_path);
+    let hnsw = Hnsw::read(&hnsw_path, embeddings.count());
+    let query = client.embed(&[&query_text]).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }).remove(0);
+    if query.len() != embeddings.dimensions {
+        eprintln!("Error: The query has {} dimensions but the embeddings have {}, use the same model as the embed command", query.len(), embeddings.dimensions);
+        std::process::exit(1);
+    }
+
+    let results = hnsw.search(&embeddings, &query, limit, ef);
+    let passages = load_passages(data_path, &results.iter().map(|(index, _)| *index).collect::<Vec<_>>());
+    for (rank, ((index, similarity), passage)) in results.iter().zip(&passages).enumerate() {
+        let text = passage["text"].as_str().unwrap_or_default();
+        let snippet: String = text.chars().take(200).collect();
+        println!("{:>2}) {} - {} (ID: {}, similarity {:.3})", rank + 1, passage["title"].as_str().unwrap_or_default(), passage["section"].as_str().unwrap_or_default(), embeddings.keys[*index].0, similarity);
+        println!("    {}{}", snippet.replace('\n', " "), if snippet.len() < text.len() { "..." } else { "" });
+    }
+    if results.is_empty() {
+        println!("No matches");
+    }
+}
@@ -0,0 +1,89 @@
+use indicatif::ParallelProgressIterator;
+use rayon::prelude::*;
+use crate::graph::DenseGraph;
+use crate::helpers::create_progress_bar;
+
+pub const DEFAULT_REGISTERS: usize = 64;
+
+pub fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// HyperLogLog estimate for one counter's registers
+fn estimate(registers: &[u8]) -> f64 {
+    let m = registers.len() as f64;
+    let alpha = match registers.len() {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m),
+    };
+    let sum: f64 = registers.iter().map(|&register| 2f64.powi(-(register as i32))).sum();
+    let raw = alpha * m * m / sum;
+    let zeros = registers.iter().filter(|&&register| register == 0).count();
+    // Linear counting is more accurate for small sets
+    if raw <= 2.5 * m && zeros > 0 { m * (m / zeros as f64).ln() } else { raw }
+}
+
+// Estimates the neighbourhood function N(t), the number of ordered pairs (u, v) where v can be reached from u in at
+// most t links, for t = 0, 1, ... until it stops growing. Each node keeps a HyperLogLog counter of the nodes it can
+// reach, and each round unions in the counters of its successors (Boldi, Rosa and Vigna's HyperANF).
+pub fn neighbourhood_function(graph: &DenseGraph, register_count: usize, max_rounds: usize) -> Vec<f64> {
+    let node_count = graph.node_count();
+    let index_bits = register_count.trailing_zeros();
+    let mut registers = vec![0u8; node_count * register_count];
+    for (node, counter) in registers.chunks_mut(register_count).enumerate() {
+        let hash = splitmix64(node as u64);
+        let register = (hash & (register_count as u64 - 1)) as usize;
+        counter[register] = ((hash >> index_bits).trailing_zeros() + 1).min(64 - index_bits + 1) as u8;
+    }
+
+    let total = |registers: &[u8]| registers.par_chunks(register_count).map(estimate).sum::<f64>();
+    let mut function = vec![total(&registers)];
+    let mut next = registers.clone();
+    for round in 1..=max_rounds {
+        let progress_bar = create_progress_bar(node_count as u64, &format!("Estimating distances (round {})", round));
+        let changed = next.par_chunks_mut(register_count).enumerate().progress_with(progress_bar.clone()).map(|(node, counter)| {
+            let mut changed = false;
+            for &successor in graph.successors(node) {
+                let other = &registers[successor as usize * register_count..(successor as usize + 1) * register_count];
+                for (register, &value) in counter.iter_mut().zip(other) {
+                    if value > *register {
+                        *register = value;
+                        changed = true;
+                    }
+                }
+            }
+            changed
+        }).reduce(|| false, |a, b| a || b);
+        progress_bar.finish_and_clear();
+        if !changed { break; }
+        registers.copy_from_slice(&next);
+        function.push(total(&registers));
+    }
+    function
+}
+
+// Prints the effective diameter (the distance within which 90% of reachable pairs lie, interpolated) and the average
+// distance between reachable pairs
+pub fn print_distance_stats(graph: &DenseGraph, register_count: usize) {
+    let function = neighbourhood_function(graph, register_count, 1000);
+    // N(0) counts each node reaching itself
+    let reachable = function.last().unwrap() - function[0];
+    println!("\nDistances (estimated with HyperANF, {} registers per node):", register_count);
+    if reachable <= 0.0 {
+        println!("  No article can reach another");
+        return;
+    }
+    let target = function[0] + 0.9 * reachable;
+    let round = function.iter().position(|&pairs| pairs >= target).unwrap();
+    let effective_diameter = round as f64 - 1.0 + (target - function[round - 1]) / (function[round] - function[round - 1]);
+    let average_distance: f64 = function.windows(2).enumerate().map(|(t, pair)| (t + 1) as f64 * (pair[1] - pair[0]).max(0.0)).sum::<f64>() / reachable;
+    println!("  Reachable pairs: {:.3e} ({:.1}% of all pairs)", reachable, 100.0 * reachable / (graph.node_count() as f64 * (graph.node_count() as f64 - 1.0)));
+    println!("  Effective diameter (90th percentile): {:.2}", effective_diameter);
+    println!("  Average distance: {:.2}", average_distance);
+    println!("  Rounds until no counter changed: {}", function.len() - 1);
+}
@@ -1,30 +1,53 @@
-use std::io::Write;
-use std::path::Path;
-use std::fs::File;
-use std::collections::HashMap;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::fs::{File, OpenOptions, read_to_string, remove_file};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
-use threadpool::ThreadPool;
+use std::sync::atomic::Ordering;
 use indicatif::ProgressIterator;
 use html_escape::decode_html_entities;
-use crate::helpers::{IGNORE, create_progress_bar, load_index, load_chunk};
+use crate::helpers::{Args, INTERRUPTED, Page, PageId, create_progress_bar, handle_interrupts, get_chunk_ranges, load_index, locate_dump_files};
+use crate::complete::write_title_fst;
+use crate::links::{PageInfo, get_article_byte_string, get_header, is_partial, set_partial};
+use crate::dump::DumpSink;
+use crate::redirects::{REDIRECTS_FILE, RedirectSink};
+use crate::duplicates::{dedupe_ids, resolve_title_collisions};
+use crate::philosophy::{FIRST_LINKS_FILE, first_link, write_first_links};
+use crate::pipeline::{self, PageSink, SinkChunk};
+use crate::positions::{self, POSITIONS_FILE, get_positions_byte_string};
+use crate::chunk_stats;
+use crate::categories::{self, CATEGORIES_FILE, extract_categories};
+use crate::files::{self, FILES_FILE, extract_files, get_names_byte_string};
+use crate::preflight;
+use crate::split::SplitWriter;
+use crate::storage::upload_files;
+use crate::summary::RunSummary;
+use crate::titles::TitleTable;
+#[cfg(feature = "store")]
+use crate::store::StoreSink;
+use crate::config::config;
+use crate::namespaces::is_ignored;
+use tracing::{debug, info, trace, warn};
 
-fn extract_links(text: &str) -> Vec<String> {
+// Strips the label and section from the inside of a [[...]] link and returns the lowercase target title, or None if
+// it points into an ignored namespace
+pub fn normalize_link(link: &str) -> Option<String> {
+    let link = link.split('|').next().unwrap();
+    let link = link.split('#').next().unwrap();
+    let decoded_link = decode_html_entities(link).to_string();
+    (!is_ignored(&decoded_link)).then(|| decoded_link.to_lowercase())
+}
+
+// Returns each link's target along with the byte offset of its opening brackets
+pub fn extract_links(text: &str) -> Vec<(usize, String)> {
     let mut links = Vec::new();
     let mut start = 0;
     while let Some(open_bracket) = text[start..].find("[[") {
         if let Some(close_bracket) = text[start + open_bracket + 2..].find("]]") {
             let link_start = start + open_bracket + 2;
             let link_end = start + open_bracket + 2 + close_bracket;
-            let mut link = text[link_start..link_end].to_string();
-            if link.contains('|') {
-                link = link.split('|').collect::<Vec<_>>()[0].to_string();
-            }
-            if link.contains('#') {
-                link = link.split('#').collect::<Vec<_>>()[0].to_string();
-            }
-            let decoded_link = decode_html_entities(&link).to_string();
-            if !IGNORE.iter().any(|prefix| decoded_link.starts_with(prefix)) {
-                links.push(decoded_link.to_lowercase());
+            if let Some(link) = normalize_link(&text[link_start..link_end]) {
+                links.push((start + open_bracket, link));
             }
             start = link_end + 2;
         } else {
@@ -34,123 +57,422 @@ fn extract_links(text: &str) -> Vec<String> {
     links
 }
 
-fn process_chunk(articles_path: &str, start_position: u64, end_position: u64, article_titles_to_ids: &HashMap<String, u32>) -> (HashMap<u32, Vec<u32>>, usize, usize, usize) {
-    let articles = load_chunk(articles_path, start_position, end_position);
-    let mut article_links = HashMap::new();
-    let mut total_links = 0;
-    let mut red_links = 0;
+// Returns the text before the first section heading
+fn lead_section(text: &str) -> &str {
+    if text.starts_with("==") { return ""; }
+    text.find("\n==").map_or(text, |heading| &text[..heading])
+}
+
+const CHECKPOINT_FILE: &str = "index-checkpoint.txt";
 
-    for (article_id, (_, content)) in &articles {
-        let links = extract_links(&content);
-        let mut link_ids = Vec::new();
-        for link in &links {
-            match article_titles_to_ids.get(link) {
-                Some(&link_id) => link_ids.push(link_id),
-                None => red_links += 1,
+// The records extracted from one chunk, keyed by article ID so they're written in a stable order
+struct LinkChunk {
+    chunk_index: usize,
+    article_links: BTreeMap<PageId, (PageInfo, Vec<PageId>)>,
+    articles: usize,
+    links: usize,
+    red_links: usize,
+    positions: Vec<u8>,
+}
+
+// Extracts and resolves every article's links, and writes links.bin, the split files and, with --with-positions,
+// positions.bin. A line is appended to the checkpoint after each chunk's records have been flushed:
+//   chunk_index:articles:links:red_links:links.bin length:titles.bin length:graph.bin length
+struct LinkSink {
+    titles: Arc<TitleTable>,
+    lead_links_only: bool,
+    positions: bool,
+    output: Mutex<LinkOutput>,
+}
+
+// The totals include the chunks that a resumed run skipped
+struct LinkOutput {
+    links_file: File,
+    split_writer: Option<SplitWriter>,  // until finish
+    checkpoint_file: File,
+    positions_file: Option<BufWriter<File>>,
+    articles: usize,
+    links: usize,
+    red_links: usize,
+}
+
+impl PageSink for LinkSink {
+    fn extract(&self, chunk_index: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        let mut article_links = BTreeMap::new();
+        let mut total_links = 0;
+        let mut red_links = 0;
+        let mut positions = Vec::new();
+
+        for (article_id, page) in pages {
+            let links = extract_links(if self.lead_links_only { lead_section(&page.text) } else { &page.text });
+            let mut link_ids = Vec::new();
+            let mut occurrences = Vec::new();
+            for (offset, link) in &links {
+                match self.titles.find(link) {
+                    Some(link_id) => {
+                        link_ids.push(link_id);
+                        occurrences.push((link_id, *offset as u32));
+                    }
+                    None => {
+                        trace!(article_id, link, "red link");
+                        red_links += 1;
+                    }
+                }
+            }
+            let info = PageInfo {
+                namespace: page.namespace,
+                redirect: page.redirect,
+                text_length: page.text.len() as u32,
+                word_count: page.text.split_whitespace().count() as u32,
+            };
+            if self.positions {
+                positions.extend(get_positions_byte_string(*article_id, &occurrences));
             }
+            article_links.insert(*article_id, (info, link_ids));
+            total_links += links.len();
+        }
+
+        debug!(chunk_index, articles = pages.len(), total_links, red_links, "processed chunk");
+        Box::new(LinkChunk { chunk_index, article_links, articles: pages.len(), links: total_links, red_links, positions })
+    }
+
+    fn write(&self, chunk: SinkChunk) {
+        let chunk = chunk.downcast::<LinkChunk>().unwrap();
+        let output = &mut *self.output.lock().unwrap();
+        let split_writer = output.split_writer.as_mut().unwrap();
+        if let Some(positions_file) = &mut output.positions_file {
+            positions_file.write_all(&chunk.positions).expect("Failed to write positions file");
+        }
+        for (&article_id, (info, link_ids)) in chunk.article_links.iter() {
+            let title = self.titles.title(article_id).expect("Article ID not found");
+            let output_buffer = get_article_byte_string(article_id, title, info, link_ids);
+            output.links_file.write_all(&output_buffer).expect("Failed to write to output file");
+            split_writer.write(article_id, title, info, link_ids);
+        }
+        let (titles_length, graph_length) = split_writer.flush();
+        let links_length = output.links_file.stream_position().expect("Failed to get links file position");
+        writeln!(output.checkpoint_file, "{}:{}:{}:{}:{}:{}:{}", chunk.chunk_index, chunk.articles, chunk.links, chunk.red_links, links_length, titles_length, graph_length)
+            .expect("Failed to write checkpoint");
+        output.articles += chunk.articles;
+        output.links += chunk.links;
+        output.red_links += chunk.red_links;
+    }
+
+    fn finish(&self) {
+        let mut output = self.output.lock().unwrap();
+        output.split_writer.take().unwrap().finish();
+        if let Some(positions_file) = &mut output.positions_file {
+            positions_file.flush().expect("Failed to flush positions file");
         }
-        article_links.insert(*article_id, link_ids);
-        total_links += links.len();
     }
+}
 
-    (article_links, articles.len(), total_links, red_links)
+// Records the first link of each article for philosophy, written out sorted once the pass is over
+struct FirstLinkSink {
+    titles: Arc<TitleTable>,
+    path: PathBuf,
+    first_links: Mutex<Vec<(PageId, PageId)>>,
 }
 
-fn get_article_byte_string(article_id: u32, title: &str, link_ids: &[u32]) -> Vec<u8> {
-    let mut output_buffer = Vec::new();
-    output_buffer.extend_from_slice(&article_id.to_le_bytes());
+impl PageSink for FirstLinkSink {
+    fn extract(&self, _: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        let first_links: Vec<(PageId, PageId)> = pages.iter()
+            .filter_map(|(article_id, page)| first_link(&page.text, |link| self.titles.find(link)).map(|link_id| (*article_id, link_id)))
+            .collect();
+        Box::new(first_links)
+    }
 
-    let title_bytes = title.as_bytes();
-    output_buffer.extend_from_slice(&(title_bytes.len() as u32).to_le_bytes());
-    output_buffer.extend_from_slice(title_bytes);
+    fn write(&self, chunk: SinkChunk) {
+        self.first_links.lock().unwrap().append(&mut chunk.downcast::<Vec<(PageId, PageId)>>().unwrap());
+    }
 
-    output_buffer.extend_from_slice(&(link_ids.len() as u32).to_le_bytes());
-    for &link_id in link_ids {
-        output_buffer.extend_from_slice(&link_id.to_le_bytes());
+    // The first links aren't checkpointed, so an interrupted run leaves them out rather than writing some of them
+    fn finish(&self) {
+        if INTERRUPTED.load(Ordering::SeqCst) { return; }
+        let mut first_links = self.first_links.lock().unwrap();
+        first_links.sort_unstable();
+        write_first_links(&self.path, &first_links);
     }
+}
 
-    output_buffer.extend_from_slice(&u32::MAX.to_le_bytes());
-    output_buffer
+// Records the names each page refers to, like the files it embeds or the categories it's in, in the format of
+// files.bin and categories.bin
+struct NameSink {
+    extract_names: fn(&str) -> Vec<String>,
+    file: Mutex<BufWriter<File>>,
 }
 
+impl NameSink {
+    fn create(path: &Path, header: &[u8], extract_names: fn(&str) -> Vec<String>) -> Self {
+        let mut file = BufWriter::new(File::create(path).expect("Failed to create output file"));
+        file.write_all(header).expect("Failed to write output file");
+        NameSink { extract_names, file: Mutex::new(file) }
+    }
+}
 
-pub fn index(data_path: &Path) {
-    let index_path = data_path.join("enwiki-20240801-pages-articles-multistream-index.txt.bz2");
-    let articles_path = data_path.join("enwiki-20240801-pages-articles-multistream.xml.bz2");
-    if !index_path.exists() || !articles_path.exists() {
-        eprintln!("Error: Unable to locate data files in {}", data_path.to_str().unwrap());
+impl PageSink for NameSink {
+    fn extract(&self, _: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        let mut names = Vec::new();
+        for (article_id, page) in pages {
+            names.extend(get_names_byte_string(*article_id, &(self.extract_names)(&page.text)));
+        }
+        Box::new(names)
+    }
+
+    fn write(&self, chunk: SinkChunk) {
+        self.file.lock().unwrap().write_all(&chunk.downcast::<Vec<u8>>().unwrap()).expect("Failed to write output file");
+    }
+
+    fn finish(&self) {
+        self.file.lock().unwrap().flush().expect("Failed to flush output file");
+    }
+}
+
+#[derive(Default)]
+struct Checkpoint {
+    chunks: HashSet<usize>,
+    articles: usize,
+    links: usize,
+    red_links: usize,
+    lengths: Option<(u64, u64, u64)>,
+}
+
+fn load_checkpoint(data_path: &Path) -> Checkpoint {
+    let mut links_header = [0u8; 8];
+    let links_partial = File::open(data_path.join("links.bin"))
+        .and_then(|mut links_file| links_file.read_exact(&mut links_header))
+        .is_ok_and(|_| is_partial(&links_header));
+    let checkpoint_path = data_path.join(CHECKPOINT_FILE);
+    if !links_partial || !checkpoint_path.exists() {
+        eprintln!("Error: No interrupted index run to resume in {}", data_path.to_str().unwrap());
         std::process::exit(1);
     }
 
-    let seek_position_map = load_index(index_path.to_str().unwrap());
-    println!("Total number of chunks: {}", seek_position_map.len());
+    let mut checkpoint = Checkpoint::default();
+    for line in read_to_string(checkpoint_path).expect("Unable to read checkpoint").lines() {
+        let fields: Vec<u64> = line.split(':').filter_map(|field| field.parse().ok()).collect();
+        let &[chunk_index, articles, links, red_links, links_length, titles_length, graph_length] = fields.as_slice() else {
+            break;  // A line cut off by a crash, and everything after it is suspect
+        };
+        checkpoint.chunks.insert(chunk_index as usize);
+        checkpoint.articles += articles as usize;
+        checkpoint.links += links as usize;
+        checkpoint.red_links += red_links as usize;
+        checkpoint.lengths = Some((links_length, titles_length, graph_length));
+    }
+    checkpoint
+}
+
+// What an index run writes. The index command always writes links.bin and the split files and takes the rest from its
+// flags, while process takes all of them from --extract.
+pub struct Outputs {
+    pub links: bool,
+    pub first_links: bool,
+    pub positions: bool,
+    pub files: bool,
+    pub categories: bool,
+    pub redirects: bool,
+    pub dump: bool,
+    pub store: bool,
+}
+
+pub fn index(args: &Args) {
+    let outputs = Outputs {
+        links: true, first_links: args.flag("first-links"), positions: args.flag("with-positions"), files: args.flag("with-files"),
+        categories: args.flag("with-categories"), redirects: args.flag("with-redirects"), dump: args.flag("dump"), store: args.flag("store"),
+    };
+    run(args, "index", outputs);
+}
+
+#[cfg(feature = "store")]
+fn create_store_sink(data_path: &Path, articles_path: &Path) -> Arc<dyn PageSink> {
+    Arc::new(StoreSink::create(data_path, articles_path))
+}
+
+#[cfg(not(feature = "store"))]
+fn create_store_sink(_data_path: &Path, _articles_path: &Path) -> Arc<dyn PageSink> {
+    eprintln!("Error: The article store requires building with --features store");
+    std::process::exit(1);
+}
 
-    let article_titles_to_ids: HashMap<String, u32> = seek_position_map
-        .values()
+// Builds the title index and then makes one pass over the dump that feeds every output in `outputs`
+pub fn run(args: &Args, command: &str, outputs: Outputs) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let mut summary = RunSummary::new(command, args);
+    summary.stage("hash inputs");
+    summary.input(&index_path);
+    summary.input(&articles_path);
+
+    summary.stage("load index");
+    let mut seek_position_map = load_index(index_path.to_str().unwrap());
+    info!("Total number of chunks: {}", seek_position_map.len());
+    let (duplicate_ids, dropped_pages) = dedupe_ids(&mut seek_position_map);
+
+    summary.stage("title index");
+    let mut titles = TitleTable::new(seek_position_map.values()
         .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating title index"))
-        .flat_map(|articles| articles.iter().map(|(id, title)| (title.to_lowercase(), *id)))
-        .collect();
-    let article_ids_to_titles: HashMap<u32, String> = seek_position_map
-        .values()
-        .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating id index"))
-        .flat_map(|articles| articles.iter().map(|(id, title)| (*id, title.clone())))
-        .collect();
-    println!("Total articles: {}", article_titles_to_ids.len());
-
-    let mut positions: Vec<&u64> = seek_position_map.keys().collect();
-    let file = File::open(&articles_path).expect("Unable to open articles file");
-    let file_size = file.metadata().expect("Failed to get file metadata").len();
-    positions.push(&file_size);
-    positions.sort_unstable();
-
-    let num_threads = 8;
-    let pool = ThreadPool::new(num_threads);
-    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
-    let total_articles = Arc::new(Mutex::new(0));
-    let total_links = Arc::new(Mutex::new(0));
-    let red_links = Arc::new(Mutex::new(0));
-    let article_titles_to_ids = Arc::new(article_titles_to_ids);
-    let article_ids_to_titles = Arc::new(article_ids_to_titles);
-    let progress_bar = Arc::new(create_progress_bar((positions.len()-1) as u64, "Extracting articles"));
-    let output_file = Arc::new(Mutex::new(File::create(data_path.join("links.bin")).expect("Failed to create output file")));
-
-    // Process chunks in using the thread pool
-    for chunk_index in 0..positions.len()-1 {
-        let start_position = *positions[chunk_index];
-        let end_position = *positions[chunk_index + 1];
-
-        let total_articles = Arc::clone(&total_articles);
-        let total_links = Arc::clone(&total_links);
-        let red_links = Arc::clone(&red_links);
-        let article_titles_to_ids = Arc::clone(&article_titles_to_ids);
-        let article_ids_to_titles = Arc::clone(&article_ids_to_titles);
-        let articles_path = Arc::clone(&articles_path);
-        let progress_bar = Arc::clone(&progress_bar);
-        let output_file = Arc::clone(&output_file);
-
-        pool.execute(move || {
-            let (chunk_article_links, chunk_article_count, chunk_total_links, chunk_red_links) =
-                process_chunk(&articles_path, start_position, end_position, &article_titles_to_ids);
-
-            *(total_articles.lock().unwrap()) += chunk_article_count;
-            *(total_links.lock().unwrap()) += chunk_total_links;
-            *(red_links.lock().unwrap()) += chunk_red_links;
-
-            let mut output_file = output_file.lock().unwrap();
-            for (&article_id, link_ids) in chunk_article_links.iter() {
-                let title = article_ids_to_titles.get(&article_id).expect("Article ID not found");
-                let output_buffer = get_article_byte_string(article_id, title, link_ids);
-                output_file.write_all(&output_buffer).expect("Failed to write to output file");
+        .flatten());
+    let duplicate_titles = resolve_title_collisions(&mut titles, articles_path.to_str().unwrap(), &seek_position_map);
+    info!("Total articles: {}", titles.len());
+    if outputs.links {
+        let resolved_titles = seek_position_map.values().flatten().filter(|(id, title)| duplicate_titles == 0 || titles.find(&title.to_lowercase()) == Some(*id));
+        write_title_fst(&data_path.join("titles.fst"), resolved_titles);
+    }
+
+    let mut chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    if chunk_ranges.len() < seek_position_map.len() {
+        info!("Processing {} of {} chunks", chunk_ranges.len(), seek_position_map.len());
+    }
+    preflight::check(args, data_path, &preflight::estimate_index(&seek_position_map, &chunk_ranges));
+
+    // Only links.bin and the split files are checkpointed, so they're the only outputs a run can resume
+    let Outputs { links, first_links, positions, files, categories, redirects, dump, store } = outputs;
+    if args.flag("resume") && (!links || first_links || positions || files || categories || redirects || dump || store) {
+        if command == "index" {
+            eprintln!("Error: --first-links, --with-positions, --with-files, --with-categories, --with-redirects, --dump and --store can't be combined with --resume, re-run the index from the start");
+        } else {
+            eprintln!("Error: --resume only works when links are the only extract, re-run from the start");
+        }
+        std::process::exit(1);
+    }
+    let titles = Arc::new(titles);
+    let mut sinks: Vec<Arc<dyn PageSink>> = Vec::new();
+    let link_sink = links.then(|| {
+        // When resuming, pick up the output files where the last checkpoint left them and skip the chunks it lists
+        let checkpoint = if args.flag("resume") { load_checkpoint(data_path) } else { Checkpoint::default() };
+        let links_path = data_path.join("links.bin");
+        let (links_file, split_writer) = match checkpoint.lengths {
+            Some((links_length, titles_length, graph_length)) => {
+                let total_chunks = chunk_ranges.len();
+                chunk_ranges.retain(|(chunk_index, _, _)| !checkpoint.chunks.contains(chunk_index));
+                info!("Skipping {} chunks already indexed", total_chunks - chunk_ranges.len());
+                let mut links_file = OpenOptions::new().write(true).open(&links_path).expect("Failed to open links file");
+                links_file.set_len(links_length).expect("Failed to truncate links file");
+                links_file.seek(SeekFrom::End(0)).expect("Failed to seek to end of links file");
+                (links_file, SplitWriter::resume(data_path, titles_length, graph_length))
             }
+            None => {
+                let mut links_file = File::create(&links_path).expect("Failed to create output file");
+                links_file.write_all(&get_header(true)).expect("Failed to write to output file");
+                (links_file, SplitWriter::create(data_path))
+            }
+        };
+        let checkpoint_file = OpenOptions::new().create(true).append(true).open(data_path.join(CHECKPOINT_FILE)).expect("Failed to open checkpoint");
+        if checkpoint.lengths.is_none() {
+            checkpoint_file.set_len(0).expect("Failed to reset checkpoint");
+        }
+        // Leftovers from an earlier run with different options would no longer match links.bin
+        for (enabled, file_name) in [(first_links, FIRST_LINKS_FILE), (positions, POSITIONS_FILE), (files, FILES_FILE), (categories, CATEGORIES_FILE), (redirects, REDIRECTS_FILE)] {
+            if !enabled && data_path.join(file_name).exists() {
+                remove_file(data_path.join(file_name)).expect("Failed to remove stale output file");
+            }
+        }
+        let positions_file = positions.then(|| {
+            let mut positions_file = BufWriter::new(File::create(data_path.join(POSITIONS_FILE)).expect("Failed to create positions file"));
+            positions_file.write_all(&positions::get_header()).expect("Failed to write positions file");
+            positions_file
+        });
+        let link_sink = Arc::new(LinkSink {
+            titles: Arc::clone(&titles),
+            lead_links_only: args.flag("lead-links-only"),
+            positions,
+            output: Mutex::new(LinkOutput {
+                links_file, split_writer: Some(split_writer), checkpoint_file, positions_file,
+                articles: checkpoint.articles, links: checkpoint.links, red_links: checkpoint.red_links,
+            }),
+        });
+        sinks.push(link_sink.clone());
+        link_sink
+    });
+
+    // Every other output comes from the same pass over the dump
+    if first_links {
+        sinks.push(Arc::new(FirstLinkSink { titles: Arc::clone(&titles), path: data_path.join(FIRST_LINKS_FILE), first_links: Mutex::new(Vec::new()) }));
+    }
+    if files {
+        sinks.push(Arc::new(NameSink::create(&data_path.join(FILES_FILE), &files::get_header(), extract_files)));
+    }
+    if categories {
+        sinks.push(Arc::new(NameSink::create(&data_path.join(CATEGORIES_FILE), &categories::get_header(), extract_categories)));
+    }
+    if redirects {
+        sinks.push(Arc::new(RedirectSink::create(&data_path.join(REDIRECTS_FILE), Arc::clone(&titles))));
+    }
+    // --dump writes the articles out like the dump command would, without a second pass over the dump
+    let dump_sink = dump.then(|| Arc::new(DumpSink::from_args(args, data_path, &seek_position_map, &mut chunk_ranges).0));
+    if let Some(dump_sink) = &dump_sink {
+        sinks.push(dump_sink.clone());
+    }
+    if store {
+        sinks.push(create_store_sink(data_path, &articles_path));
+    }
+    handle_interrupts();
 
-            progress_bar.inc(1);
-        })
+    summary.stage(if links { "extract links" } else { "extract pages" });
+    let (timings, elapsed) = pipeline::run(&articles_path, chunk_ranges, &sinks, Arc::new(dropped_pages), args.flag("deterministic"), "Extracting articles");
+    summary.stage(if links { "write offsets" } else { "finish outputs" });
+    for sink in &sinks {
+        sink.finish();
     }
+    let mut output = link_sink.as_ref().map(|link_sink| link_sink.output.lock().unwrap());
+    let (total_articles, total_links, red_links) = match &output {
+        Some(output) => (output.articles, output.links, output.red_links),
+        None => (timings.iter().map(|timing| timing.articles).sum(), 0, 0),
+    };
 
-    pool.join();
-    progress_bar.finish_and_clear();
+    // The files are complete up to the last checkpoint, but links.bin stays marked partial until a run finishes
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        if links {
+            eprintln!("Interrupted after {} articles, re-run with --resume to finish indexing", total_articles);
+        } else {
+            eprintln!("Interrupted after {} articles", total_articles);
+        }
+        std::process::exit(130);
+    }
+    if let Some(output) = &mut output {
+        set_partial(&mut output.links_file, false);
+        remove_file(data_path.join(CHECKPOINT_FILE)).expect("Failed to remove checkpoint");
+    }
 
-    println!("Total articles extracted: {}", *total_articles.lock().unwrap());
-    println!("Total links extracted: {}", *total_links.lock().unwrap());
-    println!("Total red links: {}", *red_links.lock().unwrap());
-}
\ No newline at end of file
+    println!("Total articles extracted: {}", total_articles);
+    if links {
+        println!("Total links extracted: {}", total_links);
+        println!("Total red links: {}", red_links);
+    }
+    if let Some(dump_sink) = &dump_sink {
+        println!("Total articles dumped: {}", dump_sink.articles());
+    }
+
+    // Timings only cover the chunks processed in this run, not the ones a resumed run skipped
+    let hot_chunks = chunk_stats::hot_chunks(&timings).len();
+    if args.flag("chunk-stats") {
+        chunk_stats::report(&timings, config().threads, elapsed, data_path);
+    } else if hot_chunks > 0 {
+        warn!("{} chunks were far slower than the median, re-run with --chunk-stats for details", hot_chunks);
+    }
+
+    summary.count("chunks", seek_position_map.len());
+    summary.count("titles", titles.len());
+    summary.count("articles", total_articles);
+    if links {
+        summary.count("links", total_links);
+        summary.count("red_links", red_links);
+    }
+    if let Some(dump_sink) = &dump_sink {
+        summary.count("dumped_articles", dump_sink.articles());
+    }
+    summary.count("duplicate_ids", duplicate_ids);
+    summary.count("duplicate_titles", duplicate_titles);
+    summary.count("hot_chunks", hot_chunks);
+    summary.write(data_path);
+
+    if let Some(location) = args.value("upload") {
+        let mut names = if links { vec!["links.bin", "titles.fst", "titles.bin", "graph.bin", "offsets.idx"] } else { Vec::new() };
+        for (enabled, file_name) in [(first_links, FIRST_LINKS_FILE), (positions, POSITIONS_FILE), (files, FILES_FILE), (categories, CATEGORIES_FILE), (redirects, REDIRECTS_FILE)] {
+            if enabled { names.push(file_name); }
+        }
+        names.push("run-summary.json");
+        upload_files(location, data_path, &names);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::corpus::plain_text;
+use crate::helpers::skip_nested;
+
+// Templates that lay out their positional parameters as a list
+const LIST_TEMPLATES: [&str; 6] = ["hlist", "flatlist", "plainlist", "ubl", "unbulleted list", "plain list"];
+
+// A template call like {{Infobox person|name=Ada Lovelace|birth_date={{birth date|1815|12|10}}}}. Named parameters
+// keep their names, and positional ones are numbered from "1" like MediaWiki does. Values are raw wikitext.
+pub struct Template {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Template {
+    // Parses the text between the braces
+    pub fn parse(inner: &str) -> Self {
+        let mut parts = split_top_level(inner).into_iter();
+        let name = parts.next().unwrap_or_default().trim().replace('_', " ");
+        let mut params = Vec::new();
+        let mut position = 0;
+        for part in parts {
+            match part.split_once('=') {
+                Some((key, value)) if !key.contains("{{") && !key.contains("[[") => params.push((key.trim().to_string(), value.trim().to_string())),
+                _ => {
+                    position += 1;
+                    params.push((position.to_string(), part.trim().to_string()));
+                }
+            }
+        }
+        Template { name, params }
+    }
+
+    // Whether the template has the given name, ignoring case
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    // The raw value of a parameter, if it's set to something other than whitespace
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str()).filter(|value| !value.is_empty())
+    }
+
+    // The plain text of a parameter, without links, templates or references. The items of list templates like
+    // {{hlist|Poet|Novelist}} and of bulleted lines are joined with commas.
+    pub fn text(&self, key: &str) -> Option<String> {
+        let value = self.get(key)?;
+        let text = match templates(value).into_iter().next().filter(|template| LIST_TEMPLATES.iter().any(|name| template.is(name))) {
+            Some(list) => list.params.iter().filter(|(key, _)| key.parse::<usize>().is_ok()).map(|(_, item)| plain_text(item)).collect::<Vec<_>>().join("\n"),
+            None => plain_text(value),
+        };
+        let text = text.lines().map(str::trim).filter(|item| !item.is_empty()).collect::<Vec<_>>().join(", ");
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+// Splits template contents at the pipes that aren't inside a nested template, link or table
+pub fn split_top_level(inner: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < inner.len() {
+        let rest = &inner[i..];
+        if rest.starts_with("{{") {
+            i += skip_nested(rest, "{{", "}}");
+        } else if rest.starts_with("[[") {
+            i += skip_nested(rest, "[[", "]]");
+        } else if rest.starts_with('|') {
+            parts.push(&inner[start..i]);
+            i += 1;
+            start = i;
+        } else {
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    parts.push(&inner[start..]);
+    parts
+}
+
+// Returns every template in the wikitext, outermost first and then the ones nested in its parameters, in order
+pub fn templates(text: &str) -> Vec<Template> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = text[i..].find("{{") {
+        let start = i + offset;
+        let length = skip_nested(&text[start..], "{{", "}}");
+        let inner = &text[start + 2..start + length];
+        let inner = inner.strip_suffix("}}").unwrap_or(inner);
+        // Parser functions like {{#if:...}} and magic words like {{DEFAULTSORT:...}} aren't template calls
+        if !inner.starts_with('#') && !inner.split('|').next().unwrap().contains(':') {
+            found.push(Template::parse(inner));
+        }
+        found.extend(templates(inner));
+        i = start + length;
+    }
+    found
+}
+
+// The article's infobox, the first template whose name starts with "Infobox"
+pub fn infobox(text: &str) -> Option<Template> {
+    templates(text).into_iter().find(|template| template.name.to_lowercase().starts_with("infobox"))
+}
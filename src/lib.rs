@@ -0,0 +1,99 @@
+// Casts of page IDs to u64 are no-ops when they are already u64
+#![cfg_attr(feature = "u64-ids", allow(clippy::unnecessary_cast))]
+
+// Declares modules that are only built with the cli feature
+macro_rules! cli {
+    ($($item:item)*) => { $(#[cfg(feature = "cli")] $item)* };
+}
+
+pub mod links;
+pub mod split;
+pub mod query;
+
+cli! {
+    pub mod index;
+    pub mod pipeline;
+    pub mod process;
+    pub mod analyse;
+    pub mod analyse_text;
+    pub mod helpers;
+    pub mod dump;
+    pub mod history;
+    pub mod diff;
+    pub mod merge;
+    pub mod verify;
+    pub mod verify_dump;
+    pub mod gen_testdata;
+    pub mod random;
+    pub mod lookup;
+    pub mod shell;
+    pub mod titles;
+    pub mod logging;
+    pub mod summary;
+    pub mod preflight;
+    pub mod config;
+    pub mod namespaces;
+    pub mod duplicates;
+    pub mod philosophy;
+    pub mod quality;
+    pub mod positions;
+    pub mod files;
+    pub mod redirects;
+    pub mod export;
+    pub mod export_titles;
+    pub mod grep;
+    pub mod graph;
+    pub mod communities;
+    pub mod hyperanf;
+    pub mod walks;
+    pub mod related;
+    pub mod similarity;
+    pub mod minhash;
+    pub mod ngrams;
+    pub mod sample;
+    pub mod sample_articles;
+    pub mod sentences;
+    pub mod bulk;
+    pub mod biographies;
+    pub mod candidates;
+    pub mod categories;
+    pub mod lists;
+    pub mod infobox;
+    pub mod corpus;
+    pub mod embed;
+    pub mod hnsw;
+    pub mod serve;
+    pub mod render;
+    pub mod site;
+    pub mod book;
+    pub mod pdf;
+    pub mod zim;
+    pub mod viz;
+    pub mod stats;
+    pub mod chunk_stats;
+    pub mod bench;
+    pub mod parse;
+    pub mod recompress;
+    pub mod storage;
+    pub mod watch;
+    pub mod stubs;
+    pub mod wiktionary;
+    pub mod complete;
+}
+
+#[cfg(feature = "async-io")]
+pub mod aio;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "tantivy")]
+pub mod search;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "onnx")]
+pub mod onnx;
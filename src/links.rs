@@ -0,0 +1,362 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "cli")]
+use indicatif::ParallelProgressIterator;
+#[cfg(feature = "cli")]
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+#[cfg(feature = "cli")]
+use crate::helpers::create_progress_bar;
+#[cfg(feature = "cli")]
+use tracing::warn;
+
+// Page IDs fit in a u32 on every wiki today. Building with --features u64-ids widens them everywhere, and links.bin
+// records the width it was written with so a build can tell whether it can read a file.
+#[cfg(not(feature = "u64-ids"))]
+pub type PageId = u32;
+#[cfg(feature = "u64-ids")]
+pub type PageId = u64;
+
+// The graph maps are keyed by article ID and hit once per link by the analyses, so they use FxHash
+pub struct LinkGraph {
+    pub links: FxHashMap<PageId, Vec<PageId>>,
+    pub titles: FxHashMap<PageId, String>,
+    pub pages: FxHashMap<PageId, PageInfo>,
+}
+
+pub struct Record {
+    pub article_id: PageId,
+    pub title: String,
+    pub info: PageInfo,
+    pub links: Vec<PageId>,
+}
+
+// Page metadata stored in each record. Fields that a file's version doesn't have read as zero.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct PageInfo {
+    pub namespace: u32,
+    pub redirect: bool,
+    pub text_length: u32,  // bytes of wikitext
+    pub word_count: u32,
+}
+
+impl PageInfo {
+    pub fn write(&self, buffer: &mut Vec<u8>) {
+        write_varint(buffer, self.namespace);
+        write_varint(buffer, if self.redirect { REDIRECT_FLAG } else { 0 });
+        write_varint(buffer, self.text_length);
+        write_varint(buffer, self.word_count);
+    }
+
+    pub fn read(buffer: &[u8], offset: &mut usize) -> Result<Self, String> {
+        let namespace = read_varint(buffer, offset)?;
+        let redirect = read_varint(buffer, offset)? & REDIRECT_FLAG != 0;
+        Ok(PageInfo { namespace, redirect, text_length: read_varint(buffer, offset)?, word_count: read_varint(buffer, offset)? })
+    }
+}
+
+// Version 1 files have no header, and each record is: article_id, title_length, title, link_count, link_ids...,
+// u32::MAX (all little-endian u32s). Version 2 files start with MAGIC and a little-endian u32 version, and each
+// record is: body_length, then a body of article_id, title_length, title, link_count, and the link IDs sorted and
+// delta-encoded, with every integer stored as a LEB128 varint. The body length lets readers skip records without
+// decoding their links. Version 3 adds the page's namespace ID and a flags varint (bit 0 set for redirects) after
+// the article_id, and version 4 follows those with the length of the article's wikitext in bytes and its word
+// count. Records from older versions read as mainspace pages that aren't redirects, with zero length. Version 5 has
+// the same records, but the header goes on to a little-endian u32 with the width of a page ID in bytes, 4 or 8
+// depending on whether the writer was built with u64-ids. Older versions always have 4-byte IDs.
+pub const MAGIC: &[u8; 4] = b"WKLN";
+pub const VERSION: u32 = 5;
+pub const ID_WIDTH: u32 = size_of::<PageId>() as u32;
+const REDIRECT_FLAG: u32 = 1;
+// The high bit of the version marks a file that's still being written, or that an interrupted index left behind.
+// The index clears it once every chunk has been written.
+const PARTIAL_FLAG: u32 = 1 << 31;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LinksVersion { V1, V2, V3, V4, V5 }
+
+pub fn get_header(partial: bool) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&(if partial { VERSION | PARTIAL_FLAG } else { VERSION }).to_le_bytes());
+    header.extend_from_slice(&ID_WIDTH.to_le_bytes());
+    header
+}
+
+pub fn is_partial(buffer: &[u8]) -> bool {
+    buffer.starts_with(MAGIC) && read_u32(buffer, MAGIC.len()).is_ok_and(|version| version & PARTIAL_FLAG != 0)
+}
+
+// Rewrites the version field of an open links file to set or clear the partial flag
+pub fn set_partial(file: &mut File, partial: bool) {
+    let header = get_header(partial);
+    file.seek(SeekFrom::Start(0)).expect("Failed to seek to header");
+    file.write_all(&header).expect("Failed to write header");
+    file.seek(SeekFrom::End(0)).expect("Failed to seek to end of file");
+}
+
+// Returns the file's format version and the offset of its first record
+pub fn read_header(buffer: &[u8]) -> Result<(LinksVersion, usize), String> {
+    if !buffer.starts_with(MAGIC) {
+        return Ok((LinksVersion::V1, 0));
+    }
+    match read_u32(buffer, MAGIC.len())? & !PARTIAL_FLAG {
+        2 => Ok((LinksVersion::V2, MAGIC.len() + 4)),
+        3 => Ok((LinksVersion::V3, MAGIC.len() + 4)),
+        4 => Ok((LinksVersion::V4, MAGIC.len() + 4)),
+        5 => check_id_width(read_u32(buffer, MAGIC.len() + 4)?).map(|_| (LinksVersion::V5, MAGIC.len() + 8)),
+        version => Err(format!("unsupported links file version {}", version)),
+    }
+}
+
+// Checks that this build can read page IDs of the width a file header records
+pub fn check_id_width(width: u32) -> Result<(), String> {
+    match width {
+        4 | 8 if width <= ID_WIDTH => Ok(()),
+        4 | 8 => Err(format!("the file has {}-byte page IDs, rebuild with --features u64-ids to read it", width)),
+        _ => Err(format!("unsupported page ID width {}", width)),
+    }
+}
+
+// Decodes a little-endian page ID stored in 4 or 8 bytes, after check_id_width has accepted the width
+pub fn read_fixed_id(bytes: &[u8]) -> PageId {
+    match bytes.len() {
+        4 => u32::from_le_bytes(bytes.try_into().unwrap()) as PageId,
+        _ => u64::from_le_bytes(bytes.try_into().unwrap()) as PageId,
+    }
+}
+
+pub fn write_varint(buffer: &mut Vec<u8>, value: u32) {
+    write_varint64(buffer, value as u64);
+}
+
+fn write_varint64(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+// Reads the varint at `*offset` and advances past it
+pub fn read_varint(buffer: &[u8], offset: &mut usize) -> Result<u32, String> {
+    let value = read_varint64(buffer, offset)?;
+    u32::try_from(value).map_err(|_| format!("varint {} overflows u32", value))
+}
+
+fn read_varint64(buffer: &[u8], offset: &mut usize) -> Result<u64, String> {
+    let start = *offset;
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *buffer.get(*offset).ok_or_else(|| format!("unexpected end of file at byte {}", buffer.len()))?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(format!("varint at byte {} is too long", start))
+}
+
+// Page IDs are varints like everything else, so the same records hold IDs of either width
+pub fn write_id(buffer: &mut Vec<u8>, id: PageId) {
+    write_varint64(buffer, id as u64);
+}
+
+pub fn read_id(buffer: &[u8], offset: &mut usize) -> Result<PageId, String> {
+    let value = read_varint64(buffer, offset)?;
+    if value > PageId::MAX as u64 {
+        return Err(format!("page ID {} is too large, rebuild with --features u64-ids", value));
+    }
+    Ok(value as PageId)
+}
+
+// Writes the link count followed by the sorted, delta-encoded link IDs
+pub fn write_links(buffer: &mut Vec<u8>, link_ids: &[PageId]) {
+    let mut sorted_links = link_ids.to_vec();
+    sorted_links.sort_unstable();
+    write_varint(buffer, sorted_links.len() as u32);
+    let mut previous = 0;
+    for link_id in sorted_links {
+        write_id(buffer, link_id - previous);
+        previous = link_id;
+    }
+}
+
+// Reads a link list written by write_links, where `body` ends at the end of the record
+pub fn read_links(body: &[u8], offset: &mut usize) -> Result<Vec<PageId>, String> {
+    // Every link takes at least one byte, so a count larger than the rest of the body is corrupt
+    let link_count = read_varint(body, offset)? as usize;
+    if link_count > body.len() - *offset {
+        return Err(format!("link count {} runs past end of record", link_count));
+    }
+    let mut links = Vec::with_capacity(link_count);
+    let mut previous: PageId = 0;
+    for _ in 0..link_count {
+        previous = previous.checked_add(read_id(body, offset)?).ok_or("link ID overflows the page ID type")?;
+        links.push(previous);
+    }
+    Ok(links)
+}
+
+// Encodes a version 5 record. Links are sorted, so their order isn't preserved.
+pub fn get_article_byte_string(article_id: PageId, title: &str, info: &PageInfo, link_ids: &[PageId]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_id(&mut body, article_id);
+    info.write(&mut body);
+    write_varint(&mut body, title.len() as u32);
+    body.extend_from_slice(title.as_bytes());
+
+    write_links(&mut body, link_ids);
+
+    let mut output_buffer = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut output_buffer, body.len() as u32);
+    output_buffer.extend_from_slice(&body);
+    output_buffer
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Result<u32, String> {
+    buffer.get(offset..offset+4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| format!("unexpected end of file at byte {}", buffer.len()))
+}
+
+// Reads just the length fields of the record starting at `offset` to find where the next one starts
+#[cfg(feature = "cli")]
+fn next_record_offset(buffer: &[u8], offset: usize, version: LinksVersion) -> Result<usize, String> {
+    match version {
+        LinksVersion::V1 => {
+            let title_length = read_u32(buffer, offset+4)? as usize;
+            let link_count = read_u32(buffer, offset+8+title_length)? as usize;
+            Ok(offset + 8 + title_length + 4 + 4 * link_count + 4)
+        }
+        LinksVersion::V2 | LinksVersion::V3 | LinksVersion::V4 | LinksVersion::V5 => {
+            let mut i = offset;
+            let body_length = read_varint(buffer, &mut i)? as usize;
+            Ok(i + body_length)
+        }
+    }
+}
+
+fn parse_record_v1(buffer: &[u8], offset: usize) -> Result<(Record, usize), String> {
+    let article_id = read_u32(buffer, offset)? as PageId;
+    let title_length = read_u32(buffer, offset+4)? as usize;
+    let title_bytes = buffer.get(offset+8..offset+8+title_length)
+        .ok_or_else(|| format!("title length {} runs past end of file", title_length))?;
+    let title = String::from_utf8(title_bytes.to_vec()).map_err(|_| "title is not valid UTF-8".to_string())?;
+    let link_count = read_u32(buffer, offset+8+title_length)? as usize;
+    let links_start = offset + 8 + title_length + 4;
+    if links_start + 4 * link_count + 4 > buffer.len() {
+        return Err(format!("link count {} runs past end of file", link_count));
+    }
+    let links: Vec<PageId> = (0..link_count)
+        .map(|j| read_fixed_id(&buffer[links_start+4*j..links_start+4*j+4]))
+        .collect();
+    let separator = read_u32(buffer, links_start + 4 * link_count)?;
+    if separator != u32::MAX {
+        return Err(format!("expected separator u32::MAX, found {:#010x}", separator));
+    }
+
+    Ok((Record { article_id, title, info: PageInfo::default(), links }, links_start + 4 * link_count + 4))
+}
+
+fn parse_record_v2(buffer: &[u8], offset: usize, version: LinksVersion) -> Result<(Record, usize), String> {
+    let mut i = offset;
+    let body_length = read_varint(buffer, &mut i)? as usize;
+    let body_start = i;
+    let end = body_start + body_length;
+    let body = buffer.get(..end).ok_or_else(|| format!("record length {} runs past end of file", body_length))?;
+
+    let article_id = read_id(body, &mut i)?;
+    let info = match version {
+        LinksVersion::V3 => {
+            let namespace = read_varint(body, &mut i)?;
+            PageInfo { namespace, redirect: read_varint(body, &mut i)? & REDIRECT_FLAG != 0, ..Default::default() }
+        }
+        LinksVersion::V4 | LinksVersion::V5 => PageInfo::read(body, &mut i)?,
+        _ => PageInfo::default(),
+    };
+    let title_length = read_varint(body, &mut i)? as usize;
+    let title_bytes = body.get(i..i+title_length)
+        .ok_or_else(|| format!("title length {} runs past end of record", title_length))?;
+    let title = String::from_utf8(title_bytes.to_vec()).map_err(|_| "title is not valid UTF-8".to_string())?;
+    i += title_length;
+
+    let links = read_links(body, &mut i)?;
+    if i != end {
+        return Err(format!("record length {} doesn't match its contents ({} bytes)", body_length, i - body_start));
+    }
+
+    Ok((Record { article_id, title, info, links }, end))
+}
+
+// Parses the record starting at `offset`, returning it along with the offset of the next record
+pub fn parse_record(buffer: &[u8], offset: usize, version: LinksVersion) -> Result<(Record, usize), String> {
+    match version {
+        LinksVersion::V1 => parse_record_v1(buffer, offset),
+        LinksVersion::V2 | LinksVersion::V3 | LinksVersion::V4 | LinksVersion::V5 => parse_record_v2(buffer, offset, version),
+    }
+}
+
+pub fn read_links_file(links_file_path: &Path) -> Vec<u8> {
+    if !links_file_path.exists() {
+        eprintln!("Error: Unable to locate {}", links_file_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+
+    let file = File::open(links_file_path).expect("Unable to open links file");
+    let mut reader = BufReader::new(file);
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).expect("Unable to read links file");
+    buffer
+}
+
+#[cfg(feature = "cli")]
+pub fn load_links(links_file_path: &Path) -> LinkGraph {
+    let buffer = read_links_file(links_file_path);
+    parse_links_file(links_file_path, &buffer).unwrap_or_else(|err| panic!("{}", err))
+}
+
+// Like load_links, but returns an error instead of exiting or panicking when the file is missing or corrupt, for
+// callers that mustn't take their process down
+#[cfg(feature = "cli")]
+pub fn try_load_links(links_file_path: &Path) -> Result<LinkGraph, String> {
+    let buffer = std::fs::read(links_file_path).map_err(|err| format!("Unable to read {}: {}", links_file_path.to_str().unwrap(), err))?;
+    parse_links_file(links_file_path, &buffer)
+}
+
+#[cfg(feature = "cli")]
+fn parse_links_file(links_file_path: &Path, buffer: &[u8]) -> Result<LinkGraph, String> {
+    let (version, mut i) = read_header(buffer).map_err(|err| format!("Invalid links file: {}", err))?;
+    if is_partial(buffer) {
+        warn!("{} is from an interrupted index run and only has some of the articles, re-run index with --resume to finish it", links_file_path.to_str().unwrap());
+    }
+
+    // Find where each record starts, then parse the records in parallel
+    let progress_bar = create_progress_bar(buffer.len() as u64, "Scanning links");
+    let mut offsets = Vec::new();
+    while i < buffer.len() {
+        offsets.push(i);
+        i = next_record_offset(buffer, i, version).map_err(|err| format!("Corrupt record at byte {}: {}", i, err))?;
+        progress_bar.set_position(i as u64);
+    }
+    progress_bar.finish_and_clear();
+
+    let progress_bar = create_progress_bar(offsets.len() as u64, "Parsing links");
+    let records: Vec<Record> = offsets.par_iter()
+        .progress_with(progress_bar.clone())
+        .map(|&offset| parse_record(buffer, offset, version).map(|(record, _)| record).map_err(|err| format!("Corrupt record at byte {}: {}", offset, err)))
+        .collect::<Result<_, _>>()?;
+    progress_bar.finish_and_clear();
+
+    let mut links: FxHashMap<PageId, Vec<PageId>> = FxHashMap::with_capacity_and_hasher(records.len(), Default::default());
+    let mut titles: FxHashMap<PageId, String> = FxHashMap::with_capacity_and_hasher(records.len(), Default::default());
+    let mut pages: FxHashMap<PageId, PageInfo> = FxHashMap::with_capacity_and_hasher(records.len(), Default::default());
+    for record in records {
+        pages.insert(record.article_id, record.info);
+        titles.insert(record.article_id, record.title);
+        links.insert(record.article_id, record.links);
+    }
+
+    Ok(LinkGraph { links, titles, pages })
+}
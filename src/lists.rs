@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use html_escape::decode_html_entities;
+use indicatif::ProgressIterator;
+use serde_json::{Value, json};
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::plain_text;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::index::normalize_link;
+use crate::infobox::split_top_level;
+use crate::titles::TitleTable;
+
+// Returns the target of every link in a piece of wikitext, with the ID of the article it points to if it exists
+fn row_links(text: &str, titles: &TitleTable) -> Vec<Value> {
+    let mut links = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start + 2..].find("]]") else { break };
+        let inner = &rest[start + 2..start + 2 + length];
+        if let Some(lowercase) = normalize_link(inner) {
+            let target = decode_html_entities(inner.split(['|', '#']).next().unwrap().trim()).to_string();
+            if !target.is_empty() {
+                links.push(json!({ "target": target, "id": titles.find(lowercase.trim()) }));
+            }
+        }
+        rest = &rest[start + 2 + length + 2..];
+    }
+    links
+}
+
+// Drops the attributes from a table cell like `style="text-align:left" | [[Paris]]`
+fn cell_content(cell: &str) -> &str {
+    let parts = split_top_level(cell);
+    match parts.as_slice() {
+        [attributes, content] if attributes.contains('=') => content,
+        _ => cell,
+    }
+}
+
+// A list article's rows in the order they appear: each bulleted or numbered item, and each row of each table with the
+// table's column headers
+struct ListParser<'a> {
+    id: PageId,
+    title: &'a str,
+    titles: &'a TitleTable,
+    section: String,
+    columns: Vec<String>,
+    cells: Vec<String>,  // raw wikitext of the cells of the table row being read
+    header_row: bool,
+    rows: Vec<Value>,
+}
+
+impl ListParser<'_> {
+    fn push(&mut self, kind: &str, raw: &str, fields: Value) {
+        let mut row = json!({ "list_id": self.id, "list_title": self.title, "section": self.section, "kind": kind });
+        row.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        row["links"] = Value::Array(row_links(raw, self.titles));
+        self.rows.push(row);
+    }
+
+    // Finishes the table row being read. A row of only header cells before any data gives the table's columns.
+    fn end_row(&mut self) {
+        if self.cells.is_empty() { return; }
+        let cells = std::mem::take(&mut self.cells);
+        let texts: Vec<String> = cells.iter().map(|cell| plain_text(cell_content(cell)).replace('\n', " ")).collect();
+        if self.header_row {
+            self.columns = texts;
+        } else if texts.iter().any(|text| !text.is_empty()) {
+            let columns = self.columns.clone();
+            self.push("row", &cells.join("\n"), json!({ "columns": columns, "cells": texts }));
+        }
+    }
+
+    fn parse(&mut self, text: &str) {
+        let mut in_table = false;
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            if in_table {
+                if trimmed.starts_with("|}") {
+                    self.end_row();
+                    in_table = false;
+                } else if trimmed.starts_with("|-") {
+                    self.end_row();
+                    self.header_row = false;
+                } else if trimmed.starts_with("|+") {
+                    continue;
+                } else if let Some(cells) = trimmed.strip_prefix('!') {
+                    // Header cells only name the columns in a row of nothing but header cells
+                    self.header_row = self.header_row || (self.cells.is_empty() && self.columns.is_empty());
+                    self.cells.extend(cells.split("!!").flat_map(|cell| cell.split("||")).map(str::to_string));
+                } else if let Some(cells) = trimmed.strip_prefix('|') {
+                    self.header_row = false;
+                    self.cells.extend(cells.split("||").map(str::to_string));
+                } else if let Some(last) = self.cells.last_mut() {
+                    last.push('\n');
+                    last.push_str(line);
+                }
+            } else if trimmed.starts_with("{|") {
+                in_table = true;
+                self.columns.clear();
+                self.header_row = false;
+            } else if trimmed.starts_with('=') && trimmed.trim_end().ends_with('=') {
+                self.section = trimmed.trim_matches(|c: char| c == '=' || c.is_whitespace()).to_string();
+            } else if trimmed.starts_with(['*', '#']) {
+                let item = trimmed.trim_start_matches(['*', '#', ':']);
+                let text = plain_text(item);
+                if !text.is_empty() {
+                    self.push("item", item, json!({ "text": text }));
+                }
+            }
+        }
+    }
+}
+
+// Parses the items and table rows of every "List of ..." article into lists.ndjson, one JSON object per row with the
+// list's ID and title, the section it's in, the row's plain text (or its cells and the table's column headers), and
+// the articles it links to, with their IDs when they exist.
+pub fn lists(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("lists.ndjson"));
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let titles = Arc::new(TitleTable::new(seek_position_map.values()
+        .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating title index"))
+        .flatten()));
+    let chunk_ranges: Vec<(usize, u64, u64)> = get_chunk_ranges(&seek_position_map, &articles_path, args).into_iter()
+        .filter(|(_, start_position, _)| seek_position_map[start_position].iter().any(|(_, title)| title.starts_with("List of ")))
+        .collect();
+
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let output_file = Arc::new(Mutex::new(BufWriter::new(File::create(&output_path).expect("Failed to create output file"))));
+    let totals = Arc::new(Mutex::new((0, 0)));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Parsing lists"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let titles = Arc::clone(&titles);
+        let output_file = Arc::clone(&output_file);
+        let totals = Arc::clone(&totals);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut pages: Vec<_> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(_, page)| page.namespace == 0 && !page.redirect && page.title.starts_with("List of "))
+                .collect();
+            pages.sort_unstable_by_key(|(id, _)| *id);
+            let mut output = Vec::new();
+            let mut rows = 0;
+            for (id, page) in &pages {
+                let mut parser = ListParser {
+                    id: *id, title: &page.title, titles: &titles, section: String::new(), columns: Vec::new(), cells: Vec::new(), header_row: false, rows: Vec::new(),
+                };
+                parser.parse(&page.text);
+                for row in &parser.rows {
+                    writeln!(output, "{}", row).unwrap();
+                }
+                rows += parser.rows.len();
+            }
+            output_file.lock().unwrap().write_all(&output).expect("Failed to write output file");
+            let mut totals = totals.lock().unwrap();
+            totals.0 += pages.len();
+            totals.1 += rows;
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    output_file.lock().unwrap().flush().expect("Failed to flush output file");
+
+    let (lists, rows) = *totals.lock().unwrap();
+    println!("Parsed {} rows from {} list articles", rows, lists);
+    println!("Wrote list rows to {}", output_path.to_str().unwrap());
+}
@@ -0,0 +1,40 @@
+use std::io::IsTerminal;
+use std::sync::atomic::Ordering;
+use tracing::Level;
+use crate::helpers::{Args, PROGRESS_ENABLED, PROGRESS_JSON};
+
+// Diagnostics go to stderr through tracing, while command results stay on stdout. The default level is info,
+// -v and -vv raise it to debug and trace, and -q lowers it to warnings only. --log-format json emits one JSON
+// object per event for log collectors.
+pub fn init(args: &Args) {
+    let level = match (args.count('v'), args.count('q')) {
+        (_, q) if q > 1 => Level::ERROR,
+        (_, 1) => Level::WARN,
+        (0, _) => Level::INFO,
+        (1, _) => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let builder = tracing_subscriber::fmt().with_writer(std::io::stderr).with_max_level(level);
+    match args.value("log-format") {
+        None | Some("text") => builder.without_time().with_target(false).with_ansi(std::io::stderr().is_terminal()).init(),
+        Some("json") => builder.json().init(),
+        Some(other) => {
+            eprintln!("Error: Unknown log format {} (expected text or json)", other);
+            std::process::exit(1);
+        }
+    }
+
+    // --progress json reports progress even when stderr isn't a terminal, since it's meant for wrapper scripts
+    match args.value("progress") {
+        None | Some("bars") => {}
+        Some("json") => PROGRESS_JSON.store(true, Ordering::Relaxed),
+        Some("none") => PROGRESS_ENABLED.store(false, Ordering::Relaxed),
+        Some(other) => {
+            eprintln!("Error: Unknown progress mode {} (expected bars, json or none)", other);
+            std::process::exit(1);
+        }
+    }
+    if args.flag("no-progress") || args.count('q') > 0 || !std::io::stderr().is_terminal() {
+        PROGRESS_ENABLED.store(false, Ordering::Relaxed);
+    }
+}
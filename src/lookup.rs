@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::helpers::{PageId, dump_size, load_chunk_pipelined, load_index};
+#[cfg(feature = "store")]
+use crate::store::ArticleStore;
+
+// Each decompressed chunk holds ~100 articles, typically a few MB of wikitext
+pub const DEFAULT_CACHE_SIZE: usize = 32;
+
+type Chunk = Arc<HashMap<PageId, (String, String)>>;
+
+// Least-recently-used cache of decompressed chunks, keyed by seek offset
+pub struct ChunkCache {
+    capacity: usize,
+    chunks: HashMap<u64, (Chunk, u64)>,  // position -> (chunk, last access tick)
+    tick: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        ChunkCache { capacity, chunks: HashMap::new(), tick: 0, hits: 0, misses: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn get(&mut self, position: u64) -> Option<Chunk> {
+        self.tick += 1;
+        match self.chunks.get_mut(&position) {
+            Some((chunk, last_access)) => {
+                *last_access = self.tick;
+                self.hits += 1;
+                Some(Arc::clone(chunk))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, position: u64, chunk: Chunk) {
+        if self.capacity == 0 { return; }
+        if self.chunks.len() >= self.capacity {
+            let oldest = self.chunks.iter().min_by_key(|(_, (_, last_access))| *last_access).map(|(position, _)| *position);
+            self.chunks.remove(&oldest.unwrap());
+        }
+        self.chunks.insert(position, (chunk, self.tick));
+    }
+}
+
+#[cfg(not(feature = "store"))]
+struct ArticleStore;
+
+#[cfg(not(feature = "store"))]
+impl ArticleStore {
+    fn open_for_dump(_data_path: &Path, _articles_path: &Path) -> Option<Self> {
+        None
+    }
+
+    fn get(&self, _id: PageId) -> Option<String> {
+        None
+    }
+}
+
+// Random access to individual articles in the multistream dump, keyed by title or ID. Articles are read from the
+// article store instead when the index or process command wrote one from the same dump, falling back to the dump for
+// any it doesn't have.
+pub struct ArticleLookup {
+    articles_path: String,
+    file_size: u64,
+    positions: Vec<u64>,  // sorted chunk start positions
+    titles_to_ids: HashMap<String, PageId>,  // lowercase title -> id
+    ids_to_articles: HashMap<PageId, (String, u64)>,  // id -> (title, chunk start position)
+    store: Option<ArticleStore>,
+    pub cache: Mutex<ChunkCache>,
+}
+
+impl ArticleLookup {
+    pub fn new(index_path: &Path, articles_path: &Path, cache_size: usize) -> Self {
+        let seek_position_map = load_index(index_path.to_str().unwrap());
+        let mut positions: Vec<u64> = seek_position_map.keys().copied().collect();
+        positions.sort_unstable();
+
+        let mut titles_to_ids = HashMap::new();
+        let mut ids_to_articles = HashMap::new();
+        for (position, articles) in seek_position_map {
+            for (id, title) in articles {
+                titles_to_ids.insert(title.to_lowercase(), id);
+                ids_to_articles.insert(id, (title, position));
+            }
+        }
+
+        ArticleLookup {
+            articles_path: articles_path.to_str().unwrap().to_string(),
+            file_size: dump_size(articles_path),
+            positions,
+            titles_to_ids,
+            ids_to_articles,
+            store: index_path.parent().and_then(|data_path| ArticleStore::open_for_dump(data_path, articles_path)),
+            cache: Mutex::new(ChunkCache::new(cache_size)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids_to_articles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids_to_articles.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &PageId> {
+        self.ids_to_articles.keys()
+    }
+
+    pub fn find(&self, title: &str) -> Option<PageId> {
+        self.titles_to_ids.get(&title.trim().to_lowercase()).copied()
+    }
+
+    pub fn title(&self, id: PageId) -> Option<&str> {
+        self.ids_to_articles.get(&id).map(|(title, _)| title.as_str())
+    }
+
+    // The start positions of the dump's chunks in file order
+    pub fn positions(&self) -> &[u64] {
+        &self.positions
+    }
+
+    pub fn position(&self, id: PageId) -> Option<u64> {
+        self.ids_to_articles.get(&id).map(|(_, position)| *position)
+    }
+
+    // Returns the IDs of articles whose titles contain `query`, shortest titles first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<PageId> {
+        let query = query.trim().to_lowercase();
+        let mut matches: Vec<(&String, PageId)> = self.titles_to_ids.iter()
+            .filter(|(title, _)| title.contains(&query))
+            .map(|(title, id)| (title, *id))
+            .collect();
+        matches.sort_by_key(|(title, id)| (title.len(), *id));
+        matches.into_iter().take(limit).map(|(_, id)| id).collect()
+    }
+
+    // Returns the decompressed chunk starting at `start_position`, from the cache if possible
+    pub fn get_chunk(&self, start_position: u64) -> Chunk {
+        if let Some(chunk) = self.cache.lock().unwrap().get(start_position) {
+            return chunk;
+        }
+        let next = self.positions.partition_point(|&position| position <= start_position);
+        let end_position = self.positions.get(next).copied().unwrap_or(self.file_size);
+        let chunk = Arc::new(load_chunk_pipelined(&self.articles_path, start_position, end_position));
+        self.cache.lock().unwrap().insert(start_position, Arc::clone(&chunk));
+        chunk
+    }
+
+    pub fn get(&self, id: PageId) -> Option<String> {
+        if let Some(text) = self.store.as_ref().and_then(|store| store.get(id)) {
+            return Some(text);
+        }
+        let chunk = self.get_chunk(self.position(id)?);
+        chunk.get(&id).map(|(_, content)| content.clone())
+    }
+}
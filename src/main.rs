@@ -1,35 +1,200 @@
-mod index;
-mod analyse;
-mod helpers;
-mod dump;
-
 use std::env;
-use std::path::Path;
+use wikipedia::*;
+use wikipedia::helpers::Args;
 
 fn print_commands() {
     println!("Available commands:");
-    println!("  index    - Run the indexing process");
-    println!("  analyse  - Run the analysis process");
-    println!("  dump     - Dump articles into individual files");
+    println!("  index    - Run the indexing process (--limit N, --byte-range START-END, --resume after an interrupted run, --deterministic writes chunks in dump order,");
+    println!("             --first-links also records each article's first link for philosophy,");
+    println!("             --with-positions records the byte offset of every link occurrence in positions.bin,");
+    println!("             --with-files records the [[File:...]] and [[Image:...]] references of every article in files.bin,");
+    println!("             --with-categories records the categories of every page in categories.bin for analyse's breakdown by category,");
+    println!("             --with-redirects lists every redirect and its target in redirects.tsv,");
+    println!("             --lead-links-only keeps only the links before each article's first heading,");
+    println!("             --dump also writes the articles out in the same pass, taking the dump command's options,");
+    println!("             --store writes the articles to an embedded key-value store in article-store/ that serve, shell and book read them from (store feature),");
+    println!("             --chunk-stats reports chunk sizes, per-chunk times and hot chunks and writes chunk-times.tsv,");
+    println!("             --upload s3://BUCKET/PREFIX|gs://BUCKET/PREFIX|DIR copies the outputs there when indexing finishes)");
+    println!("  process  - Write any of the index outputs and the article dump in a single pass over the dump (--extract links,categories,redirects,text,");
+    println!("             also files, positions, first-links and store, with the index options and, for text, the dump command's options)");
+    println!("  analyse  - Run the analysis process (also writes double-redirects.tsv and broken-redirects.tsv, --k-core adds a k-core decomposition,");
+    println!("             --distances estimates the effective diameter and average distance with HyperANF, --registers N,");
+    println!("             breaks counts and degrees down by namespace and, with categories.bin, by top-level category under --top-category NAME)");
+    println!("  analyse-text - Count words in the plain text of every article and fit Zipf's law, writing word-frequencies.csv, articles.csv and zipf.csv");
+    println!("             to text-stats (--output DIR, --format csv|parquet, --min-count N, --keep-case, --article-terms also writes every article's term counts)");
+    println!("  communities - Detect communities with Louvain and write communities.tsv (--resolution R, --output FILE)");
+    println!("  walks    - Write node2vec random walks for training embeddings (--p P, --q Q, --length N, --walks N, --seed N, --tokens ids|titles,");
+    println!("             --directed, --output FILE)");
+    println!("  related  - List articles related to a title by personalized PageRank (related <data_path> <title> --limit N --alpha A --epsilon E)");
+    println!("  similarity - Write each article's most similar articles by co-citation and coupling to similarity.tsv (--limit N, --max-degree N, --output FILE)");
+    println!("  similar  - List the articles most similar to a title by co-citation and coupling (similar <data_path> <title> --limit N --max-degree N)");
+    println!("  embed    - Embed article passages with an OpenAI-compatible embeddings endpoint and write embeddings.bin and passages.jsonl");
+    println!("             (--endpoint URL, --model NAME, --api-key KEY, --batch-size N, --chunk-tokens N, --tokenizer words|FILE.tiktoken, --abstracts),");
+    println!("             or with --onnx-model DIR and the onnx feature, with a local sentence-embedding model in DIR/model.onnx and DIR/tokenizer.json");
+    println!("  index-semantic - Build an HNSW index over embeddings.bin for semantic search (--ef-construction N, --seed N)");
+    println!("  search-semantic - Find the passages nearest to a query (search-semantic <data_path> <query> --endpoint URL --model NAME or --onnx-model DIR --limit N --ef N)");
+    println!("  serve    - Serve articles at /article?title=TITLE (&format=html renders them) and passage retrieval for RAG at /retrieve?q=QUERY&k=N&mode=hybrid|bm25|vector");
+    println!("             (--port N, --endpoint URL, --model NAME, --onnx-model DIR, --ef N, --chunk-tokens N, --workers N, --queue N, --max-retrieve N, --max-article N,");
+    println!("             --timeout SECS, --cache-size N, --max-graphql N, --grpc-port N to also serve proto/wikipedia.proto with the grpc feature);");
+    println!("             with the graphql feature, GraphQL queries over articles, links, backlinks, categories and paths at /graphql");
+    println!("  viz      - Serve an interactive force-directed view of the link graph for exploring an article's neighborhood, expanding nodes on click");
+    println!("             (viz <data_path> [title] --port N, --max-neighbors N, --d3 URL to load D3 from somewhere other than its CDN)");
+    println!("  build-site - Render articles to a static HTML mirror in <data_path>/site, linked to each other, with title search and a random article page");
+    println!("             (--output DIR, --titles FILE with one title per line or --quality TIERS to render only those articles, --limit N, --byte-range START-END)");
+    println!("  export-book - Bundle articles into an EPUB or PDF with a table of contents for offline reading, written to <data_path>/book.epub");
+    println!("             (--titles FILE with one title per line, or --category NAME --depth N with categories.bin, --max-articles N, --format epub|pdf,");
+    println!("             --title TEXT, --language CODE, --output FILE)");
+    println!("  near-duplicates - Cluster near-duplicate articles with MinHash and write near-duplicates.tsv (--threshold T, --bands N, --shingle N, --output FILE)");
+    println!("  philosophy - Follow first-link chains and report how many reach Philosophy and which loops the rest end in (--target TITLE)");
+    println!("  biographies - Write the people with articles to biographies.ndjson with their name, birth and death dates and places, occupation and");
+    println!("             Wikidata item, from their infobox, date templates and categories (--format ndjson|tsv, --output FILE, --limit N, --byte-range START-END),");
+    println!("             reading Wikidata items from the page_props.sql.gz dump when it's in <data_path>");
+    println!("  lists    - Parse the bulleted items and table rows of every \"List of ...\" article into lists.ndjson with their section, text or cells and column");
+    println!("             headers, and the articles they link to (--output FILE, --limit N, --byte-range START-END)");
+    println!("  quality  - Assign each article a quality tier (FA, FL, A, GA, B, C, Start, Stub, List or Unassessed) from its featured, good and stub templates");
+    println!("             and the WikiProject banners on its talk page, and write quality.tsv (--limit N, --byte-range START-END)");
+    println!("  parse    - Write every page as NDJSON in dump order to pages.ndjson, or stream it to stdout for pipelines (--stdout, --output FILE, --limit N, --byte-range START-END,");
+    println!("             --source stub|abstract reads the much smaller stub-meta-current or abstract dump instead, for metadata or lead text without article text,");
+    println!("             --wiktionary writes one entry per language and part of speech of a Wiktionary dump with its definitions, --language NAME)");
+    println!("  recompress - Convert the bz2 dump into a zstd dump that every command reads several times faster (--level N, default 9)");
+    println!("  watch    - Download, index and export each new dump snapshot once it's finished (--wiki enwiki, --every 24h, --mirror URL, --exports F,G, --keep N, --once)");
+    println!("  dump     - Dump articles into individual files (--limit N, --byte-range START-END, --resume, --name-by-title, --compress zstd|gzip, --archive FILE.tar[.zst|.gz],");
+    println!("             --quality FA,GA keeps only the articles quality.tsv puts in those tiers,");
+    println!("             --metadata yaml|json starts each article with its id, title, namespace, revision timestamp and redirect target)");
+    println!("  history  - Analyse a pages-meta-history dump (edits, editors, revision <title> <id|timestamp>)");
+    println!("  diff     - Compare two links.bin snapshots (diff <old_links.bin> <new_links.bin>)");
+    println!("  merge    - Merge partial links.bin segments (merge <out.bin> <segment.bin>...)");
+    println!("  verify   - Check a links.bin file for corruption (verify <links.bin>)");
+    println!("  verify-dump - Check the dump files against the published md5sums/dumpstatus.json");
+    println!("  gen-testdata - Generate a small synthetic dump and index in <data_path>");
+    println!("  grep     - Print every line of article wikitext matching a regex as title:line:offset:text (grep <data_path> <pattern> --namespace N,M,");
+    println!("             --max-count N stops after N matches, --ignore-case, --limit N, --byte-range START-END)");
+    println!("  bench    - Time reading, decompressing, parsing and link extraction on one thread and whole chunks on every thread, over a fixed sample");
+    println!("             of the dump, to compare machines and catch regressions (--chunks N, --iterations N, --output FILE writes the report as JSON)");
+    println!("  stats    - Report page counts by namespace, the number of chunks and their sizes, and an estimated index time from the dump index alone");
+    println!("  random   - Print the title and first paragraph of a random article");
+    println!("  export   - Export the link graph for other tools (--format csr|bv|npz|neo4j|postgres|duckdb|elasticsearch|meilisearch|llm-jsonl|ngrams|candidates|sentences|zim|sqlite-fts, --output DIR, --connection URL, --batch-size N, --endpoint URL,");
+    println!("             --upload s3://BUCKET/PREFIX|gs://BUCKET/PREFIX|DIR copies the exported files there)");
+    println!("             llm-jsonl writes plain text chunks with title, id and section to chunks.jsonl (--chunk-tokens N, --tokenizer words|FILE.tiktoken, --compress zstd|gzip, --quality FA,GA)");
+    println!("             ngrams writes sharded counts of every 1- to N-gram of plain article text (--max-n N up to 5, --min-count N, --shards N, --keep-case)");
+    println!("             candidates writes an entity-linking table of surface forms and their ranked candidate articles with prior probabilities, from");
+    println!("             anchor text, redirects, titles and disambiguation pages, to candidates.tsv (--min-count N, --max-candidates N)");
+    println!("             sentences writes plain text one sentence per line with its links as character offsets and article IDs to sentences.jsonl");
+    println!("             (--linked-only, --compress zstd|gzip, --quality FA,GA)");
+    println!("             zim packages the pages build-site renders into <dump prefix>.zim for Kiwix and other offline readers (--titles FILE, --quality FA,GA,");
+    println!("             --language CODE in ISO 639-3, --date YYYY-MM-DD if the dump prefix has no date)");
+    println!("             sqlite-fts writes article text with an FTS5 full-text index to wikipedia.sqlite for searching with sqlite3 (sqlite feature,");
+    println!("             --quality FA,GA, --limit N, --byte-range START-END)");
+    println!("  export-titles - Write every page's id and title sorted by ID to titles.tsv straight from the dump index (--format tsv|csv, --output FILE,");
+    println!("             --namespaces and --redirects add each page's namespace and redirect target, read from the stub dump if present, --limit N, --byte-range START-END)");
+    println!("  sample   - Export a sampled subgraph (--method node|edge|forest-fire, --size N, --burn P, --seed N, --format F, --output DIR)");
+    println!("  sample-articles - Draw articles evenly across strata and write them with a sample.tsv manifest (--by length|in-degree|category, --size N,");
+    println!("             --strata N, --categories A,B, --seed N, --format txt|ndjson, --metadata yaml|json, --output DIR)");
+    println!("  index-search - Build a full-text search index over titles and article text (--limit N, --byte-range START-END)");
+    println!("  search-text - Query the full-text search index (search-text <data_path> <query> --limit N --title-boost N)");
+    println!("  complete - Complete a title prefix using titles.fst (complete <data_path> <prefix> --limit N --fuzzy N)");
+    println!("  shell    - Load the indexes once and answer interactive queries (--cache-size N)");
+    println!();
+    println!("index, process, dump and analyse write run-summary.json to <data_path> (--no-hash skips hashing the inputs, --memory-stats records the peak RSS of each stage)");
+    println!("index and dump check for enough disk space and memory before starting (--force runs anyway)");
+    println!("Settings are read from wikipedia.toml or --config FILE (data_path, dump_prefix, dump_url, threads, ignore_namespaces, include_namespaces, [output] dump/export)");
+    println!("Global options: -v/-vv (more logging), -q/-qq (less logging), --log-format text|json, --progress bars|json|none, --no-progress, --threads N, --dump-prefix PREFIX,");
+    println!("                --ignore-namespaces A,B, --include-namespaces A,B (e.g. --include-namespaces Category,Portal),");
+    println!("                the default ignored namespaces follow the project in the dump prefix, so Wiktionary, Wikisource and other sister project dumps work too");
+    println!("                --dump-url URL reads the dump from an http(s)://, s3:// or gs:// location with range requests, downloading only the index and the chunks a command needs");
+    println!("Building with the async-io feature serves HTTP, reads remote dumps and downloads snapshots on an async runtime, with many requests in flight on a few threads");
+    println!("Buckets use AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY (GCS_ACCESS_KEY_ID/GCS_SECRET_ACCESS_KEY HMAC keys for gs://), AWS_REGION and AWS_ENDPOINT_URL for other S3-compatible services");
 }
 
+// Commands that read files named on the command line rather than a data directory
+const NO_DATA_PATH: [&str; 4] = ["diff", "merge", "verify", "history"];
+// Commands whose data path is followed by a title, query or pattern. With a data path in the config file, a single
+// positional is that argument, so a title of several words needs quotes.
+const ARGUMENT_AFTER_DATA_PATH: [&str; 7] = ["related", "similar", "search-semantic", "search-text", "complete", "grep", "viz"];
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        println!("Usage: {} <command> <data_path>", args[0]);
+    if args.len() < 2 {
+        println!("Usage: {} <command> <data_path> [options]", args[0]);
         print_commands();
         return;
     }
 
     let command = &args[1];
-    let data_path = Path::new(&args[2]);
+    let mut options = Args::parse(&args[2..]);
+    logging::init(&options);
+    config::load(&options);
+
+    // Commands that take a data path can get it from the config file instead, when they're given fewer positionals
+    // than they take with one. Whether a positional names a directory doesn't say anything, since gen-testdata creates
+    // its own and the title a command looks up could be a directory too.
+    let takes_data_path = !NO_DATA_PATH.contains(&command.as_str());
+    let positionals = if ARGUMENT_AFTER_DATA_PATH.contains(&command.as_str()) { 2 } else { 1 };
+    if let Some(data_path) = config::config().data_path.as_ref().filter(|_| takes_data_path) {
+        if options.positional.len() < positionals {
+            options.positional.insert(0, data_path.to_str().unwrap().to_string());
+        }
+    }
+    if options.positional.is_empty() {
+        println!("Usage: {} <command> <data_path> [options]", args[0]);
+        print_commands();
+        return;
+    }
     match command.as_str() {
-        "index" => index::index(data_path),
-        "analyse" => analyse::analyse(data_path),
-        "dump" => dump::dump(data_path),
+        "index" => index::index(&options),
+        "process" => process::process(&options),
+        "analyse" => analyse::analyse(&options),
+        "analyse-text" => analyse_text::analyse_text(&options),
+        "philosophy" => philosophy::philosophy(&options),
+        "biographies" => biographies::biographies(&options),
+        "lists" => lists::lists(&options),
+        "quality" => quality::quality(&options),
+        "communities" => communities::communities(&options),
+        "walks" => walks::walks(&options),
+        "related" => related::related(&options),
+        "similarity" => similarity::similarity(&options),
+        "similar" => similarity::similar(&options),
+        "near-duplicates" => minhash::near_duplicates(&options),
+        "embed" => embed::embed(&options),
+        "index-semantic" => hnsw::index_semantic(&options),
+        "search-semantic" => hnsw::search_semantic(&options),
+        "serve" => serve::serve(&options),
+        "build-site" => site::build_site(&options),
+        "export-book" => book::export_book(&options),
+        "viz" => viz::viz(&options),
+        "parse" => parse::parse(&options),
+        "recompress" => recompress::recompress(&options),
+        "watch" => watch::watch(&options),
+        "dump" => dump::dump(&options),
+        "history" => history::history(&options),
+        "diff" => diff::diff(&options),
+        "merge" => merge::merge(&options),
+        "verify" => verify::verify(&options),
+        "verify-dump" => verify_dump::verify_dump(&options),
+        "gen-testdata" => gen_testdata::gen_testdata(&options),
+        "random" => random::random(&options),
+        "stats" => stats::stats(&options),
+        "bench" => bench::bench(&options),
+        "grep" => grep::grep(&options),
+        "shell" => shell::shell(&options),
+        "export" => export::export(&options),
+        "export-titles" => export_titles::export_titles(&options),
+        "sample" => sample::sample(&options),
+        "sample-articles" => sample_articles::sample_articles(&options),
+        "complete" => complete::complete(&options),
+        #[cfg(feature = "tantivy")]
+        "index-search" => search::index_search(&options),
+        #[cfg(feature = "tantivy")]
+        "search-text" => search::search_text(&options),
+        #[cfg(not(feature = "tantivy"))]
+        "index-search" | "search-text" => {
+            eprintln!("Error: {} requires building with --features tantivy", command);
+            std::process::exit(1);
+        }
         _ => {
             println!("Unknown command: {}", command);
             print_commands();
         }
     }
+    helpers::wait_for_progress_reporters();
 }
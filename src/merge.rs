@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::helpers::{Args, PageId, create_progress_bar};
+use crate::links::{Record, get_article_byte_string, get_header, is_partial, parse_record, read_header, read_links_file};
+use tracing::{info, warn};
+
+pub fn merge(args: &Args) {
+    let (output_path, segment_paths) = match &args.positional[..] {
+        [output_path, segment_paths @ ..] if !segment_paths.is_empty() => (Path::new(output_path), segment_paths),
+        _ => {
+            eprintln!("Usage: merge <out.bin> <segment.bin>...");
+            std::process::exit(1);
+        }
+    };
+    if segment_paths.iter().any(|segment_path| Path::new(segment_path) == output_path) {
+        eprintln!("Error: Output file {} is also listed as an input segment", output_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+
+    // Records from later segments replace records with the same article ID from earlier ones
+    let mut records: HashMap<PageId, Record> = HashMap::new();
+    let mut duplicates = 0;
+    let mut conflicts = 0;
+    // The output keeps the partial flag if any segment is from an interrupted run, so later commands still warn
+    let mut partial = false;
+    for segment_path in segment_paths {
+        let buffer = read_links_file(Path::new(segment_path));
+        let progress_bar = create_progress_bar(buffer.len() as u64, &format!("Reading {}", segment_path));
+        let mut segment_records = 0;
+        let (version, mut i) = read_header(&buffer).unwrap_or_else(|err| {
+            eprintln!("Error: {} is not a links file ({})", segment_path, err);
+            std::process::exit(1);
+        });
+        if is_partial(&buffer) {
+            warn!("{} is from an interrupted index run, so the merged file will be marked as incomplete", segment_path);
+            partial = true;
+        }
+        while i < buffer.len() {
+            match parse_record(&buffer, i, version) {
+                Ok((record, next)) => {
+                    if let Some(existing) = records.get(&record.article_id) {
+                        duplicates += 1;
+                        if existing.title != record.title || existing.links != record.links {
+                            conflicts += 1;
+                        }
+                    }
+                    records.insert(record.article_id, record);
+                    segment_records += 1;
+                    i = next;
+                }
+                Err(err) => {
+                    // Interrupted runs leave a truncated final record, so keep everything before the damage
+                    warn!("{} is corrupt at byte {} ({}), skipping the remaining {} bytes", segment_path, i, err, buffer.len() - i);
+                    partial = true;
+                    break;
+                }
+            }
+            progress_bar.set_position(i as u64);
+        }
+        progress_bar.finish_and_clear();
+        info!("Read {} records from {}", segment_records, segment_path);
+    }
+
+    let mut article_ids: Vec<PageId> = records.keys().copied().collect();
+    article_ids.sort_unstable();
+
+    let mut output_file = BufWriter::new(File::create(output_path).expect("Failed to create output file"));
+    output_file.write_all(&get_header(partial)).expect("Failed to write to output file");
+    for article_id in &article_ids {
+        let record = &records[article_id];
+        let output_buffer = get_article_byte_string(record.article_id, &record.title, &record.info, &record.links);
+        output_file.write_all(&output_buffer).expect("Failed to write to output file");
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    println!("Total articles merged: {}", article_ids.len());
+    println!("Duplicate article IDs: {} ({} with differing contents)", duplicates, conflicts);
+    if partial {
+        println!("Some segments were incomplete, so {} is marked as from an interrupted run", output_path.to_str().unwrap());
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHasher};
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::plain_text;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::hyperanf::splitmix64;
+
+// Each LSH band is this many minhashes, packed into one 64-bit key
+const ROWS: usize = 4;
+
+// Hash functions h(x) = a * x + b with a random odd multiplier, one per minhash
+fn permutations(count: usize) -> Vec<(u64, u64)> {
+    (0..count as u64).map(|i| (splitmix64(2 * i) | 1, splitmix64(2 * i + 1))).collect()
+}
+
+// The MinHash signature of an article's word shingles, keeping the top 16 bits of each minimum. Articles shorter
+// than one shingle are treated as a single shingle.
+fn signature(text: &str, shingle_size: usize, permutations: &[(u64, u64)]) -> Option<Vec<u16>> {
+    let words: Vec<u64> = text.split_whitespace().map(|word| {
+        let mut hasher = FxHasher::default();
+        hasher.write(word.to_lowercase().as_bytes());
+        hasher.finish()
+    }).collect();
+    if words.is_empty() { return None; }
+    let mut minima = vec![u64::MAX; permutations.len()];
+    for window in words.windows(shingle_size.min(words.len())) {
+        let shingle = splitmix64(window.iter().fold(0, |hash, word| hash.rotate_left(7) ^ word));
+        for (minimum, (a, b)) in minima.iter_mut().zip(permutations) {
+            *minimum = (*minimum).min(a.wrapping_mul(shingle).wrapping_add(*b));
+        }
+    }
+    Some(minima.into_iter().map(|minimum| (minimum >> 48) as u16).collect())
+}
+
+// The share of matching minhashes, which estimates the Jaccard similarity of the two articles' shingles
+fn estimated_similarity(a: &[u16], b: &[u16]) -> f64 {
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+fn find(parents: &mut [u32], mut node: u32) -> u32 {
+    while parents[node as usize] != node {
+        parents[node as usize] = parents[parents[node as usize] as usize];
+        node = parents[node as usize];
+    }
+    node
+}
+
+// Finds clusters of near-duplicate articles with MinHash and locality-sensitive hashing: articles whose signatures
+// agree on every minhash in some band become candidates, and candidates whose estimated similarity reaches the
+// threshold are merged into one cluster. Writes the clusters to near-duplicates.tsv.
+pub fn near_duplicates(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let threshold: f64 = args.parse_value("threshold").unwrap_or(0.8);
+    let bands: usize = args.parse_value("bands").unwrap_or(16);
+    let shingle_size: usize = args.parse_value("shingle").unwrap_or(5);
+    if !(threshold > 0.0 && threshold <= 1.0) || bands == 0 || shingle_size == 0 {
+        eprintln!("Error: --threshold must be between 0 and 1, and --bands and --shingle must be positive");
+        std::process::exit(1);
+    }
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("near-duplicates.tsv"));
+    let hash_count = bands * ROWS;
+    let permutations = Arc::new(permutations(hash_count));
+
+    // Sign every article in the main namespace from its plain text
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let signed = Arc::new(Mutex::new(Vec::new()));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Signing articles"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let permutations = Arc::clone(&permutations);
+        let signed = Arc::clone(&signed);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let pages = load_chunk_pages(&articles_path, start_position, end_position);
+            let chunk_signed: Vec<(PageId, String, Vec<u16>)> = pages.into_iter()
+                .filter(|(_, page)| page.namespace == 0 && !page.redirect)
+                .filter_map(|(id, page)| Some((id, page.title, signature(&plain_text(&page.text), shingle_size, &permutations)?)))
+                .collect();
+            signed.lock().unwrap().extend(chunk_signed);
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    let mut signed = Arc::try_unwrap(signed).ok().unwrap().into_inner().unwrap();
+    signed.sort_unstable_by_key(|(id, _, _)| *id);
+
+    // Within each band, sort the articles by their band key and compare each run of equal keys against its first
+    // article and each article against the one before it, which links up a cluster without comparing every pair
+    let mut parents: Vec<u32> = (0..signed.len() as u32).collect();
+    let progress_bar = create_progress_bar(bands as u64, "Comparing candidates");
+    for band in 0..bands {
+        let mut keys: Vec<(u64, u32)> = signed.par_iter().enumerate().map(|(index, (_, _, signature))| {
+            let key = signature[band * ROWS..(band + 1) * ROWS].iter().fold(0u64, |key, &minhash| (key << 16) | minhash as u64);
+            (key, index as u32)
+        }).collect();
+        keys.par_sort_unstable();
+        for group in keys.chunk_by(|a, b| a.0 == b.0).filter(|group| group.len() > 1) {
+            for (position, &(_, index)) in group.iter().enumerate().skip(1) {
+                for (_, other) in [group[0], group[position - 1]] {
+                    if estimated_similarity(&signed[index as usize].2, &signed[other as usize].2) >= threshold {
+                        let (root, other_root) = (find(&mut parents, index), find(&mut parents, other));
+                        parents[root.max(other_root) as usize] = root.min(other_root);
+                    }
+                }
+            }
+        }
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+
+    // Clusters are ranked by size, and each one is represented by its lowest article ID
+    let mut clusters: FxHashMap<u32, Vec<usize>> = FxHashMap::default();
+    for index in 0..signed.len() {
+        let root = find(&mut parents, index as u32);
+        clusters.entry(root).or_default().push(index);
+    }
+    let mut clusters: Vec<Vec<usize>> = clusters.into_values().filter(|members| members.len() > 1).collect();
+    for members in &mut clusters {
+        members.sort_unstable();
+    }
+    clusters.sort_unstable_by_key(|members| (std::cmp::Reverse(members.len()), members[0]));
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    writeln!(output_file, "cluster\tarticle_id\ttitle\tsimilarity").expect("Failed to write output file");
+    for (cluster, members) in clusters.iter().enumerate() {
+        let representative = &signed[members[0]].2;
+        for &member in members {
+            let (id, title, signature) = &signed[member];
+            writeln!(output_file, "{}\t{}\t{}\t{:.3}", cluster + 1, id, title, estimated_similarity(representative, signature)).expect("Failed to write output file");
+        }
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    let duplicates: usize = clusters.iter().map(|members| members.len()).sum();
+    println!("Signed {} articles with {} minhashes in {} bands", signed.len(), hash_count, bands);
+    println!("Found {} clusters covering {} near-duplicate articles ({:.2}%)", clusters.len(), duplicates, 100.0 * duplicates as f64 / signed.len().max(1) as f64);
+    println!("\nTop 10 largest clusters:");
+    for (rank, members) in clusters.iter().take(10).enumerate() {
+        let titles: Vec<&str> = members.iter().take(3).map(|&member| signed[member].1.as_str()).collect();
+        let more = if members.len() > 3 { ", ..." } else { "" };
+        println!("{:>2}) {} articles: {}{}", rank + 1, members.len(), titles.join(", "), more);
+    }
+    println!("\nWrote clusters to {}", output_path.to_str().unwrap());
+}
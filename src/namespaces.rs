@@ -0,0 +1,82 @@
+use crate::config::config;
+
+// Pages in these namespaces are skipped when loading the index and parsing articles, and links into them are
+// dropped. The list can be changed with ignore_namespaces/include_namespaces in wikipedia.toml or the
+// --ignore-namespaces and --include-namespaces options. "Wikipedia" stands for the project namespace, which the sister
+// projects name after themselves.
+pub const DEFAULT_IGNORED: [&str; 7] = ["Category", "Wikipedia", "File", "Template", "Draft", "Portal", "Module"];
+
+// Sister projects by the suffix of their database name (enwiktionary, frwikisource), with their project namespace and
+// the extra namespaces that hold project pages rather than content. Wikisource's Page and Index namespaces hold the
+// scanned pages that its works transclude, so reading them too would count every text twice.
+const PROJECTS: [(&str, &str, &[&str]); 8] = [
+    ("wiktionary", "Wiktionary", &["Appendix", "Rhymes", "Citations", "Concordance", "Index"]),
+    ("wikiquote", "Wikiquote", &[]),
+    ("wikisource", "Wikisource", &["Page", "Index"]),
+    ("wikibooks", "Wikibooks", &[]),
+    ("wikinews", "Wikinews", &[]),
+    ("wikiversity", "Wikiversity", &[]),
+    ("wikivoyage", "Wikivoyage", &[]),
+    ("wiki", "Wikipedia", &[]),
+];
+
+fn project(dump_prefix: &str) -> &'static (&'static str, &'static str, &'static [&'static str]) {
+    let database = dump_prefix.split('-').next().unwrap();
+    PROJECTS.iter().find(|(suffix, _, _)| database.ends_with(suffix)).unwrap_or(&PROJECTS[7])
+}
+
+// Returns the namespaces ignored by default for the wiki a dump prefix like enwiktionary-20240801 belongs to
+pub fn default_ignored(dump_prefix: &str) -> Vec<&'static str> {
+    let (_, project_namespace, extras) = project(dump_prefix);
+    DEFAULT_IGNORED.iter().map(|&namespace| if namespace == "Wikipedia" { project_namespace } else { namespace })
+        .chain(extras.iter().copied())
+        .collect()
+}
+
+// Returns the names of the namespaces other than the main one in the wiki a dump prefix belongs to, subject
+// namespaces and then their talk namespaces, which are what a title's prefix is matched against to tell its namespace
+pub fn namespace_names(dump_prefix: &str) -> Vec<String> {
+    let (_, project_namespace, extras) = project(dump_prefix);
+    let subjects: Vec<&str> = ["User", project_namespace, "File", "MediaWiki", "Template", "Help", "Category", "Portal", "Draft", "TimedText", "Module"]
+        .into_iter()
+        .chain(extras.iter().copied())
+        .collect();
+    let talk = subjects.iter().map(|subject| format!("{} talk", subject));
+    std::iter::once("Talk".to_string()).chain(subjects.iter().map(|subject| subject.to_string())).chain(talk).collect()
+}
+
+// Whether a dump prefix belongs to a Wiktionary
+pub fn is_wiktionary(dump_prefix: &str) -> bool {
+    dump_prefix.split('-').next().unwrap().ends_with("wiktionary")
+}
+
+// Turns namespace names like "Category" or "Category:" into the title prefixes they match
+pub fn to_prefixes<'a>(namespaces: impl Iterator<Item = &'a str>) -> Vec<String> {
+    namespaces.map(str::trim).filter(|namespace| !namespace.is_empty())
+        .map(|namespace| format!("{}:", namespace.trim_end_matches(':')))
+        .collect()
+}
+
+// Applies the namespaces to additionally ignore, then the ones to keep, to a list of ignored prefixes
+pub fn apply_overrides(prefixes: &mut Vec<String>, ignore: &[String], include: &[String]) {
+    for prefix in ignore {
+        if !prefixes.contains(prefix) {
+            prefixes.push(prefix.clone());
+        }
+    }
+    prefixes.retain(|prefix| !include.contains(prefix));
+}
+
+// The canonical names of the standard namespaces, by ID
+const NAMESPACE_NAMES: [(u32, &str); 16] = [
+    (0, "(Main)"), (1, "Talk"), (2, "User"), (3, "User talk"), (4, "Project"), (5, "Project talk"), (6, "File"), (7, "File talk"),
+    (8, "MediaWiki"), (10, "Template"), (12, "Help"), (14, "Category"), (15, "Category talk"), (100, "Portal"), (118, "Draft"), (828, "Module"),
+];
+
+pub fn namespace_name(id: u32) -> Option<&'static str> {
+    NAMESPACE_NAMES.iter().find(|(namespace, _)| *namespace == id).map(|(_, name)| *name)
+}
+
+pub fn is_ignored(title: &str) -> bool {
+    config().ignore_prefixes.iter().any(|prefix| title.starts_with(prefix.as_str()))
+}
@@ -0,0 +1,116 @@
+use std::fs::{File, create_dir_all, read_to_string, remove_dir_all};
+use std::hash::BuildHasher;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use indicatif::ParallelProgressIterator;
+use rayon::prelude::*;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+use threadpool::ThreadPool;
+use crate::analyse_text::words;
+use crate::config::config;
+use crate::corpus::plain_text;
+use crate::helpers::{Args, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+
+const MAX_ORDER: usize = 5;
+
+// Counts the 1- to `max_order`-grams of a chunk's articles, keyed by (order, n-gram). N-grams don't cross lines, so
+// they stay within a paragraph, heading or list item.
+fn count_chunk(articles_path: &str, start_position: u64, end_position: u64, max_order: usize, keep_case: bool) -> FxHashMap<(usize, String), u64> {
+    let mut counts = FxHashMap::default();
+    for page in load_chunk_pages(articles_path, start_position, end_position).into_values() {
+        if page.namespace != 0 || page.redirect { continue; }
+        for line in plain_text(&page.text).lines() {
+            let line_words: Vec<String> = words(line, keep_case).collect();
+            for order in 1..=max_order.min(line_words.len()) {
+                for window in line_words.windows(order) {
+                    *counts.entry((order, window.join(" "))).or_default() += 1;
+                }
+            }
+        }
+    }
+    counts
+}
+
+// Writes the counts of every n-gram of up to --max-n words (3 by default, at most 5) in the plain text of articles in
+// the main namespace. Each chunk's counts are split by hash into --shards spill files, and the shards are then merged
+// in parallel, keeping the n-grams seen at least --min-count times, into {n}grams-SSSSS-of-NNNNN.tsv files with
+// `ngram<TAB>count` lines sorted by count. Every n-gram lands in exactly one shard, so shards never need merging.
+pub fn export_ngrams(args: &Args, data_path: &Path, output_dir: &Path) {
+    let max_order: usize = args.parse_value("max-n").unwrap_or(3);
+    let min_count: u64 = args.parse_value("min-count").unwrap_or(2);
+    let shards: usize = args.parse_value("shards").unwrap_or(64);
+    if !(1..=MAX_ORDER).contains(&max_order) || shards == 0 {
+        eprintln!("Error: --max-n must be between 1 and {}, and --shards must be positive", MAX_ORDER);
+        std::process::exit(1);
+    }
+    let keep_case = args.flag("keep-case");
+    let spill_dir = output_dir.join("spill");
+    create_dir_all(&spill_dir).expect("Failed to create output directory");
+    let spill_path = |shard: usize| spill_dir.join(format!("{:05}.tsv", shard));
+    let spill_files: Arc<Vec<Mutex<BufWriter<File>>>> = Arc::new((0..shards)
+        .map(|shard| Mutex::new(BufWriter::new(File::create(spill_path(shard)).expect("Failed to create spill file"))))
+        .collect());
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Counting n-grams"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let spill_files = Arc::clone(&spill_files);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut outputs = vec![Vec::new(); shards];
+            for ((order, ngram), count) in count_chunk(&articles_path, start_position, end_position, max_order, keep_case) {
+                let shard = (FxBuildHasher.hash_one(&ngram) % shards as u64) as usize;
+                writeln!(outputs[shard], "{}\t{}\t{}", order, ngram, count).unwrap();
+            }
+            for (spill_file, output) in spill_files.iter().zip(outputs) {
+                spill_file.lock().unwrap().write_all(&output).expect("Failed to write spill file");
+            }
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    for spill_file in spill_files.iter() {
+        spill_file.lock().unwrap().flush().expect("Failed to flush spill file");
+    }
+
+    let kept: Vec<[usize; MAX_ORDER]> = (0..shards).into_par_iter().progress_with(create_progress_bar(shards as u64, "Merging shards")).map(|shard| {
+        let mut counts: FxHashMap<(usize, &str), u64> = FxHashMap::default();
+        let contents = read_to_string(spill_path(shard)).expect("Failed to read spill file");
+        for line in contents.lines() {
+            let mut fields = line.split('\t');
+            let (Some(order), Some(ngram), Some(count)) = (fields.next(), fields.next(), fields.next()) else { continue };
+            *counts.entry((order.parse().unwrap(), ngram)).or_default() += count.parse::<u64>().unwrap();
+        }
+
+        let mut kept = [0; MAX_ORDER];
+        for order in 1..=max_order {
+            let mut ngrams: Vec<(&str, u64)> = counts.iter()
+                .filter(|((ngram_order, _), count)| *ngram_order == order && **count >= min_count)
+                .map(|((_, ngram), count)| (*ngram, *count))
+                .collect();
+            ngrams.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let output_path = output_dir.join(format!("{}grams-{:05}-of-{:05}.tsv", order, shard, shards));
+            let mut output_file = BufWriter::new(File::create(output_path).expect("Failed to create output file"));
+            for (ngram, count) in &ngrams {
+                writeln!(output_file, "{}\t{}", ngram, count).expect("Failed to write output file");
+            }
+            output_file.flush().expect("Failed to flush output file");
+            kept[order - 1] = ngrams.len();
+        }
+        kept
+    }).collect();
+    remove_dir_all(&spill_dir).expect("Failed to remove spill files");
+
+    for order in 1..=max_order {
+        let total: usize = kept.iter().map(|shard| shard[order - 1]).sum();
+        println!("{}-grams: {} seen at least {} times", order, total, min_count);
+    }
+    println!("Exported n-grams in {} shards to {}", shards, output_dir.to_str().unwrap());
+}
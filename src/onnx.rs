@@ -0,0 +1,94 @@
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use ort::session::{Session, SessionInputValue};
+use ort::value::Tensor;
+use tokenizers::{Encoding, PaddingParams, Tokenizer, TruncationParams};
+
+// Passages longer than this many model tokens are cut off, since the BERT-style models most sentence-transformers
+// exports are built on have no position embeddings past it
+const MAX_MODEL_TOKENS: usize = 512;
+
+// Loads the ONNX Runtime shared library the first time a model is, from ORT_DYLIB_PATH or else the library search path.
+// Loading it up front turns a missing library into an error rather than a panic inside ort.
+fn load_runtime() -> Result<(), String> {
+    static LOADED: OnceLock<Result<(), String>> = OnceLock::new();
+    LOADED.get_or_init(|| {
+        let library = std::env::var("ORT_DYLIB_PATH").ok().filter(|path| !path.is_empty())
+            .unwrap_or_else(|| format!("{}onnxruntime{}", DLL_PREFIX, DLL_SUFFIX));
+        let environment = ort::init_from(&library)
+            .map_err(|err| format!("Failed to load ONNX Runtime from {}, set ORT_DYLIB_PATH to its shared library: {}", library, err))?;
+        environment.commit();
+        Ok(())
+    }).clone()
+}
+
+// A sentence-embedding model exported to ONNX, run locally, so that embedding a dump needs no server and no network.
+// The directory holds model.onnx, or onnx/model.onnx as sentence-transformers repositories lay it out, and the
+// tokenizer.json it was trained with. Models with a pooled sentence_embedding output are used as they are, and the
+// token embeddings of the rest are mean-pooled over the attention mask, as sentence-transformers does.
+pub struct OnnxModel {
+    pub path: PathBuf,
+    session: Mutex<Session>,  // running a session needs exclusive access, and each run uses several threads already
+    tokenizer: Tokenizer,
+    token_type_ids: bool,  // whether the model takes token_type_ids, which BERT models do and others don't
+}
+
+impl OnnxModel {
+    pub fn load(model_dir: &Path) -> Result<Self, String> {
+        let model_path = ["model.onnx", "onnx/model.onnx"].iter().map(|name| model_dir.join(name)).find(|path| path.exists())
+            .ok_or_else(|| format!("No model.onnx or onnx/model.onnx in {}", model_dir.display()))?;
+        let tokenizer_path = model_dir.join("tokenizer.json");
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|err| format!("Failed to load {}: {}", tokenizer_path.display(), err))?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+        tokenizer.with_truncation(Some(TruncationParams { max_length: MAX_MODEL_TOKENS, ..TruncationParams::default() }))
+            .map_err(|err| format!("Failed to set up {}: {}", tokenizer_path.display(), err))?;
+
+        load_runtime()?;
+        let session = Session::builder().and_then(|mut builder| builder.commit_from_file(&model_path))
+            .map_err(|err| format!("Failed to load {}: {}", model_path.display(), err))?;
+        let token_type_ids = session.inputs().iter().any(|input| input.name() == "token_type_ids");
+        Ok(OnnxModel { path: model_dir.to_path_buf(), session: Mutex::new(session), tokenizer, token_type_ids })
+    }
+
+    // Returns one vector per text, in order, not yet normalized
+    pub fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), true).map_err(|err| format!("Failed to tokenize passages: {}", err))?;
+        let length = encodings.first().map_or(0, |encoding| encoding.len());
+        let tensor = |values: Vec<i64>| Tensor::from_array(([texts.len(), length], values)).map_err(|err| err.to_string());
+        let column = |values: fn(&Encoding) -> &[u32]| encodings.iter().flat_map(|encoding| values(encoding).iter().map(|&value| value as i64)).collect::<Vec<i64>>();
+        let mask = column(Encoding::get_attention_mask);
+        let mut inputs: Vec<(&str, SessionInputValue)> = vec![
+            ("input_ids", tensor(column(Encoding::get_ids))?.into()),
+            ("attention_mask", tensor(mask.clone())?.into()),
+        ];
+        if self.token_type_ids {
+            inputs.push(("token_type_ids", tensor(column(Encoding::get_type_ids))?.into()));
+        }
+
+        let mut session = self.session.lock().unwrap();
+        let outputs = session.run(inputs).map_err(|err| format!("Failed to run {}: {}", self.path.display(), err))?;
+        if let Some(pooled) = outputs.get("sentence_embedding") {
+            let (shape, values) = pooled.try_extract_tensor::<f32>().map_err(|err| err.to_string())?;
+            return Ok(values.chunks(shape[1] as usize).map(<[f32]>::to_vec).collect());
+        }
+        let (shape, values) = outputs[0].try_extract_tensor::<f32>().map_err(|err| err.to_string())?;
+        if shape.len() != 3 {
+            return Err(format!("{} should output token embeddings or a sentence_embedding, but its first output has shape {:?}", self.path.display(), &shape[..]));
+        }
+        let dimensions = shape[2] as usize;
+        let mut vectors = vec![vec![0.0; dimensions]; texts.len()];
+        for (text, vector) in vectors.iter_mut().enumerate() {
+            let mut tokens = 0;
+            for token in (0..length).filter(|token| mask[text * length + token] == 1) {
+                let start = (text * length + token) * dimensions;
+                for (sum, value) in vector.iter_mut().zip(&values[start..start + dimensions]) {
+                    *sum += value;
+                }
+                tokens += 1;
+            }
+            vector.iter_mut().for_each(|value| *value /= tokens.max(1) as f32);
+        }
+        Ok(vectors)
+    }
+}
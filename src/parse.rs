@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use serde_json::json;
+use threadpool::ThreadPool;
+use tracing::warn;
+use crate::config::config;
+use crate::namespaces::is_wiktionary;
+use crate::stubs::write_ndjson;
+use crate::wiktionary::write_entries;
+use crate::helpers::{Args, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+
+// Encodes a chunk's pages as NDJSON, one object per page in ID order, or with `wiktionary` one object per dictionary
+// entry of each main namespace page, in `language` if given
+fn encode_chunk(articles_path: &str, start_position: u64, end_position: u64, wiktionary: bool, language: Option<&str>) -> Vec<u8> {
+    let mut pages: Vec<_> = load_chunk_pages(articles_path, start_position, end_position).into_iter().collect();
+    pages.sort_unstable_by_key(|(id, _)| *id);
+    let mut output = Vec::new();
+    for (id, page) in pages {
+        if wiktionary {
+            if page.namespace == 0 && !page.redirect {
+                write_entries(&mut output, id, &page.title, &page.text, language);
+            }
+            continue;
+        }
+        let record = json!({ "id": id, "title": page.title, "namespace": page.namespace, "redirect": page.redirect, "text": page.text });
+        writeln!(output, "{}", record).expect("Failed to encode page");
+    }
+    output
+}
+
+pub fn exit_on_write_error(err: std::io::Error) -> ! {
+    // The reader went away, as with `| head`, which isn't an error
+    if err.kind() == ErrorKind::BrokenPipe {
+        std::process::exit(0);
+    }
+    eprintln!("Error: Failed to write output: {}", err);
+    std::process::exit(1);
+}
+
+// Streams every page in the dump as NDJSON, in dump order, to stdout with --stdout or to pages.ndjson (--output
+// FILE). Finished chunks pass through a channel with room for one chunk per thread, so when the reader falls behind
+// the workers block instead of piling up output in memory.
+pub fn parse(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let source = args.value("source").unwrap_or("articles");
+    if !["articles", "stub", "abstract"].contains(&source) {
+        eprintln!("Error: Unknown source {} (expected articles, stub or abstract)", source);
+        std::process::exit(1);
+    }
+    let to_stdout = args.flag("stdout");
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("pages.ndjson"));
+    let mut output: Box<dyn Write> = if to_stdout {
+        Box::new(BufWriter::new(std::io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(&output_path).expect("Failed to create output file")))
+    };
+
+    // The stub and abstract dumps are read in one pass, since they're gzipped rather than split into chunks
+    if source != "articles" {
+        let pages = write_ndjson(data_path, source, &mut output);
+        output.flush().unwrap_or_else(|err| exit_on_write_error(err));
+        if !to_stdout {
+            println!("Wrote {} pages to {}", pages, output_path.to_str().unwrap());
+        }
+        return;
+    }
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let wiktionary = args.flag("wiktionary");
+    let language = Arc::new(args.value("language").map(str::to_string));
+    if wiktionary && !is_wiktionary(&config().dump_prefix) {
+        warn!("--wiktionary expects a Wiktionary dump, but the dump prefix is {}", config().dump_prefix);
+    }
+    let progress_bar = create_progress_bar(chunk_ranges.len() as u64, "Parsing pages");
+    let (sender, receiver) = mpsc::sync_channel(num_threads);
+
+    for (sequence, (_, start_position, end_position)) in chunk_ranges.into_iter().enumerate() {
+        let articles_path = Arc::clone(&articles_path);
+        let language = Arc::clone(&language);
+        let sender = sender.clone();
+        pool.execute(move || {
+            // The receiver is gone once the output has closed, so there's nothing left to do
+            let _ = sender.send((sequence, encode_chunk(&articles_path, start_position, end_position, wiktionary, language.as_deref())));
+        });
+    }
+    drop(sender);
+
+    // Chunks finish out of order, so hold early ones back until everything before them has been written
+    let mut pending = BTreeMap::new();
+    let mut next_sequence = 0;
+    let mut records = 0;
+    for (sequence, chunk) in receiver.iter() {
+        pending.insert(sequence, chunk);
+        while let Some(chunk) = pending.remove(&next_sequence) {
+            records += chunk.iter().filter(|&&byte| byte == b'\n').count();
+            output.write_all(&chunk).and_then(|_| if to_stdout { output.flush() } else { Ok(()) }).unwrap_or_else(|err| exit_on_write_error(err));
+            next_sequence += 1;
+            progress_bar.inc(1);
+        }
+    }
+    output.flush().unwrap_or_else(|err| exit_on_write_error(err));
+    progress_bar.finish_and_clear();
+    if !to_stdout {
+        println!("Wrote {} {} to {}", records, if wiktionary { "entries" } else { "pages" }, output_path.to_str().unwrap());
+    }
+}
@@ -0,0 +1,291 @@
+use std::fs::write;
+use std::io::Write;
+use std::path::Path;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use crate::corpus::{SKIPPED_SECTIONS, plain_text, split_sections};
+
+// A4 in points, with one inch margins
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 72.0;
+const FOOTER_Y: f32 = 40.0;
+const TEXT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+
+// The advance widths of printable ASCII in thousandths of the font size, from the Adobe metrics for the standard
+// Helvetica fonts, which every PDF reader has built in. Other characters are taken to be as wide as a digit.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556,
+    556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611,
+    611, 278, 278, 556, 278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+const DEFAULT_WIDTH: u16 = 556;
+
+// The first objects of the file, with the pages after them and then the links and bookmarks
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const OUTLINES: usize = 3;
+const INFO: usize = 4;
+const FIRST_PAGE: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font { Regular, Bold }
+
+struct Style { font: Font, size: f32, leading: f32 }
+
+const TITLE: Style = Style { font: Font::Bold, size: 20.0, leading: 26.0 };
+const HEADING: Style = Style { font: Font::Bold, size: 13.0, leading: 18.0 };
+const BODY: Style = Style { font: Font::Regular, size: 10.5, leading: 14.5 };
+const TOC_ENTRY: Style = Style { font: Font::Regular, size: 11.0, leading: 16.0 };
+const FOOTER: Style = Style { font: Font::Regular, size: 9.0, leading: 9.0 };
+
+// Encodes text in WinAnsiEncoding, the standard fonts' encoding, which covers Latin-1 and typographic punctuation.
+// Everything else becomes a question mark, since the standard fonts have no other glyphs.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars().map(|c| match c {
+        ' '..='~' => c as u8,
+        '\u{a0}' => b' ',
+        '\u{a1}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80, '…' => 0x85, '‘' => 0x91, '’' => 0x92, '“' => 0x93, '”' => 0x94, '•' => 0x95, '–' => 0x96, '—' => 0x97,
+        _ => b'?',
+    }).collect()
+}
+
+fn width(text: &[u8], style: &Style) -> f32 {
+    let widths = if style.font == Font::Bold { &HELVETICA_BOLD_WIDTHS } else { &HELVETICA_WIDTHS };
+    let total: u32 = text.iter().map(|&c| if (32..=126).contains(&c) { widths[c as usize - 32] } else { DEFAULT_WIDTH } as u32).sum();
+    total as f32 * style.size / 1000.0
+}
+
+// Breaks encoded text into lines that fit in `max_width`, between words where possible
+fn wrap(text: &[u8], style: &Style, max_width: f32) -> Vec<Vec<u8>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<u8> = Vec::new();
+    for word in text.split(|&c| c == b' ').filter(|word| !word.is_empty()) {
+        let candidate = if line.is_empty() { word.to_vec() } else { [&line[..], b" ", word].concat() };
+        if width(&candidate, style) <= max_width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // Words wider than a whole line, like long URLs, are broken wherever they run out of room
+        for &c in word {
+            if !line.is_empty() && width(&[&line[..], &[c]].concat(), style) > max_width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// A string of encoded text for a content stream, with its delimiters escaped
+fn literal(text: &[u8]) -> Vec<u8> {
+    let mut output = vec![b'('];
+    for &c in text {
+        if matches!(c, b'(' | b')' | b'\\') { output.push(b'\\'); }
+        output.push(c);
+    }
+    output.push(b')');
+    output
+}
+
+// A string outside a content stream, like a bookmark title, in UTF-16 so it isn't limited to the fonts' encoding
+fn text_string(text: &str) -> String {
+    let units: String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+    format!("<FEFF{}>", units)
+}
+
+fn show_text(content: &mut Vec<u8>, text: &[u8], style: &Style, x: f32, y: f32) {
+    let font = if style.font == Font::Bold { "F2" } else { "F1" };
+    content.extend_from_slice(format!("BT /{} {} Tf {:.2} {:.2} Td ", font, style.size, x, y).as_bytes());
+    content.extend_from_slice(&literal(text));
+    content.extend_from_slice(b" Tj ET\n");
+}
+
+// Lays text out down the pages, starting a new page whenever the current one is full
+struct Layout {
+    pages: Vec<Vec<u8>>,  // the content stream of each page, without its page number
+    y: f32,  // the baseline of the last line on the current page
+}
+
+impl Layout {
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    // Makes sure there's room for `height` more points on the current page
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn paragraph(&mut self, text: &str, style: &Style, space_before: f32) {
+        let lines = wrap(&encode(text), style, TEXT_WIDTH);
+        if self.y < PAGE_HEIGHT - MARGIN {
+            self.y -= space_before;
+        }
+        for line in lines {
+            self.reserve(style.leading);
+            self.y -= style.leading;
+            show_text(self.pages.last_mut().unwrap(), &line, style, MARGIN, self.y);
+        }
+    }
+
+    fn heading(&mut self, text: &str) {
+        // Headings stay with at least the first couple of lines under them
+        self.reserve(HEADING.leading + 8.0 + 2.0 * BODY.leading);
+        self.paragraph(text, &HEADING, 8.0);
+    }
+}
+
+// How many table of contents entries fit on each of its pages, the first of which also has the book's title
+fn toc_capacity(first: bool) -> usize {
+    let height = PAGE_HEIGHT - 2.0 * MARGIN - if first { TITLE.leading + HEADING.leading + 16.0 } else { 0.0 };
+    (height / TOC_ENTRY.leading) as usize
+}
+
+// Writes the chapters, (title, wikitext) pairs, to a PDF: the book's title and a table of contents with page numbers
+// first, then each article's plain text from a new page, with its sections as headings. The table of contents entries
+// link to the articles and each article gets a bookmark.
+pub fn write_pdf(path: &Path, title: &str, chapters: &[(String, String)]) {
+    let mut layout = Layout { pages: Vec::new(), y: 0.0 };
+    let mut chapter_pages = Vec::new();
+    for (chapter_title, text) in chapters {
+        layout.new_page();
+        chapter_pages.push(layout.pages.len() - 1);
+        layout.paragraph(chapter_title, &TITLE, 0.0);
+        for (section, wikitext) in split_sections(text) {
+            if SKIPPED_SECTIONS.contains(&section.to_lowercase().as_str()) { continue; }
+            let section_text = plain_text(wikitext);
+            if section_text.is_empty() { continue; }
+            if section != "Introduction" {
+                layout.heading(&section);
+            }
+            for paragraph in section_text.lines().filter(|line| !line.trim().is_empty()) {
+                layout.paragraph(paragraph, &BODY, 5.0);
+            }
+        }
+    }
+
+    // The table of contents goes in front, so the article pages are numbered after however many pages it takes
+    let mut toc_pages = 1;
+    let mut remaining = chapters.len().saturating_sub(toc_capacity(true));
+    while remaining > 0 {
+        toc_pages += 1;
+        remaining = remaining.saturating_sub(toc_capacity(false));
+    }
+    let page_count = toc_pages + layout.pages.len();
+    let page_object = |page: usize| FIRST_PAGE + 2 * page;
+    let mut next_object = FIRST_PAGE + 2 * page_count;
+    let mut pages: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();  // (content, link annotation objects) of each page
+    let mut annotations = Vec::new();  // (object, annotation)
+    let mut entries = chapters.iter().zip(&chapter_pages);
+    for toc_page in 0..toc_pages {
+        let mut content = Vec::new();
+        let mut links = Vec::new();
+        let mut y = PAGE_HEIGHT - MARGIN;
+        if toc_page == 0 {
+            y -= TITLE.leading;
+            let title_line = wrap(&encode(title), &TITLE, TEXT_WIDTH).into_iter().next().unwrap_or_default();
+            show_text(&mut content, &title_line, &TITLE, MARGIN, y);
+            y -= HEADING.leading + 16.0;
+            show_text(&mut content, b"Contents", &HEADING, MARGIN, y);
+        }
+        for ((chapter_title, _), &chapter_page) in entries.by_ref().take(toc_capacity(toc_page == 0)) {
+            y -= TOC_ENTRY.leading;
+            let number = encode(&(toc_pages + chapter_page + 1).to_string());
+            let number_width = width(&number, &TOC_ENTRY);
+            // Titles too long for the line are cut short with an ellipsis
+            let mut entry = encode(chapter_title);
+            let available = TEXT_WIDTH - number_width - 12.0;
+            if width(&entry, &TOC_ENTRY) > available {
+                while !entry.is_empty() && width(&entry, &TOC_ENTRY) + width(&[0x85], &TOC_ENTRY) > available {
+                    entry.pop();
+                }
+                entry.push(0x85);
+            }
+            show_text(&mut content, &entry, &TOC_ENTRY, MARGIN, y);
+            show_text(&mut content, &number, &TOC_ENTRY, PAGE_WIDTH - MARGIN - number_width, y);
+            let annotation = format!("<< /Type /Annot /Subtype /Link /Rect [{:.2} {:.2} {:.2} {:.2}] /Border [0 0 0] /Dest [{} 0 R /Fit] >>",
+                MARGIN, y - 4.0, PAGE_WIDTH - MARGIN, y + TOC_ENTRY.size, page_object(toc_pages + chapter_page));
+            annotations.push((next_object, annotation));
+            links.push(next_object);
+            next_object += 1;
+        }
+        pages.push((content, links));
+    }
+    pages.extend(layout.pages.into_iter().map(|content| (content, Vec::new())));
+
+    let mut objects: Vec<Vec<u8>> = vec![Vec::new(); next_object + chapters.len() - 1];
+    let mut set = |object: usize, body: Vec<u8>| objects[object - 1] = body;
+    let kids: Vec<String> = (0..page_count).map(|page| format!("{} 0 R", page_object(page))).collect();
+    set(CATALOG, format!("<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>", PAGES, OUTLINES).into_bytes());
+    set(PAGES, format!(
+        "<< /Type /Pages /Kids [{}] /Count {} /MediaBox [0 0 {} {}] /Resources << /Font << \
+         /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >> \
+         /F2 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >> >> >> >>",
+        kids.join(" "), page_count, PAGE_WIDTH, PAGE_HEIGHT).into_bytes());
+    set(INFO, format!("<< /Title {} /Producer (wikipedia {}) >>", text_string(title), env!("CARGO_PKG_VERSION")).into_bytes());
+    for (page, (mut content, links)) in pages.into_iter().enumerate() {
+        let number = (page + 1).to_string();
+        show_text(&mut content, number.as_bytes(), &FOOTER, (PAGE_WIDTH - width(number.as_bytes(), &FOOTER)) / 2.0, FOOTER_Y);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let annots = if links.is_empty() { String::new() } else {
+            format!(" /Annots [{}]", links.iter().map(|link| format!("{} 0 R", link)).collect::<Vec<_>>().join(" "))
+        };
+        set(page_object(page), format!("<< /Type /Page /Parent {} 0 R /Contents {} 0 R{} >>", PAGES, page_object(page) + 1, annots).into_bytes());
+        let mut stream = format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", compressed.len()).into_bytes();
+        stream.extend_from_slice(&compressed);
+        stream.extend_from_slice(b"\nendstream");
+        set(page_object(page) + 1, stream);
+    }
+    for (object, annotation) in annotations {
+        set(object, annotation.into_bytes());
+    }
+    // A bookmark for each article, in a flat list under the outline root
+    let first_bookmark = next_object;
+    let last_bookmark = first_bookmark + chapters.len() - 1;
+    set(OUTLINES, format!("<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>", first_bookmark, last_bookmark, chapters.len()).into_bytes());
+    for (i, ((chapter_title, _), &chapter_page)) in chapters.iter().zip(&chapter_pages).enumerate() {
+        let object = first_bookmark + i;
+        let mut bookmark = format!("<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]", text_string(chapter_title), OUTLINES, page_object(toc_pages + chapter_page));
+        if object > first_bookmark { bookmark.push_str(&format!(" /Prev {} 0 R", object - 1)); }
+        if object < last_bookmark { bookmark.push_str(&format!(" /Next {} 0 R", object + 1)); }
+        bookmark.push_str(" >>");
+        set(object, bookmark.into_bytes());
+    }
+
+    // Each object's offset goes in the cross-reference table at the end
+    let mut output = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, body) in objects.iter().enumerate() {
+        offsets.push(output.len());
+        output.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        output.extend_from_slice(body);
+        output.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_offset = output.len();
+    output.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        output.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    output.extend_from_slice(format!("trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, CATALOG, INFO, xref_offset).as_bytes());
+    write(path, output).expect("Failed to write PDF file");
+}
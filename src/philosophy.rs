@@ -0,0 +1,215 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use rustc_hash::FxHashMap;
+use crate::helpers::{Args, PageId, create_progress_bar, skip_nested};
+use crate::index::normalize_link;
+use crate::links::{ID_WIDTH, LinkGraph, check_id_width, load_links, read_fixed_id, read_links_file};
+use crate::split::{read_page_info, read_titles, split_files_exist};
+
+pub const FIRST_LINKS_FILE: &str = "first-links.bin";
+const MAGIC: &[u8; 4] = b"WKFL";
+const VERSION: u32 = 2;
+
+// Returns the ID of the first link in the article body that isn't inside parentheses or italics, skipping templates,
+// tables, refs, comments, file and category links, and links to pages that don't exist
+pub fn first_link(text: &str, resolve: impl Fn(&str) -> Option<PageId>) -> Option<PageId> {
+    let mut i = 0;
+    let mut parentheses = 0;
+    let mut italic = false;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("{{") {
+            i += skip_nested(rest, "{{", "}}");
+        } else if rest.starts_with("{|") {
+            i += skip_nested(rest, "{|", "|}");
+        } else if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |end| end + 3);
+        } else if rest.starts_with("<ref>") || rest.starts_with("<ref ") {
+            let Some(tag_end) = rest.find('>') else { break };
+            i += match rest[..tag_end].ends_with('/') {
+                true => tag_end + 1,
+                false => rest.find("</ref>").map_or(rest.len(), |end| end + 6),
+            };
+        } else if rest.starts_with("[[") {
+            let length = skip_nested(rest, "[[", "]]");
+            if parentheses == 0 && !italic {
+                let link = rest[2..length].strip_suffix("]]").unwrap_or(&rest[2..length]);
+                if let Some(link_id) = normalize_link(link).and_then(|link| resolve(&link)) {
+                    return Some(link_id);
+                }
+            }
+            i += length;
+        } else if rest.starts_with("''") {
+            // '' toggles italics, ''' is bold, and ''''' is both
+            let run = rest.bytes().take_while(|&b| b == b'\'').count();
+            if run != 3 { italic = !italic; }
+            i += run;
+        } else {
+            match rest.as_bytes()[0] {
+                b'(' => parentheses += 1,
+                b')' => parentheses = (parentheses - 1).max(0),
+                b'\n' => italic = false,
+                _ => {}
+            }
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    None
+}
+
+// first-links.bin is the header, then the width of a page ID in bytes as a little-endian u32 like in links.bin,
+// followed by (article ID, first link ID) pairs sorted by article ID. Version 1 files had no width and 4-byte IDs.
+pub fn write_first_links(path: &Path, first_links: &[(PageId, PageId)]) {
+    let mut file = BufWriter::new(File::create(path).expect("Failed to create first links file"));
+    file.write_all(MAGIC).expect("Failed to write first links file");
+    file.write_all(&VERSION.to_le_bytes()).expect("Failed to write first links file");
+    file.write_all(&ID_WIDTH.to_le_bytes()).expect("Failed to write first links file");
+    for (article_id, link_id) in first_links {
+        file.write_all(&article_id.to_le_bytes()).expect("Failed to write first links file");
+        file.write_all(&link_id.to_le_bytes()).expect("Failed to write first links file");
+    }
+    file.flush().expect("Failed to flush first links file");
+}
+
+pub fn read_first_links(path: &Path) -> FxHashMap<PageId, PageId> {
+    let buffer = read_links_file(path);
+    let width = buffer.get(8..12).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+    let Some(width) = width.filter(|_| buffer.starts_with(MAGIC) && buffer[4..8] == VERSION.to_le_bytes()) else {
+        eprintln!("Error: {} is not a valid first links file, re-run the index command with --first-links", path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    if let Err(err) = check_id_width(width) {
+        eprintln!("Error: {}: {}", path.to_str().unwrap(), err);
+        std::process::exit(1);
+    }
+    let width = width as usize;
+    if !(buffer.len() - 12).is_multiple_of(2 * width) {
+        eprintln!("Error: {} is truncated", path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    buffer[12..].chunks_exact(2 * width)
+        .map(|pair| (read_fixed_id(&pair[..width]), read_fixed_id(&pair[width..])))
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Target(u32),  // steps to the target
+    Loop(usize),  // index into the list of loops
+    DeadEnd,
+}
+
+pub fn philosophy(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let first_links_path = data_path.join(FIRST_LINKS_FILE);
+    if !first_links_path.exists() {
+        eprintln!("Error: Unable to locate {} in {}, re-run the index command with --first-links", FIRST_LINKS_FILE, data_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let first_links = read_first_links(&first_links_path);
+    let (titles, pages) = if split_files_exist(data_path) {
+        (read_titles(data_path), read_page_info(data_path))
+    } else {
+        let LinkGraph { titles, pages, .. } = load_links(&data_path.join("links.bin"));
+        (titles, pages)
+    };
+    let title = |id: &PageId| titles.get(id).cloned().unwrap_or_else(|| format!("Unknown (ID: {})", id));
+
+    // Titles that only differ in case can share a lowercase form, so prefer a non-redirect and then the lowest ID
+    let target_title = args.value("target").unwrap_or("Philosophy");
+    let Some(target) = titles.iter()
+        .filter(|(_, title)| title.to_lowercase() == target_title.to_lowercase())
+        .map(|(id, _)| *id)
+        .min_by_key(|id| (pages.get(id).is_some_and(|info| info.redirect), *id)) else {
+        eprintln!("Error: No article titled {}", target_title);
+        std::process::exit(1);
+    };
+
+    // Only articles in the main namespace start a chain, but the chains can pass through anything
+    let mut articles: Vec<PageId> = pages.iter().filter(|(_, info)| !info.redirect && info.namespace == 0).map(|(id, _)| *id).collect();
+    articles.sort_unstable();
+
+    // Follow each chain until it reaches a page whose outcome is already known, a page without a first link, or a
+    // page already on the chain, then fill in the outcome for every page along the way
+    let mut outcomes = FxHashMap::default();
+    outcomes.insert(target, Outcome::Target(0));
+    let mut loops: Vec<Vec<PageId>> = Vec::new();
+    let progress_bar = create_progress_bar(articles.len() as u64, "Following first links");
+    for &start in &articles {
+        progress_bar.inc(1);
+        let mut chain = Vec::new();
+        let mut positions = FxHashMap::default();
+        let mut current = start;
+        let outcome = loop {
+            if let Some(&outcome) = outcomes.get(&current) { break outcome; }
+            if let Some(&position) = positions.get(&current) {
+                let outcome = Outcome::Loop(loops.len());
+                loops.push(chain.split_off(position));
+                break outcome;
+            }
+            match first_links.get(&current) {
+                Some(&next) => {
+                    positions.insert(current, chain.len());
+                    chain.push(current);
+                    current = next;
+                }
+                None => break Outcome::DeadEnd,
+            }
+        };
+        if let Outcome::Loop(index) = outcome {
+            for &id in &loops[index] {
+                outcomes.insert(id, outcome);
+            }
+        }
+        if let Outcome::DeadEnd = outcome {
+            outcomes.insert(current, outcome);
+        }
+        for (distance, &id) in chain.iter().rev().enumerate() {
+            outcomes.insert(id, match outcome {
+                Outcome::Target(steps) => Outcome::Target(steps + distance as u32 + 1),
+                outcome => outcome,
+            });
+        }
+    }
+    progress_bar.finish_and_clear();
+
+    let mut steps = Vec::new();
+    let mut loop_sizes = vec![0; loops.len()];
+    let mut dead_ends = 0;
+    for id in &articles {
+        match outcomes[id] {
+            Outcome::Target(count) => steps.push((count, *id)),
+            Outcome::Loop(index) => loop_sizes[index] += 1,
+            Outcome::DeadEnd => dead_ends += 1,
+        }
+    }
+
+    let percent = |count: usize| 100.0 * count as f64 / articles.len().max(1) as f64;
+    let looping = articles.len() - steps.len() - dead_ends;
+    println!("Articles: {}", articles.len());
+    println!("Articles with a first link: {}", articles.iter().filter(|id| first_links.contains_key(id)).count());
+    println!("Reach {}: {} ({:.1}%)", title(&target), steps.len(), percent(steps.len()));
+    println!("End in a loop: {} ({:.1}%)", looping, percent(looping));
+    println!("End at a page without a first link: {} ({:.1}%)", dead_ends, percent(dead_ends));
+
+    if !steps.is_empty() {
+        steps.sort_unstable();
+        let mean = steps.iter().map(|&(count, _)| count as f64).sum::<f64>() / steps.len() as f64;
+        let (longest, longest_id) = steps[steps.len() - 1];
+        println!("Steps to {}: median {}, mean {:.1}, max {} (from {})", title(&target), steps[steps.len() / 2].0, mean, longest, title(&longest_id));
+    }
+
+    let mut loop_order: Vec<usize> = (0..loops.len()).collect();
+    loop_order.sort_by_key(|&index| (std::cmp::Reverse(loop_sizes[index]), index));
+    println!("\nLoops: {}", loops.len());
+    println!("Top 10 loops by number of articles that end in them:");
+    for (rank, &index) in loop_order.iter().take(10).enumerate() {
+        let mut members: Vec<String> = loops[index].iter().take(10).map(title).collect();
+        if loops[index].len() > 10 {
+            members.push(format!("... ({} pages)", loops[index].len()));
+        }
+        members.push(title(&loops[index][0]));
+        println!("{:>2}) {} ({})", rank + 1, members.join(" -> "), loop_sizes[index]);
+    }
+}
@@ -0,0 +1,179 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::sync::mpsc::SyncSender;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::chunk_stats::ChunkTiming;
+use crate::config::config;
+use crate::duplicates::DroppedPages;
+use crate::helpers::{INTERRUPTED, Page, PageId, create_progress_bar, is_remote, parse_chunk_bytes, read_chunk_bytes};
+#[cfg(feature = "async-io")]
+use crate::{aio, storage};
+
+// Remote dumps are read by several threads, since each range request spends most of its time waiting on the network
+const REMOTE_READERS: usize = 4;
+// With the async-io feature, a remote dump is read by one thread with this many range requests in flight instead
+#[cfg(feature = "async-io")]
+const ASYNC_REMOTE_REQUESTS: usize = 16;
+// In a deterministic pass, how many chunks per thread can be read ahead of the next one to be written
+const READ_AHEAD_PER_THREAD: usize = 4;
+
+// What a sink extracted from one chunk, handed back to the same sink's write
+pub type SinkChunk = Box<dyn Any + Send>;
+
+// The chunks left to read, numbered in the order they're written
+type ChunkRanges = Arc<Mutex<std::iter::Enumerate<std::vec::IntoIter<(usize, u64, u64)>>>>;
+// How many chunks have been written, for readers waiting to get too far ahead
+type Written = Arc<(Mutex<usize>, Condvar)>;
+// (sequence, chunk index, start position, compressed bytes, time taken to read them)
+type RawChunk = (usize, usize, u64, Vec<u8>, Duration);
+
+// Something that consumes the pages of a pass over the dump, like the link extractor behind links.bin or the article
+// dumper, so that one pass can feed several of them instead of decompressing the dump once for each. Every chunk's
+// pages go to extract on one of the worker threads, and what it returns goes to write on the writer thread, one chunk
+// at a time and in dump order when the pass is deterministic. Work that doesn't depend on order, like writing a file
+// for each article, can be done in extract, while anything appended to a shared file belongs in write.
+pub trait PageSink: Send + Sync {
+    fn extract(&self, chunk_index: usize, pages: &HashMap<PageId, Page>) -> SinkChunk;
+    fn write(&self, chunk: SinkChunk);
+    // Called by whoever ran the pass once it's over, including when it was interrupted
+    fn finish(&self) {}
+}
+
+// Passes every chunk in `chunk_ranges` through the sinks, leaving out the pages in `dropped_pages`, and returns how
+// long each chunk took and how long the whole pass took. The sinks aren't finished, so callers can time that apart.
+//
+// Chunks go through three stages joined by bounded channels: readers fetch each chunk's compressed bytes, workers
+// parse them and run the sinks' extract, and this thread runs their write. A full channel holds up the stage before
+// it, so only a few chunks per thread are in memory however far ahead the readers could get. In a deterministic pass,
+// a reader also waits until the chunk READ_AHEAD_PER_THREAD * threads places before its own has been written, which
+// bounds how many finished chunks can wait behind a slow one.
+pub fn run(articles_path: &Path, chunk_ranges: Vec<(usize, u64, u64)>, sinks: &[Arc<dyn PageSink>], dropped_pages: Arc<DroppedPages>, deterministic: bool, message: &str) -> (Vec<ChunkTiming>, Duration) {
+    let num_threads = config().threads;
+    let sinks: Arc<Vec<Arc<dyn PageSink>>> = Arc::new(sinks.to_vec());
+    let progress_bar = create_progress_bar(chunk_ranges.len() as u64, message);
+    let started = Instant::now();
+
+    let read_ahead = READ_AHEAD_PER_THREAD * num_threads;
+    let written: Written = Arc::new((Mutex::new(0), Condvar::new()));
+    let chunk_ranges: ChunkRanges = Arc::new(Mutex::new(chunk_ranges.into_iter().enumerate()));
+    let (raw_sender, raw_receiver) = mpsc::sync_channel::<RawChunk>(num_threads);
+    let readers = match is_remote(articles_path) {
+        true if spawn_async_reader(articles_path.to_str().unwrap(), Arc::clone(&chunk_ranges), Arc::clone(&written), deterministic.then_some(read_ahead), raw_sender.clone()) => 0,
+        true => REMOTE_READERS,
+        false => 1,
+    };
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    for _ in 0..readers {
+        let (chunk_ranges, written, raw_sender, articles_path) = (Arc::clone(&chunk_ranges), Arc::clone(&written), raw_sender.clone(), Arc::clone(&articles_path));
+        thread::spawn(move || loop {
+            // After an interrupt, no more chunks are read and only the ones already read get written
+            if INTERRUPTED.load(Ordering::SeqCst) { break; }
+            let Some((sequence, (chunk_index, start_position, end_position))) = chunk_ranges.lock().unwrap().next() else { break };
+            if deterministic {
+                let (next_sequence, condvar) = &*written;
+                let mut next_sequence = next_sequence.lock().unwrap();
+                while sequence >= *next_sequence + read_ahead && !INTERRUPTED.load(Ordering::SeqCst) {
+                    next_sequence = condvar.wait_timeout(next_sequence, Duration::from_millis(100)).unwrap().0;
+                }
+            }
+            let read_started = Instant::now();
+            let buffer = read_chunk_bytes(&articles_path, start_position, end_position);
+            if raw_sender.send((sequence, chunk_index, start_position, buffer, read_started.elapsed())).is_err() { break; }
+        });
+    }
+    drop(raw_sender);
+
+    let raw_receiver = Arc::new(Mutex::new(raw_receiver));
+    let (sender, receiver) = mpsc::sync_channel(num_threads);
+    for _ in 0..num_threads {
+        let (raw_receiver, sender, sinks, dropped_pages) = (Arc::clone(&raw_receiver), sender.clone(), Arc::clone(&sinks), Arc::clone(&dropped_pages));
+        thread::spawn(move || loop {
+            let Ok((sequence, chunk_index, start_position, buffer, read_time)) = raw_receiver.lock().unwrap().recv() else { break };
+            if INTERRUPTED.load(Ordering::SeqCst) { continue; }
+            let (chunk_started, bytes) = (Instant::now(), buffer.len() as u64);
+            let mut pages = parse_chunk_bytes(buffer, start_position);
+            pages.retain(|page_id, _| !dropped_pages.contains(&(start_position, *page_id)));
+            let chunks: Vec<SinkChunk> = sinks.iter().map(|sink| sink.extract(chunk_index, &pages)).collect();
+            let timing = ChunkTiming {
+                chunk_index, start_position, bytes, articles: pages.len(),
+                duration: read_time + chunk_started.elapsed(), finished: started.elapsed(),
+            };
+            if sender.send((sequence, chunks, timing)).is_err() { break; }
+        });
+    }
+    drop(sender);
+
+    // Chunks finish out of order, so in a deterministic pass the early ones wait until everything before them has
+    // been written
+    let mut pending = BTreeMap::new();
+    let mut next_sequence = 0;
+    let mut timings = Vec::new();
+    for (sequence, chunks, timing) in receiver {
+        timings.push(timing);
+        pending.insert(sequence, chunks);
+        while let Some(chunks) = if deterministic { pending.remove(&next_sequence) } else { pending.pop_first().map(|(_, chunks)| chunks) } {
+            for (sink, chunk) in sinks.iter().zip(chunks) {
+                sink.write(chunk);
+            }
+            next_sequence += 1;
+            progress_bar.inc(1);
+        }
+        if deterministic {
+            let (written, condvar) = &*written;
+            *written.lock().unwrap() = next_sequence;
+            condvar.notify_all();
+        }
+    }
+    progress_bar.finish_and_clear();
+    (timings, started.elapsed())
+}
+
+// Reads a remote dump from one thread that keeps ASYNC_REMOTE_REQUESTS range requests in flight on the async runtime,
+// rather than a thread for each request. Returns false without the async-io feature, leaving it to the reader threads.
+#[cfg(feature = "async-io")]
+fn spawn_async_reader(articles_path: &str, chunk_ranges: ChunkRanges, written: Written, read_ahead: Option<usize>, raw_sender: SyncSender<RawChunk>) -> bool {
+    let (storage, name) = storage::open_object(articles_path);
+    let (storage, name, articles_path) = (Arc::new(storage), Arc::new(name), Arc::new(articles_path.to_string()));
+    thread::spawn(move || aio::runtime().block_on(async move {
+        let mut requests = tokio::task::JoinSet::new();
+        let mut held = None;
+        loop {
+            while requests.len() < ASYNC_REMOTE_REQUESTS && !INTERRUPTED.load(Ordering::SeqCst) {
+                let Some((sequence, (chunk_index, start_position, end_position))) = held.take().or_else(|| chunk_ranges.lock().unwrap().next()) else { break };
+                // A chunk too far ahead of the writer waits for it, but only once nothing is in flight, since the
+                // chunk the writer needs next may be one of those
+                if let Some(read_ahead) = read_ahead {
+                    let (next_sequence, condvar) = &*written;
+                    let mut next_sequence = next_sequence.lock().unwrap();
+                    if sequence >= *next_sequence + read_ahead && !requests.is_empty() {
+                        held = Some((sequence, (chunk_index, start_position, end_position)));
+                        break;
+                    }
+                    while sequence >= *next_sequence + read_ahead && !INTERRUPTED.load(Ordering::SeqCst) {
+                        next_sequence = condvar.wait_timeout(next_sequence, Duration::from_millis(100)).unwrap().0;
+                    }
+                }
+                let (storage, name, articles_path) = (Arc::clone(&storage), Arc::clone(&name), Arc::clone(&articles_path));
+                requests.spawn(async move {
+                    let read_started = Instant::now();
+                    let read = || aio::read_range(storage.http_request(&name, Some((start_position, end_position))).unwrap(), start_position, end_position);
+                    let buffer = aio::retry(&format!("Reading bytes {}-{} of {}", start_position, end_position, articles_path), read).await
+                        .unwrap_or_else(|err| panic!("Error reading bytes {}-{} of {}: {}", start_position, end_position, articles_path, err));
+                    (sequence, chunk_index, start_position, buffer, read_started.elapsed())
+                });
+            }
+            let Some(result) = requests.join_next().await else { break };
+            if raw_sender.send(result.expect("A range request panicked")).is_err() { break; }
+        }
+    }));
+    true
+}
+
+#[cfg(not(feature = "async-io"))]
+fn spawn_async_reader(_articles_path: &str, _chunk_ranges: ChunkRanges, _written: Written, _read_ahead: Option<usize>, _raw_sender: SyncSender<RawChunk>) -> bool {
+    false
+}
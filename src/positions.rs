@@ -0,0 +1,109 @@
+use std::path::Path;
+use rustc_hash::FxHashMap;
+use crate::helpers::PageId;
+use crate::links::{PageInfo, read_id, read_links_file, read_varint, write_id, write_varint};
+
+// positions.bin records where each resolved link occurs in its article's wikitext, so repeated links and their
+// placement aren't lost. It starts with MAGIC and a little-endian u32 version, and each record is: body_length, then a
+// body of article_id, occurrence_count, and a (link_id, offset delta) pair per occurrence in text order, where the
+// offset is the byte position of the link's opening brackets. Every integer is a LEB128 varint.
+pub const POSITIONS_FILE: &str = "positions.bin";
+const MAGIC: &[u8; 4] = b"WKPS";
+const VERSION: u32 = 1;
+
+pub fn get_header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+// Encodes one article's link occurrences, given as (link ID, byte offset) pairs in text order
+pub fn get_positions_byte_string(article_id: PageId, occurrences: &[(PageId, u32)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_id(&mut body, article_id);
+    write_varint(&mut body, occurrences.len() as u32);
+    let mut previous = 0;
+    for &(link_id, offset) in occurrences {
+        write_id(&mut body, link_id);
+        write_varint(&mut body, offset - previous);
+        previous = offset;
+    }
+
+    let mut output_buffer = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut output_buffer, body.len() as u32);
+    output_buffer.extend_from_slice(&body);
+    output_buffer
+}
+
+fn parse_record(buffer: &[u8], offset: &mut usize) -> Result<(PageId, Vec<(PageId, u32)>), String> {
+    let body_length = read_varint(buffer, offset)? as usize;
+    let body_end = offset.checked_add(body_length).filter(|&end| end <= buffer.len()).ok_or("record runs past end of file")?;
+    let body = &buffer[..body_end];
+    let article_id = read_id(body, offset)?;
+    let count = read_varint(body, offset)? as usize;
+    if count > body_end - *offset {
+        return Err(format!("occurrence count {} runs past end of record", count));
+    }
+    let mut occurrences = Vec::with_capacity(count);
+    let mut position: u32 = 0;
+    for _ in 0..count {
+        let link_id = read_id(body, offset)?;
+        position = position.checked_add(read_varint(body, offset)?).ok_or("link offset overflows u32")?;
+        occurrences.push((link_id, position));
+    }
+    *offset = body_end;
+    Ok((article_id, occurrences))
+}
+
+pub fn read_positions(path: &Path) -> FxHashMap<PageId, Vec<(PageId, u32)>> {
+    let buffer = read_links_file(path);
+    if !buffer.starts_with(MAGIC) || buffer.get(4..8) != Some(&VERSION.to_le_bytes()[..]) {
+        eprintln!("Error: {} is not a valid positions file", path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let mut positions = FxHashMap::default();
+    let mut i = 8;
+    while i < buffer.len() {
+        let offset = i;
+        let (article_id, occurrences) = parse_record(&buffer, &mut i).unwrap_or_else(|err| panic!("Corrupt positions record at byte {}: {}", offset, err));
+        positions.insert(article_id, occurrences);
+    }
+    positions
+}
+
+// Reports how often articles link to the same page more than once, how dense their links are, and how many links
+// sit near the start of the text
+pub fn print_position_stats(path: &Path, pages: &FxHashMap<PageId, PageInfo>) {
+    let positions = read_positions(path);
+    let mut occurrences = 0;
+    let mut distinct_pairs = 0;
+    let mut repeated_pairs = 0;
+    let mut sized_occurrences = 0;
+    let mut early_occurrences = 0;
+    let mut densities = Vec::new();
+    for (article_id, article_occurrences) in &positions {
+        let mut counts = FxHashMap::default();
+        for &(link_id, _) in article_occurrences {
+            *counts.entry(link_id).or_insert(0) += 1;
+        }
+        occurrences += article_occurrences.len();
+        distinct_pairs += counts.len();
+        repeated_pairs += counts.values().filter(|&&count| count > 1).count();
+
+        let Some(info) = pages.get(article_id).filter(|info| !info.redirect && info.word_count > 0) else { continue };
+        sized_occurrences += article_occurrences.len();
+        early_occurrences += article_occurrences.iter().filter(|&&(_, offset)| (offset as u64) * 10 < info.text_length as u64).count();
+        densities.push(article_occurrences.len() as f64 * 1000.0 / info.word_count as f64);
+    }
+
+    println!("\nLink positions:");
+    println!("  Link occurrences: {}", occurrences);
+    println!("  Distinct article-target pairs: {} ({:.2} occurrences each)", distinct_pairs, occurrences as f64 / distinct_pairs.max(1) as f64);
+    println!("  Targets linked more than once from the same article: {} ({:.1}%)", repeated_pairs, 100.0 * repeated_pairs as f64 / distinct_pairs.max(1) as f64);
+    println!("  Occurrences in the first 10% of the text: {:.1}%", 100.0 * early_occurrences as f64 / sized_occurrences.max(1) as f64);
+    if !densities.is_empty() {
+        densities.sort_unstable_by(f64::total_cmp);
+        let mean = densities.iter().sum::<f64>() / densities.len() as f64;
+        println!("  Links per 1000 words: median {:.1}, mean {:.1}", densities[densities.len() / 2], mean);
+    }
+}
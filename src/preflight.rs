@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs::read_to_string;
+use tracing::{info, warn};
+use crate::config::config;
+use crate::helpers::{Args, PageId};
+use crate::links::ID_WIDTH;
+
+// Rough sizes measured on enwiki: the articles decompress to about 5x the multistream bz2 size, and the
+// index output (links.bin, graph.bin, titles.bin and titles.fst) takes about 10% of it, mostly for the links
+const DECOMPRESSION_RATIO: f64 = 5.0;
+const RECOMPRESSION_RATIO: f64 = 1.2;
+const INDEX_OUTPUT_RATIO: f64 = 0.1;
+// Per-title memory for the seek position map entries and the hash table around them, on top of the title text
+const SEEK_MAP_BYTES_PER_TITLE: u64 = 64;
+// Per-title memory for the title table entries, its lookup table and the split writer's offsets
+const TITLE_TABLE_BYTES_PER_TITLE: u64 = 64;
+// Each worker holds a compressed chunk, its decompressed XML and the parsed articles at once, and there's one worker
+// per thread
+const BYTES_PER_WORKER: u64 = 64_000_000;
+
+pub struct Estimate {
+    pub disk_bytes: u64,
+    pub memory_bytes: u64,
+}
+
+fn selected_bytes(chunk_ranges: &[(usize, u64, u64)]) -> u64 {
+    chunk_ranges.iter().map(|(_, start_position, end_position)| end_position - start_position).sum()
+}
+
+fn seek_map_bytes(seek_position_map: &HashMap<u64, Vec<(PageId, String)>>) -> (u64, u64) {
+    let titles = seek_position_map.values().flatten();
+    let (count, text) = titles.fold((0, 0), |(count, text), (_, title)| (count + 1, text + title.len() as u64));
+    (count, count * SEEK_MAP_BYTES_PER_TITLE + text)
+}
+
+pub fn estimate_index(seek_position_map: &HashMap<u64, Vec<(PageId, String)>>, chunk_ranges: &[(usize, u64, u64)]) -> Estimate {
+    let (title_count, seek_map_memory) = seek_map_bytes(seek_position_map);
+    let offsets_bytes = (ID_WIDTH as u64 + 16) * title_count;
+    Estimate {
+        disk_bytes: (selected_bytes(chunk_ranges) as f64 * INDEX_OUTPUT_RATIO) as u64 + offsets_bytes,
+        memory_bytes: seek_map_memory * 2 + title_count * TITLE_TABLE_BYTES_PER_TITLE + config().threads as u64 * BYTES_PER_WORKER,
+    }
+}
+
+pub fn estimate_dump(seek_position_map: &HashMap<u64, Vec<(PageId, String)>>, chunk_ranges: &[(usize, u64, u64)], args: &Args) -> Estimate {
+    let (_, seek_map_memory) = seek_map_bytes(seek_position_map);
+    let compressed = args.value("compress").is_some() || args.value("archive").is_some_and(|archive| !archive.ends_with(".tar"));
+    let ratio = if compressed { RECOMPRESSION_RATIO } else { DECOMPRESSION_RATIO };
+    let file_names_memory = if args.flag("name-by-title") { seek_map_memory } else { 0 };
+    Estimate {
+        disk_bytes: (selected_bytes(chunk_ranges) as f64 * ratio) as u64,
+        memory_bytes: seek_map_memory + file_names_memory + config().threads as u64 * BYTES_PER_WORKER,
+    }
+}
+
+#[cfg(unix)]
+fn available_disk(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_disk(_path: &Path) -> Option<u64> {
+    None
+}
+
+// MemAvailable counts reclaimable page cache, so it's a better guide than MemFree. Only Linux has it.
+fn available_memory() -> Option<u64> {
+    let meminfo = read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+// Compares the estimate against the free space on the volume holding `output_path` and the available RAM, and
+// stops before any output is written if either is short, unless --force is given
+pub fn check(args: &Args, output_path: &Path, estimate: &Estimate) {
+    info!("Estimated output size {}, estimated peak memory {}", format_size(estimate.disk_bytes), format_size(estimate.memory_bytes));
+    let mut problems = Vec::new();
+    if let Some(disk) = available_disk(output_path).filter(|&disk| disk < estimate.disk_bytes) {
+        problems.push(format!("{} needs about {} of disk space but only {} is free", output_path.to_str().unwrap(), format_size(estimate.disk_bytes), format_size(disk)));
+    }
+    if let Some(memory) = available_memory().filter(|&memory| memory < estimate.memory_bytes) {
+        problems.push(format!("this run needs about {} of memory but only {} is available", format_size(estimate.memory_bytes), format_size(memory)));
+    }
+    if problems.is_empty() { return; }
+
+    if args.flag("force") {
+        for problem in problems {
+            warn!("{}, continuing because of --force", problem);
+        }
+    } else {
+        for problem in problems {
+            eprintln!("Error: {}", problem);
+        }
+        eprintln!("Free up space or pass --force to run anyway");
+        std::process::exit(1);
+    }
+}
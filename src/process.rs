@@ -0,0 +1,49 @@
+use crate::helpers::Args;
+use crate::index::{self, Outputs};
+use tracing::info;
+
+// What process can extract, and the file each one writes
+const EXTRACTS: [(&str, &str); 8] = [
+    ("links", "links.bin and the split files"),
+    ("categories", "categories.bin"),
+    ("redirects", "redirects.tsv"),
+    ("text", "the article dump"),
+    ("files", "files.bin"),
+    ("positions", "positions.bin"),
+    ("first-links", "first-links.bin"),
+    ("store", "the article store"),
+];
+const DEFAULT_EXTRACTS: &str = "links,categories,redirects,text";
+
+// Produces any mix of the index outputs and the article dump from one pass over the dump, where running index and then
+// dump would decompress all of it twice. The text extract takes the dump command's options.
+pub fn process(args: &Args) {
+    let names: Vec<&str> = EXTRACTS.iter().map(|(extract, _)| *extract).collect();
+    let extracts: Vec<&str> = args.value("extract").unwrap_or(DEFAULT_EXTRACTS).split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+    if let Some(name) = extracts.iter().find(|name| !names.contains(name)) {
+        eprintln!("Error: Unknown extract {} (expected {})", name, names.join(", "));
+        std::process::exit(1);
+    }
+    if extracts.is_empty() {
+        eprintln!("Error: --extract needs at least one of {}", names.join(", "));
+        std::process::exit(1);
+    }
+    let outputs = Outputs {
+        links: extracts.contains(&"links"),
+        first_links: extracts.contains(&"first-links"),
+        positions: extracts.contains(&"positions"),
+        files: extracts.contains(&"files"),
+        categories: extracts.contains(&"categories"),
+        redirects: extracts.contains(&"redirects"),
+        dump: extracts.contains(&"text"),
+        store: extracts.contains(&"store"),
+    };
+    // Link positions are recorded as the links are resolved, so they come from the link extractor
+    if outputs.positions && !outputs.links {
+        eprintln!("Error: The positions extract also needs links");
+        std::process::exit(1);
+    }
+    let writes: Vec<&str> = EXTRACTS.iter().filter(|(extract, _)| extracts.contains(extract)).map(|(_, output)| *output).collect();
+    info!("Writing {} in one pass over the dump", writes.join(", "));
+    index::run(args, "process", outputs);
+}
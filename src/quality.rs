@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, read_to_string};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use regex::Regex;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+
+pub const QUALITY_FILE: &str = "quality.tsv";
+const TALK_NAMESPACE: u32 = 1;
+
+// Assessment classes from best to worst. Lists are assessed on their own scale, so List sits below the prose classes.
+pub const TIERS: [&str; 9] = ["FA", "FL", "A", "GA", "B", "C", "Start", "Stub", "List"];
+const UNASSESSED: &str = "Unassessed";
+
+// The templates that featured and good articles carry on the article itself, and stub templates like {{Physics-stub}}
+static ARTICLE_TEMPLATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\{\{\s*(featured article|featured list|good article|[^{}|]*stub)\s*(\||\}\})").unwrap()
+});
+// The class parameter of the WikiProject banners on talk pages, as in {{WikiProject Physics|class=B|importance=high}}
+static TALK_CLASS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\|\s*class\s*=\s*([a-z]+)").unwrap());
+
+// Turns a class name as written in a template into its index in TIERS
+fn tier_index(name: &str) -> Option<usize> {
+    TIERS.iter().position(|tier| tier.eq_ignore_ascii_case(name.trim()))
+}
+
+// The best tier named by the article's own templates
+fn article_tier(text: &str) -> Option<usize> {
+    ARTICLE_TEMPLATE.captures_iter(text).filter_map(|captures| {
+        match captures[1].to_lowercase().as_str() {
+            "featured article" => tier_index("FA"),
+            "featured list" => tier_index("FL"),
+            "good article" => tier_index("GA"),
+            _ => tier_index("Stub"),
+        }
+    }).min()
+}
+
+// The best class any WikiProject banner on a talk page gives its article
+fn talk_tier(text: &str) -> Option<usize> {
+    TALK_CLASS.captures_iter(text).filter_map(|captures| tier_index(&captures[1])).min()
+}
+
+// Loads the IDs of the articles whose tier is one of the comma-separated tiers given by --quality, or None without it
+pub fn load_quality_filter(args: &Args, data_path: &Path) -> Option<HashSet<PageId>> {
+    let tiers: Vec<&str> = args.value("quality")?.split(',').map(str::trim).collect();
+    if let Some(tier) = tiers.iter().find(|tier| tier_index(tier).is_none() && !tier.eq_ignore_ascii_case(UNASSESSED)) {
+        eprintln!("Error: Unknown quality tier {} (expected {} or {})", tier, TIERS.join(", "), UNASSESSED);
+        std::process::exit(1);
+    }
+    let quality_path = data_path.join(QUALITY_FILE);
+    let Ok(contents) = read_to_string(&quality_path) else {
+        eprintln!("Error: Unable to read {}, run the quality command first", quality_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    Some(contents.lines().skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let (id, _, tier) = (fields.next()?, fields.next()?, fields.next()?);
+            tiers.iter().any(|wanted| wanted.eq_ignore_ascii_case(tier)).then(|| id.parse().ok()).flatten()
+        })
+        .collect())
+}
+
+// Assigns every article in the main namespace a quality tier and writes them to quality.tsv. Featured and good
+// article templates on the article itself take precedence, then the best class given by the WikiProject banners on
+// its talk page, for dumps like pages-meta-current that include talk pages, and finally stub templates. Articles with
+// none of these are unassessed.
+pub fn quality(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let articles = Arc::new(Mutex::new(Vec::new()));  // (id, title, tier from the article's templates)
+    let talk_tiers = Arc::new(Mutex::new(HashMap::new()));  // article title -> tier from its talk page
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Reading assessments"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let articles = Arc::clone(&articles);
+        let talk_tiers = Arc::clone(&talk_tiers);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut chunk_articles = Vec::new();
+            let mut chunk_talk_tiers = Vec::new();
+            for (id, page) in load_chunk_pages(&articles_path, start_position, end_position) {
+                if page.namespace == 0 && !page.redirect {
+                    chunk_articles.push((id, article_tier(&page.text), page.title));
+                } else if page.namespace == TALK_NAMESPACE {
+                    if let (Some((_, title)), Some(tier)) = (page.title.split_once(':'), talk_tier(&page.text)) {
+                        chunk_talk_tiers.push((title.to_string(), tier));
+                    }
+                }
+            }
+            articles.lock().unwrap().extend(chunk_articles);
+            talk_tiers.lock().unwrap().extend(chunk_talk_tiers);
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+
+    let mut articles = Arc::try_unwrap(articles).ok().unwrap().into_inner().unwrap();
+    let talk_tiers = Arc::try_unwrap(talk_tiers).ok().unwrap().into_inner().unwrap();
+    articles.sort_unstable_by_key(|(id, _, _)| *id);
+    let stub = tier_index("Stub");
+    let mut counts = [0; TIERS.len() + 1];
+    let output_path = data_path.join(QUALITY_FILE);
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    writeln!(output_file, "article_id\ttitle\ttier").expect("Failed to write output file");
+    for (id, template_tier, title) in &articles {
+        let tier = match (template_tier.filter(|&tier| Some(tier) != stub), talk_tiers.get(title)) {
+            (Some(tier), _) => Some(tier),
+            (None, Some(&tier)) => Some(tier),
+            (None, None) => *template_tier,
+        };
+        counts[tier.unwrap_or(TIERS.len())] += 1;
+        writeln!(output_file, "{}\t{}\t{}", id, title, tier.map_or(UNASSESSED, |tier| TIERS[tier])).expect("Failed to write output file");
+    }
+    output_file.flush().expect("Failed to flush output file");
+
+    println!("Assessed {} articles, {} with a class on their talk page", articles.len(), talk_tiers.len());
+    for (name, count) in TIERS.iter().chain([&UNASSESSED]).zip(counts) {
+        println!("  {}: {} ({:.2}%)", name, count, 100.0 * count as f64 / articles.len().max(1) as f64);
+    }
+    println!("Wrote quality tiers to {}", output_path.to_str().unwrap());
+}
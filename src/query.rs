@@ -0,0 +1,168 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use rustc_hash::FxHashMap;
+use crate::links::{LinkGraph, PageId, check_id_width, is_partial, parse_record, read_fixed_id, read_header};
+use crate::split::{GRAPH_MAGIC, HEADER_SIZE, OFFSETS_HEADER_SIZE, OFFSETS_MAGIC, TITLES_MAGIC, parse_graph_record, parse_title, validate_header};
+
+// Queries over the files the index command writes that don't touch the file system or start threads, so they build
+// for wasm32 without the cli feature:
+//
+//   cargo build --lib --no-default-features --target wasm32-unknown-unknown
+//
+// A browser can then fetch links.bin, or offsets.idx, titles.bin and graph.bin, as static files and query them.
+
+// Random access to the bytes of a file, wherever they're kept
+pub trait ByteSource {
+    fn size(&self) -> u64;
+    // Copies bytes from `position` into the buffer, returning how many there were before the end of the file
+    fn read_at(&self, position: u64, buffer: &mut [u8]) -> Result<usize, String>;
+}
+
+impl ByteSource for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_at(&self, position: u64, buffer: &mut [u8]) -> Result<usize, String> {
+        let rest = self.get(position as usize..).unwrap_or_default();
+        let length = rest.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&rest[..length]);
+        Ok(length)
+    }
+}
+
+// Fetches individual titles and link lists by article ID from the split files, with a binary search of the sorted
+// entries in offsets.idx and a single read from titles.bin or graph.bin
+pub struct RecordReader<S> {
+    offsets: S,
+    titles: S,
+    graph: S,
+    id_width: usize,
+    num_entries: u64,
+}
+
+impl<S: ByteSource> RecordReader<S> {
+    pub fn new(offsets: S, titles: S, graph: S) -> Result<Self, String> {
+        for (source, magic, name) in [(&offsets, OFFSETS_MAGIC, "offsets.idx"), (&titles, TITLES_MAGIC, "titles.bin"), (&graph, GRAPH_MAGIC, "graph.bin")] {
+            let mut header = [0; HEADER_SIZE as usize];
+            let bytes_read = source.read_at(0, &mut header)?;
+            validate_header(&header[..bytes_read], magic, name)?;
+        }
+        let mut width = [0; 4];
+        if offsets.read_at(HEADER_SIZE, &mut width)? < width.len() {
+            return Err("offsets.idx ends before its ID width".to_string());
+        }
+        let id_width = u32::from_le_bytes(width);
+        check_id_width(id_width).map_err(|err| format!("offsets.idx: {}", err))?;
+        let entry_size = id_width as u64 + 16;
+        let entries_size = offsets.size() - OFFSETS_HEADER_SIZE;
+        if !entries_size.is_multiple_of(entry_size) {
+            return Err("offsets.idx ends partway through an entry, re-run the index command".to_string());
+        }
+        Ok(RecordReader { offsets, titles, graph, id_width: id_width as usize, num_entries: entries_size / entry_size })
+    }
+
+    fn entry(&self, index: u64, entry: &mut [u8]) -> Result<PageId, String> {
+        if self.offsets.read_at(OFFSETS_HEADER_SIZE + index * entry.len() as u64, entry)? < entry.len() {
+            return Err(format!("offsets.idx ends before entry {}", index));
+        }
+        Ok(read_fixed_id(&entry[..self.id_width]))
+    }
+
+    fn offsets(&self, article_id: PageId) -> Result<Option<(u64, u64)>, String> {
+        let mut entry = vec![0; self.id_width + 16];
+        let (mut low, mut high) = (0, self.num_entries);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.entry(middle, &mut entry)?.cmp(&article_id) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => {
+                    let titles_offset = u64::from_le_bytes(entry[self.id_width..self.id_width + 8].try_into().unwrap());
+                    let graph_offset = u64::from_le_bytes(entry[self.id_width + 8..].try_into().unwrap());
+                    return Ok(Some((titles_offset, graph_offset)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Reads a record of unknown length, fetching more bytes if the first read doesn't cover it all
+    fn read_record<T>(source: &S, position: u64, parse: impl Fn(&[u8], &mut usize) -> Result<T, String>) -> Result<T, String> {
+        let mut buffer = vec![0; 4096];
+        loop {
+            let bytes_read = source.read_at(position, &mut buffer)?;
+            match parse(&buffer[..bytes_read], &mut 0) {
+                Err(_) if bytes_read == buffer.len() => buffer.resize(buffer.len() * 4, 0),
+                result => return result,
+            }
+        }
+    }
+
+    pub fn title(&self, article_id: PageId) -> Result<Option<String>, String> {
+        let Some((titles_offset, _)) = self.offsets(article_id)? else { return Ok(None) };
+        let (_, title) = Self::read_record(&self.titles, titles_offset, parse_title)
+            .map_err(|err| format!("Corrupt title record at byte {}: {}", titles_offset, err))?;
+        Ok(Some(title))
+    }
+
+    pub fn links(&self, article_id: PageId) -> Result<Option<Vec<PageId>>, String> {
+        let Some((_, graph_offset)) = self.offsets(article_id)? else { return Ok(None) };
+        let (_, links) = Self::read_record(&self.graph, graph_offset, parse_graph_record)
+            .map_err(|err| format!("Corrupt graph record at byte {}: {}", graph_offset, err))?;
+        Ok(Some(links))
+    }
+}
+
+// Parses a whole links.bin on the current thread. A file from an interrupted index run is an error, since it's
+// missing some of the articles.
+pub fn parse_link_graph(buffer: &[u8]) -> Result<LinkGraph, String> {
+    let (version, mut i) = read_header(buffer)?;
+    if is_partial(buffer) {
+        return Err("links.bin is from an interrupted index run, re-run index with --resume to finish it".to_string());
+    }
+    let mut graph = LinkGraph { links: FxHashMap::default(), titles: FxHashMap::default(), pages: FxHashMap::default() };
+    while i < buffer.len() {
+        let (record, next) = parse_record(buffer, i, version).map_err(|err| format!("Corrupt record at byte {}: {}", i, err))?;
+        graph.pages.insert(record.article_id, record.info);
+        graph.titles.insert(record.article_id, record.title);
+        graph.links.insert(record.article_id, record.links);
+        i = next;
+    }
+    Ok(graph)
+}
+
+// Inverts the outgoing links into the articles linking to each article
+pub fn backlinks(links: &FxHashMap<PageId, Vec<PageId>>) -> FxHashMap<PageId, Vec<PageId>> {
+    let mut backlinks: FxHashMap<PageId, Vec<PageId>> = FxHashMap::default();
+    for (&article_id, article_links) in links {
+        for &link in article_links {
+            backlinks.entry(link).or_default().push(article_id);
+        }
+    }
+    backlinks
+}
+
+// Breadth-first search over outgoing links, returning the articles on a shortest path from `from` to `to` inclusive
+pub fn shortest_path(links: &FxHashMap<PageId, Vec<PageId>>, from: PageId, to: PageId) -> Option<Vec<PageId>> {
+    let mut parents: HashMap<PageId, PageId> = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(id) = queue.pop_front() {
+        if id == to {
+            let mut path = vec![to];
+            while *path.last().unwrap() != from {
+                path.push(parents[path.last().unwrap()]);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &link in links.get(&id).into_iter().flatten() {
+            if let Entry::Vacant(entry) = parents.entry(link) {
+                entry.insert(id);
+                queue.push_back(link);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,37 @@
+use std::path::Path;
+use rand::seq::IteratorRandom;
+use crate::helpers::{Args, locate_dump_files, skip_nested};
+use crate::lookup::ArticleLookup;
+
+// Skips leading templates, tables, and file links and returns the first paragraph of prose
+pub fn first_paragraph(text: &str) -> &str {
+    let mut rest = text.trim_start();
+    loop {
+        let (open, close) = match rest.get(..2) {
+            Some("{{") => ("{{", "}}"),
+            Some("{|") => ("{|", "|}"),
+            Some("[[") if rest.starts_with("[[File:") || rest.starts_with("[[Image:") => ("[[", "]]"),
+            _ => break,
+        };
+        rest = rest[skip_nested(rest, open, close)..].trim_start();
+    }
+    rest.split("\n\n").next().unwrap_or("").trim()
+}
+
+pub fn random(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let lookup = ArticleLookup::new(&index_path, &articles_path, 0);
+
+    let Some(&article_id) = lookup.ids().choose(&mut rand::thread_rng()) else {
+        eprintln!("Error: The index contains no articles");
+        std::process::exit(1);
+    };
+    let content = lookup.get(article_id).unwrap_or_else(|| {
+        eprintln!("Error: Article {} not found in its chunk", article_id);
+        std::process::exit(1);
+    });
+
+    println!("{} (ID: {}, chunk at byte {})\n", lookup.title(article_id).unwrap(), article_id, lookup.position(article_id).unwrap());
+    println!("{}", first_paragraph(&content));
+}
@@ -0,0 +1,94 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, create_progress_bar, decompress_index, get_dump_paths, get_zstd_dump_paths, load_chunk_xml};
+
+// Returns the byte range of every chunk in the dump, including chunks of pages that load_index ignores
+fn get_all_chunk_ranges(index_path: &Path, articles_path: &Path) -> Vec<(u64, u64)> {
+    let index_file = File::open(index_path).expect("Unable to open index file");
+    let mut positions: Vec<u64> = BufReader::new(index_file).lines().map_while(Result::ok)
+        .filter_map(|line| line.split_once(':').and_then(|(position, _)| position.parse().ok()))
+        .collect();
+    positions.push(articles_path.metadata().expect("Failed to get file metadata").len());
+    positions.sort_unstable();
+    positions.dedup();
+    positions.windows(2).map(|window| (window[0], window[1])).collect()
+}
+
+// Converts the bz2 dump into a zstd dump with one frame per chunk, so any chunk can still be read on its own but
+// decompresses several times faster, and writes a copy of the index with the new offsets. Every command that reads
+// the dump uses the zstd dump once it exists.
+pub fn recompress(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let level: i32 = args.parse_value("level").unwrap_or(9);
+    if !zstd::compression_level_range().contains(&level) {
+        eprintln!("Error: --level must be between {} and {}", zstd::compression_level_range().start(), zstd::compression_level_range().end());
+        std::process::exit(1);
+    }
+    let (index_path, articles_path) = get_dump_paths(data_path);
+    if !index_path.exists() || !articles_path.exists() {
+        eprintln!("Error: Unable to locate the bz2 dump in {}", data_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+    let index_path = decompress_index(index_path.to_str().unwrap());
+    let (zstd_index_path, zstd_articles_path) = get_zstd_dump_paths(data_path);
+    let partial_articles_path = PathBuf::from(format!("{}.partial", zstd_articles_path.to_str().unwrap()));
+    let partial_index_path = PathBuf::from(format!("{}.partial", zstd_index_path.to_str().unwrap()));
+
+    let chunk_ranges = get_all_chunk_ranges(&index_path, &articles_path);
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path_str = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = create_progress_bar(chunk_ranges.len() as u64, "Recompressing chunks");
+    let (sender, receiver) = mpsc::sync_channel(num_threads);
+
+    for (sequence, &(start_position, end_position)) in chunk_ranges.iter().enumerate() {
+        let articles_path = Arc::clone(&articles_path_str);
+        let sender = sender.clone();
+        pool.execute(move || {
+            let xml = load_chunk_xml(&articles_path, start_position, end_position);
+            let frame = zstd::bulk::compress(&xml, level).expect("Failed to compress chunk");
+            sender.send((sequence, frame)).expect("Failed to send compressed chunk");
+        });
+    }
+    drop(sender);
+
+    // Frames have to be written in dump order, so hold early ones back until everything before them has been written
+    let mut output = BufWriter::new(File::create(&partial_articles_path).expect("Failed to create zstd dump"));
+    let mut new_positions = HashMap::new();
+    let mut pending = BTreeMap::new();
+    let mut next_sequence = 0;
+    let mut position = 0;
+    for (sequence, frame) in receiver.iter() {
+        pending.insert(sequence, frame);
+        while let Some(frame) = pending.remove(&next_sequence) {
+            new_positions.insert(chunk_ranges[next_sequence].0, position);
+            output.write_all(&frame).expect("Failed to write zstd dump");
+            position += frame.len() as u64;
+            next_sequence += 1;
+            progress_bar.inc(1);
+        }
+    }
+    output.flush().expect("Failed to flush zstd dump");
+    progress_bar.finish_and_clear();
+
+    let index_file = File::open(&index_path).expect("Unable to open index file");
+    let mut zstd_index = BufWriter::new(File::create(&partial_index_path).expect("Failed to create zstd index"));
+    for line in BufReader::new(index_file).lines().map_while(Result::ok) {
+        let Some((old_position, rest)) = line.split_once(':') else { continue };
+        let Some(new_position) = old_position.parse().ok().and_then(|old_position: u64| new_positions.get(&old_position)) else { continue };
+        writeln!(zstd_index, "{}:{}", new_position, rest).expect("Failed to write zstd index");
+    }
+    zstd_index.flush().expect("Failed to flush zstd index");
+
+    // Only move the files into place once both are complete, so an interrupted run is never picked up
+    std::fs::rename(&partial_articles_path, &zstd_articles_path).expect("Failed to rename zstd dump");
+    std::fs::rename(&partial_index_path, &zstd_index_path).expect("Failed to rename zstd index");
+    let original_size = articles_path.metadata().expect("Failed to get file metadata").len();
+    println!("Recompressed {} chunks to {} ({:.1} MB, {:.0}% of the bz2 dump)",
+        chunk_ranges.len(), zstd_articles_path.to_str().unwrap(), position as f64 / 1e6, 100.0 * position as f64 / original_size as f64);
+}
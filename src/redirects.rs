@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use rustc_hash::FxHashMap;
+use crate::helpers::{Page, PageId};
+use crate::index::normalize_link;
+use crate::links::PageInfo;
+use crate::pipeline::{PageSink, SinkChunk};
+use crate::titles::TitleTable;
+
+pub const REDIRECTS_FILE: &str = "redirects.tsv";
+pub const DOUBLE_REDIRECTS_FILE: &str = "double-redirects.tsv";
+pub const BROKEN_REDIRECTS_FILE: &str = "broken-redirects.tsv";
+const MAX_HOPS: usize = 100;
+
+// A redirect's only link is its target. Redirects whose target resolves to another redirect are double redirects, and
+// redirects with no resolved link point at a page that doesn't exist (or into an ignored namespace).
+// Writes both reports to `data_path` as TSV and returns how many of each were found.
+pub fn write_redirect_reports(data_path: &Path, links: &FxHashMap<PageId, Vec<PageId>>, pages: &FxHashMap<PageId, PageInfo>, title: impl Fn(&PageId) -> String) -> (usize, usize) {
+    let is_redirect = |id: &PageId| pages.get(id).is_some_and(|info| info.redirect);
+    let mut redirects: Vec<PageId> = pages.iter().filter(|(_, info)| info.redirect).map(|(id, _)| *id).collect();
+    redirects.sort_unstable();
+    let target = |id: &PageId| links.get(id).and_then(|links| links.first()).copied();
+
+    let mut double_file = BufWriter::new(File::create(data_path.join(DOUBLE_REDIRECTS_FILE)).expect("Failed to create redirect report"));
+    let mut broken_file = BufWriter::new(File::create(data_path.join(BROKEN_REDIRECTS_FILE)).expect("Failed to create redirect report"));
+    writeln!(double_file, "redirect_id\tredirect_title\ttarget_id\ttarget_title\tfinal_target_title").expect("Failed to write redirect report");
+    writeln!(broken_file, "redirect_id\tredirect_title").expect("Failed to write redirect report");
+
+    let (mut double_redirects, mut broken_redirects) = (0, 0);
+    for id in &redirects {
+        match target(id) {
+            Some(target_id) if is_redirect(&target_id) => {
+                // Follow the rest of the chain, giving up if it's long enough that it must loop
+                let mut final_target = target_id;
+                let mut hops = 0;
+                while let Some(next) = target(&final_target).filter(|_| is_redirect(&final_target) && hops < MAX_HOPS) {
+                    final_target = next;
+                    hops += 1;
+                }
+                let final_title = match (is_redirect(&final_target), target(&final_target)) {
+                    (false, _) => title(&final_target),
+                    (true, Some(_)) => "(redirect loop)".to_string(),
+                    (true, None) => "(broken redirect)".to_string(),
+                };
+                writeln!(double_file, "{}\t{}\t{}\t{}\t{}", id, title(id), target_id, title(&target_id), final_title).expect("Failed to write redirect report");
+                double_redirects += 1;
+            }
+            Some(_) => {}
+            None => {
+                writeln!(broken_file, "{}\t{}", id, title(id)).expect("Failed to write redirect report");
+                broken_redirects += 1;
+            }
+        }
+    }
+    double_file.flush().expect("Failed to flush redirect report");
+    broken_file.flush().expect("Failed to flush redirect report");
+    (double_redirects, broken_redirects)
+}
+
+// Lists every redirect with its target title as the dump gives it, and the ID of the page that title resolves to, or
+// nothing if it doesn't resolve to a page in the dump
+pub struct RedirectSink {
+    titles: Arc<TitleTable>,
+    file: Mutex<BufWriter<File>>,
+}
+
+impl RedirectSink {
+    pub fn create(path: &Path, titles: Arc<TitleTable>) -> Self {
+        let mut file = BufWriter::new(File::create(path).expect("Failed to create redirects file"));
+        writeln!(file, "redirect_id\tredirect_title\ttarget_title\ttarget_id").expect("Failed to write redirects file");
+        RedirectSink { titles, file: Mutex::new(file) }
+    }
+}
+
+impl PageSink for RedirectSink {
+    fn extract(&self, _: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        let mut redirects: Vec<(&PageId, &Page)> = pages.iter().filter(|(_, page)| page.redirect).collect();
+        redirects.sort_unstable_by_key(|(id, _)| **id);
+        let mut rows = Vec::new();
+        for (id, page) in redirects {
+            let target = page.redirect_target.as_deref().unwrap_or_default();
+            let target_id = normalize_link(target).and_then(|target| self.titles.find(target.trim()));
+            writeln!(rows, "{}\t{}\t{}\t{}", id, page.title, target, target_id.map(|id| id.to_string()).unwrap_or_default()).expect("Failed to write redirect");
+        }
+        Box::new(rows)
+    }
+
+    fn write(&self, chunk: SinkChunk) {
+        self.file.lock().unwrap().write_all(&chunk.downcast::<Vec<u8>>().unwrap()).expect("Failed to write redirects file");
+    }
+
+    fn finish(&self) {
+        self.file.lock().unwrap().flush().expect("Failed to flush redirects file");
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use rustc_hash::{FxHashMap, FxHashSet};
+use crate::complete::TitleCompleter;
+use crate::graph::{DenseGraph, build_dense_graph};
+use crate::helpers::{Args, PageId};
+use crate::links::PageInfo;
+use crate::split::{load_graph, load_page_info, load_titles};
+
+// Looks up the article named on the command line after the data path, following it if it's a redirect, and exits
+// with an error if there's no such article
+pub fn find_article(args: &Args, data_path: &Path, usage: &str, graph: &DenseGraph, pages: &FxHashMap<PageId, PageInfo>) -> usize {
+    let query = args.positional[1..].join(" ");
+    if query.is_empty() {
+        eprintln!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let Some(completer) = TitleCompleter::open(data_path) else {
+        eprintln!("Error: Unable to locate titles.fst in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let Some(node) = completer.find(&query).and_then(|id| graph.index_of(id)) else {
+        eprintln!("Error: No article titled {}", query);
+        std::process::exit(1);
+    };
+    let is_redirect = |node: usize| pages.get(&graph.ids[node]).is_some_and(|info| info.redirect);
+    match graph.successors(node).first() {
+        Some(&target) if is_redirect(node) => target as usize,
+        _ => node,
+    }
+}
+
+// Approximates personalized PageRank seeded at `seed` by pushing residual mass along out-links until every node's
+// residual is below `epsilon` times its out-degree (Andersen, Chung and Lang). Mass reaching an article without links
+// returns to the seed. Only touches the neighborhood of the seed, so it's fast even on the full graph.
+pub fn personalized_pagerank(graph: &DenseGraph, seed: usize, alpha: f64, epsilon: f64) -> FxHashMap<usize, f64> {
+    let mut scores = FxHashMap::default();
+    let mut residuals = FxHashMap::default();
+    let mut queue = VecDeque::from([seed]);
+    let mut queued = FxHashSet::from_iter([seed]);
+    residuals.insert(seed, 1.0);
+    let threshold = |node: usize| epsilon * graph.successors(node).len().max(1) as f64;
+
+    while let Some(node) = queue.pop_front() {
+        queued.remove(&node);
+        let residual = residuals.insert(node, 0.0).unwrap_or(0.0);
+        *scores.entry(node).or_insert(0.0) += alpha * residual;
+        let successors = graph.successors(node);
+        let mut push = |target: usize, mass: f64| {
+            let target_residual = residuals.entry(target).or_insert(0.0);
+            *target_residual += mass;
+            if *target_residual >= threshold(target) && queued.insert(target) {
+                queue.push_back(target);
+            }
+        };
+        if successors.is_empty() {
+            push(seed, (1.0 - alpha) * residual);
+        } else {
+            let share = (1.0 - alpha) * residual / successors.len() as f64;
+            for &successor in successors {
+                push(successor as usize, share);
+            }
+        }
+    }
+    scores
+}
+
+pub fn related(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let alpha: f64 = args.parse_value("alpha").unwrap_or(0.15);
+    let epsilon: f64 = args.parse_value("epsilon").unwrap_or(1e-7);
+    let limit: usize = args.parse_value("limit").unwrap_or(20);
+    if !(alpha > 0.0 && alpha < 1.0) || epsilon.is_nan() || epsilon <= 0.0 {
+        eprintln!("Error: --alpha must be between 0 and 1 and --epsilon must be positive");
+        std::process::exit(1);
+    }
+
+    let Some(links) = load_graph(data_path) else {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let graph = build_dense_graph(&links);
+    drop(links);
+    let pages = load_page_info(data_path).unwrap();
+    let seed = find_article(args, data_path, "related <data_path> <title> [--limit N] [--alpha A] [--epsilon E]", &graph, &pages);
+
+    // Rank everything but the seed itself and redirects, which only pass their score along
+    let scores = personalized_pagerank(&graph, seed, alpha, epsilon);
+    let mut ranked: Vec<(usize, f64)> = scores.into_iter()
+        .filter(|&(node, _)| node != seed && !pages.get(&graph.ids[node]).is_some_and(|info| info.redirect))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let titles = load_titles(data_path).unwrap();
+    let title = |node: usize| titles.get(&graph.ids[node]).cloned().unwrap_or_else(|| format!("Unknown (ID: {})", graph.ids[node]));
+    println!("Articles related to {}:", title(seed));
+    for (rank, (node, score)) in ranked.iter().take(limit).enumerate() {
+        println!("{:>2}) {} ({:.5})", rank + 1, title(*node), score);
+    }
+    if ranked.is_empty() {
+        println!("No related articles");
+    }
+}
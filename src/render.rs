@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use html_escape::{decode_html_entities, encode_double_quoted_attribute, encode_text};
+use crate::corpus::{DROPPED_TAGS, categories, plain_text};
+use crate::helpers::skip_nested;
+use crate::infobox::{Template, split_top_level};
+use crate::namespaces::is_ignored;
+
+// The styles for rendered articles, which pages embed or link to
+pub const STYLE: &str = "\
+body { margin: 0; font-family: sans-serif; line-height: 1.6; color: #202122; }
+main { max-width: 60em; margin: 0 auto; padding: 1em; }
+h1, h2 { font-family: serif; font-weight: normal; border-bottom: 1px solid #a2a9b1; }
+a { color: #3366cc; text-decoration: none; }
+a:hover { text-decoration: underline; }
+a.external::after { content: \" \\2197\"; font-size: 0.8em; }
+.missing { color: #54595d; }
+.toc { display: inline-block; padding: 0.5em 1em; border: 1px solid #a2a9b1; background: #f8f9fa; }
+.toc ul { margin: 0; padding-left: 1.2em; list-style: none; }
+.infobox { float: right; clear: right; width: 22em; margin: 0 0 1em 1em; border: 1px solid #a2a9b1; background: #f8f9fa; font-size: 0.9em; }
+.infobox caption { font-weight: bold; }
+.infobox th { text-align: left; vertical-align: top; padding-right: 0.5em; }
+.image { display: inline-block; max-width: 15em; margin: 0.5em; font-size: 0.85em; vertical-align: top; }
+.image.thumb { float: right; clear: right; border: 1px solid #c8ccd1; padding: 0.2em; background: #f8f9fa; }
+.image .placeholder { display: block; padding: 2em 0.5em; background: #eaecf0; color: #54595d; text-align: center; overflow-wrap: anywhere; }
+.categories { clear: both; margin-top: 2em; padding: 0.5em; border: 1px solid #a2a9b1; background: #f8f9fa; font-size: 0.9em; }
+";
+
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+// Options of a file link that aren't its caption
+const IMAGE_OPTIONS: [&str; 21] = [
+    "thumb", "thumbnail", "frame", "framed", "frameless", "border", "left", "right", "center", "centre", "none", "upright",
+    "baseline", "middle", "sub", "super", "top", "text-top", "bottom", "text-bottom", "mini",
+];
+// Infobox parameters that are layout rather than facts about the subject
+const SKIPPED_INFOBOX_KEYS: [&str; 9] = ["name", "image", "caption", "alt", "size", "upright", "signature", "module", "embed"];
+
+// The id a heading gets, which is what a link to a section of the article refers to
+pub fn anchor(text: &str) -> String {
+    plain_text(text).trim().replace(' ', "_")
+}
+
+// A full page for a single rendered article, with the styles inline
+pub fn html_document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n<main>\n<h1>{title}</h1>\n{body}</main>\n</body>\n</html>\n",
+        title = encode_text(title),
+    )
+}
+
+fn is_file(target: &str) -> bool {
+    let target = target.trim_start_matches(':').to_lowercase();
+    target.starts_with("file:") || target.starts_with("image:")
+}
+
+// Formats the year, month and day parameters of date templates like {{birth date|1815|12|10}}
+fn date(template: &Template, year: &str, month: &str, day: &str) -> String {
+    let month_name = template.get(month).and_then(|month| month.parse::<usize>().ok()).and_then(|month| MONTHS.get(month.wrapping_sub(1)));
+    match (template.get(year), month_name, template.get(day)) {
+        (Some(year), Some(month), Some(day)) => format!("{} {} {}", day.trim_start_matches('0'), month, year),
+        (Some(year), Some(month), None) => format!("{} {}", month, year),
+        (Some(year), _, _) => year.to_string(),
+        _ => String::new(),
+    }
+}
+
+// Expands the common inline templates to the wikitext they stand for, like {{convert|5|km}} to "5 km" and
+// {{main|Paris}} to a line linking to Paris. Everything else, citations and navigation boxes and so on, expands to
+// nothing.
+fn expand_template(template: &Template) -> String {
+    let param = |key: &str| template.get(key).unwrap_or_default().to_string();
+    let positional: Vec<&str> = template.params.iter().filter(|(key, _)| key.parse::<usize>().is_ok()).map(|(_, value)| value.as_str()).collect();
+    let name = template.name.to_lowercase();
+    match name.as_str() {
+        "lang" | "transl" => param("2"),
+        _ if name.starts_with("lang-") => param("1"),
+        "nowrap" | "small" | "big" | "nobold" | "noitalic" | "ipa" | "respell" | "math" | "var" | "abbr" => param("1"),
+        "hlist" | "flatlist" | "plainlist" | "ubl" | "unbulleted list" | "plain list" => positional.join(" · "),
+        "main" | "see also" | "further" => {
+            let label = match name.as_str() { "main" => "Main article", "see also" => "See also", _ => "Further information" };
+            let links: Vec<String> = positional.iter().map(|target| format!("[[{}]]", target)).collect();
+            format!("\n:''{}: {}''\n", label, links.join(", "))
+        }
+        "convert" | "cvt" => match positional.as_slice() {
+            [from, "-" | "–" | "to" | "and" | "or", to, unit, ..] => format!("{}–{} {}", from, to, unit),
+            [value, unit, ..] => format!("{} {}", value, unit),
+            _ => String::new(),
+        },
+        "circa" | "c." => format!("c. {}", param("1")).trim_end().to_string(),
+        "birth date" | "death date" | "start date" | "end date" | "birth date and age" | "death date and age" | "dob" => date(template, "1", "2", "3"),
+        "ndash" | "snd" => "–".to_string(),
+        "mdash" => "—".to_string(),
+        "nbsp" => "&nbsp;".to_string(),
+        "'" => "'".to_string(),
+        "sic" => "[sic]".to_string(),
+        _ => String::new(),
+    }
+}
+
+// A heading or a block of content in an article, kept apart so that headings over nothing can be dropped
+enum Block {
+    Heading(usize, String, String),  // level, heading wikitext, anchor
+    Content(String),
+}
+
+// Renders wikitext to HTML for reading: headings with anchors and a table of contents, paragraphs, lists, links,
+// bold and italics, the infobox and a handful of common inline templates, with images as placeholders since the dump
+// has no media. Tables, references and other templates are dropped.
+pub struct HtmlRenderer<F> {
+    link: F,  // the URL of an article by title, or None if there's nothing to link to
+}
+
+impl<F: Fn(&str) -> Option<String>> HtmlRenderer<F> {
+    pub fn new(link: F) -> Self {
+        HtmlRenderer { link }
+    }
+
+    // The URL of a link target, with the section it points to if any
+    pub fn href(&self, target: &str) -> Option<String> {
+        let target = target.trim().trim_start_matches(':');
+        let (page, fragment) = target.split_once('#').unwrap_or((target, ""));
+        let fragment = if fragment.is_empty() { String::new() } else { format!("#{}", anchor(fragment)) };
+        if page.trim().is_empty() {
+            return (!fragment.is_empty()).then_some(fragment);
+        }
+        (self.link)(page.trim()).map(|url| url + &fragment)
+    }
+
+    fn image(&self, inner: &str) -> String {
+        let parts = split_top_level(inner);
+        let name = parts[0].trim().split_once(':').map_or("", |(_, name)| name.trim());
+        let options: Vec<&str> = parts[1..].iter().map(|part| part.trim()).collect();
+        let is_option = |part: &&str| IMAGE_OPTIONS.contains(part) || part.contains('=') && !part.contains("[[") || part.ends_with("px");
+        let thumb = options.iter().any(|option| ["thumb", "thumbnail", "frame", "framed", "mini"].contains(option));
+        let caption = options.iter().rev().find(|part| !is_option(part)).map(|caption| self.inline(caption)).unwrap_or_default();
+        format!("<span class=\"image{}\"><span class=\"placeholder\">{}</span>{}</span>",
+            if thumb { " thumb" } else { "" }, encode_text(name), caption)
+    }
+
+    // Renders a single line of wikitext: links, bold and italics, line breaks and escaped text
+    pub fn inline(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut open: Vec<&str> = Vec::new();  // the bold and italic tags open here, innermost last
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            if rest.starts_with("[[") {
+                let length = skip_nested(rest, "[[", "]]");
+                let inner = rest[2..length].strip_suffix("]]").unwrap_or(&rest[2..length]);
+                i += length;
+                let target = inner.split('|').next().unwrap().trim();
+                if is_file(target) {
+                    output.push_str(&self.image(inner));
+                    continue;
+                }
+                // Categories and interlanguage links aren't part of the text, as in plain_text
+                let language = target.split_once(':').is_some_and(|(prefix, _)| (2..=3).contains(&prefix.len()) && prefix.bytes().all(|c| c.is_ascii_lowercase()));
+                if is_ignored(target) || language { continue; }
+                // Letters straight after the link, as in [[apple]]s, are part of its label
+                let trail_length = text[i..].len() - text[i..].trim_start_matches(|c: char| c.is_ascii_lowercase()).len();
+                let label = inner.split_once('|').map_or(target.trim_start_matches(':'), |(_, label)| label);
+                let label = format!("{}{}", self.inline(label), &text[i..i + trail_length]);
+                i += trail_length;
+                match self.href(target) {
+                    Some(href) => output.push_str(&format!("<a href=\"{}\">{}</a>", encode_double_quoted_attribute(&href), label)),
+                    None => output.push_str(&format!("<span class=\"missing\">{}</span>", label)),
+                }
+            } else if rest.starts_with("[http") || rest.starts_with("[//") {
+                let end = rest.find(']').unwrap_or(rest.len());
+                let (url, label) = rest[1..end].split_once(' ').unwrap_or((&rest[1..end], ""));
+                let label = if label.trim().is_empty() { encode_text(url).to_string() } else { self.inline(label.trim()) };
+                output.push_str(&format!("<a class=\"external\" href=\"{}\">{}</a>", encode_double_quoted_attribute(url), label));
+                i += (end + 1).min(rest.len());
+            } else if rest.starts_with("''") {
+                let quotes = rest.len() - rest.trim_start_matches('\'').len();
+                let (toggle_bold, toggle_italic) = match quotes {
+                    2 => (false, true),
+                    3 | 4 => (true, false),
+                    _ => (true, true),
+                };
+                // Whichever is innermost closes first, and closing one that isn't innermost closes the ones inside it
+                // and reopens them after, so the tags always nest and the output is valid XHTML for EPUBs too
+                let order = if open.last() == Some(&"b") { [("b", toggle_bold), ("i", toggle_italic)] } else { [("i", toggle_italic), ("b", toggle_bold)] };
+                for (tag, _) in order.into_iter().filter(|(_, toggle)| *toggle) {
+                    match open.iter().position(|open| *open == tag) {
+                        Some(index) => {
+                            for inner in open[index..].iter().rev() { output.push_str(&format!("</{}>", inner)); }
+                            open.remove(index);
+                            for inner in &open[index..] { output.push_str(&format!("<{}>", inner)); }
+                        }
+                        None => {
+                            output.push_str(&format!("<{}>", tag));
+                            open.push(tag);
+                        }
+                    }
+                }
+                i += quotes;
+            } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') {
+                // Other tags are dropped, keeping their contents, except for line breaks
+                let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+                let name = rest[1..].split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap().to_lowercase();
+                if name == "br" { output.push_str("<br/>"); }
+                i += tag_end;
+            } else {
+                let c = rest.chars().next().unwrap();
+                match c {
+                    '&' if rest.find(';').is_some_and(|end| end > 1 && rest[1..end].bytes().all(|b| b.is_ascii_alphanumeric() || b == b'#')) => {
+                        let end = rest.find(';').unwrap() + 1;
+                        output.push_str(&encode_text(&decode_html_entities(&rest[..end])));
+                        i += end;
+                        continue;
+                    }
+                    '&' => output.push_str("&amp;"),
+                    '<' => output.push_str("&lt;"),
+                    '>' => output.push_str("&gt;"),
+                    _ => output.push(c),
+                }
+                i += c.len_utf8();
+            }
+        }
+        for tag in open.iter().rev() {
+            output.push_str(&format!("</{}>", tag));
+        }
+        output
+    }
+
+    // Lays out the infobox's parameters as a table, with the image as a placeholder at the top
+    fn infobox(&self, infobox: &Template) -> String {
+        let caption = infobox.get("name").map(str::to_string)
+            .unwrap_or_else(|| infobox.name.trim_start_matches(|c: char| c.is_alphabetic()).trim().to_string());
+        let mut html = format!("<table class=\"infobox\">\n<caption>{}</caption>\n", self.inline(&expand_templates(&caption)));
+        if let Some(image) = infobox.get("image") {
+            let image = if image.contains(':') { image.to_string() } else { format!("File:{}", image) };
+            let caption = infobox.get("caption").unwrap_or_default();
+            html.push_str(&format!("<tr><td colspan=\"2\">{}</td></tr>\n", self.image(&format!("{}|{}", image.trim_matches(['[', ']']), caption))));
+        }
+        for (key, value) in &infobox.params {
+            if value.is_empty() || key.parse::<usize>().is_ok() || SKIPPED_INFOBOX_KEYS.iter().any(|skipped| key.contains(skipped)) { continue; }
+            let value: Vec<String> = expand_templates(value).lines()
+                .map(|line| self.inline(line.trim_start_matches(['*', '#']).trim()))
+                .filter(|line| !line.is_empty())
+                .collect();
+            if value.is_empty() { continue; }
+            let label = key.replace('_', " ");
+            let mut chars = label.chars();
+            let label = chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or(label.clone());
+            html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", encode_text(&label), value.join("<br/>")));
+        }
+        html.push_str("</table>\n");
+        html
+    }
+
+    // Renders an article's wikitext to HTML, with a table of contents when it has enough sections
+    pub fn render(&self, text: &str) -> String {
+        // A redirect is a single link, which would otherwise render as a numbered list item
+        let trimmed = text.trim_start();
+        if trimmed.get(..9).is_some_and(|start| start.eq_ignore_ascii_case("#redirect")) {
+            return format!("<p>Redirect to {}</p>\n", self.inline(trimmed[9..].lines().next().unwrap().trim_start_matches(':').trim()));
+        }
+        let mut blocks = Vec::new();
+        let mut paragraph: Vec<String> = Vec::new();
+        let mut lists = String::new();  // the markers of the lists the current line is nested in, like "*#"
+        let mut anchors: HashMap<String, usize> = HashMap::new();
+        let close_lists = |lists: &mut String, depth: usize, blocks: &mut Vec<Block>| {
+            while lists.len() > depth {
+                let tag = match lists.pop().unwrap() { '#' => "</ol>", ':' | ';' => "</dl>", _ => "</ul>" };
+                blocks.push(Block::Content(tag.to_string()));
+            }
+        };
+        let flush_paragraph = |paragraph: &mut Vec<String>, blocks: &mut Vec<Block>| {
+            let html = paragraph.join("\n");
+            if !html.trim().is_empty() {
+                blocks.push(Block::Content(format!("<p>{}</p>", html.trim())));
+            }
+            paragraph.clear();
+        };
+
+        let (text, infobox) = strip_markup(text);
+        if let Some(infobox) = infobox {
+            blocks.push(Block::Content(self.infobox(&infobox)));
+        }
+        for line in text.lines() {
+            let line = line.trim_end();
+            let markers = line.len() - line.trim_start_matches(['*', '#', ':', ';']).len();
+            if line.len() > 2 && line.starts_with('=') && line.ends_with('=') {
+                flush_paragraph(&mut paragraph, &mut blocks);
+                close_lists(&mut lists, 0, &mut blocks);
+                let level = (line.len() - line.trim_start_matches('=').len()).min(line.len() - line.trim_end_matches('=').len()).clamp(2, 6);
+                let heading = line.trim_matches('=').trim();
+                // Repeated headings get numbered anchors, Notes, Notes_2 and so on, as on Wikipedia
+                let base = anchor(heading);
+                let count = anchors.entry(base.clone()).or_default();
+                *count += 1;
+                let anchor = if *count == 1 { base } else { format!("{}_{}", base, count) };
+                blocks.push(Block::Heading(level, heading.to_string(), anchor));
+            } else if markers > 0 {
+                flush_paragraph(&mut paragraph, &mut blocks);
+                let markers = &line[..markers];
+                let common = lists.chars().zip(markers.chars()).take_while(|(a, b)| a == b || (":;".contains(*a) && ":;".contains(*b))).count();
+                close_lists(&mut lists, common, &mut blocks);
+                for marker in markers[common..].chars() {
+                    blocks.push(Block::Content(match marker { '#' => "<ol>", ':' | ';' => "<dl>", _ => "<ul>" }.to_string()));
+                    lists.push(marker);
+                }
+                let item = self.inline(line[markers.len()..].trim());
+                blocks.push(Block::Content(match markers.chars().last().unwrap() {
+                    ':' => format!("<dd>{}</dd>", item),
+                    ';' => format!("<dt>{}</dt>", item),
+                    _ => format!("<li>{}</li>", item),
+                }));
+            } else if line.trim().is_empty() {
+                flush_paragraph(&mut paragraph, &mut blocks);
+                close_lists(&mut lists, 0, &mut blocks);
+            } else if line.starts_with("----") {
+                flush_paragraph(&mut paragraph, &mut blocks);
+                close_lists(&mut lists, 0, &mut blocks);
+                blocks.push(Block::Content("<hr/>".to_string()));
+            } else if !(line.starts_with("__") && line.ends_with("__")) {
+                close_lists(&mut lists, 0, &mut blocks);
+                paragraph.push(self.inline(line.trim()));
+            }
+        }
+        flush_paragraph(&mut paragraph, &mut blocks);
+        close_lists(&mut lists, 0, &mut blocks);
+
+        // Sections left with nothing in them, like a references section that was only a template, are dropped
+        let keep: Vec<bool> = (0..blocks.len()).map(|i| match &blocks[i] {
+            Block::Heading(level, _, _) => blocks[i + 1..].iter()
+                .find(|block| !matches!(block, Block::Heading(next, _, _) if next > level))
+                .is_some_and(|block| matches!(block, Block::Content(_))),
+            Block::Content(_) => true,
+        }).collect();
+        let blocks: Vec<&Block> = blocks.iter().zip(keep).filter(|(_, keep)| *keep).map(|(block, _)| block).collect();
+
+        let mut html = String::new();
+        let headings: Vec<(usize, &str, &str)> = blocks.iter().filter_map(|block| match block {
+            Block::Heading(level, heading, anchor) => Some((*level, heading.as_str(), anchor.as_str())),
+            Block::Content(_) => None,
+        }).collect();
+        let mut toc_written = headings.len() < 4;
+        for block in blocks {
+            match block {
+                Block::Heading(level, heading, anchor) => {
+                    if !toc_written {
+                        html.push_str("<nav class=\"toc\"><b>Contents</b>\n<ul>\n");
+                        for (level, heading, anchor) in &headings {
+                            html.push_str(&format!("<li style=\"margin-left: {}em\"><a href=\"#{}\">{}</a></li>\n",
+                                level - 2, encode_double_quoted_attribute(anchor), encode_text(&plain_text(heading))));
+                        }
+                        html.push_str("</ul></nav>\n");
+                        toc_written = true;
+                    }
+                    html.push_str(&format!("<h{level} id=\"{}\">{}</h{level}>\n", encode_double_quoted_attribute(anchor), self.inline(heading)));
+                }
+                Block::Content(content) => {
+                    html.push_str(content);
+                    html.push('\n');
+                }
+            }
+        }
+        let categories = categories(&text);
+        if !categories.is_empty() {
+            let names: Vec<String> = categories.iter().map(|name| encode_text(name).to_string()).collect();
+            html.push_str(&format!("<div class=\"categories\">Categories: {}</div>\n", names.join(" | ")));
+        }
+        html
+    }
+}
+
+// Expands the templates in a template's expansion or an infobox value, which can have templates of their own
+fn expand_templates(text: &str) -> String {
+    strip_markup(text).0
+}
+
+// Drops the parts of the wikitext that aren't rendered, comments, tables, references and the like, and expands the
+// templates, returning the article's infobox separately
+fn strip_markup(text: &str) -> (String, Option<Template>) {
+    let mut output = String::with_capacity(text.len());
+    let mut infobox = None;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |end| end + 3);
+        } else if rest.starts_with("{{") {
+            let length = skip_nested(rest, "{{", "}}");
+            let inner = rest[2..length].strip_suffix("}}").unwrap_or(&rest[2..length]);
+            i += length;
+            // Parser functions like {{#if:...}} and magic words like {{DEFAULTSORT:...}} aren't template calls
+            if inner.starts_with('#') || inner.split('|').next().unwrap().contains(':') { continue; }
+            let template = Template::parse(inner);
+            if template.name.to_lowercase().starts_with("infobox") {
+                infobox = infobox.or(Some(template));
+            } else {
+                output.push_str(&expand_templates(&expand_template(&template)));
+            }
+        } else if rest.starts_with("{|") {
+            i += skip_nested(rest, "{|", "|}");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let tag_end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let name = rest[1..].split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap().to_lowercase();
+            if DROPPED_TAGS.contains(&name.as_str()) {
+                i += tag_end;
+                if !rest[..tag_end].ends_with("/>") {
+                    let closing = format!("</{}>", name);
+                    i += text[i..].find(&closing).map_or(text.len() - i, |end| end + closing.len());
+                }
+            } else {
+                output.push('<');
+                i += 1;
+            }
+        } else {
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            i += c.len_utf8();
+        }
+    }
+    (output, infobox)
+}
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rustc_hash::FxHashSet;
+use crate::export::{GRAPH_FORMATS, export_graph};
+use crate::graph::{DenseGraph, build_dense_graph};
+use crate::helpers::Args;
+use crate::split::load_graph;
+
+fn random_nodes(graph: &DenseGraph, size: usize, rng: &mut StdRng) -> Vec<usize> {
+    rand::seq::index::sample(rng, graph.node_count(), size).into_vec()
+}
+
+// Picks links uniformly at random and keeps both ends until there are enough articles
+fn random_edges(graph: &DenseGraph, size: usize, rng: &mut StdRng) -> Vec<usize> {
+    let reverse = graph.transpose();
+    let linked = (0..graph.node_count()).filter(|&node| !graph.successors(node).is_empty() || !reverse.successors(node).is_empty()).count();
+    let size = size.min(linked);
+    let mut seen = FxHashSet::default();
+    let mut nodes = Vec::new();
+    while nodes.len() < size {
+        let edge = rng.gen_range(0..graph.edges.len());
+        let source = graph.offsets.partition_point(|&offset| offset <= edge as u64) - 1;
+        for node in [source, graph.edges[edge] as usize] {
+            if nodes.len() < size && seen.insert(node) {
+                nodes.push(node);
+            }
+        }
+    }
+    nodes
+}
+
+// Forest fire sampling (Leskovec and Faloutsos): from a random article, burn a geometrically distributed number of
+// its unburned neighbors, with mean burn / (1 - burn), following links in either direction, then keep burning from
+// each of those. When a fire dies out, a new one starts from another random article.
+fn forest_fire(graph: &DenseGraph, size: usize, burn: f64, rng: &mut StdRng) -> Vec<usize> {
+    let reverse = graph.transpose();
+    let mut burned = vec![false; graph.node_count()];
+    let mut nodes = Vec::new();
+    while nodes.len() < size {
+        let start = rng.gen_range(0..graph.node_count());
+        if burned[start] { continue; }
+        burned[start] = true;
+        nodes.push(start);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let mut neighbors: Vec<usize> = graph.successors(node).iter().chain(reverse.successors(node))
+                .map(|&neighbor| neighbor as usize)
+                .filter(|&neighbor| !burned[neighbor])
+                .collect();
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            let mut count = 0;
+            while rng.gen::<f64>() < burn { count += 1; }
+            let count = count.min(neighbors.len()).min(size - nodes.len());
+            let (chosen, _) = neighbors.partial_shuffle(rng, count);
+            for &neighbor in chosen.iter() {
+                burned[neighbor] = true;
+                nodes.push(neighbor);
+                queue.push_back(neighbor);
+            }
+            if nodes.len() >= size { break; }
+        }
+    }
+    nodes
+}
+
+pub fn sample(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let method = args.value("method").unwrap_or("forest-fire");
+    let format = args.value("format").unwrap_or("csr");
+    let size: usize = args.parse_value("size").unwrap_or(1000);
+    let burn: f64 = args.parse_value("burn").unwrap_or(0.7);
+    let mut rng = StdRng::seed_from_u64(args.parse_value("seed").unwrap_or(0));
+    if !["node", "edge", "forest-fire"].contains(&method) {
+        eprintln!("Error: Unknown sampling method {} (expected node, edge or forest-fire)", method);
+        std::process::exit(1);
+    }
+    if !GRAPH_FORMATS.contains(&format) {
+        eprintln!("Error: Unknown export format {} (expected {})", format, GRAPH_FORMATS.join(", "));
+        std::process::exit(1);
+    }
+    if size == 0 || !(0.0..1.0).contains(&burn) {
+        eprintln!("Error: --size must be positive and --burn must be at least 0 and less than 1");
+        std::process::exit(1);
+    }
+    let output_dir = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("sample").join(format));
+
+    let Some(links) = load_graph(data_path) else {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let graph = build_dense_graph(&links);
+    drop(links);
+    let size = size.min(graph.node_count());
+
+    // Each method picks a set of articles, and the sample is the subgraph they induce
+    let nodes = match method {
+        "node" => random_nodes(&graph, size, &mut rng),
+        "edge" if graph.edges.is_empty() => Vec::new(),
+        "edge" => random_edges(&graph, size, &mut rng),
+        _ => forest_fire(&graph, size, burn, &mut rng),
+    };
+    println!("Sampled {} of {} articles by {} sampling", nodes.len(), graph.node_count(), method);
+    export_graph(args, data_path, format, &graph.subgraph(&nodes), &output_dir);
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rustc_hash::FxHashMap;
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::categories;
+use crate::dump::Metadata;
+use crate::helpers::{Args, Page, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::split::{load_graph, load_page_info};
+
+// Loads the pages of the chunks in parallel and hands each chunk's main namespace articles to `visit`
+fn for_each_chunk<F: Fn(HashMap<PageId, Page>) + Send + Sync + 'static>(articles_path: &Path, chunk_ranges: Vec<(usize, u64, u64)>, message: &str, visit: F) {
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let visit = Arc::new(visit);
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, message));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let visit = Arc::clone(&visit);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut pages = load_chunk_pages(&articles_path, start_position, end_position);
+            pages.retain(|_, page| page.namespace == 0 && !page.redirect);
+            visit(pages);
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+}
+
+// Splits articles sorted by a key into `count` strata of equal size, named after the percentiles they cover
+fn percentile_strata(mut keyed: Vec<(u64, PageId)>, count: usize) -> Vec<(String, Vec<PageId>)> {
+    keyed.sort_unstable();
+    let count = count.min(keyed.len()).max(1);
+    (0..count).map(|stratum| {
+        let (start, end) = (stratum * keyed.len() / count, (stratum + 1) * keyed.len() / count);
+        let name = format!("p{}-p{}", stratum * 100 / count, (stratum + 1) * 100 / count);
+        (name, keyed[start..end].iter().map(|(_, id)| *id).collect())
+    }).collect()
+}
+
+// Groups articles by category, taking the categories given by --categories or else the --strata largest ones
+fn category_strata(articles_path: &Path, chunk_ranges: Vec<(usize, u64, u64)>, count: usize, chosen: Option<&str>) -> Vec<(String, Vec<PageId>)> {
+    let members: Arc<Mutex<HashMap<String, Vec<PageId>>>> = Arc::new(Mutex::new(HashMap::new()));
+    let shared_members = Arc::clone(&members);
+    for_each_chunk(articles_path, chunk_ranges, "Reading categories", move |pages| {
+        let mut chunk_members: Vec<(String, PageId)> = Vec::new();
+        for (id, page) in pages {
+            chunk_members.extend(categories(&page.text).into_iter().map(|category| (category, id)));
+        }
+        let mut members = shared_members.lock().unwrap();
+        for (category, id) in chunk_members {
+            members.entry(category).or_default().push(id);
+        }
+    });
+    let mut members = Arc::try_unwrap(members).ok().unwrap().into_inner().unwrap();
+    for ids in members.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    match chosen {
+        Some(chosen) => chosen.split(',').map(|category| {
+            let category = category.trim().trim_start_matches("Category:").to_string();
+            let ids = members.remove(&category).unwrap_or_default();
+            (category, ids)
+        }).collect(),
+        None => {
+            let mut strata: Vec<(String, Vec<PageId>)> = members.into_iter().collect();
+            strata.sort_unstable_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+            strata.truncate(count);
+            strata
+        }
+    }
+}
+
+// Draws --size articles from the main namespace split evenly across strata, by article length (--by length, from
+// the word counts in links.bin), incoming links (--by in-degree) or category (--by category), and writes them with a
+// sample.tsv manifest of the stratum each one was drawn from. Length and in-degree strata are --strata equal-sized
+// percentile ranges, and category strata are the categories given by --categories or else the --strata largest ones.
+// An article in several chosen categories is only drawn once.
+pub fn sample_articles(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let by = args.value("by").unwrap_or("length");
+    let format = args.value("format").unwrap_or("txt");
+    let size: usize = args.parse_value("size").unwrap_or(1000);
+    let strata_count: usize = args.parse_value("strata").unwrap_or(10);
+    let mut rng = StdRng::seed_from_u64(args.parse_value("seed").unwrap_or(0));
+    if !["length", "in-degree", "category"].contains(&by) {
+        eprintln!("Error: Unknown stratification {} (expected length, in-degree or category)", by);
+        std::process::exit(1);
+    }
+    if !["txt", "ndjson"].contains(&format) {
+        eprintln!("Error: Unknown format {} (expected txt or ndjson)", format);
+        std::process::exit(1);
+    }
+    if size == 0 || strata_count == 0 {
+        eprintln!("Error: --size and --strata must be positive");
+        std::process::exit(1);
+    }
+    let metadata = Metadata::from_args(args);
+    let output_dir = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("sample-articles"));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+    let missing_links = || -> ! {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let strata = match by {
+        "length" => {
+            let pages = load_page_info(data_path).unwrap_or_else(|| missing_links());
+            if pages.values().all(|info| info.word_count == 0) {
+                eprintln!("Error: Article lengths aren't recorded in this links file, re-run the index command");
+                std::process::exit(1);
+            }
+            let keyed = pages.iter().filter(|(_, info)| info.namespace == 0 && !info.redirect).map(|(id, info)| (info.word_count as u64, *id)).collect();
+            percentile_strata(keyed, strata_count)
+        }
+        "in-degree" => {
+            let links = load_graph(data_path).unwrap_or_else(|| missing_links());
+            let pages = load_page_info(data_path).unwrap_or_else(|| missing_links());
+            let mut incoming: FxHashMap<PageId, u64> = FxHashMap::default();
+            for targets in links.values() {
+                for target in targets {
+                    *incoming.entry(*target).or_default() += 1;
+                }
+            }
+            let keyed = pages.iter().filter(|(_, info)| info.namespace == 0 && !info.redirect)
+                .map(|(id, _)| (incoming.get(id).copied().unwrap_or(0), *id))
+                .collect();
+            percentile_strata(keyed, strata_count)
+        }
+        _ => category_strata(&articles_path, chunk_ranges.clone(), strata_count, args.value("categories")),
+    };
+
+    // Each stratum gets an equal share of the sample, with the remainder going to the first strata
+    let mut sampled: HashMap<PageId, String> = HashMap::new();
+    for (index, (name, ids)) in strata.iter().enumerate() {
+        let quota = size / strata.len() + usize::from(index < size % strata.len());
+        let candidates: Vec<PageId> = ids.iter().copied().filter(|id| !sampled.contains_key(id)).collect();
+        if candidates.len() < quota {
+            println!("Stratum {} only has {} articles, fewer than its share of {}", name, candidates.len(), quota);
+        }
+        for id in candidates.choose_multiple(&mut rng, quota) {
+            sampled.insert(*id, name.clone());
+        }
+    }
+
+    // Only the chunks holding a sampled article need to be read again
+    let chunk_ranges: Vec<(usize, u64, u64)> = chunk_ranges.into_iter()
+        .filter(|(_, start_position, _)| seek_position_map[start_position].iter().any(|(id, _)| sampled.contains_key(id)))
+        .collect();
+    let sampled = Arc::new(sampled);
+    let selected: Arc<Mutex<Vec<(PageId, Page)>>> = Arc::new(Mutex::new(Vec::new()));
+    let (shared_sampled, shared_selected) = (Arc::clone(&sampled), Arc::clone(&selected));
+    for_each_chunk(&articles_path, chunk_ranges, "Reading sampled articles", move |pages| {
+        let chosen: Vec<(PageId, Page)> = pages.into_iter().filter(|(id, _)| shared_sampled.contains_key(id)).collect();
+        shared_selected.lock().unwrap().extend(chosen);
+    });
+    let mut selected = Arc::try_unwrap(selected).ok().unwrap().into_inner().unwrap();
+    selected.sort_unstable_by_key(|(id, _)| *id);
+
+    create_dir_all(&output_dir).expect("Failed to create output directory");
+    let mut manifest = BufWriter::new(File::create(output_dir.join("sample.tsv")).expect("Failed to create output file"));
+    writeln!(manifest, "article_id\ttitle\tstratum").expect("Failed to write manifest");
+    let output_path = output_dir.join(format!("articles.{}", format));
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    for (id, page) in &selected {
+        let stratum = &sampled[id];
+        writeln!(manifest, "{}\t{}\t{}", id, page.title, stratum).expect("Failed to write manifest");
+        match format {
+            "ndjson" => {
+                let record = json!({ "id": id, "title": page.title, "namespace": page.namespace, "stratum": stratum, "text": page.text });
+                writeln!(output_file, "{}", record).expect("Failed to write output file");
+            }
+            _ => write!(output_file, "{}\n{}\n\n", metadata.header(*id, page), page.text).expect("Failed to write output file"),
+        }
+    }
+    manifest.flush().expect("Failed to flush manifest");
+    output_file.flush().expect("Failed to flush output file");
+
+    println!("Sampled {} articles from {} strata by {}", selected.len(), strata.len(), by);
+    println!("Wrote the sample to {}", output_path.to_str().unwrap());
+}
@@ -0,0 +1,158 @@
+use std::path::Path;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::io::IsTerminal;
+use std::sync::Arc;
+use threadpool::ThreadPool;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, FAST, INDEXED, STORED, TEXT};
+use tantivy::snippet::{Snippet, SnippetGenerator};
+use tantivy::{Index, IndexWriter, TantivyDocument, doc};
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk, load_index, locate_dump_files};
+use crate::config::config;
+use tracing::info;
+
+const WRITER_MEMORY_BUDGET: usize = 500_000_000;
+
+fn schema() -> (Schema, Field, Field, Field) {
+    let mut schema_builder = Schema::builder();
+    let id = schema_builder.add_u64_field("id", INDEXED | STORED | FAST);
+    let title = schema_builder.add_text_field("title", TEXT | STORED);
+    let text = schema_builder.add_text_field("text", TEXT | STORED);
+    (schema_builder.build(), id, title, text)
+}
+
+pub fn index_search(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let search_index_path = data_path.join("search-index");
+    if search_index_path.exists() {
+        remove_dir_all(&search_index_path).expect("Failed to remove existing search index");
+    }
+    create_dir_all(&search_index_path).expect("Failed to create search index directory");
+    let (schema, id_field, title_field, text_field) = schema();
+    let index = Index::create_in_dir(&search_index_path, schema).expect("Failed to create search index");
+    let writer: Arc<IndexWriter> = Arc::new(index.writer(WRITER_MEMORY_BUDGET).expect("Failed to create index writer"));
+
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Indexing article text"));
+
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let progress_bar = Arc::clone(&progress_bar);
+        let writer = Arc::clone(&writer);
+
+        pool.execute(move || {
+            let articles = load_chunk(&articles_path, start_position, end_position);
+            for (article_id, (title, content)) in articles {
+                writer.add_document(doc!(id_field => article_id as u64, title_field => title, text_field => content))
+                    .expect("Failed to add document");
+            }
+            progress_bar.inc(1);
+        })
+    }
+
+    pool.join();
+    progress_bar.finish_and_clear();
+
+    info!("Committing search index...");
+    let mut writer = Arc::try_unwrap(writer).ok().unwrap();
+    writer.commit().expect("Failed to commit search index");
+    writer.wait_merging_threads().expect("Failed to merge index segments");
+    println!("Search index written to {}", search_index_path.to_str().unwrap());
+}
+
+// Bolds the matched terms when printing to a terminal
+fn highlight(snippet: &Snippet) -> String {
+    let fragment = snippet.fragment().replace('\n', " ");
+    if !std::io::stdout().is_terminal() { return fragment; }
+    let mut result = String::new();
+    let mut start = 0;
+    for range in snippet.highlighted() {
+        result.push_str(&fragment[start..range.start]);
+        result.push_str(&format!("\x1b[1m{}\x1b[0m", &fragment[range.clone()]));
+        start = range.end;
+    }
+    result.push_str(&fragment[start..]);
+    result
+}
+
+// An open full-text index with a query parser over titles and text, where matches in the title count for more
+pub struct TextSearcher {
+    index: Index,
+    id_field: Field,
+    title_field: Field,
+    text_field: Field,
+    query_parser: QueryParser,
+}
+
+impl TextSearcher {
+    pub fn open(data_path: &Path, title_boost: f32) -> Option<Self> {
+        let search_index_path = data_path.join("search-index");
+        if !search_index_path.exists() { return None; }
+        let index = Index::open_in_dir(&search_index_path).expect("Failed to open search index");
+        let schema = index.schema();
+        let id_field = schema.get_field("id").expect("Search index is missing the id field");
+        let title_field = schema.get_field("title").expect("Search index is missing the title field");
+        let text_field = schema.get_field("text").expect("Search index is missing the text field");
+        let mut query_parser = QueryParser::for_index(&index, vec![title_field, text_field]);
+        query_parser.set_field_boost(title_field, title_boost);
+        Some(TextSearcher { index, id_field, title_field, text_field, query_parser })
+    }
+
+    // The best matching articles as (id, title, text, BM25 score), parsing the query leniently so that stray
+    // operators in free text don't fail the search
+    pub fn search(&self, query_text: &str, limit: usize) -> Vec<(PageId, String, String, f32)> {
+        let (query, _) = self.query_parser.parse_query_lenient(query_text);
+        let searcher = self.index.reader().expect("Failed to open index reader").searcher();
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score()).expect("Search failed");
+        top_docs.into_iter().map(|(score, doc_address)| {
+            let document: TantivyDocument = searcher.doc(doc_address).expect("Failed to load document");
+            let id = document.get_first(self.id_field).and_then(|value| value.as_u64()).unwrap_or_default() as PageId;
+            let title = document.get_first(self.title_field).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+            let text = document.get_first(self.text_field).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+            (id, title, text, score)
+        }).collect()
+    }
+}
+
+pub fn search_text(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let query_text = args.positional[1..].join(" ");
+    if query_text.is_empty() {
+        eprintln!("Usage: search-text <data_path> <query> [--limit N] [--title-boost N]");
+        std::process::exit(1);
+    }
+    let limit = args.parse_value("limit").unwrap_or(10);
+    let title_boost = args.parse_value("title-boost").unwrap_or(3.0);
+
+    let Some(text_searcher) = TextSearcher::open(data_path, title_boost) else {
+        eprintln!("Error: Unable to locate search-index in {}, run the index-search command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let TextSearcher { index, id_field, title_field, text_field, query_parser } = text_searcher;
+    let query = query_parser.parse_query(&query_text).unwrap_or_else(|err| {
+        eprintln!("Error: Invalid query: {}", err);
+        std::process::exit(1);
+    });
+
+    let searcher = index.reader().expect("Failed to open index reader").searcher();
+    let top_docs = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score()).expect("Search failed");
+    let snippet_generator = SnippetGenerator::create(&searcher, &*query, text_field).expect("Failed to create snippet generator");
+
+    for (rank, (score, doc_address)) in top_docs.iter().enumerate() {
+        let document: TantivyDocument = searcher.doc(*doc_address).expect("Failed to load document");
+        let id = document.get_first(id_field).and_then(|value| value.as_u64()).unwrap_or_default();
+        let title = document.get_first(title_field).and_then(|value| value.as_str()).unwrap_or_default();
+        println!("{:>2}) {} (ID: {}, score {:.2})", rank + 1, title, id, score);
+        println!("    {}", highlight(&snippet_generator.snippet_from_doc(&document)));
+    }
+    if top_docs.is_empty() {
+        println!("No matches");
+    }
+}
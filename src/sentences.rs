@@ -0,0 +1,214 @@
+use std::fs::create_dir_all;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use indicatif::ProgressIterator;
+use serde_json::json;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::corpus::{SKIPPED_SECTIONS, plain_text, split_sections};
+use crate::helpers::{Args, OutputCompression, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files, skip_nested};
+use crate::index::normalize_link;
+use crate::quality::load_quality_filter;
+use crate::titles::TitleTable;
+
+// Private use characters that mark a link in the wikitext as START id TARGET_END anchor END. They pass through
+// plain_text untouched, so the links can be found again in its output, and they're dropped along with any template,
+// reference or table the link was in.
+const LINK_START: char = '\u{E000}';
+const LINK_TARGET_END: char = '\u{E001}';
+const LINK_END: char = '\u{E002}';
+
+// Words that end in a period without ending the sentence
+const ABBREVIATIONS: [&str; 24] = [
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "mt", "ft", "no", "vs", "etc", "e.g", "i.e", "c", "ca", "approx",
+    "gen", "col", "lt", "sgt", "inc", "co",
+];
+
+// A span of a line's text, in characters, that links to an article
+struct Span {
+    start: usize,
+    end: usize,
+    id: PageId,
+}
+
+// Replaces each link to an existing article with its marked anchor text. Links to missing articles, into ignored
+// namespaces, and with links nested inside them are left for plain_text to handle as usual.
+fn mark_links(text: &str, titles: &TitleTable) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while let Some(offset) = text[i..].find("[[") {
+        let start = i + offset;
+        output.push_str(&text[i..start]);
+        let length = skip_nested(&text[start..], "[[", "]]");
+        let link = &text[start..start + length];
+        let inner = link[2..].strip_suffix("]]").unwrap_or(&link[2..]);
+        let anchor = inner.rsplit('|').next().unwrap().trim();
+        let id = normalize_link(inner).filter(|_| !inner.contains("[[") && !anchor.is_empty()).and_then(|target| titles.find(target.trim()));
+        match id {
+            Some(id) => output.extend([LINK_START.to_string(), id.to_string(), LINK_TARGET_END.to_string(), anchor.to_string(), LINK_END.to_string()]),
+            None => output.push_str(link),
+        }
+        i = start + length;
+    }
+    output.push_str(&text[i..]);
+    output
+}
+
+// Strips the link markers from a line of plain text and returns it with the spans they marked
+fn extract_spans(line: &str) -> (Vec<char>, Vec<Span>) {
+    let mut chars: Vec<char> = Vec::with_capacity(line.len());
+    let mut spans = Vec::new();
+    let mut open: Option<(usize, PageId)> = None;
+    let mut iter = line.chars();
+    while let Some(c) = iter.next() {
+        match c {
+            LINK_START => {
+                let id: String = iter.by_ref().take_while(|&c| c != LINK_TARGET_END).collect();
+                open = id.trim().parse().ok().map(|id| (chars.len(), id));
+            }
+            LINK_END => {
+                if let Some((start, id)) = open.take() {
+                    // Leave out any whitespace at the edges of the anchor
+                    let start = start + chars[start..].iter().take_while(|c| c.is_whitespace()).count();
+                    let end = chars.len() - chars[start..].iter().rev().take_while(|c| c.is_whitespace()).count();
+                    if start < end {
+                        spans.push(Span { start, end, id });
+                    }
+                }
+            }
+            LINK_TARGET_END => {}
+            _ => chars.push(c),
+        }
+    }
+    (chars, spans)
+}
+
+// Whether a sentence can end with the period at `end`, which it can't after an abbreviation or an initial
+fn ends_sentence(chars: &[char], end: usize) -> bool {
+    if chars[end] != '.' { return true; }
+    let word_start = chars[..end].iter().rposition(|c| c.is_whitespace() || *c == '(').map_or(0, |i| i + 1);
+    let word: String = chars[word_start..end].iter().collect::<String>().to_lowercase();
+    let initial = end - word_start == 1 && chars[word_start].is_uppercase();
+    !initial && !ABBREVIATIONS.contains(&word.as_str())
+}
+
+// Splits a line into sentences at a period, question mark or exclamation mark (and any closing quotes or brackets)
+// followed by whitespace and a capital letter, digit or opening quote, never inside a link. Returns the character
+// range of each sentence.
+fn split_sentences(chars: &[char], spans: &[Span]) -> Vec<(usize, usize)> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if matches!(chars[i], '.' | '?' | '!') && ends_sentence(chars, i) {
+            let mut end = i + 1;
+            while end < chars.len() && matches!(chars[end], '"' | '\'' | ')' | ']' | '”' | '’') {
+                end += 1;
+            }
+            let next = chars[end..].iter().position(|c| !c.is_whitespace()).map(|offset| end + offset);
+            let in_link = spans.iter().any(|span| span.start < end && end < span.end);
+            if let Some(next) = next.filter(|&next| next > end && !in_link) {
+                if chars[next].is_uppercase() || chars[next].is_ascii_digit() || matches!(chars[next], '"' | '“' | '(') {
+                    sentences.push((start, end));
+                    start = next;
+                    i = next;
+                    continue;
+                }
+            }
+            i = end;
+            continue;
+        }
+        i += 1;
+    }
+    if start < chars.len() {
+        sentences.push((start, chars.len()));
+    }
+    sentences
+}
+
+// Writes the article's sentences as JSON lines and returns how many there were and how many links they had
+fn write_article_sentences(output: &mut Vec<u8>, id: PageId, title: &str, text: &str, titles: &TitleTable, linked_only: bool) -> (usize, usize) {
+    let (mut index, mut count, mut links) = (0, 0, 0);
+    let text = text.replace([LINK_START, LINK_TARGET_END, LINK_END], "");
+    for (section, wikitext) in split_sections(&text) {
+        if SKIPPED_SECTIONS.contains(&section.to_lowercase().as_str()) { continue; }
+        for line in plain_text(&mark_links(wikitext, titles)).lines() {
+            let (chars, spans) = extract_spans(line);
+            for (start, end) in split_sentences(&chars, &spans) {
+                let sentence_links: Vec<_> = spans.iter()
+                    .filter(|span| span.start >= start && span.end <= end)
+                    .map(|span| json!({ "start": span.start - start, "end": span.end - start, "id": span.id }))
+                    .collect();
+                // Sentences are numbered in order across the whole article, including the ones --linked-only skips
+                index += 1;
+                if linked_only && sentence_links.is_empty() { continue; }
+                let text: String = chars[start..end].iter().collect();
+                let record = json!({ "id": id, "title": title, "section": section, "sentence": index - 1, "text": text, "links": sentence_links });
+                writeln!(output, "{}", record).expect("Failed to write sentence");
+                count += 1;
+                links += sentence_links.len();
+            }
+        }
+    }
+    (count, links)
+}
+
+// Exports the plain text of articles one sentence per line to sentences.jsonl, with the links in each sentence kept as
+// stand-off annotations: the start and end character offsets of the anchor text in the sentence and the ID of the
+// article it links to, with redirects left unresolved. Links to missing articles become plain text. This gives a
+// distantly supervised corpus for named entity recognition and entity linking. --linked-only keeps just the sentences
+// with a link, and --quality FA,GA keeps just the articles quality.tsv puts in those tiers.
+pub fn export_sentences(args: &Args, data_path: &Path, output_dir: &Path) {
+    let linked_only = args.flag("linked-only");
+    let quality = Arc::new(load_quality_filter(args, data_path));
+    create_dir_all(output_dir).expect("Failed to create output directory");
+    let compression = OutputCompression::from_args(args);
+    let output_path = output_dir.join("sentences.jsonl");
+    let output_file = Arc::new(Mutex::new(compression.create(&output_path)));
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let titles = Arc::new(TitleTable::new(seek_position_map.values()
+        .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating title index"))
+        .flatten()));
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let totals = Arc::new(Mutex::new((0, 0, 0)));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Exporting sentences"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let titles = Arc::clone(&titles);
+        let quality = Arc::clone(&quality);
+        let output_file = Arc::clone(&output_file);
+        let totals = Arc::clone(&totals);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            let mut pages: Vec<_> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(id, page)| page.namespace == 0 && !page.redirect && quality.as_ref().as_ref().is_none_or(|quality| quality.contains(id)))
+                .collect();
+            pages.sort_unstable_by_key(|(id, _)| *id);
+            let mut output = Vec::new();
+            let (mut sentences, mut links) = (0, 0);
+            for (id, page) in &pages {
+                let (article_sentences, article_links) = write_article_sentences(&mut output, *id, &page.title, &page.text, &titles, linked_only);
+                sentences += article_sentences;
+                links += article_links;
+            }
+            output_file.lock().unwrap().write_all(&output).expect("Failed to write output file");
+            let mut totals = totals.lock().unwrap();
+            totals.0 += pages.len();
+            totals.1 += sentences;
+            totals.2 += links;
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+    Arc::try_unwrap(output_file).ok().unwrap().into_inner().unwrap().flush().expect("Failed to flush output file");
+
+    let (articles, sentences, links) = *totals.lock().unwrap();
+    println!("Exported {} sentences with {} links from {} articles to {}{}", sentences, links, articles, output_path.to_str().unwrap(), compression.extension());
+}
@@ -0,0 +1,546 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+#[cfg(not(feature = "async-io"))]
+use std::io::Read;
+#[cfg(not(feature = "async-io"))]
+use std::sync::{Mutex, mpsc};
+#[cfg(not(feature = "async-io"))]
+use std::time::Instant;
+use rustc_hash::FxHashMap;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use crate::embed::{EMBEDDINGS_FILE, PASSAGES_FILE, EmbeddingClient, Embeddings};
+use crate::config::config;
+use crate::helpers::{Args, PageId, locate_dump_files};
+use crate::hnsw::{HNSW_FILE, Hnsw, load_embeddings};
+use crate::lookup::{ArticleLookup, DEFAULT_CACHE_SIZE};
+use crate::render::{HtmlRenderer, html_document};
+use crate::split::LinkStore;
+#[cfg(feature = "async-io")]
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "async-io")]
+use tokio::sync::Semaphore;
+#[cfg(feature = "async-io")]
+use crate::aio;
+#[cfg(feature = "graphql")]
+use crate::graphql::GraphqlSchema;
+#[cfg(feature = "grpc")]
+use crate::grpc::spawn_grpc_server;
+#[cfg(feature = "tantivy")]
+use crate::corpus::{Tokenizer, article_passages};
+#[cfg(feature = "tantivy")]
+use crate::search::TextSearcher;
+
+// Rank constant for reciprocal rank fusion, which damps the difference between the very top ranks
+const FUSION_K: f64 = 60.0;
+// Request bodies are GraphQL queries, which are never this long
+const MAX_BODY_SIZE: usize = 1 << 20;
+
+// The HNSW index with the vectors it links and the client that embeds queries
+struct VectorIndex {
+    hnsw: Hnsw,
+    embeddings: Embeddings,
+    client: EmbeddingClient,
+    passages_path: PathBuf,
+    passage_offsets: Vec<u64>,  // byte offset of each line in passages.jsonl
+}
+
+impl VectorIndex {
+    fn open(args: &Args, data_path: &Path) -> Option<Self> {
+        if !data_path.join(HNSW_FILE).exists() || (args.value("endpoint").is_none() && args.value("onnx-model").is_none()) { return None; }
+        let embeddings = load_embeddings(data_path);
+        let hnsw = Hnsw::read(&data_path.join(HNSW_FILE), embeddings.count());
+        let passages_path = data_path.join(PASSAGES_FILE);
+        let mut reader = BufReader::new(File::open(&passages_path).expect("Failed to open passages file"));
+        let mut passage_offsets = Vec::with_capacity(embeddings.count());
+        let mut offset = 0;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let length = reader.read_until(b'\n', &mut line).expect("Failed to read passages file");
+            if length == 0 { break; }
+            passage_offsets.push(offset);
+            offset += length as u64;
+        }
+        if passage_offsets.len() != embeddings.count() {
+            eprintln!("Error: {} has {} passages but {} has {} embeddings, run the embed command again", PASSAGES_FILE, passage_offsets.len(), EMBEDDINGS_FILE, embeddings.count());
+            std::process::exit(1);
+        }
+        let client = EmbeddingClient::from_args(args, "serve <data_path> --endpoint URL or --onnx-model DIR");
+        Some(VectorIndex { hnsw, embeddings, client, passages_path, passage_offsets })
+    }
+
+    fn passage(&self, index: usize) -> Value {
+        let mut passages_file = File::open(&self.passages_path).expect("Failed to open passages file");
+        passages_file.seek(SeekFrom::Start(self.passage_offsets[index])).expect("Failed to seek in passages file");
+        let mut line = String::new();
+        BufReader::new(passages_file).read_line(&mut line).expect("Failed to read passages file");
+        serde_json::from_str(&line).unwrap_or_default()
+    }
+
+    // The nearest passages as (passage, cosine similarity), or an error if the query couldn't be embedded
+    fn search(&self, query_text: &str, limit: usize, ef: usize) -> Result<Vec<(Value, f32)>, String> {
+        let query = self.client.embed(&[query_text])?.remove(0);
+        if query.len() != self.embeddings.dimensions {
+            warn!("The query has {} dimensions but the embeddings have {}", query.len(), self.embeddings.dimensions);
+            return Ok(Vec::new());
+        }
+        Ok(self.hnsw.search(&self.embeddings, &query, limit, ef).into_iter()
+            .map(|(index, similarity)| (self.passage(index), similarity))
+            .collect())
+    }
+}
+
+// The full-text index, where each matching article is represented by its lead passage
+#[cfg(feature = "tantivy")]
+struct TextIndex {
+    searcher: TextSearcher,
+    tokenizer: Tokenizer,
+    chunk_tokens: usize,
+}
+
+#[cfg(feature = "tantivy")]
+impl TextIndex {
+    fn open(args: &Args, data_path: &Path) -> Option<Self> {
+        let searcher = TextSearcher::open(data_path, 3.0)?;
+        Some(TextIndex { searcher, tokenizer: Tokenizer::from_args(args), chunk_tokens: args.parse_value("chunk-tokens").unwrap_or(256) })
+    }
+
+    // The best BM25 matches as (passage, score)
+    fn search(&self, query_text: &str, limit: usize) -> Vec<(Value, f32)> {
+        self.searcher.search(query_text, limit).into_iter().map(|(id, title, text, score)| {
+            let passage = article_passages(&text, self.chunk_tokens, &self.tokenizer).into_iter().next();
+            let (section, text) = passage.map_or_else(Default::default, |passage| (passage.section, passage.text));
+            (json!({ "id": id, "title": title, "section": section, "chunk": 0, "text": text }), score)
+        }).collect()
+    }
+}
+
+#[cfg(not(feature = "tantivy"))]
+struct TextIndex;
+
+#[cfg(not(feature = "tantivy"))]
+impl TextIndex {
+    fn open(_args: &Args, _data_path: &Path) -> Option<Self> {
+        None
+    }
+
+    fn search(&self, _query_text: &str, _limit: usize) -> Vec<(Value, f32)> {
+        Vec::new()
+    }
+}
+
+#[cfg(not(feature = "graphql"))]
+struct GraphqlSchema;
+
+#[cfg(not(feature = "graphql"))]
+impl GraphqlSchema {
+    fn new(_lookup: Arc<ArticleLookup>, _links: Arc<LinkStore>, _data_path: &Path) -> Self {
+        GraphqlSchema
+    }
+
+    fn execute(&self, _request: &str) -> Result<Value, String> {
+        Err("GraphQL requires building with --features graphql".to_string())
+    }
+}
+
+struct Retriever {
+    vector_index: Option<VectorIndex>,
+    text_index: Option<TextIndex>,
+    ef: usize,
+}
+
+impl Retriever {
+    // Runs the searches the mode asks for and merges them with reciprocal rank fusion, keyed by (article, chunk)
+    fn retrieve(&self, query_text: &str, k: usize, mode: &str) -> Result<Value, (u16, String)> {
+        let use_vectors = mode != "bm25";
+        let use_bm25 = mode != "vector";
+        if self.vector_index.is_none() && self.text_index.is_none() {
+            return Err((400, "Retrieval is disabled, no search index is loaded".to_string()));
+        }
+        if (mode == "vector" && self.vector_index.is_none()) || (mode == "bm25" && self.text_index.is_none()) {
+            return Err((400, format!("The {} index isn't loaded", mode)));
+        }
+        // When the embedding endpoint fails, hybrid searches carry on with BM25 alone
+        let vector_hits = match &self.vector_index {
+            Some(vector_index) if use_vectors => match vector_index.search(query_text, 2 * k, self.ef) {
+                Ok(hits) => hits,
+                Err(err) if use_bm25 && self.text_index.is_some() => {
+                    warn!("Falling back to BM25: {}", err);
+                    Vec::new()
+                }
+                Err(err) => return Err((502, err)),
+            },
+            _ => Vec::new(),
+        };
+        let bm25_hits = match &self.text_index {
+            Some(text_index) if use_bm25 => text_index.search(query_text, 2 * k),
+            _ => Vec::new(),
+        };
+
+        let mut fused: FxHashMap<(u64, u64), (Value, f64)> = FxHashMap::default();
+        for (source, hits) in [("vector", vector_hits), ("bm25", bm25_hits)] {
+            for (rank, (passage, score)) in hits.into_iter().enumerate() {
+                let key = (passage["id"].as_u64().unwrap_or_default(), passage["chunk"].as_u64().unwrap_or_default());
+                let entry = fused.entry(key).or_insert_with(|| (passage, 0.0));
+                entry.0[format!("{}_rank", source)] = json!(rank + 1);
+                entry.0[format!("{}_score", source)] = json!(score);
+                entry.1 += 1.0 / (FUSION_K + rank as f64 + 1.0);
+            }
+        }
+        let mut results: Vec<((u64, u64), (Value, f64))> = fused.into_iter().collect();
+        results.sort_by(|(key, (_, score)), (other_key, (_, other_score))| other_score.total_cmp(score).then(key.cmp(other_key)));
+        let results: Vec<Value> = results.into_iter().take(k).map(|(_, (mut passage, score))| {
+            passage["score"] = json!(score);
+            passage
+        }).collect();
+        Ok(json!({ "query": query_text, "mode": mode, "results": results }))
+    }
+}
+
+// Decodes a query string component, where + is a space and %XX is a byte
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => decoded.push(b' '),
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Caps how many requests a route handles at once, so slow routes can't take over every worker
+struct RouteLimit {
+    active: AtomicUsize,
+    limit: usize,
+}
+
+// Releases a RouteLimit slot when dropped
+struct RouteSlot<'a>(&'a RouteLimit);
+
+impl RouteLimit {
+    fn new(limit: usize) -> Self {
+        RouteLimit { active: AtomicUsize::new(0), limit: limit.max(1) }
+    }
+
+    fn acquire(&self) -> Option<RouteSlot<'_>> {
+        self.active.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| (active < self.limit).then_some(active + 1)).ok()?;
+        Some(RouteSlot(self))
+    }
+}
+
+impl Drop for RouteSlot<'_> {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct Server {
+    retriever: Retriever,
+    lookup: Arc<ArticleLookup>,
+    graphql: GraphqlSchema,
+    retrieve_limit: RouteLimit,
+    article_limit: RouteLimit,
+    graphql_limit: RouteLimit,
+    timeout: Duration,
+}
+
+fn response_text(status: u16, content_type: &str, body: &str) -> String {
+    let reason = match status { 200 => "OK", 400 => "Bad Request", 404 => "Not Found", 502 => "Bad Gateway", 503 => "Service Unavailable", _ => "Internal Server Error" };
+    let retry_after = if status == 503 { "Retry-After: 1\r\n" } else { "" };
+    format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}", status, reason, content_type, body.len(), retry_after, body)
+}
+
+pub fn write_body(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) {
+    if let Err(err) = stream.write_all(response_text(status, content_type, body).as_bytes()) {
+        warn!("Failed to write response: {}", err);
+    }
+}
+
+pub fn write_response(stream: &mut TcpStream, status: u16, body: &Value) {
+    write_body(stream, status, "application/json", &body.to_string());
+}
+
+// Renders an article to a standalone HTML page whose links point at the other articles' pages on this server
+pub fn article_html(lookup: &ArticleLookup, id: PageId) -> Option<String> {
+    let renderer = HtmlRenderer::new(|title| lookup.find(title).map(|id| format!("/article?id={}&format=html", id)));
+    Some(html_document(lookup.title(id)?, &renderer.render(&lookup.get(id)?)))
+}
+
+fn busy_error(reason: &str) -> Value {
+    json!({ "error": format!("Server is busy ({}), try again shortly", reason) })
+}
+
+fn busy(stream: &mut TcpStream, reason: &str) {
+    write_response(stream, 503, &busy_error(reason));
+}
+
+fn too_large_error() -> Value {
+    json!({ "error": format!("Request bodies are limited to {} bytes", MAX_BODY_SIZE) })
+}
+
+// Returns the length from a Content-Length header line
+fn content_length(header: &str) -> Option<usize> {
+    let (name, value) = header.split_once(':')?;
+    if name.trim().eq_ignore_ascii_case("content-length") { Some(value.trim().parse().unwrap_or(0)) } else { None }
+}
+
+#[cfg(not(feature = "async-io"))]
+fn handle_connection(mut stream: TcpStream, server: &Server, queued_at: Instant) {
+    if queued_at.elapsed() > server.timeout {
+        busy(&mut stream, "request timed out in the queue");
+        return;
+    }
+    stream.set_read_timeout(Some(server.timeout)).expect("Failed to set read timeout");
+    stream.set_write_timeout(Some(server.timeout)).expect("Failed to set write timeout");
+
+    // Only the request line and the body matter, the other headers are read and dropped
+    let mut reader = BufReader::new(stream.try_clone().expect("Failed to clone connection"));
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() { return; }
+    let mut header = String::new();
+    let mut length = 0;
+    while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+        length = content_length(&header).unwrap_or(length);
+        header.clear();
+    }
+    if length > MAX_BODY_SIZE {
+        write_response(&mut stream, 400, &too_large_error());
+        return;
+    }
+    let mut body = vec![0; length];
+    if reader.read_exact(&mut body).is_err() { return; }
+    respond(&mut stream, server, &request_line, &body);
+}
+
+// Routes a request that's been read in full and writes the response
+fn respond(stream: &mut TcpStream, server: &Server, request_line: &str, body: &[u8]) {
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query_string) = target.split_once('?').unwrap_or((target, ""));
+    let params: FxHashMap<String, String> = query_string.split('&').filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect();
+    info!(path, query_string, "request");
+
+    match path {
+        "/retrieve" => {
+            let query_text = params.get("q").map(String::as_str).unwrap_or("").trim();
+            let k = params.get("k").and_then(|k| k.parse().ok()).unwrap_or(10usize);
+            let mode = params.get("mode").map(String::as_str).unwrap_or("hybrid");
+            if query_text.is_empty() || k == 0 || !["hybrid", "bm25", "vector"].contains(&mode) {
+                write_response(stream, 400, &json!({ "error": "Expected /retrieve?q=QUERY&k=N&mode=hybrid|bm25|vector with a non-empty query and positive k" }));
+                return;
+            }
+            let Some(_slot) = server.retrieve_limit.acquire() else { return busy(stream, "too many retrieve requests") };
+            match server.retriever.retrieve(query_text, k, mode) {
+                Ok(body) => write_response(stream, 200, &body),
+                Err((status, error)) => write_response(stream, status, &json!({ "error": error })),
+            }
+        }
+        "/article" => {
+            let id = match (params.get("id"), params.get("title")) {
+                (Some(id), _) => id.parse().ok().filter(|id| server.lookup.title(*id).is_some()),
+                (None, Some(title)) => server.lookup.find(title),
+                (None, None) => {
+                    write_response(stream, 400, &json!({ "error": "Expected /article?title=TITLE or /article?id=ID" }));
+                    return;
+                }
+            };
+            let Some(id) = id else {
+                write_response(stream, 404, &json!({ "error": "No such article" }));
+                return;
+            };
+            // Reading an article can mean decompressing a whole chunk, which is the expensive part
+            let Some(_slot) = server.article_limit.acquire() else { return busy(stream, "too many article requests") };
+            if params.get("format").is_some_and(|format| format == "html") {
+                let html = article_html(&server.lookup, id).unwrap_or_default();
+                return write_body(stream, 200, "text/html; charset=utf-8", &html);
+            }
+            let text = server.lookup.get(id).unwrap_or_default();
+            write_response(stream, 200, &json!({ "id": id, "title": server.lookup.title(id), "text": text }));
+        }
+        "/graphql" => {
+            // POST bodies are JSON requests, and GET requests put the same fields in the query string
+            let request = if request_line.starts_with("POST") {
+                String::from_utf8_lossy(body).into_owned()
+            } else {
+                let variables = params.get("variables").and_then(|variables| serde_json::from_str::<Value>(variables).ok());
+                json!({ "query": params.get("query"), "operationName": params.get("operationName"), "variables": variables }).to_string()
+            };
+            let Some(_slot) = server.graphql_limit.acquire() else { return busy(stream, "too many graphql requests") };
+            match server.graphql.execute(&request) {
+                Ok(body) => write_response(stream, 200, &body),
+                Err(error) => write_response(stream, 400, &json!({ "error": error })),
+            }
+        }
+        _ => write_response(stream, 404, &json!({ "error": format!("No route for {}", path) })),
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn spawn_grpc_server(_lookup: Arc<ArticleLookup>, _links: Arc<LinkStore>, _port: u16) {
+    eprintln!("Error: --grpc-port requires building with --features grpc");
+    std::process::exit(1);
+}
+
+// Serves articles and passage retrieval over HTTP. GET /article?title=... (or ?id=...) returns an article's wikitext
+// straight from the dump, or with &format=html the article rendered to a page linking to the others, and
+// GET /retrieve?q=...&k=10 returns the top passages with their article ID, title, section and text, merging HNSW vector
+// search (needs index-semantic and --endpoint or --onnx-model) with BM25 full-text search (needs index-search and the
+// tantivy feature), whichever are available. POST /graphql (or GET /graphql?query=...) runs GraphQL queries over
+// articles, their links, backlinks and categories, title search and shortest paths (needs the graphql feature). With
+// --grpc-port, the gRPC interface in proto/wikipedia.proto is served on that port as well (needs the grpc feature).
+//
+// Connections wait in a bounded queue for a fixed pool of workers, and are turned away with a 503 when the queue is
+// full, when they've waited longer than the timeout, or when their route is already at its concurrency limit.
+pub fn serve(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let port: u16 = args.parse_value("port").unwrap_or(8080);
+    let workers: usize = args.parse_value::<usize>("workers").unwrap_or(config().threads).max(1);
+    let queue_size: usize = args.parse_value("queue").unwrap_or(64);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let retriever = Retriever {
+        vector_index: VectorIndex::open(args, data_path),
+        text_index: TextIndex::open(args, data_path),
+        ef: args.parse_value("ef").unwrap_or(64),
+    };
+    let lookup = Arc::new(ArticleLookup::new(&index_path, &articles_path, args.parse_value("cache-size").unwrap_or(DEFAULT_CACHE_SIZE)));
+    // The link graph is only loaded if a GraphQL or gRPC query needs it, and then shared between them
+    let links = Arc::new(LinkStore::open(data_path));
+    let server = Arc::new(Server {
+        retriever,
+        graphql: GraphqlSchema::new(Arc::clone(&lookup), Arc::clone(&links), data_path),
+        lookup,
+        retrieve_limit: RouteLimit::new(args.parse_value("max-retrieve").unwrap_or(workers)),
+        article_limit: RouteLimit::new(args.parse_value("max-article").unwrap_or(workers.div_ceil(2))),
+        graphql_limit: RouteLimit::new(args.parse_value("max-graphql").unwrap_or(workers.div_ceil(2))),
+        timeout: Duration::from_secs(args.parse_value("timeout").unwrap_or(30)),
+    });
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|err| {
+        eprintln!("Error: Unable to listen on port {}: {}", port, err);
+        std::process::exit(1);
+    });
+    let sources: Vec<&str> = [("vector", server.retriever.vector_index.is_some()), ("bm25", server.retriever.text_index.is_some())].into_iter()
+        .filter(|(_, available)| *available)
+        .map(|(source, _)| source)
+        .collect();
+    println!("Serving {} articles on http://127.0.0.1:{}/article with {} workers", server.lookup.len(), port, workers);
+    if sources.is_empty() {
+        println!("Retrieval is disabled, run index-semantic and pass --endpoint or --onnx-model, or run index-search with the tantivy feature");
+    } else {
+        println!("Serving {} retrieval on http://127.0.0.1:{}/retrieve", sources.join(" and "), port);
+    }
+    if cfg!(feature = "graphql") {
+        println!("Serving GraphQL on http://127.0.0.1:{}/graphql", port);
+    }
+    if let Some(grpc_port) = args.parse_value("grpc-port") {
+        spawn_grpc_server(Arc::clone(&server.lookup), links, grpc_port);
+    }
+
+    serve_connections(listener, server, workers, queue_size);
+}
+
+// Hands each connection to a worker through the queue
+#[cfg(not(feature = "async-io"))]
+fn serve_connections(listener: TcpListener, server: Arc<Server>, workers: usize, queue_size: usize) {
+    let (sender, receiver) = mpsc::sync_channel::<(TcpStream, Instant)>(queue_size);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..workers {
+        let receiver = Arc::clone(&receiver);
+        let server = Arc::clone(&server);
+        std::thread::spawn(move || loop {
+            let Ok((stream, queued_at)) = receiver.lock().unwrap().recv() else { break };
+            handle_connection(stream, &server, queued_at);
+        });
+    }
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(mpsc::TrySendError::Full((mut stream, _))) = sender.try_send((stream, Instant::now())) {
+                    busy(&mut stream, "request queue is full");
+                }
+            }
+            Err(err) => warn!("Failed to accept connection: {}", err),
+        }
+    }
+}
+
+// Accepts connections and reads their requests on the async runtime, so a client that's slow to send its request, or
+// sends nothing at all, doesn't hold a worker. Requests that have arrived in full wait for one of the workers on the
+// blocking pool, and are turned away when more than `queue_size` are waiting or one waits longer than the timeout.
+#[cfg(feature = "async-io")]
+fn serve_connections(listener: TcpListener, server: Arc<Server>, workers: usize, queue_size: usize) {
+    let workers = Arc::new(Semaphore::new(workers));
+    let waiting = Arc::new(AtomicUsize::new(0));
+    aio::runtime().block_on(async move {
+        listener.set_nonblocking(true).expect("Failed to make the listener non-blocking");
+        let listener = tokio::net::TcpListener::from_std(listener).expect("Failed to register the listener");
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => { tokio::spawn(handle_async(stream, Arc::clone(&server), Arc::clone(&workers), Arc::clone(&waiting), queue_size)); }
+                Err(err) => warn!("Failed to accept connection: {}", err),
+            }
+        }
+    });
+}
+
+#[cfg(feature = "async-io")]
+async fn write_async(stream: &mut tokio::net::TcpStream, status: u16, body: &Value) {
+    if let Err(err) = stream.write_all(response_text(status, "application/json", &body.to_string()).as_bytes()).await {
+        warn!("Failed to write response: {}", err);
+    }
+}
+
+#[cfg(feature = "async-io")]
+async fn handle_async(mut stream: tokio::net::TcpStream, server: Arc<Server>, workers: Arc<Semaphore>, waiting: Arc<AtomicUsize>, queue_size: usize) {
+    // Only the request line and the body matter, the other headers are read and dropped. A body that's too long is
+    // left unread.
+    let read = async {
+        let mut reader = tokio::io::BufReader::new(&mut stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.ok()?;
+        let mut header = String::new();
+        let mut length = 0;
+        while reader.read_line(&mut header).await.is_ok_and(|read| read > 2) {
+            length = content_length(&header).unwrap_or(length);
+            header.clear();
+        }
+        if length > MAX_BODY_SIZE { return Some((request_line, None)); }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.ok()?;
+        Some((request_line, Some(body)))
+    };
+    let Ok(Some((request_line, body))) = tokio::time::timeout(server.timeout, read).await else { return };
+    let Some(body) = body else { return write_async(&mut stream, 400, &too_large_error()).await };
+
+    if waiting.fetch_add(1, Ordering::SeqCst) >= queue_size {
+        waiting.fetch_sub(1, Ordering::SeqCst);
+        return write_async(&mut stream, 503, &busy_error("request queue is full")).await;
+    }
+    let worker = tokio::time::timeout(server.timeout, workers.acquire_owned()).await;
+    waiting.fetch_sub(1, Ordering::SeqCst);
+    let Ok(Ok(worker)) = worker else { return write_async(&mut stream, 503, &busy_error("request timed out in the queue")).await };
+
+    let Ok(mut stream) = stream.into_std() else { return };
+    tokio::task::spawn_blocking(move || {
+        let _worker = worker;
+        stream.set_nonblocking(false).expect("Failed to make the connection blocking");
+        stream.set_write_timeout(Some(server.timeout)).expect("Failed to set write timeout");
+        respond(&mut stream, &server, &request_line, &body);
+    });
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::io::{BufRead, Write};
+use crate::helpers::{Args, PageId, locate_dump_files};
+use crate::complete::TitleCompleter;
+use crate::query::shortest_path;
+use crate::lookup::{ArticleLookup, DEFAULT_CACHE_SIZE};
+use crate::serve::article_html;
+use crate::split::LinkStore;
+
+const HELP: &str = "Commands:
+  get <title>              - Print the article's wikitext
+  get --html <title>       - Print the article rendered to an HTML page, linked like serve's /article?format=html
+  links <title>            - List the articles it links to
+  backlinks <title>        - List the articles linking to it
+  path <title> -> <title>  - Find the shortest link path between two articles
+  search <text>            - Find articles whose titles contain the text
+  complete <prefix>        - Complete a title prefix, allowing one typo if nothing matches exactly
+  cache                    - Show chunk cache statistics
+  help                     - Show this message
+  quit                     - Exit the shell";
+
+struct Shell {
+    lookup: ArticleLookup,
+    links: LinkStore,
+    completer: Option<TitleCompleter>,  // for title completion, if titles.fst exists
+}
+
+impl Shell {
+    fn find(&self, title: &str) -> Result<PageId, String> {
+        self.lookup.find(title).ok_or_else(|| format!("No article titled {}", title.trim()))
+    }
+
+    fn print_articles(&self, ids: &[PageId]) {
+        for id in ids {
+            println!("  {}", self.lookup.title(*id).unwrap_or("Unknown"));
+        }
+        println!("({} articles)", ids.len());
+    }
+
+    fn execute(&self, line: &str) -> Result<(), String> {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "get" => match argument.strip_prefix("--html ") {
+                Some(title) => println!("{}", article_html(&self.lookup, self.find(title)?).ok_or("Article not found in its chunk")?),
+                None => println!("{}", self.lookup.get(self.find(argument)?).ok_or("Article not found in its chunk")?),
+            },
+            "links" => {
+                let id = self.find(argument)?;
+                self.print_articles(&self.links.links(id)?);
+            }
+            "backlinks" => {
+                let id = self.find(argument)?;
+                self.print_articles(self.links.backlinks(id)?);
+            }
+            "path" => {
+                let (from, to) = argument.split_once("->").ok_or("Usage: path <title> -> <title>")?;
+                match shortest_path(self.links.all_links()?, self.find(from)?, self.find(to)?) {
+                    Some(path) => println!("{}", path.iter().map(|id| self.lookup.title(*id).unwrap_or("Unknown")).collect::<Vec<_>>().join(" -> ")),
+                    None => println!("No path found"),
+                }
+            }
+            "search" => self.print_articles(&self.lookup.search(argument, 20)),
+            "complete" => {
+                let completer = self.completer.as_ref().ok_or("titles.fst not found, run the index command first")?;
+                let mut matches = completer.complete(argument, 0, 20)?;
+                if matches.is_empty() {
+                    matches = completer.complete(argument, 1, 20)?;
+                }
+                self.print_articles(&matches.iter().map(|(_, id)| *id).collect::<Vec<_>>());
+            }
+            "cache" => {
+                let cache = self.lookup.cache.lock().unwrap();
+                println!("{} chunks cached, {} hits, {} misses", cache.len(), cache.hits, cache.misses);
+            }
+            "help" => println!("{}", HELP),
+            "" => {}
+            _ => return Err(format!("Unknown command: {} (type help for a list of commands)", command)),
+        }
+        Ok(())
+    }
+}
+
+pub fn shell(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let lookup = ArticleLookup::new(&index_path, &articles_path, args.parse_value("cache-size").unwrap_or(DEFAULT_CACHE_SIZE));
+
+    let links = LinkStore::open(data_path);
+    let completer = TitleCompleter::open(data_path);
+    let shell = Shell { lookup, links, completer };
+    println!("Loaded {} articles. Type help for a list of commands.", shell.lookup.len());
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().expect("Failed to flush stdout");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("Failed to read from stdin") == 0 { break; }
+        let line = line.trim();
+        if line == "quit" || line == "exit" { break; }
+        if let Err(err) = shell.execute(line) {
+            println!("Error: {}", err);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use indicatif::ProgressIterator;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use crate::graph::{DenseGraph, build_dense_graph};
+use crate::helpers::{Args, PageId, create_progress_bar};
+use crate::links::PageInfo;
+use crate::related::find_article;
+use crate::split::{load_graph, load_page_info, load_titles};
+
+// Articles are scored and written this many at a time, to bound memory
+const BLOCK_SIZE: usize = 65536;
+
+struct Similarity {
+    graph: DenseGraph,
+    reverse: DenseGraph,
+    redirects: Vec<bool>,
+    max_degree: usize,
+}
+
+impl Similarity {
+    fn load(args: &Args, data_path: &Path) -> (Self, FxHashMap<PageId, PageInfo>) {
+        let Some(links) = load_graph(data_path) else {
+            eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+            std::process::exit(1);
+        };
+        let graph = build_dense_graph(&links);
+        drop(links);
+        let reverse = graph.transpose();
+        let pages = load_page_info(data_path).unwrap();
+        let redirects = graph.ids.iter().map(|id| pages.get(id).is_some_and(|info| info.redirect)).collect();
+        let max_degree = args.parse_value("max-degree").unwrap_or(1000);
+        (Similarity { graph, reverse, redirects, max_degree }, pages)
+    }
+
+    // Counts, for every other article B, how many articles link to both this one and B (co-citation) and how many
+    // articles both link to (bibliographic coupling). Articles with more than `max_degree` links on the side being
+    // followed are skipped, since hubs say little about similarity and would make this quadratic.
+    fn counts(&self, node: usize) -> FxHashMap<u32, (u32, u32)> {
+        let mut counts: FxHashMap<u32, (u32, u32)> = FxHashMap::default();
+        for citing in self.reverse.unique_successors(node) {
+            if self.graph.successors(citing as usize).len() > self.max_degree { continue; }
+            for other in self.graph.unique_successors(citing as usize) {
+                counts.entry(other).or_default().0 += 1;
+            }
+        }
+        for cited in self.graph.unique_successors(node) {
+            if self.reverse.successors(cited as usize).len() > self.max_degree { continue; }
+            for other in self.reverse.unique_successors(cited as usize) {
+                counts.entry(other).or_default().1 += 1;
+            }
+        }
+        counts.remove(&(node as u32));
+        counts
+    }
+
+    // The most similar articles by co-citation plus coupling, leaving out redirects
+    fn most_similar(&self, node: usize, limit: usize) -> Vec<(u32, u32, u32)> {
+        let mut ranked: Vec<(u32, u32, u32)> = self.counts(node).into_iter()
+            .filter(|(other, _)| !self.redirects[*other as usize])
+            .map(|(other, (cocitation, coupling))| (other, cocitation, coupling))
+            .collect();
+        ranked.sort_unstable_by_key(|&(other, cocitation, coupling)| (std::cmp::Reverse(cocitation + coupling), other));
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+// Writes the most similar articles for every article to similarity.tsv
+pub fn similarity(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let limit: usize = args.parse_value("limit").unwrap_or(10);
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("similarity.tsv"));
+    let (similarity, _) = Similarity::load(args, data_path);
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    writeln!(output_file, "article_id\tsimilar_id\trank\tcocitation\tcoupling").expect("Failed to write output file");
+    let articles: Vec<usize> = (0..similarity.graph.node_count()).filter(|&node| !similarity.redirects[node]).collect();
+    let mut pairs = 0;
+    for block in articles.chunks(BLOCK_SIZE).progress_with(create_progress_bar(articles.len().div_ceil(BLOCK_SIZE) as u64, "Scoring similarity")) {
+        let results: Vec<Vec<(u32, u32, u32)>> = block.par_iter().map(|&node| similarity.most_similar(node, limit)).collect();
+        for (&node, similar) in block.iter().zip(&results) {
+            for (rank, &(other, cocitation, coupling)) in similar.iter().enumerate() {
+                writeln!(output_file, "{}\t{}\t{}\t{}\t{}", similarity.graph.ids[node], similarity.graph.ids[other as usize], rank + 1, cocitation, coupling)
+                    .expect("Failed to write output file");
+            }
+            pairs += similar.len();
+        }
+    }
+    output_file.flush().expect("Failed to flush output file");
+    println!("Wrote {} similar pairs for {} articles to {}", pairs, articles.len(), output_path.to_str().unwrap());
+}
+
+// Lists the articles most similar to one title
+pub fn similar(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let limit: usize = args.parse_value("limit").unwrap_or(20);
+    let (similarity, pages) = Similarity::load(args, data_path);
+    let node = find_article(args, data_path, "similar <data_path> <title> [--limit N] [--max-degree N]", &similarity.graph, &pages);
+
+    let titles = load_titles(data_path).unwrap();
+    let title = |node: usize| {
+        let id = similarity.graph.ids[node];
+        titles.get(&id).cloned().unwrap_or_else(|| format!("Unknown (ID: {})", id))
+    };
+    let similar = similarity.most_similar(node, limit);
+    println!("Articles most similar to {}:", title(node));
+    for (rank, (other, cocitation, coupling)) in similar.iter().enumerate() {
+        println!("{:>2}) {} (co-cited {}, coupled {})", rank + 1, title(*other as usize), cocitation, coupling);
+    }
+    if similar.is_empty() {
+        println!("No similar articles");
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use indicatif::ProgressIterator;
+use threadpool::ThreadPool;
+use crate::config::config;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::index::normalize_link;
+use crate::quality::load_quality_filter;
+use crate::render::{HtmlRenderer, STYLE};
+use crate::titles::TitleTable;
+
+// Article pages are bucketed by ID so no directory holds more than a thousand of them, and every page sits two levels
+// below the root of the site
+const ROOT: &str = "../../";
+
+// The site's own styles, on top of the ones for the rendered articles
+const SITE_STYLE: &str = "\
+header { display: flex; gap: 1em; align-items: center; padding: 0.5em 1em; border-bottom: 1px solid #a2a9b1; background: #f8f9fa; }
+header .home { font-weight: bold; color: inherit; text-decoration: none; }
+header form { flex: 1; }
+header input, #q { width: 100%; max-width: 30em; padding: 0.3em; font-size: 1em; }
+";
+
+const SEARCH_SCRIPT: &str = "\
+const pagePath = id => 'wiki/' + Math.floor(id / 1000) + '/' + id + '.html';
+const input = document.getElementById('q');
+const results = document.getElementById('results');
+
+// Titles that are the query come first, then titles starting with it, then titles containing it, shortest first
+function search(query) {
+  query = query.trim().toLowerCase();
+  results.replaceChildren();
+  if (!query) return;
+  const matches = [];
+  for (const [id, title] of SEARCH_INDEX) {
+    const lower = title.toLowerCase();
+    if (lower.includes(query)) matches.push([lower === query ? 0 : lower.startsWith(query) ? 1 : 2, title.length, id, title]);
+  }
+  matches.sort((a, b) => a[0] - b[0] || a[1] - b[1]);
+  for (const [, , id, title] of matches.slice(0, 50)) {
+    const link = document.createElement('a');
+    link.href = pagePath(id);
+    link.textContent = title;
+    const item = document.createElement('li');
+    item.appendChild(link);
+    results.appendChild(item);
+  }
+}
+
+input.addEventListener('input', () => search(input.value));
+const query = new URLSearchParams(location.search).get('q');
+if (query) {
+  input.value = query;
+  search(query);
+}
+";
+
+fn page_path(id: PageId) -> String {
+    format!("wiki/{}/{}.html", id / 1000, id)
+}
+
+fn page_html(title: &str, head: &str, body: &str) -> String {
+    let site = encode_text(&config().dump_prefix).to_string();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title} - {site}</title>\n<link rel=\"stylesheet\" href=\"{ROOT}style.css\">\n{head}</head>\n<body>\n\
+         <header><a class=\"home\" href=\"{ROOT}index.html\">{site}</a>\
+         <form action=\"{ROOT}index.html\"><input name=\"q\" type=\"search\" placeholder=\"Search\"></form>\
+         <a href=\"{ROOT}random.html\">Random article</a></header>\n<main>\n<h1>{title}</h1>\n{body}</main>\n</body>\n</html>\n",
+        title = encode_text(title),
+    )
+}
+
+// Resolves the titles in a file, one per line, to article IDs
+fn load_title_list(path: &str, titles: &TitleTable) -> HashSet<PageId> {
+    let contents = read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Error: Failed to read {}: {}", path, err);
+        std::process::exit(1);
+    });
+    let wanted: Vec<&str> = contents.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let ids: HashSet<PageId> = wanted.iter().filter_map(|title| titles.find(&title.replace('_', " ").to_lowercase())).collect();
+    if ids.len() < wanted.len() {
+        println!("Skipping {} titles from {} that aren't in the dump", wanted.len() - ids.len(), path);
+    }
+    ids
+}
+
+// A file of the rendered site, with its path relative to the root of the site
+pub enum SiteFile {
+    // Article pages have titles, and the other files like the stylesheet don't
+    Data { path: String, title: String, mime_type: &'static str, contents: Vec<u8> },
+    Redirect { path: String, title: String, target: String, target_title: String },
+}
+
+// Where the rendered site goes: a directory for build-site, or a ZIM archive for export --format zim
+pub trait SiteOutput: Send + Sync {
+    // Takes a batch of files at a time, the pages of one chunk of the dump or the site's other files
+    fn add(&self, files: Vec<SiteFile>);
+}
+
+struct SiteDirectory(PathBuf);
+
+impl SiteOutput for SiteDirectory {
+    fn add(&self, files: Vec<SiteFile>) {
+        for file in files {
+            let (path, contents) = match file {
+                SiteFile::Data { path, contents, .. } => (path, contents),
+                SiteFile::Redirect { path, title, target, target_title } => {
+                    let href = encode_double_quoted_attribute(&format!("{}{}", ROOT, target)).to_string();
+                    let html = page_html(&title, &format!("<meta http-equiv=\"refresh\" content=\"0; url={}\">\n", href),
+                        &format!("<p>Redirecting to <a href=\"{}\">{}</a></p>\n", href, encode_text(&target_title)));
+                    (path, html.into_bytes())
+                }
+            };
+            let path = self.0.join(path);
+            create_dir_all(path.parent().unwrap()).expect("Failed to create output directory");
+            write(&path, contents).expect("Failed to write site file");
+        }
+    }
+}
+
+// Renders the articles chosen by --titles and --quality, or all of them, to `output`, followed by the search and random
+// article pages at index.html and random.html, and returns how many articles and redirects there were
+pub fn render_site(args: &Args, data_path: &Path, output: Arc<dyn SiteOutput>) -> (usize, usize) {
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let titles = Arc::new(TitleTable::new(seek_position_map.values()
+        .progress_with(create_progress_bar(seek_position_map.len() as u64, "Creating title index"))
+        .flatten()));
+
+    let mut selected = args.value("titles").map(|path| load_title_list(path, &titles));
+    if let Some(quality) = load_quality_filter(args, data_path) {
+        selected = Some(match selected {
+            Some(selected) => selected.intersection(&quality).copied().collect(),
+            None => quality,
+        });
+    }
+    let chunk_ranges: Vec<(usize, u64, u64)> = get_chunk_ranges(&seek_position_map, &articles_path, args).into_iter()
+        .filter(|(_, start_position, _)| selected.as_ref().is_none_or(|selected| seek_position_map[start_position].iter().any(|(id, _)| selected.contains(id))))
+        .collect();
+    let selected = Arc::new(selected);
+
+    let pool = ThreadPool::new(config().threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let entries = Arc::new(Mutex::new(Vec::new()));  // (id, title) of every page, for the search index
+    let totals = Arc::new(Mutex::new((0, 0)));
+    let progress_bar = Arc::new(create_progress_bar(chunk_ranges.len() as u64, "Rendering articles"));
+    for (_, start_position, end_position) in chunk_ranges {
+        let articles_path = Arc::clone(&articles_path);
+        let output = Arc::clone(&output);
+        let titles = Arc::clone(&titles);
+        let selected = Arc::clone(&selected);
+        let entries = Arc::clone(&entries);
+        let totals = Arc::clone(&totals);
+        let progress_bar = Arc::clone(&progress_bar);
+        pool.execute(move || {
+            // Links go to the other article pages, and only to the ones on the site
+            let selected = selected.as_ref().as_ref();
+            let renderer = HtmlRenderer::new(|title| {
+                let id = titles.find(normalize_link(title)?.trim())?;
+                selected.is_none_or(|selected| selected.contains(&id)).then(|| format!("{}{}", ROOT, page_path(id)))
+            });
+            let mut files = Vec::new();
+            let mut chunk_entries = Vec::new();
+            let (mut articles, mut redirects) = (0, 0);
+            for (id, page) in load_chunk_pages(&articles_path, start_position, end_position) {
+                if page.namespace != 0 || selected.is_some_and(|selected| !selected.contains(&id)) { continue; }
+                let path = page_path(id);
+                if page.redirect {
+                    // Redirects to articles that aren't on the site still get a page, since links to them are rendered
+                    let target_title = page.redirect_target.unwrap_or_default();
+                    redirects += 1;
+                    match renderer.href(&target_title).as_deref().and_then(|href| href.strip_prefix(ROOT)) {
+                        Some(target) => files.push(SiteFile::Redirect { path, title: page.title.clone(), target: target.to_string(), target_title }),
+                        None => {
+                            let html = page_html(&page.title, "", &format!("<p>Redirects to <span class=\"missing\">{}</span>, which isn't in this mirror</p>\n", encode_text(&target_title)));
+                            files.push(SiteFile::Data { path, title: page.title.clone(), mime_type: "text/html", contents: html.into_bytes() });
+                        }
+                    }
+                } else {
+                    articles += 1;
+                    let html = page_html(&page.title, "", &renderer.render(&page.text));
+                    files.push(SiteFile::Data { path, title: page.title.clone(), mime_type: "text/html", contents: html.into_bytes() });
+                }
+                chunk_entries.push((id, page.title));
+            }
+            output.add(files);
+            entries.lock().unwrap().extend(chunk_entries);
+            let mut totals = totals.lock().unwrap();
+            totals.0 += articles;
+            totals.1 += redirects;
+            progress_bar.inc(1);
+        })
+    }
+    pool.join();
+    progress_bar.finish_and_clear();
+
+    // The search index is a script rather than JSON so the pages work when opened from disk, where fetch isn't allowed
+    let mut entries = std::mem::take(&mut *entries.lock().unwrap());
+    entries.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+    let index: Vec<String> = entries.iter().map(|(id, title)| format!("[{},{}]", id, serde_json::to_string(title).unwrap())).collect();
+    let (articles, redirects) = *totals.lock().unwrap();
+    let search_page = page_html("Search", "", &format!(
+        "<p>{} articles. <a href=\"random.html\">Random article</a></p>\n<input id=\"q\" type=\"search\" placeholder=\"Search titles\" autofocus>\n\
+         <ul id=\"results\"></ul>\n<script src=\"search-index.js\"></script>\n<script src=\"search.js\"></script>\n", articles));
+    let random_page = page_html("Random article", "", "<script src=\"search-index.js\"></script>\n<script>\n\
+         const [id] = SEARCH_INDEX[Math.floor(Math.random() * SEARCH_INDEX.length)];\n\
+         location.replace('wiki/' + Math.floor(id / 1000) + '/' + id + '.html');\n</script>\n");
+    let file = |path: &str, mime_type, contents: String| SiteFile::Data { path: path.to_string(), title: String::new(), mime_type, contents: contents.into_bytes() };
+    output.add(vec![
+        file("search-index.js", "application/javascript", format!("window.SEARCH_INDEX = [\n{}\n];\n", index.join(",\n"))),
+        file("search.js", "application/javascript", SEARCH_SCRIPT.to_string()),
+        file("style.css", "text/css", format!("{}{}", STYLE, SITE_STYLE)),
+        file("index.html", "text/html", search_page.replace(ROOT, "")),
+        file("random.html", "text/html", random_page.replace(ROOT, "")),
+    ]);
+    (articles, redirects)
+}
+
+// Renders articles to a static HTML site in --output (data_path/site by default) that can be browsed straight from
+// disk: a page for each article with its links pointing at the other pages, a search page over all the titles and a
+// random article link. With --titles FILE (one title per line) or --quality TIERS only those articles are rendered,
+// and links to anything else are left as plain text. Redirects get pages that forward to their targets, though when
+// only some articles are rendered a link through a redirect is left as text, since the redirect isn't one of them.
+pub fn build_site(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("site"));
+    let (articles, redirects) = render_site(args, data_path, Arc::new(SiteDirectory(output_path.clone())));
+    println!("Rendered {} articles and {} redirects", articles, redirects);
+    println!("Wrote site to {}", output_path.join("index.html").to_str().unwrap());
+}
@@ -0,0 +1,438 @@
+use std::path::{Path, PathBuf};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+#[cfg(feature = "cli")]
+use std::sync::OnceLock;
+use rustc_hash::FxHashMap;
+#[cfg(feature = "cli")]
+use crate::helpers::create_progress_bar;
+#[cfg(feature = "cli")]
+use crate::links::{load_links, try_load_links};
+use crate::links::{ID_WIDTH, PageId, PageInfo, read_id, read_links, read_links_file, read_varint, write_id, write_links, write_varint};
+use crate::query::{ByteSource, RecordReader};
+#[cfg(feature = "cli")]
+use crate::query::backlinks;
+
+// The index also writes the link graph as three separate files, so tools that only need titles or only need
+// edges can skip the rest, and single records can be fetched without reading anything else:
+//   titles.bin  - records of article_id, namespace, flags (bit 0 set for redirects), text_length, word_count,
+//                 title_length, title
+//   graph.bin   - records of body_length, then a body of article_id, link_count, and the delta-encoded link IDs
+//   offsets.idx - the width of the page IDs in bytes as a little-endian u32, then an entry for every article sorted
+//                 by ID: its ID in that many little-endian bytes and the u64 offsets of its records in titles.bin and
+//                 graph.bin, so lookups are a binary search and the file's size follows the number of articles rather
+//                 than the highest ID
+// Each file starts with its own magic number and a little-endian u32 version, and integers in the records are
+// LEB128 varints as in links.bin. Files written by older versions aren't read, and the index command has to be re-run
+// to replace them.
+pub const TITLES_MAGIC: &[u8; 4] = b"WKTI";
+pub const GRAPH_MAGIC: &[u8; 4] = b"WKGR";
+pub const OFFSETS_MAGIC: &[u8; 4] = b"WKOF";
+const VERSION: u32 = 3;
+// Version 3 of offsets.idx was a table with an entry for every ID up to the highest one
+const OFFSETS_VERSION: u32 = 4;
+pub const HEADER_SIZE: u64 = 8;
+// offsets.idx follows the header with the ID width
+pub const OFFSETS_HEADER_SIZE: u64 = HEADER_SIZE + 4;
+
+fn version(magic: &[u8; 4]) -> u32 {
+    if magic == OFFSETS_MAGIC { OFFSETS_VERSION } else { VERSION }
+}
+
+fn write_header(writer: &mut impl Write, magic: &[u8; 4]) {
+    writer.write_all(magic).expect("Failed to write header");
+    writer.write_all(&version(magic).to_le_bytes()).expect("Failed to write header");
+}
+
+pub fn validate_header(buffer: &[u8], magic: &[u8; 4], name: &str) -> Result<(), String> {
+    if !buffer.starts_with(magic) || buffer.get(4..8) != Some(&version(magic).to_le_bytes()[..]) {
+        return Err(format!("{} has an unrecognized header, re-run the index command", name));
+    }
+    Ok(())
+}
+
+fn check_header(buffer: &[u8], magic: &[u8; 4], name: &str) {
+    validate_header(buffer, magic, name).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+}
+
+pub fn split_files_exist(data_path: &Path) -> bool {
+    ["titles.bin", "graph.bin", "offsets.idx"].iter().all(|name| data_path.join(name).exists())
+}
+
+pub struct SplitWriter {
+    titles_file: BufWriter<File>,
+    graph_file: BufWriter<File>,
+    titles_position: u64,
+    graph_position: u64,
+    offsets: Vec<(PageId, u64, u64)>,  // article_id, titles.bin offset, graph.bin offset
+    offsets_path: PathBuf,
+}
+
+impl SplitWriter {
+    pub fn create(data_path: &Path) -> Self {
+        let mut titles_file = BufWriter::new(File::create(data_path.join("titles.bin")).expect("Failed to create titles file"));
+        let mut graph_file = BufWriter::new(File::create(data_path.join("graph.bin")).expect("Failed to create graph file"));
+        write_header(&mut titles_file, TITLES_MAGIC);
+        write_header(&mut graph_file, GRAPH_MAGIC);
+        SplitWriter {
+            titles_file,
+            graph_file,
+            titles_position: HEADER_SIZE,
+            graph_position: HEADER_SIZE,
+            offsets: Vec::new(),
+            offsets_path: data_path.join("offsets.idx"),
+        }
+    }
+
+    // Reopens the files left by an interrupted index, dropping anything past the lengths saved in its last
+    // checkpoint, and rebuilds the offsets of the records that were already written
+    pub fn resume(data_path: &Path, titles_length: u64, graph_length: u64) -> Self {
+        let titles_path = data_path.join("titles.bin");
+        let graph_path = data_path.join("graph.bin");
+        for (path, length) in [(&titles_path, titles_length), (&graph_path, graph_length)] {
+            let file = OpenOptions::new().write(true).open(path).expect("Failed to open split file for resuming");
+            file.set_len(length).expect("Failed to truncate split file");
+        }
+
+        let titles_buffer = read_links_file(&titles_path);
+        let graph_buffer = read_links_file(&graph_path);
+        check_header(&titles_buffer, TITLES_MAGIC, "titles.bin");
+        check_header(&graph_buffer, GRAPH_MAGIC, "graph.bin");
+        let mut offsets = Vec::new();
+        let (mut i, mut j) = (HEADER_SIZE as usize, HEADER_SIZE as usize);
+        while i < titles_buffer.len() {
+            let (titles_offset, graph_offset) = (i, j);
+            let (article_id, _, _) = parse_title_record(&titles_buffer, &mut i).unwrap_or_else(|err| panic!("Corrupt title record at byte {}: {}", titles_offset, err));
+            let (graph_id, _) = parse_graph_record(&graph_buffer, &mut j).unwrap_or_else(|err| panic!("Corrupt graph record at byte {}: {}", graph_offset, err));
+            assert_eq!(article_id, graph_id, "titles.bin and graph.bin are out of sync at article {}", article_id);
+            offsets.push((article_id, titles_offset as u64, graph_offset as u64));
+        }
+
+        let open_for_append = |path: &Path| BufWriter::new(OpenOptions::new().append(true).open(path).expect("Failed to open split file for resuming"));
+        SplitWriter {
+            titles_file: open_for_append(&titles_path),
+            graph_file: open_for_append(&graph_path),
+            titles_position: titles_length,
+            graph_position: graph_length,
+            offsets,
+            offsets_path: data_path.join("offsets.idx"),
+        }
+    }
+
+    pub fn write(&mut self, article_id: PageId, title: &str, info: &PageInfo, link_ids: &[PageId]) {
+        let mut title_record = Vec::new();
+        write_id(&mut title_record, article_id);
+        info.write(&mut title_record);
+        write_varint(&mut title_record, title.len() as u32);
+        title_record.extend_from_slice(title.as_bytes());
+
+        let mut body = Vec::new();
+        write_id(&mut body, article_id);
+        write_links(&mut body, link_ids);
+        let mut graph_record = Vec::with_capacity(body.len() + 5);
+        write_varint(&mut graph_record, body.len() as u32);
+        graph_record.extend_from_slice(&body);
+
+        self.titles_file.write_all(&title_record).expect("Failed to write titles file");
+        self.graph_file.write_all(&graph_record).expect("Failed to write graph file");
+        self.offsets.push((article_id, self.titles_position, self.graph_position));
+        self.titles_position += title_record.len() as u64;
+        self.graph_position += graph_record.len() as u64;
+    }
+
+    // Flushes both files, returning their lengths
+    pub fn flush(&mut self) -> (u64, u64) {
+        self.titles_file.flush().expect("Failed to flush titles file");
+        self.graph_file.flush().expect("Failed to flush graph file");
+        (self.titles_position, self.graph_position)
+    }
+
+    pub fn finish(mut self) {
+        self.flush();
+
+        self.offsets.sort_unstable_by_key(|(article_id, _, _)| *article_id);
+        let mut offsets_file = BufWriter::new(File::create(&self.offsets_path).expect("Failed to create offsets file"));
+        write_header(&mut offsets_file, OFFSETS_MAGIC);
+        offsets_file.write_all(&ID_WIDTH.to_le_bytes()).expect("Failed to write offsets file");
+        for (article_id, titles_offset, graph_offset) in self.offsets {
+            offsets_file.write_all(&article_id.to_le_bytes()).expect("Failed to write offsets file");
+            offsets_file.write_all(&titles_offset.to_le_bytes()).expect("Failed to write offsets file");
+            offsets_file.write_all(&graph_offset.to_le_bytes()).expect("Failed to write offsets file");
+        }
+        offsets_file.flush().expect("Failed to flush offsets file");
+    }
+}
+
+// Reads every record in titles.bin, without touching the graph
+#[cfg(feature = "cli")]
+pub fn read_titles(data_path: &Path) -> FxHashMap<PageId, String> {
+    let buffer = read_links_file(&data_path.join("titles.bin"));
+    check_header(&buffer, TITLES_MAGIC, "titles.bin");
+    parse_titles(&buffer).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[cfg(feature = "cli")]
+fn parse_titles(buffer: &[u8]) -> Result<FxHashMap<PageId, String>, String> {
+    let progress_bar = create_progress_bar(buffer.len() as u64, "Reading titles");
+    let mut titles = FxHashMap::default();
+    let mut i = HEADER_SIZE as usize;
+    while i < buffer.len() {
+        let offset = i;
+        let (article_id, title) = parse_title(buffer, &mut i).map_err(|err| format!("Corrupt title record at byte {}: {}", offset, err))?;
+        titles.insert(article_id, title);
+        progress_bar.set_position(i as u64);
+    }
+    progress_bar.finish_and_clear();
+    Ok(titles)
+}
+
+// Reads the page info of every record in titles.bin, without the titles themselves
+pub fn read_page_info(data_path: &Path) -> FxHashMap<PageId, PageInfo> {
+    let buffer = read_links_file(&data_path.join("titles.bin"));
+    check_header(&buffer, TITLES_MAGIC, "titles.bin");
+    let mut pages = FxHashMap::default();
+    let mut i = HEADER_SIZE as usize;
+    while i < buffer.len() {
+        let offset = i;
+        let (article_id, _, info) = parse_title_record(&buffer, &mut i).unwrap_or_else(|err| panic!("Corrupt title record at byte {}: {}", offset, err));
+        pages.insert(article_id, info);
+    }
+    pages
+}
+
+// Reads every record in graph.bin, without touching the titles
+#[cfg(feature = "cli")]
+pub fn read_graph(data_path: &Path) -> FxHashMap<PageId, Vec<PageId>> {
+    let buffer = read_links_file(&data_path.join("graph.bin"));
+    check_header(&buffer, GRAPH_MAGIC, "graph.bin");
+    parse_graph(&buffer).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[cfg(feature = "cli")]
+fn parse_graph(buffer: &[u8]) -> Result<FxHashMap<PageId, Vec<PageId>>, String> {
+    let progress_bar = create_progress_bar(buffer.len() as u64, "Reading graph");
+    let mut links = FxHashMap::default();
+    let mut i = HEADER_SIZE as usize;
+    while i < buffer.len() {
+        let offset = i;
+        let (article_id, article_links) = parse_graph_record(buffer, &mut i).map_err(|err| format!("Corrupt graph record at byte {}: {}", offset, err))?;
+        links.insert(article_id, article_links);
+        progress_bar.set_position(i as u64);
+    }
+    progress_bar.finish_and_clear();
+    Ok(links)
+}
+
+// Reads one of the split files and checks its header, returning an error rather than exiting
+#[cfg(feature = "cli")]
+fn try_read_split_file(data_path: &Path, name: &str, magic: &[u8; 4]) -> Result<Vec<u8>, String> {
+    let path = data_path.join(name);
+    let buffer = std::fs::read(&path).map_err(|err| format!("Unable to read {}: {}", path.to_str().unwrap(), err))?;
+    validate_header(&buffer, magic, name)?;
+    Ok(buffer)
+}
+
+// Loads just the outgoing links, from graph.bin if the split files exist or from links.bin otherwise
+#[cfg(feature = "cli")]
+pub fn load_graph(data_path: &Path) -> Option<FxHashMap<PageId, Vec<PageId>>> {
+    let links_file_path = data_path.join("links.bin");
+    if split_files_exist(data_path) {
+        Some(read_graph(data_path))
+    } else if links_file_path.exists() {
+        Some(load_links(&links_file_path).links)
+    } else {
+        None
+    }
+}
+
+// Loads just the titles, from titles.bin if the split files exist or from links.bin otherwise
+#[cfg(feature = "cli")]
+pub fn load_titles(data_path: &Path) -> Option<FxHashMap<PageId, String>> {
+    let links_file_path = data_path.join("links.bin");
+    if split_files_exist(data_path) {
+        Some(read_titles(data_path))
+    } else if links_file_path.exists() {
+        Some(load_links(&links_file_path).titles)
+    } else {
+        None
+    }
+}
+
+// Loads just the page info, from titles.bin if the split files exist or from links.bin otherwise
+#[cfg(feature = "cli")]
+pub fn load_page_info(data_path: &Path) -> Option<FxHashMap<PageId, PageInfo>> {
+    let links_file_path = data_path.join("links.bin");
+    if split_files_exist(data_path) {
+        Some(read_page_info(data_path))
+    } else if links_file_path.exists() {
+        Some(load_links(&links_file_path).pages)
+    } else {
+        None
+    }
+}
+
+// Like load_graph and load_titles, but a stale or corrupt file is an error instead of ending the process, for the
+// language bindings. They're Ok(None) when the index hasn't been built.
+#[cfg(feature = "cli")]
+pub fn try_load_graph(data_path: &Path) -> Result<Option<FxHashMap<PageId, Vec<PageId>>>, String> {
+    let links_file_path = data_path.join("links.bin");
+    if split_files_exist(data_path) {
+        parse_graph(&try_read_split_file(data_path, "graph.bin", GRAPH_MAGIC)?).map(Some)
+    } else if links_file_path.exists() {
+        try_load_links(&links_file_path).map(|graph| Some(graph.links))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn try_load_titles(data_path: &Path) -> Result<Option<FxHashMap<PageId, String>>, String> {
+    let links_file_path = data_path.join("links.bin");
+    if split_files_exist(data_path) {
+        parse_titles(&try_read_split_file(data_path, "titles.bin", TITLES_MAGIC)?).map(Some)
+    } else if links_file_path.exists() {
+        try_load_links(&links_file_path).map(|graph| Some(graph.titles))
+    } else {
+        Ok(None)
+    }
+}
+
+fn parse_title_record(buffer: &[u8], offset: &mut usize) -> Result<(PageId, String, PageInfo), String> {
+    let article_id = read_id(buffer, offset)?;
+    let info = PageInfo::read(buffer, offset)?;
+    let title_length = read_varint(buffer, offset)? as usize;
+    let title_bytes = buffer.get(*offset..*offset+title_length)
+        .ok_or_else(|| format!("title length {} runs past end of file", title_length))?;
+    let title = String::from_utf8(title_bytes.to_vec()).map_err(|_| "title is not valid UTF-8".to_string())?;
+    *offset += title_length;
+    Ok((article_id, title, info))
+}
+
+pub fn parse_title(buffer: &[u8], offset: &mut usize) -> Result<(PageId, String), String> {
+    parse_title_record(buffer, offset).map(|(article_id, title, _)| (article_id, title))
+}
+
+pub fn parse_graph_record(buffer: &[u8], offset: &mut usize) -> Result<(PageId, Vec<PageId>), String> {
+    let body_length = read_varint(buffer, offset)? as usize;
+    let end = *offset + body_length;
+    let body = buffer.get(..end).ok_or_else(|| format!("record length {} runs past end of file", body_length))?;
+    let article_id = read_id(body, offset)?;
+    let links = read_links(body, offset)?;
+    if *offset != end {
+        return Err(format!("record length {} doesn't match its contents", body_length));
+    }
+    Ok((article_id, links))
+}
+
+// A file read through a ByteSource, seeking before each read
+pub struct FileSource {
+    file: Mutex<File>,
+    size: u64,
+}
+
+impl ByteSource for FileSource {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&self, position: u64, buffer: &mut [u8]) -> Result<usize, String> {
+        let file = &mut *self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(position)).map_err(|err| format!("Failed to seek: {}", err))?;
+        let mut total = 0;
+        while total < buffer.len() {
+            match file.read(&mut buffer[total..]).map_err(|err| format!("Failed to read: {}", err))? {
+                0 => break,
+                n => total += n,
+            }
+        }
+        Ok(total)
+    }
+}
+
+// Fetches individual titles and link lists by article ID with a binary search of offsets.idx and a single read from
+// the titles or graph file, so nothing needs to be loaded up front
+pub struct RecordIndex {
+    reader: RecordReader<FileSource>,
+}
+
+impl RecordIndex {
+    pub fn open(data_path: &Path) -> Self {
+        RecordIndex::try_open(data_path).unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        })
+    }
+
+    // Like open, but returns an error rather than exiting when a file is missing or has the wrong header
+    pub fn try_open(data_path: &Path) -> Result<Self, String> {
+        let open = |name: &str| {
+            let file = File::open(data_path.join(name)).map_err(|_| format!("Unable to open {} in {}", name, data_path.to_str().unwrap()))?;
+            let size = file.metadata().map_err(|err| format!("Failed to get the size of {}: {}", name, err))?.len();
+            Ok::<_, String>(FileSource { file: Mutex::new(file), size })
+        };
+        let reader = RecordReader::new(open("offsets.idx")?, open("titles.bin")?, open("graph.bin")?)?;
+        Ok(RecordIndex { reader })
+    }
+
+    pub fn title(&self, article_id: PageId) -> Option<String> {
+        self.reader.title(article_id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn links(&self, article_id: PageId) -> Option<Vec<PageId>> {
+        self.reader.links(article_id).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+#[cfg(feature = "cli")]
+type Graph = (FxHashMap<PageId, Vec<PageId>>, FxHashMap<PageId, Vec<PageId>>);  // outgoing links and backlinks
+
+// Outgoing links and backlinks by article ID. Single link lists come from the split files when they exist, and the
+// whole graph is loaded the first time anything needs backlinks or a path.
+#[cfg(feature = "cli")]
+pub struct LinkStore {
+    data_path: PathBuf,
+    record_index: Option<RecordIndex>,
+    graph: OnceLock<Option<Graph>>,  // the full graph, loaded the first time it's needed
+}
+
+#[cfg(feature = "cli")]
+impl LinkStore {
+    pub fn open(data_path: &Path) -> Self {
+        let record_index = split_files_exist(data_path).then(|| RecordIndex::open(data_path));
+        LinkStore { data_path: data_path.to_path_buf(), record_index, graph: OnceLock::new() }
+    }
+
+    // Like open, but returns an error rather than exiting when the split files can't be read
+    pub fn try_open(data_path: &Path) -> Result<Self, String> {
+        let record_index = split_files_exist(data_path).then(|| RecordIndex::try_open(data_path)).transpose()?;
+        Ok(LinkStore { data_path: data_path.to_path_buf(), record_index, graph: OnceLock::new() })
+    }
+
+    fn graph(&self) -> Result<&Graph, String> {
+        self.graph.get_or_init(|| {
+            let links = load_graph(&self.data_path)?;
+            let backlinks = backlinks(&links);
+            Some((links, backlinks))
+        }).as_ref().ok_or_else(|| "links.bin not found, run the index command first".to_string())
+    }
+
+    // The full map of outgoing links, for searches across the graph
+    pub fn all_links(&self) -> Result<&FxHashMap<PageId, Vec<PageId>>, String> {
+        Ok(&self.graph()?.0)
+    }
+
+    pub fn links(&self, id: PageId) -> Result<Vec<PageId>, String> {
+        match &self.record_index {
+            Some(record_index) => Ok(record_index.links(id).unwrap_or_default()),
+            None => Ok(self.graph()?.0.get(&id).cloned().unwrap_or_default()),
+        }
+    }
+
+    pub fn backlinks(&self, id: PageId) -> Result<&[PageId], String> {
+        Ok(self.graph()?.1.get(&id).map_or(&[], Vec::as_slice))
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::{create_dir_all, remove_file};
+use std::path::Path;
+use std::sync::{Arc, mpsc};
+use rusqlite::{Connection, params};
+use threadpool::ThreadPool;
+use tracing::info;
+use crate::config::config;
+use crate::corpus::plain_text;
+use crate::helpers::{Args, PageId, create_progress_bar, get_chunk_ranges, load_chunk_pages, load_index, locate_dump_files};
+use crate::quality::load_quality_filter;
+
+// The articles table holds the text, and the FTS5 table indexes it without keeping a second copy. Porter stemming
+// lets a search for "running" find "runs".
+const SQLITE_TABLES: &str = "
+CREATE TABLE articles (id INTEGER PRIMARY KEY, title TEXT NOT NULL, text TEXT NOT NULL);
+CREATE VIRTUAL TABLE articles_fts USING fts5(title, text, content='articles', content_rowid='id', tokenize='porter unicode61');
+";
+
+// A single-file SQLite database of the plain text of every article, with an FTS5 full-text index over titles and
+// text, for searching a small wiki or a subset of a large one with nothing more than sqlite3. Chunks are parsed on
+// the thread pool and inserted from this thread in one transaction, and the index is built in one go at the end,
+// which is much faster than keeping it up to date row by row.
+pub fn export_sqlite_fts(args: &Args, data_path: &Path, output_dir: &Path) {
+    let quality = Arc::new(load_quality_filter(args, data_path));
+    create_dir_all(output_dir).expect("Failed to create output directory");
+    let database_path = output_dir.join("wikipedia.sqlite");
+    if database_path.exists() {
+        remove_file(&database_path).expect("Failed to remove existing database");
+    }
+    let mut connection = Connection::open(&database_path).expect("Failed to create SQLite database");
+    // Nothing is lost if a half-built database is thrown away, so there's no need for a journal
+    connection.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;").expect("Failed to configure database");
+    connection.execute_batch(SQLITE_TABLES).expect("Failed to create tables");
+
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let seek_position_map = load_index(index_path.to_str().unwrap());
+    let chunk_ranges = get_chunk_ranges(&seek_position_map, &articles_path, args);
+
+    let num_threads = config().threads;
+    let pool = ThreadPool::new(num_threads);
+    let articles_path = Arc::new(articles_path.to_str().unwrap().to_string());
+    let progress_bar = create_progress_bar(chunk_ranges.len() as u64, "Exporting articles");
+    let (sender, receiver) = mpsc::sync_channel::<Vec<(PageId, String, String)>>(num_threads);
+    for (_, start_position, end_position) in chunk_ranges {
+        let (articles_path, quality, sender) = (Arc::clone(&articles_path), Arc::clone(&quality), sender.clone());
+        pool.execute(move || {
+            let mut rows: Vec<(PageId, String, String)> = load_chunk_pages(&articles_path, start_position, end_position).into_iter()
+                .filter(|(id, page)| page.namespace == 0 && !page.redirect && quality.as_ref().as_ref().is_none_or(|quality| quality.contains(id)))
+                .map(|(id, page)| (id, page.title, plain_text(&page.text)))
+                .collect();
+            rows.sort_unstable_by_key(|(id, _, _)| *id);
+            sender.send(rows).expect("Failed to send articles");
+        });
+    }
+    drop(sender);
+
+    let transaction = connection.transaction().expect("Failed to start transaction");
+    let mut articles = 0;
+    {
+        let mut statement = transaction.prepare("INSERT INTO articles (id, title, text) VALUES (?1, ?2, ?3)").expect("Failed to prepare insert");
+        for rows in receiver {
+            for (id, title, text) in &rows {
+                statement.execute(params![*id as i64, title, text]).expect("Failed to insert article");
+            }
+            articles += rows.len();
+            progress_bar.inc(1);
+        }
+    }
+    transaction.commit().expect("Failed to write articles");
+    progress_bar.finish_and_clear();
+
+    info!("Building the full-text index");
+    connection.execute_batch("INSERT INTO articles_fts (articles_fts) VALUES ('rebuild'); INSERT INTO articles_fts (articles_fts) VALUES ('optimize');")
+        .expect("Failed to build the full-text index");
+    println!("Exported {} articles to {}", articles, database_path.to_str().unwrap());
+    println!("Search with: sqlite3 {} \"SELECT rowid, title FROM articles_fts WHERE articles_fts MATCH 'query' ORDER BY rank LIMIT 10\"", database_path.to_str().unwrap());
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::time::Duration;
+use html_escape::decode_html_entities;
+use crate::config::config;
+use crate::helpers::{Args, PageId, ProgressReader, create_progress_bar, decompress_index, dump_size, locate_dump_files};
+use crate::namespaces::{is_ignored, namespace_names};
+use crate::preflight::format_size;
+
+// Rough throughput of the index command per thread, in bytes of the dump file a second. Decompressing bz2 dominates
+// for the original dump, and parsing the XML for the others.
+const BZ2_BYTES_PER_SECOND: f64 = 5e6;
+const ZSTD_BYTES_PER_SECOND: f64 = 25e6;
+const XML_BYTES_PER_SECOND: f64 = 80e6;
+
+pub fn percentile(sorted: &[u64], fraction: f64) -> u64 {
+    sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+// Prints how many of the sorted sizes fall in each power-of-two bucket, skipping the empty ones
+pub fn print_size_histogram(sorted: &[u64]) {
+    let mut bucket_start = 0;
+    let mut bucket_end = 1024;
+    let mut remaining = sorted;
+    while !remaining.is_empty() {
+        let count = remaining.partition_point(|&size| size < bucket_end);
+        if count > 0 {
+            println!("    {} - {}: {}", format_size(bucket_start), format_size(bucket_end), count);
+        }
+        remaining = &remaining[count..];
+        bucket_start = bucket_end;
+        bucket_end *= 2;
+    }
+}
+
+// Reports what's in a dump from its index alone, without decompressing any of the articles: how many pages there
+// are in each namespace, how many chunks they're in and how big those are, and about how long indexing will take,
+// as a quick check of a dump before a long run
+pub fn stats(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let (index_path, articles_path) = locate_dump_files(data_path);
+    let articles_size = dump_size(&articles_path);
+    let index_path = decompress_index(index_path.to_str().unwrap());
+    let file = File::open(&index_path).expect("Unable to open index file");
+    let file_size = file.metadata().expect("Unable to get file metadata").len();
+    let reader = BufReader::new(ProgressReader::new(file, create_progress_bar(file_size, "Reading index")));
+
+    // The index is read here rather than with load_index, which skips the ignored namespaces and holds every title
+    let namespaces = namespace_names(&config().dump_prefix);
+    let mut namespace_counts: HashMap<&str, usize> = HashMap::new();
+    let mut chunk_pages: HashMap<u64, usize> = HashMap::new();
+    let (mut pages, mut ignored, mut max_id) = (0, 0, 0);
+    for line in reader.lines().map_while(Result::ok) {
+        let parts: Vec<&str> = line.splitn(3, ':').collect();
+        if parts.len() != 3 { continue; }
+        let (Ok(position), Ok(id)) = (parts[0].parse::<u64>(), parts[1].parse::<PageId>()) else { continue };
+        let title = decode_html_entities(parts[2]);
+        let namespace = title.split_once(':')
+            .and_then(|(prefix, _)| namespaces.iter().find(|name| name.as_str() == prefix))
+            .map_or("(Main)", String::as_str);
+        *namespace_counts.entry(namespace).or_default() += 1;
+        *chunk_pages.entry(position).or_default() += 1;
+        pages += 1;
+        ignored += is_ignored(&title) as usize;
+        max_id = max_id.max(id);
+    }
+    if pages == 0 {
+        eprintln!("Error: No pages in {}", index_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+
+    let file_name = articles_path.file_name().unwrap().to_str().unwrap();
+    let (kind, bytes_per_second) = if file_name.ends_with(".bz2") {
+        ("bz2", BZ2_BYTES_PER_SECOND)
+    } else if file_name.ends_with(".zst") {
+        ("zstd", ZSTD_BYTES_PER_SECOND)
+    } else {
+        ("uncompressed XML", XML_BYTES_PER_SECOND)
+    };
+    println!("Dump: {} ({}, {})", file_name, kind, format_size(articles_size));
+    println!("Index: {} ({})", index_path.to_str().unwrap(), format_size(file_size));
+
+    println!("\nPages: {} ({} in ignored namespaces, which commands skip)", pages, ignored);
+    let mut namespace_counts: Vec<(&str, usize)> = namespace_counts.into_iter().collect();
+    namespace_counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (namespace, count) in namespace_counts {
+        let note = if namespace != "(Main)" && is_ignored(&format!("{}:", namespace)) { ", ignored" } else { "" };
+        println!("  {}: {} ({:.1}%{})", namespace, count, 100.0 * count as f64 / pages as f64, note);
+    }
+    println!("Highest page ID: {}", max_id);
+
+    // Chunks run from their start position to the next one's, or to the end of the file for the last
+    let mut positions: Vec<u64> = chunk_pages.keys().copied().collect();
+    positions.sort_unstable();
+    let mut sizes: Vec<u64> = positions.windows(2).map(|pair| pair[1] - pair[0])
+        .chain(std::iter::once(articles_size.saturating_sub(positions[positions.len() - 1])))
+        .collect();
+    sizes.sort_unstable();
+    let mut pages_per_chunk: Vec<u64> = chunk_pages.values().map(|&count| count as u64).collect();
+    pages_per_chunk.sort_unstable();
+    let mean = sizes.iter().sum::<u64>() / sizes.len() as u64;
+    println!("\nChunks: {}", sizes.len());
+    println!("  Pages per chunk: min {}, median {}, max {}", pages_per_chunk[0], percentile(&pages_per_chunk, 0.5), pages_per_chunk[pages_per_chunk.len() - 1]);
+    println!("  Size: min {}, median {}, mean {}, p90 {}, p99 {}, max {}",
+        format_size(sizes[0]), format_size(percentile(&sizes, 0.5)), format_size(mean), format_size(percentile(&sizes, 0.9)),
+        format_size(percentile(&sizes, 0.99)), format_size(sizes[sizes.len() - 1]));
+    println!("  Size distribution:");
+    print_size_histogram(&sizes);
+
+    let threads = config().threads;
+    let estimate = Duration::from_secs_f64(articles_size as f64 / (bytes_per_second * threads as f64));
+    println!("\nEstimated index time with {} threads: about {} (roughly {}/s per thread for a {} dump)",
+        threads, format_duration(estimate), format_size(bytes_per_second as u64), kind);
+    if kind == "bz2" {
+        println!("Running recompress first makes every later read of the dump several times faster");
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs::{File, create_dir_all};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use crate::helpers::create_progress_bar;
+
+// Where dumps are read from and outputs are uploaded to, named by a location that's either a local directory, an
+// http(s):// URL or an s3://bucket/prefix or gs://bucket/prefix bucket URL. Objects within a location are named by
+// relative paths.
+//
+// Buckets are accessed through the S3 API, which Google Cloud Storage also serves with HMAC keys, so gs:// URLs work
+// the same way. Credentials come from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN (GCS_ACCESS_KEY_ID
+// and GCS_SECRET_ACCESS_KEY for gs://), the region from AWS_REGION (us-east-1 by default), and AWS_ENDPOINT_URL points
+// s3:// at another S3-compatible service like MinIO or R2. Without credentials, requests are unsigned, which is enough
+// for public buckets.
+pub trait Storage: Send + Sync {
+    fn size(&self, name: &str) -> Result<u64, String>;
+    fn read_range(&self, name: &str, start: u64, end: u64) -> Result<Vec<u8>, String>;
+    fn reader(&self, name: &str) -> Result<(Box<dyn Read + Send>, Option<u64>), String>;  // (reader, length if known)
+    fn upload(&self, path: &Path, name: &str) -> Result<(), String>;
+    // The URL and headers of a GET for an object, or a range of it, for sending with a client other than ureq. Local
+    // storage has none.
+    fn http_request(&self, _name: &str, _range: Option<(u64, u64)>) -> Option<HttpRequest> { None }
+}
+
+pub struct HttpRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+fn range_header(start: u64, end: u64) -> String {
+    format!("bytes={}-{}", start, end - 1)
+}
+
+// Objects larger than this are uploaded to buckets in parts of this size, so memory use stays bounded and files can
+// be larger than the 5 GB limit on a single upload
+const PART_SIZE: usize = 64 * 1024 * 1024;
+pub const RETRIES: u32 = 3;
+
+pub fn is_url(location: &str) -> bool {
+    ["http://", "https://", "s3://", "gs://"].iter().any(|scheme| location.starts_with(scheme))
+}
+
+pub fn open(location: &str) -> Box<dyn Storage> {
+    if let Some(rest) = location.strip_prefix("s3://") {
+        Box::new(BucketStorage::new(rest, false))
+    } else if let Some(rest) = location.strip_prefix("gs://") {
+        Box::new(BucketStorage::new(rest, true))
+    } else if location.starts_with("http://") || location.starts_with("https://") {
+        Box::new(HttpStorage { base_url: location.trim_end_matches('/').to_string() })
+    } else {
+        Box::new(LocalStorage { root: PathBuf::from(location) })
+    }
+}
+
+// Splits the location of a single object, like a dump file, into its storage and name
+pub fn open_object(location: &str) -> (Box<dyn Storage>, String) {
+    let (parent, name) = location.rsplit_once('/').unwrap_or((".", location));
+    (open(parent), name.to_string())
+}
+
+// Retries a request a few times with backoff, since a long run against a remote dump makes many of them
+pub fn retry<T>(description: &str, mut request: impl FnMut() -> Result<T, String>) -> Result<T, String> {
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Err(err) if attempt < RETRIES => {
+                attempt += 1;
+                warn!("{} failed, retrying: {}", description, err);
+                std::thread::sleep(Duration::from_secs(1 << attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+// Uploads the named files in `dir` that exist to the storage at `location`
+pub fn upload_files(location: &str, dir: &Path, names: &[&str]) {
+    let storage = open(location);
+    let names: Vec<&str> = names.iter().copied().filter(|name| dir.join(name).is_file()).collect();
+    let progress_bar = create_progress_bar(names.len() as u64, "Uploading outputs");
+    for name in &names {
+        storage.upload(&dir.join(name), name).unwrap_or_else(|err| {
+            eprintln!("Error: Failed to upload {} to {}: {}", name, location, err);
+            std::process::exit(1);
+        });
+        progress_bar.inc(1);
+    }
+    progress_bar.finish_and_clear();
+    info!("Uploaded {} files to {}", names.len(), location);
+}
+
+// Uploads everything in `dir`, including subdirectories, to the storage at `location`
+pub fn upload_dir(location: &str, dir: &Path) {
+    fn walk(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).expect("Failed to read output directory").map_while(Result::ok) {
+            let name = format!("{}{}", prefix, entry.file_name().to_str().unwrap());
+            if entry.path().is_dir() {
+                walk(&entry.path(), &format!("{}/", name), names);
+            } else {
+                names.push(name);
+            }
+        }
+    }
+    let mut names = Vec::new();
+    walk(dir, "", &mut names);
+    names.sort_unstable();
+    upload_files(location, dir, &names.iter().map(String::as_str).collect::<Vec<_>>());
+}
+
+fn read_part(path: &Path, start: u64, length: usize) -> Result<Vec<u8>, String> {
+    let mut file = File::open(path).map_err(|err| err.to_string())?;
+    file.seek(SeekFrom::Start(start)).map_err(|err| err.to_string())?;
+    let mut buffer = Vec::with_capacity(length);
+    file.take(length as u64).read_to_end(&mut buffer).map_err(|err| err.to_string())?;
+    Ok(buffer)
+}
+
+struct LocalStorage {
+    root: PathBuf,
+}
+
+impl Storage for LocalStorage {
+    fn size(&self, name: &str) -> Result<u64, String> {
+        self.root.join(name).metadata().map(|metadata| metadata.len()).map_err(|err| err.to_string())
+    }
+
+    fn read_range(&self, name: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let mut buffer = vec![0u8; (end - start) as usize];
+        let mut file = File::open(self.root.join(name)).map_err(|err| err.to_string())?;
+        file.seek(SeekFrom::Start(start)).map_err(|err| err.to_string())?;
+        file.read_exact(&mut buffer).map_err(|err| err.to_string())?;
+        Ok(buffer)
+    }
+
+    fn reader(&self, name: &str) -> Result<(Box<dyn Read + Send>, Option<u64>), String> {
+        let file = File::open(self.root.join(name)).map_err(|err| err.to_string())?;
+        let length = file.metadata().ok().map(|metadata| metadata.len());
+        Ok((Box::new(file), length))
+    }
+
+    fn upload(&self, path: &Path, name: &str) -> Result<(), String> {
+        let destination = self.root.join(name);
+        create_dir_all(destination.parent().unwrap()).map_err(|err| err.to_string())?;
+        std::fs::copy(path, destination).map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+fn content_length(response: &ureq::http::Response<ureq::Body>) -> Option<u64> {
+    response.headers().get("Content-Length").and_then(|length| length.to_str().ok()?.parse().ok())
+}
+
+// Reads a range response, checking that the server honored the range rather than sending the whole file
+fn read_range_response(mut response: ureq::http::Response<ureq::Body>, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    if response.status() != 206 {
+        return Err(format!("expected a partial response but got {}", response.status()));
+    }
+    let buffer = response.body_mut().with_config().limit(end - start + 1).read_to_vec().map_err(|err| err.to_string())?;
+    if buffer.len() as u64 != end - start {
+        return Err(format!("got {} of {} bytes", buffer.len(), end - start));
+    }
+    Ok(buffer)
+}
+
+// A read-only web server, like the Wikimedia dump mirrors
+struct HttpStorage {
+    base_url: String,
+}
+
+impl Storage for HttpStorage {
+    fn size(&self, name: &str) -> Result<u64, String> {
+        let response = ureq::head(&format!("{}/{}", self.base_url, name)).call().map_err(|err| err.to_string())?;
+        content_length(&response).ok_or_else(|| "the server didn't report the file size".to_string())
+    }
+
+    fn read_range(&self, name: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let response = ureq::get(&format!("{}/{}", self.base_url, name))
+            .header("Range", &range_header(start, end))
+            .call()
+            .map_err(|err| err.to_string())?;
+        read_range_response(response, start, end)
+    }
+
+    fn reader(&self, name: &str) -> Result<(Box<dyn Read + Send>, Option<u64>), String> {
+        let response = ureq::get(&format!("{}/{}", self.base_url, name)).call().map_err(|err| err.to_string())?;
+        let length = content_length(&response);
+        Ok((Box::new(response.into_body().into_reader()), length))
+    }
+
+    fn upload(&self, _path: &Path, _name: &str) -> Result<(), String> {
+        Err(format!("{} is read-only, upload to an s3:// or gs:// bucket instead", self.base_url))
+    }
+
+    fn http_request(&self, name: &str, range: Option<(u64, u64)>) -> Option<HttpRequest> {
+        let headers = range.map(|(start, end)| ("Range".to_string(), range_header(start, end))).into_iter().collect();
+        Some(HttpRequest { url: format!("{}/{}", self.base_url, name), headers })
+    }
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+// An S3-compatible bucket, addressed with path-style URLs so bucket names with dots work over HTTPS
+struct BucketStorage {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    credentials: Option<Credentials>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Percent-encodes everything but unreserved characters, and slashes too if `keep_slashes`, as SigV4 requires
+fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Returns the current UTC date as YYYYMMDD and time as YYYYMMDDTHHMMSSZ
+fn amz_date() -> (String, String) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Converts days since 1970-01-01 to a civil date, from Howard Hinnant's date algorithms
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let date_time = format!("{}T{:02}{:02}{:02}Z", date, time / 3600, time % 3600 / 60, time % 60);
+    (date, date_time)
+}
+
+// Extracts the text of the first <tag>...</tag> in an XML response
+fn xml_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
+
+impl BucketStorage {
+    fn new(location: &str, google: bool) -> Self {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let (key_var, secret_var) = if google { ("GCS_ACCESS_KEY_ID", "GCS_SECRET_ACCESS_KEY") } else { ("AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY") };
+        let credentials = env(key_var).zip(env(secret_var)).map(|(access_key, secret_key)| Credentials {
+            access_key,
+            secret_key,
+            session_token: if google { None } else { env("AWS_SESSION_TOKEN") },
+        });
+        let region = if google { "auto".to_string() } else { env("AWS_REGION").or_else(|| env("AWS_DEFAULT_REGION")).unwrap_or_else(|| "us-east-1".to_string()) };
+        let endpoint = if google {
+            "https://storage.googleapis.com".to_string()
+        } else {
+            env("AWS_ENDPOINT_URL").unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+        };
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint.split_once("://").map_or(endpoint.as_str(), |(_, host)| host).to_string();
+        BucketStorage { endpoint, host, bucket: bucket.to_string(), prefix: prefix.trim_matches('/').to_string(), region, credentials }
+    }
+
+    fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() { name.to_string() } else { format!("{}/{}", self.prefix, name) }
+    }
+
+    // Returns the URL and headers of a request signed with AWS Signature Version 4
+    fn sign<'a>(&self, method: &str, name: &str, query: &[(&str, &str)], headers: &[(&'a str, String)], body: &[u8]) -> (String, Vec<(&'a str, String)>) {
+        let path = format!("/{}/{}", uri_encode(&self.bucket, false), uri_encode(&self.key(name), true));
+        let mut query: Vec<(String, String)> = query.iter().map(|(key, value)| (uri_encode(key, false), uri_encode(value, false))).collect();
+        query.sort();
+        let query = query.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join("&");
+        let url = if query.is_empty() { format!("{}{}", self.endpoint, path) } else { format!("{}{}?{}", self.endpoint, path, query) };
+
+        let mut headers = headers.to_vec();
+        if let Some(credentials) = &self.credentials {
+            let (date, date_time) = amz_date();
+            let payload_hash = sha256_hex(body);
+            let mut signed_headers = vec![("host", self.host.clone()), ("x-amz-content-sha256", payload_hash.clone()), ("x-amz-date", date_time.clone())];
+            if let Some(token) = &credentials.session_token {
+                signed_headers.push(("x-amz-security-token", token.clone()));
+            }
+            let header_names = signed_headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+            let canonical_headers: String = signed_headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+            let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, header_names, payload_hash);
+            let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+            let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", date_time, scope, sha256_hex(canonical_request.as_bytes()));
+            let mut key = format!("AWS4{}", credentials.secret_key).into_bytes();
+            for part in [date.as_str(), self.region.as_str(), "s3", "aws4_request"] {
+                key = hmac_sha256(&key, part);
+            }
+            let signature = hex(&hmac_sha256(&key, &string_to_sign));
+            headers.push(("Authorization", format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", credentials.access_key, scope, header_names, signature)));
+            headers.extend(signed_headers.into_iter().filter(|(name, _)| *name != "host"));
+        }
+        (url, headers)
+    }
+
+    // Sends a signed request. Error statuses come back as responses so the error code in the body can be reported.
+    fn request(&self, method: &str, name: &str, query: &[(&str, &str)], headers: &[(&str, String)], body: &[u8]) -> Result<ureq::http::Response<ureq::Body>, String> {
+        let (url, headers) = self.sign(method, name, query, headers, body);
+        let result = match method {
+            "GET" | "HEAD" | "DELETE" => {
+                let mut request = match method { "GET" => ureq::get(&url), "HEAD" => ureq::head(&url), _ => ureq::delete(&url) };
+                for (name, value) in &headers {
+                    request = request.header(*name, value);
+                }
+                request.config().http_status_as_error(false).build().call()
+            }
+            _ => {
+                let mut request = if method == "PUT" { ureq::put(&url) } else { ureq::post(&url) };
+                for (name, value) in &headers {
+                    request = request.header(*name, value);
+                }
+                request.config().http_status_as_error(false).build().send(body)
+            }
+        };
+        let mut response = result.map_err(|err| err.to_string())?;
+        if response.status().as_u16() >= 400 {
+            let body = response.body_mut().read_to_string().unwrap_or_default();
+            let code = xml_value(&body, "Code").unwrap_or("no details");
+            return Err(format!("{} {} returned {} ({})", method, url, response.status(), code));
+        }
+        Ok(response)
+    }
+
+    fn upload_parts(&self, path: &Path, name: &str, size: u64) -> Result<(), String> {
+        let mut response = self.request("POST", name, &[("uploads", "")], &[], &[])?;
+        let body = response.body_mut().read_to_string().map_err(|err| err.to_string())?;
+        let upload_id = xml_value(&body, "UploadId").ok_or("no upload ID in the response")?.to_string();
+        let mut parts = String::new();
+        let result = (0..size.div_ceil(PART_SIZE as u64)).try_for_each(|part| {
+            let data = read_part(path, part * PART_SIZE as u64, PART_SIZE)?;
+            let part_number = (part + 1).to_string();
+            let response = retry(&format!("Uploading part {} of {}", part_number, name), || {
+                self.request("PUT", name, &[("partNumber", &part_number), ("uploadId", &upload_id)], &[], &data)
+            })?;
+            let etag = response.headers().get("ETag").and_then(|etag| etag.to_str().ok()).ok_or("no ETag for an uploaded part")?;
+            parts.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", part_number, etag));
+            Ok::<_, String>(())
+        });
+        if let Err(err) = result {
+            // Abandoned parts are billed until the upload is aborted
+            let _ = self.request("DELETE", name, &[("uploadId", &upload_id)], &[], &[]);
+            return Err(err);
+        }
+        let complete = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        let mut response = self.request("POST", name, &[("uploadId", &upload_id)], &[], complete.as_bytes())?;
+        // A failed completion can still come back as 200 with an error in the body
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        match xml_value(&body, "Code") {
+            Some(code) => Err(format!("completing the upload failed ({})", code)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Storage for BucketStorage {
+    fn size(&self, name: &str) -> Result<u64, String> {
+        let response = self.request("HEAD", name, &[], &[], &[])?;
+        content_length(&response).ok_or_else(|| "the server didn't report the object size".to_string())
+    }
+
+    fn read_range(&self, name: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let response = self.request("GET", name, &[], &[("Range", range_header(start, end))], &[])?;
+        read_range_response(response, start, end)
+    }
+
+    fn reader(&self, name: &str) -> Result<(Box<dyn Read + Send>, Option<u64>), String> {
+        let response = self.request("GET", name, &[], &[], &[])?;
+        let length = content_length(&response);
+        Ok((Box::new(response.into_body().into_reader()), length))
+    }
+
+    fn upload(&self, path: &Path, name: &str) -> Result<(), String> {
+        let size = path.metadata().map_err(|err| err.to_string())?.len();
+        if size > PART_SIZE as u64 {
+            return self.upload_parts(path, name, size);
+        }
+        let data = read_part(path, 0, size as usize)?;
+        retry(&format!("Uploading {}", name), || self.request("PUT", name, &[], &[], &data)).map(|_| ())
+    }
+
+    fn http_request(&self, name: &str, range: Option<(u64, u64)>) -> Option<HttpRequest> {
+        let range: Vec<(&str, String)> = range.map(|(start, end)| ("Range", range_header(start, end))).into_iter().collect();
+        let (url, headers) = self.sign("GET", name, &[], &range, &[]);
+        Some(HttpRequest { url, headers: headers.into_iter().map(|(name, value)| (name.to_string(), value)).collect() })
+    }
+}
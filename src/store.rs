@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use crate::helpers::{INTERRUPTED, Page, PageId};
+use crate::pipeline::{PageSink, SinkChunk};
+
+pub const STORE_DIR: &str = "article-store";
+
+// Texts are small enough that a fast level compresses them nearly as well as a slow one
+const COMPRESSION_LEVEL: i32 = 3;
+// Key in the default tree for the name of the dump the store was last written from
+const SOURCE_KEY: &[u8] = b"source";
+
+// Articles in an embedded key-value store, so that reading one is a couple of lookups instead of decompressing the
+// whole chunk it's in. Texts are content-addressed: each is stored compressed under the SHA-256 of the text, and an
+// article's ID maps to that hash and its title, so writing a newer dump into the same store only adds the texts that
+// changed. Titles map to IDs by their lowercase form, like ArticleLookup.
+pub struct ArticleStore {
+    db: sled::Db,
+    texts: sled::Tree,  // hash -> compressed text
+    ids: sled::Tree,  // id -> hash, title
+    titles: sled::Tree,  // lowercase title -> id
+}
+
+impl ArticleStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let db = sled::open(path).map_err(|err| err.to_string())?;
+        let tree = |name: &str| db.open_tree(name).map_err(|err| err.to_string());
+        Ok(ArticleStore { texts: tree("texts")?, ids: tree("ids")?, titles: tree("titles")?, db })
+    }
+
+    // Opens the store in `data_path` if there is one that was written from the dump at `articles_path`. A store written
+    // from another dump could have older texts for some articles, so it's left alone.
+    pub fn open_for_dump(data_path: &Path, articles_path: &Path) -> Option<Self> {
+        let path = data_path.join(STORE_DIR);
+        if !path.exists() { return None; }
+        let store = ArticleStore::open(&path).map_err(|err| warn!("Failed to open {}, reading articles from the dump: {}", path.display(), err)).ok()?;
+        let source = store.source();
+        let dump_name = file_name(articles_path);
+        if source.as_deref() != Some(dump_name.as_str()) {
+            warn!("{} was written from {}, not {}, so articles are read from the dump", path.display(), source.as_deref().unwrap_or("an unfinished run"), dump_name);
+            return None;
+        }
+        Some(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    // The name of the dump the store was last fully written from
+    pub fn source(&self) -> Option<String> {
+        let source = self.db.get(SOURCE_KEY).ok()??;
+        Some(String::from_utf8_lossy(&source).into_owned())
+    }
+
+    pub fn get(&self, id: PageId) -> Option<String> {
+        let entry = self.ids.get(id.to_be_bytes()).ok()??;
+        let compressed = self.texts.get(&entry[..32]).ok()??;
+        let text = zstd::decode_all(&compressed[..]).expect("Failed to decompress stored article");
+        Some(String::from_utf8(text).expect("Stored article isn't UTF-8"))
+    }
+
+    pub fn title(&self, id: PageId) -> Option<String> {
+        let entry = self.ids.get(id.to_be_bytes()).ok()??;
+        Some(String::from_utf8_lossy(&entry[32..]).into_owned())
+    }
+
+    pub fn find(&self, title: &str) -> Option<PageId> {
+        let id = self.titles.get(title.trim().to_lowercase()).ok()??;
+        Some(PageId::from_be_bytes(id.as_ref().try_into().ok()?))
+    }
+
+    // Adds an article, compressing its text only if no other article already has the same text
+    fn insert(&self, id: PageId, page: &Page) {
+        let hash = Sha256::digest(page.text.as_bytes());
+        if !self.texts.contains_key(hash).expect("Failed to read article store") {
+            let compressed = zstd::bulk::compress(page.text.as_bytes(), COMPRESSION_LEVEL).expect("Failed to compress article");
+            self.texts.insert(hash, compressed).expect("Failed to write article store");
+        }
+        let mut entry = hash.to_vec();
+        entry.extend_from_slice(page.title.as_bytes());
+        self.ids.insert(id.to_be_bytes(), entry).expect("Failed to write article store");
+        self.titles.insert(page.title.to_lowercase(), &id.to_be_bytes()).expect("Failed to write article store");
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.to_str().unwrap().rsplit('/').next().unwrap().to_string()
+}
+
+// Writes every page of a pass into the store in `data_path`. The store takes concurrent writes, so the articles go in
+// from the worker threads as each chunk is parsed, and the dump is only recorded as the store's source once a run
+// gets through all of it.
+pub struct StoreSink {
+    store: ArticleStore,
+    dump_name: String,
+    articles: AtomicUsize,
+}
+
+impl StoreSink {
+    pub fn create(data_path: &Path, articles_path: &Path) -> Self {
+        let path = data_path.join(STORE_DIR);
+        let store = ArticleStore::open(&path).unwrap_or_else(|err| {
+            eprintln!("Error: Failed to open {}: {}", path.display(), err);
+            std::process::exit(1);
+        });
+        // Until this run finishes, the store is a mix of two dumps
+        store.db.remove(SOURCE_KEY).expect("Failed to write article store");
+        StoreSink { store, dump_name: file_name(articles_path), articles: AtomicUsize::new(0) }
+    }
+
+    pub fn articles(&self) -> usize {
+        self.articles.load(Ordering::SeqCst)
+    }
+}
+
+impl PageSink for StoreSink {
+    fn extract(&self, _: usize, pages: &HashMap<PageId, Page>) -> SinkChunk {
+        for (id, page) in pages {
+            self.store.insert(*id, page);
+        }
+        self.articles.fetch_add(pages.len(), Ordering::SeqCst);
+        Box::new(())
+    }
+
+    fn write(&self, _: SinkChunk) {}
+
+    fn finish(&self) {
+        if !INTERRUPTED.load(Ordering::SeqCst) {
+            self.store.db.insert(SOURCE_KEY, self.dump_name.as_bytes()).expect("Failed to write article store");
+        }
+        self.store.db.flush().expect("Failed to flush article store");
+    }
+}
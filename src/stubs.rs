@@ -0,0 +1,140 @@
+use std::io::{BufReader, Write};
+use std::path::Path;
+use serde_json::json;
+use xml::reader::{EventReader, XmlEvent};
+use crate::config::config;
+use crate::helpers::{PageId, open_dump_stream};
+use crate::namespaces::is_ignored;
+use crate::parse::exit_on_write_error;
+
+// The stub and abstract dumps are a few GB instead of the 20+ GB of the articles dump, since they leave out article
+// text. The stub dump has every page's metadata and latest revision, and the abstract dump has the lead text of every
+// article, though not its ID.
+pub const STUB_DUMP: &str = "stub-meta-current.xml.gz";
+pub const ABSTRACT_DUMP: &str = "abstract.xml.gz";
+
+pub struct StubPage {
+    pub id: PageId,
+    pub title: String,
+    pub namespace: u32,
+    pub redirect: Option<String>,  // target title
+    pub revision_id: u64,
+    pub timestamp: String,
+    pub length: u64,  // bytes of text in the latest revision
+}
+
+pub struct Abstract {
+    pub title: String,
+    pub url: String,
+    pub text: String,
+}
+
+pub fn get_dump_path(data_path: &Path, name: &str) -> std::path::PathBuf {
+    data_path.join(format!("{}-{}", config().dump_prefix, name))
+}
+
+fn parse_error(file_path: &Path, err: impl std::fmt::Display) -> ! {
+    eprintln!("Error: Failed to parse {}: {}", file_path.to_str().unwrap(), err);
+    std::process::exit(1);
+}
+
+// Streams every page in a stub-meta-current dump, leaving out ignored namespaces
+pub fn for_each_stub<F: FnMut(&StubPage)>(file_path: &Path, mut visit: F) {
+    let parser = EventReader::new(BufReader::new(open_dump_stream(file_path, "Reading stubs")));
+    let mut path: Vec<String> = Vec::new();
+    let mut page = StubPage { id: 0, title: String::new(), namespace: 0, redirect: None, revision_id: 0, timestamp: String::new(), length: 0 };
+
+    for event in parser {
+        match event {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                let attribute = |key: &str| attributes.iter().find(|attribute| attribute.name.local_name == key).map(|attribute| attribute.value.clone());
+                match name.local_name.as_str() {
+                    "page" => page = StubPage { id: 0, title: String::new(), namespace: 0, redirect: None, revision_id: 0, timestamp: String::new(), length: 0 },
+                    "redirect" => page.redirect = Some(attribute("title").unwrap_or_default()),
+                    "text" => page.length = attribute("bytes").and_then(|bytes| bytes.parse().ok()).unwrap_or(0),
+                    _ => {}
+                }
+                path.push(name.local_name);
+            }
+            Ok(XmlEvent::EndElement { name, .. }) => {
+                path.pop();
+                if name.local_name == "page" && !is_ignored(&page.title) {
+                    visit(&page);
+                }
+            }
+            Ok(XmlEvent::Characters(text)) => {
+                let parent = path.len().checked_sub(2).map(|i| path[i].as_str());
+                match (parent, path.last().map(String::as_str)) {
+                    (Some("page"), Some("title")) => page.title.push_str(&text),
+                    (Some("page"), Some("ns")) => page.namespace = text.parse().unwrap_or(0),
+                    (Some("page"), Some("id")) => page.id = text.parse().unwrap_or(0),
+                    (Some("revision"), Some("id")) => page.revision_id = text.parse().unwrap_or(0),
+                    (Some("revision"), Some("timestamp")) => page.timestamp.push_str(&text),
+                    _ => {}
+                }
+            }
+            Err(err) => parse_error(file_path, err),
+            _ => {}
+        }
+    }
+}
+
+// Streams every article in an abstract dump. Its titles carry a "Wikipedia: " prefix, which is removed.
+pub fn for_each_abstract<F: FnMut(&Abstract)>(file_path: &Path, mut visit: F) {
+    let parser = EventReader::new(BufReader::new(open_dump_stream(file_path, "Reading abstracts")));
+    let mut field = None;
+    let mut doc = Abstract { title: String::new(), url: String::new(), text: String::new() };
+
+    for event in parser {
+        match event {
+            Ok(XmlEvent::StartElement { name, .. }) => match name.local_name.as_str() {
+                "doc" => doc = Abstract { title: String::new(), url: String::new(), text: String::new() },
+                "title" | "url" | "abstract" => field = Some(name.local_name),
+                // Each doc ends with its sublinks, whose titles and URLs aren't the article's
+                _ => field = None,
+            },
+            Ok(XmlEvent::EndElement { name, .. }) => {
+                field = None;
+                if name.local_name == "doc" {
+                    if let Some((_, title)) = doc.title.split_once(": ") {
+                        doc.title = title.to_string();
+                    }
+                    if !is_ignored(&doc.title) {
+                        visit(&doc);
+                    }
+                }
+            }
+            Ok(XmlEvent::Characters(text)) => match field.as_deref() {
+                Some("title") => doc.title.push_str(&text),
+                Some("url") => doc.url.push_str(&text),
+                Some("abstract") => doc.text.push_str(&text),
+                _ => {}
+            },
+            Err(err) => parse_error(file_path, err),
+            _ => {}
+        }
+    }
+}
+
+// Writes the pages of a stub or abstract dump as NDJSON, like the parse command does for the articles dump. Stub
+// records have the same id, title, namespace and redirect fields plus the redirect target and latest revision, but no
+// text. Abstract records have title, url and the abstract as text. Returns the number of records.
+pub fn write_ndjson(data_path: &Path, source: &str, output: &mut dyn Write) -> usize {
+    let mut pages = 0;
+    match source {
+        "stub" => for_each_stub(&get_dump_path(data_path, STUB_DUMP), |page| {
+            let record = json!({
+                "id": page.id, "title": page.title, "namespace": page.namespace, "redirect": page.redirect.is_some(),
+                "redirect_target": page.redirect, "revision_id": page.revision_id, "timestamp": page.timestamp, "length": page.length,
+            });
+            writeln!(output, "{}", record).unwrap_or_else(|err| exit_on_write_error(err));
+            pages += 1;
+        }),
+        "abstract" => for_each_abstract(&get_dump_path(data_path, ABSTRACT_DUMP), |doc| {
+            writeln!(output, "{}", json!({ "title": doc.title, "url": doc.url, "text": doc.text })).unwrap_or_else(|err| exit_on_write_error(err));
+            pages += 1;
+        }),
+        _ => unreachable!(),
+    }
+    pages
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::fs::{read_to_string, write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde_json::{Map, Value, json};
+use tracing::info;
+use crate::helpers::{Args, dump_size, hash_file, is_remote};
+use crate::preflight::format_size;
+
+// Asks the kernel to reset the process's peak resident memory, so the next reading covers only what comes after.
+// Only Linux supports this, and only since 4.0.
+fn reset_peak_rss() -> bool {
+    write("/proc/self/clear_refs", "5").is_ok()
+}
+
+// Returns the peak resident memory of the process in bytes, from /proc on Linux and getrusage elsewhere
+fn peak_rss() -> Option<u64> {
+    let status = read_to_string("/proc/self/status").unwrap_or_default();
+    let high_water_mark = status.lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if let Some(kilobytes) = high_water_mark {
+        return Some(kilobytes * 1024);
+    }
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 { return None; }
+    // ru_maxrss is in bytes on macOS and kilobytes everywhere else
+    let max_rss = usage.ru_maxrss as u64;
+    Some(if cfg!(target_os = "macos") { max_rss } else { max_rss * 1024 })
+}
+
+// Records what a run read, how long each stage took and what it produced, and writes it all to
+// run-summary.json so runs against different dumps can be compared and reproduced
+pub struct RunSummary {
+    command: String,
+    started_at: SystemTime,
+    start: Instant,
+    hash_inputs: bool,
+    inputs: Vec<Value>,
+    stages: Vec<Value>,
+    current_stage: Option<(String, Instant)>,
+    counts: Map<String, Value>,
+    // With --memory-stats, the peak RSS so far and whether it's reset at each stage, so each stage gets its own
+    // peak, or only ever grows, so a stage's peak includes the stages before it
+    memory: Option<(u64, bool)>,
+}
+
+impl RunSummary {
+    pub fn new(command: &str, args: &Args) -> Self {
+        RunSummary {
+            command: command.to_string(),
+            started_at: SystemTime::now(),
+            start: Instant::now(),
+            hash_inputs: !args.flag("no-hash"),
+            inputs: Vec::new(),
+            stages: Vec::new(),
+            current_stage: None,
+            counts: Map::new(),
+            memory: args.flag("memory-stats").then_some((0, false)),
+        }
+    }
+
+    // Hashing the multistream dump takes a while, so --no-hash records only the size and modification time
+    pub fn input(&mut self, path: &Path) {
+        if is_remote(path) {
+            self.inputs.push(json!({ "path": path.to_str().unwrap(), "size": dump_size(path) }));
+            return;
+        }
+        let metadata = path.metadata().expect("Unable to get file metadata");
+        let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs());
+        let md5 = self.hash_inputs.then(|| hash_file(path));
+        self.inputs.push(json!({ "path": path.to_str().unwrap(), "size": metadata.len(), "modified": modified, "md5": md5 }));
+    }
+
+    // Ends the current stage, if any, and starts timing the next one
+    pub fn stage(&mut self, name: &str) {
+        self.end_stage();
+        if let Some((_, per_stage)) = self.memory.as_mut() {
+            *per_stage = reset_peak_rss();
+        }
+        self.current_stage = Some((name.to_string(), Instant::now()));
+    }
+
+    fn end_stage(&mut self) {
+        if let Some((name, start)) = self.current_stage.take() {
+            let mut stage = json!({ "name": name, "secs": start.elapsed().as_secs_f64() });
+            if let (Some((peak, _)), Some(stage_peak)) = (self.memory.as_mut(), peak_rss()) {
+                *peak = (*peak).max(stage_peak);
+                stage["peak_rss_bytes"] = json!(stage_peak);
+                info!("Peak memory during {}: {}", name, format_size(stage_peak));
+            }
+            self.stages.push(stage);
+        }
+    }
+
+    pub fn count(&mut self, name: &str, value: usize) {
+        self.counts.insert(name.to_string(), json!(value));
+    }
+
+    pub fn write(mut self, data_path: &Path) {
+        self.end_stage();
+        let mut summary = json!({
+            "command": self.command,
+            "tool_version": env!("CARGO_PKG_VERSION"),
+            "arguments": std::env::args().skip(2).collect::<Vec<_>>(),
+            "started_at": self.started_at.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default(),
+            "total_secs": self.start.elapsed().as_secs_f64(),
+            "inputs": self.inputs,
+            "stages": self.stages,
+            "counts": self.counts,
+        });
+        if let Some((peak, per_stage)) = self.memory {
+            summary["memory"] = json!({ "peak_rss_bytes": peak, "per_stage": per_stage });
+        }
+        let contents = serde_json::to_string_pretty(&summary).expect("Failed to serialize run summary");
+        write(data_path.join("run-summary.json"), contents + "\n").expect("Failed to write run-summary.json");
+    }
+}
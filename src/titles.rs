@@ -0,0 +1,98 @@
+use std::hash::BuildHasher;
+use hashbrown::HashTable;
+use rustc_hash::FxBuildHasher;
+use crate::helpers::PageId;
+
+// Compares an interned title against an already-lowercased one without allocating. Final sigma is
+// folded on both sides, since str::to_lowercase treats it specially but char::to_lowercase doesn't.
+fn eq_lowercase(title: &str, lowercase: &str) -> bool {
+    if title.is_ascii() {
+        return title.eq_ignore_ascii_case(lowercase);
+    }
+    let fold_sigma = |c| if c == 'ς' { 'σ' } else { c };
+    title.chars().flat_map(char::to_lowercase).map(fold_sigma).eq(lowercase.chars().map(fold_sigma))
+}
+
+// All titles interned in a single string arena, with entries sorted by article ID for id -> title
+// lookups and a hash table of entries for case-insensitive title -> id lookups. The hash table holds
+// the entries themselves rather than indices, so each probe only touches the table and the arena.
+// The table is probed once per extracted link, so it uses FxHash rather than the default SipHash: 10M
+// lookups against 2M titles take ~1.4s with FxHash versus ~2.2s with SipHash. End-to-end indexing is
+// dominated by bz2 decompression, so the overall speedup is smaller and mostly shows on many-core machines.
+pub struct TitleTable {
+    arena: String,
+    entries: Vec<Entry>,
+    lookup: HashTable<Entry>,
+    hasher: FxBuildHasher,
+    collisions: Vec<(PageId, PageId)>,  // (kept ID, other ID) for titles that only differ in case
+}
+
+#[derive(Clone, Copy)]
+struct Entry { id: PageId, start: u32, length: u32 }
+
+impl TitleTable {
+    pub fn new<'a>(articles: impl Iterator<Item = &'a (PageId, String)>) -> Self {
+        let mut arena = String::new();
+        let mut entries = Vec::new();
+        for (id, title) in articles {
+            entries.push(Entry { id: *id, start: arena.len() as u32, length: title.len() as u32 });
+            arena.push_str(title);
+        }
+        entries.sort_unstable_by_key(|entry| entry.id);
+
+        // Lookups hash the lowercased query directly, so each title is hashed in its lowercased form
+        let hasher = FxBuildHasher;
+        let mut lookup = HashTable::with_capacity(entries.len());
+        let mut collisions = Vec::new();
+        for &entry in &entries {
+            let lowercase = Self::slice(&arena, entry).to_lowercase();
+            let hash = hasher.hash_one(&lowercase);
+            // Entries go in by ID, so the lowest ID for each title is kept until resolve_title_collisions says otherwise
+            match lookup.find(hash, |&other| eq_lowercase(Self::slice(&arena, other), &lowercase)) {
+                Some(existing) => collisions.push((existing.id, entry.id)),
+                None => { lookup.insert_unique(hash, entry, |&other| hasher.hash_one(Self::slice(&arena, other).to_lowercase())); }
+            }
+        }
+        TitleTable { arena, entries, lookup, hasher, collisions }
+    }
+
+    fn slice(arena: &str, entry: Entry) -> &str {
+        &arena[entry.start as usize..(entry.start + entry.length) as usize]
+    }
+
+    // Number of distinct case-insensitive titles
+    pub fn len(&self) -> usize {
+        self.lookup.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lookup.is_empty()
+    }
+
+    // `lowercase` must already be lowercased with str::to_lowercase, as extracted links are
+    pub fn find(&self, lowercase: &str) -> Option<PageId> {
+        self.lookup
+            .find(self.hasher.hash_one(lowercase), |&entry| eq_lowercase(Self::slice(&self.arena, entry), lowercase))
+            .map(|entry| entry.id)
+    }
+
+    pub fn collisions(&self) -> &[(PageId, PageId)] {
+        &self.collisions
+    }
+
+    // Makes `id` the one its title resolves to
+    pub fn prefer(&mut self, id: PageId) {
+        let index = self.entries.binary_search_by_key(&id, |entry| entry.id).expect("Article ID not found");
+        let entry = self.entries[index];
+        let lowercase = Self::slice(&self.arena, entry).to_lowercase();
+        let arena = &self.arena;
+        if let Some(existing) = self.lookup.find_mut(self.hasher.hash_one(&lowercase), |&other| eq_lowercase(Self::slice(arena, other), &lowercase)) {
+            *existing = entry;
+        }
+    }
+
+    pub fn title(&self, id: PageId) -> Option<&str> {
+        let index = self.entries.binary_search_by_key(&id, |entry| entry.id).ok()?;
+        Some(Self::slice(&self.arena, self.entries[index]))
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::helpers::{Args, PageId, create_progress_bar};
+use crate::links::{LinksVersion, is_partial, parse_record, read_header, read_links_file};
+
+// Finds the next offset after `start` that follows a separator and parses as a valid record. Version 2 records
+// have no separators to search for, so everything after a framing error is unreadable.
+fn resync(buffer: &[u8], start: usize, version: LinksVersion) -> Option<usize> {
+    if version != LinksVersion::V1 { return None; }
+    (start + 1..buffer.len())
+        .filter(|&i| i >= 4 && buffer[i-4..i] == u32::MAX.to_le_bytes())
+        .find(|&i| parse_record(buffer, i, version).is_ok())
+}
+
+pub fn verify(args: &Args) {
+    let links_file_path = match args.positional.first() {
+        Some(links_file_path) => Path::new(links_file_path),
+        None => {
+            eprintln!("Usage: verify <links.bin> [--max-errors N]");
+            std::process::exit(1);
+        }
+    };
+    let max_errors = args.parse_value("max-errors").unwrap_or(20);
+
+    let buffer = read_links_file(links_file_path);
+    let (version, mut i) = read_header(&buffer).unwrap_or_else(|err| {
+        eprintln!("Error: {} is not a links file ({})", links_file_path.to_str().unwrap(), err);
+        std::process::exit(1);
+    });
+    let progress_bar = create_progress_bar(buffer.len() as u64, "Verifying records");
+    let mut errors: Vec<(usize, String)> = Vec::new();
+    let mut skipped_bytes = 0;
+    let mut record_offsets: HashMap<PageId, usize> = HashMap::new();
+    let mut duplicates: Vec<(usize, PageId, usize)> = Vec::new();
+    let mut link_targets: Vec<(usize, Vec<PageId>)> = Vec::new();
+    while i < buffer.len() {
+        match parse_record(&buffer, i, version) {
+            Ok((record, next)) => {
+                if let Some(&first_offset) = record_offsets.get(&record.article_id) {
+                    duplicates.push((i, record.article_id, first_offset));
+                } else {
+                    record_offsets.insert(record.article_id, i);
+                }
+                link_targets.push((i, record.links));
+                i = next;
+            }
+            Err(err) => {
+                let next = resync(&buffer, i, version).unwrap_or(buffer.len());
+                errors.push((i, format!("{} (skipped {} bytes)", err, next - i)));
+                skipped_bytes += next - i;
+                i = next;
+            }
+        }
+        progress_bar.set_position(i as u64);
+    }
+    progress_bar.finish_and_clear();
+
+    // Check that every link target refers to an article that has a record
+    let known_ids: HashSet<&PageId> = record_offsets.keys().collect();
+    let mut dangling_links = 0;
+    let mut dangling_records: Vec<(usize, usize)> = Vec::new();
+    for (offset, links) in &link_targets {
+        let dangling = links.iter().filter(|link| !known_ids.contains(link)).count();
+        if dangling > 0 {
+            dangling_links += dangling;
+            dangling_records.push((*offset, dangling));
+        }
+    }
+
+    // Print the corruption report
+    let version_number = match version { LinksVersion::V1 => 1, LinksVersion::V2 => 2, LinksVersion::V3 => 3, LinksVersion::V4 => 4, LinksVersion::V5 => 5 };
+    println!("Format version: {}", version_number);
+    if is_partial(&buffer) {
+        println!("Partial: yes (written by an interrupted index run)");
+    }
+    println!("Total bytes: {}", buffer.len());
+    println!("Valid records: {}", link_targets.len());
+    println!("Framing errors: {} ({} bytes unreadable)", errors.len(), skipped_bytes);
+    println!("Duplicate article IDs: {}", duplicates.len());
+    println!("Links to unknown article IDs: {} (in {} records)", dangling_links, dangling_records.len());
+
+    if !errors.is_empty() {
+        println!("\nFraming errors:");
+        for (offset, err) in errors.iter().take(max_errors) {
+            println!("  byte {}: {}", offset, err);
+        }
+    }
+    if !duplicates.is_empty() {
+        println!("\nDuplicate article IDs:");
+        for (offset, article_id, first_offset) in duplicates.iter().take(max_errors) {
+            println!("  byte {}: article {} already defined at byte {}", offset, article_id, first_offset);
+        }
+    }
+    if !dangling_records.is_empty() {
+        println!("\nRecords with links to unknown article IDs:");
+        for (offset, dangling) in dangling_records.iter().take(max_errors) {
+            println!("  byte {}: {} unknown link targets", offset, dangling);
+        }
+    }
+
+    if errors.is_empty() && duplicates.is_empty() {
+        println!("\n{} is intact", links_file_path.to_str().unwrap());
+    } else {
+        println!("\n{} is corrupt", links_file_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::fs::read_to_string;
+use serde_json::Value;
+use crate::config::config;
+use crate::helpers::{Args, get_dump_paths, hash_file};
+
+// Parses the `<md5>  <filename>` lines of a Wikimedia md5sums file
+fn load_md5sums(md5sums_path: &Path) -> HashMap<String, (String, Option<u64>)> {
+    let contents = read_to_string(md5sums_path).expect("Unable to read md5sums file");
+    contents.lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .map(|(md5, file_name)| (file_name.trim().to_string(), (md5.to_lowercase(), None)))
+        .collect()
+}
+
+// Collects the md5 and size of every file listed under the jobs of a dumpstatus.json file
+fn load_dumpstatus(dumpstatus_path: &Path) -> HashMap<String, (String, Option<u64>)> {
+    let contents = read_to_string(dumpstatus_path).expect("Unable to read dumpstatus.json");
+    let status: Value = serde_json::from_str(&contents).expect("Failed to parse dumpstatus.json");
+    let mut checksums = HashMap::new();
+    for job in status["jobs"].as_object().into_iter().flat_map(|jobs| jobs.values()) {
+        for (file_name, file) in job["files"].as_object().into_iter().flatten() {
+            if let Some(md5) = file["md5"].as_str() {
+                checksums.insert(file_name.clone(), (md5.to_lowercase(), file["size"].as_u64()));
+            }
+        }
+    }
+    checksums
+}
+
+pub fn verify_dump(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let md5sums_path = args.value("md5sums").map(Path::new).map(Path::to_path_buf)
+        .unwrap_or_else(|| data_path.join(format!("{}-md5sums.txt", config().dump_prefix)));
+    let dumpstatus_path = args.value("dumpstatus").map(Path::new).map(Path::to_path_buf)
+        .unwrap_or_else(|| data_path.join("dumpstatus.json"));
+
+    let mut checksums = HashMap::new();
+    if md5sums_path.exists() {
+        checksums.extend(load_md5sums(&md5sums_path));
+    }
+    if dumpstatus_path.exists() {
+        checksums.extend(load_dumpstatus(&dumpstatus_path));
+    }
+    if checksums.is_empty() {
+        eprintln!("Error: Unable to locate {} or {}", md5sums_path.to_str().unwrap(), dumpstatus_path.to_str().unwrap());
+        std::process::exit(1);
+    }
+
+    let (index_path, articles_path) = get_dump_paths(data_path);
+    let mut mismatches = 0;
+    for file_path in [index_path, articles_path] {
+        let file_name = file_path.file_name().unwrap().to_str().unwrap().to_string();
+        let Some((expected_md5, expected_size)) = checksums.get(&file_name) else {
+            println!("{}: no published checksum", file_name);
+            mismatches += 1;
+            continue;
+        };
+        if !file_path.exists() {
+            println!("{}: MISSING", file_name);
+            mismatches += 1;
+            continue;
+        }
+
+        // Check the size first, since a truncated download is by far the most common failure
+        let size = file_path.metadata().expect("Unable to get file metadata").len();
+        if let Some(expected_size) = expected_size.filter(|&expected_size| expected_size != size) {
+            println!("{}: SIZE MISMATCH (expected {} bytes, found {})", file_name, expected_size, size);
+            mismatches += 1;
+            continue;
+        }
+
+        let md5 = hash_file(&file_path);
+        if md5 == *expected_md5 {
+            println!("{}: OK", file_name);
+        } else {
+            println!("{}: MD5 MISMATCH (expected {}, found {})", file_name, expected_md5, md5);
+            mismatches += 1;
+        }
+    }
+
+    if mismatches > 0 {
+        eprintln!("Error: {} dump files failed verification", mismatches);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,325 @@
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use rustc_hash::FxHashMap;
+use serde_json::{Value, json};
+use threadpool::ThreadPool;
+use tracing::{info, warn};
+use crate::complete::TitleCompleter;
+use crate::config::config;
+use crate::helpers::{Args, PageId};
+use crate::links::PageInfo;
+use crate::serve::{percent_decode, write_body, write_response};
+use crate::split::{LinkStore, load_page_info, load_titles};
+
+const DEFAULT_PORT: u16 = 8081;
+const DEFAULT_MAX_NEIGHBORS: usize = 50;
+const SEARCH_LIMIT: usize = 10;
+const D3_URL: &str = "https://cdn.jsdelivr.net/npm/d3@7";
+
+// The page is a single SVG with D3's force layout. Clicking a node expands it with its links (and backlinks, per the
+// checkboxes), dragging pins it, and double-clicking releases it.
+const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Link graph</title>
+<style>
+body { margin: 0; font-family: sans-serif; overflow: hidden; }
+#panel { position: absolute; top: 0; left: 0; padding: 0.8em; background: rgba(248, 249, 250, 0.9); border: 1px solid #a2a9b1; max-width: 22em; }
+#panel input[type=search] { width: 100%; box-sizing: border-box; padding: 0.3em; }
+#suggestions { list-style: none; margin: 0; padding: 0; }
+#suggestions li { cursor: pointer; padding: 0.1em 0.3em; }
+#suggestions li:hover { background: #eaecf0; }
+#info { margin-top: 0.5em; font-size: 0.9em; color: #54595d; }
+svg { width: 100vw; height: 100vh; }
+line { stroke: #a2a9b1; stroke-opacity: 0.7; }
+circle { stroke: #fff; stroke-width: 1.5px; cursor: pointer; }
+circle.expanded { stroke: #202122; }
+text { font-size: 11px; pointer-events: none; fill: #202122; }
+</style>
+</head>
+<body>
+<svg><defs><marker id="arrow" viewBox="0 -4 8 8" refX="16" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,-4L8,0L0,4" fill="#a2a9b1"></path></marker></defs><g id="view"><g id="links"></g><g id="nodes"></g></g></svg>
+<div id="panel">
+<input id="q" type="search" placeholder="Start from an article" autocomplete="off">
+<ul id="suggestions"></ul>
+<label><input id="show-links" type="checkbox" checked> Links</label>
+<label><input id="show-backlinks" type="checkbox"> Backlinks</label>
+<button id="clear">Clear</button>
+<div id="info">Click a node to expand its neighborhood</div>
+</div>
+<script src="D3_URL"></script>
+<script>
+const nodes = new Map();  // id -> node
+const edges = new Map();  // "source>target" -> edge
+const svg = d3.select('svg');
+const view = d3.select('#view');
+const info = document.getElementById('info');
+const color = d3.scaleOrdinal(d3.schemeTableau10);
+const simulation = d3.forceSimulation()
+  .force('link', d3.forceLink().id(node => node.id).distance(70))
+  .force('charge', d3.forceManyBody().strength(-180))
+  .force('center', d3.forceCenter(innerWidth / 2, innerHeight / 2))
+  .force('collide', d3.forceCollide(12))
+  .on('tick', () => {
+    view.select('#links').selectAll('line')
+      .attr('x1', edge => edge.source.x).attr('y1', edge => edge.source.y)
+      .attr('x2', edge => edge.target.x).attr('y2', edge => edge.target.y);
+    view.select('#nodes').selectAll('g').attr('transform', node => `translate(${node.x},${node.y})`);
+  });
+svg.call(d3.zoom().scaleExtent([0.1, 8]).on('zoom', event => view.attr('transform', event.transform)));
+
+function addNode(article, near, depth) {
+  if (!nodes.has(article.id)) {
+    nodes.set(article.id, { id: article.id, title: article.title, depth, expanded: false,
+      x: near ? near.x + Math.random() * 40 - 20 : innerWidth / 2, y: near ? near.y + Math.random() * 40 - 20 : innerHeight / 2 });
+  }
+  return nodes.get(article.id);
+}
+
+function addEdge(source, target) {
+  const key = source + '>' + target;
+  if (!edges.has(key)) edges.set(key, { source, target });
+}
+
+function render() {
+  // The simulation replaces the IDs edges start with by their nodes
+  const key = edge => (edge.source.id ?? edge.source) + '>' + (edge.target.id ?? edge.target);
+  const link = view.select('#links').selectAll('line').data([...edges.values()], key);
+  link.exit().remove();
+  link.enter().append('line').attr('marker-end', 'url(#arrow)');
+  const node = view.select('#nodes').selectAll('g').data([...nodes.values()], node => node.id);
+  node.exit().remove();
+  const entered = node.enter().append('g')
+    .call(d3.drag()
+      .on('start', (event, node) => { if (!event.active) simulation.alphaTarget(0.3).restart(); node.fx = node.x; node.fy = node.y; })
+      .on('drag', (event, node) => { node.fx = event.x; node.fy = event.y; })
+      .on('end', (event) => { if (!event.active) simulation.alphaTarget(0); }));
+  entered.append('circle').attr('r', 7).on('click', (event, node) => expand(node))
+    .on('dblclick', (event, node) => { node.fx = node.fy = null; event.stopPropagation(); });
+  entered.append('title').text(node => node.title);
+  entered.append('text').attr('x', 10).attr('dy', '0.35em').text(node => node.title);
+  view.select('#nodes').selectAll('circle')
+    .attr('fill', node => color(Math.min(node.depth, 9)))
+    .classed('expanded', node => node.expanded);
+  simulation.nodes([...nodes.values()]);
+  simulation.force('link').links([...edges.values()]);
+  simulation.alpha(0.8).restart();
+}
+
+async function expand(node) {
+  info.textContent = 'Loading ' + node.title + '...';
+  const response = await fetch('neighbors?id=' + node.id);
+  const article = await response.json();
+  if (!response.ok) { info.textContent = article.error; return; }
+  node.expanded = true;
+  if (document.getElementById('show-links').checked) {
+    for (const link of article.links) addEdge(node.id, addNode(link, node, node.depth + 1).id);
+  }
+  if (document.getElementById('show-backlinks').checked) {
+    for (const backlink of article.backlinks) addEdge(addNode(backlink, node, node.depth + 1).id, node.id);
+  }
+  info.textContent = `${article.title}: ${article.link_count} links and ${article.backlink_count} backlinks` +
+    (article.links.length < article.link_count || article.backlinks.length < article.backlink_count ? ', showing the most linked-to' : '');
+  render();
+}
+
+async function start(query) {
+  const response = await fetch('neighbors?title=' + encodeURIComponent(query));
+  const article = await response.json();
+  if (!response.ok) { info.textContent = article.error; return; }
+  nodes.clear();
+  edges.clear();
+  render();
+  expand(addNode(article, null, 0));
+  history.replaceState(null, '', '?title=' + encodeURIComponent(article.title));
+}
+
+const input = document.getElementById('q');
+const suggestions = document.getElementById('suggestions');
+input.addEventListener('input', async () => {
+  const query = input.value.trim();
+  suggestions.replaceChildren();
+  if (!query) return;
+  const results = await (await fetch('search?q=' + encodeURIComponent(query))).json();
+  if (input.value.trim() !== query) return;
+  for (const result of results) {
+    const item = document.createElement('li');
+    item.textContent = result.title;
+    item.addEventListener('click', () => { suggestions.replaceChildren(); input.value = result.title; start(result.title); });
+    suggestions.appendChild(item);
+  }
+});
+input.addEventListener('keydown', event => { if (event.key === 'Enter') { suggestions.replaceChildren(); start(input.value); } });
+document.getElementById('clear').addEventListener('click', () => { nodes.clear(); edges.clear(); render(); });
+const initial = new URLSearchParams(location.search).get('title') || INITIAL_TITLE;
+if (initial) start(initial);
+</script>
+</body>
+</html>
+"##;
+
+struct Graph {
+    links: LinkStore,
+    titles: FxHashMap<PageId, String>,
+    pages: FxHashMap<PageId, PageInfo>,
+    completer: Option<TitleCompleter>,
+    max_neighbors: usize,
+}
+
+impl Graph {
+    fn find(&self, title: &str) -> Option<PageId> {
+        match &self.completer {
+            Some(completer) => completer.find(title),
+            None => {
+                let title = title.trim().to_lowercase();
+                self.titles.iter().filter(|(_, other)| other.to_lowercase() == title).map(|(id, _)| *id).min()
+            }
+        }
+    }
+
+    // Redirects stand in for the article they point to, so the graph doesn't fill up with them
+    fn resolve(&self, id: PageId) -> PageId {
+        match self.pages.get(&id) {
+            Some(info) if info.redirect => self.links.links(id).ok().and_then(|links| links.first().copied()).unwrap_or(id),
+            _ => id,
+        }
+    }
+
+    // The distinct articles among `ids` after following redirects, the ones with the most backlinks first, along
+    // with how many there were in all
+    fn neighbors(&self, id: PageId, ids: &[PageId]) -> Result<(Vec<Value>, usize), String> {
+        let mut neighbors: Vec<PageId> = ids.iter().map(|&neighbor| self.resolve(neighbor)).filter(|&neighbor| neighbor != id).collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        let count = neighbors.len();
+        let backlink_counts: Vec<usize> = neighbors.iter().map(|&neighbor| self.links.backlinks(neighbor).map(<[PageId]>::len)).collect::<Result<_, _>>()?;
+        let mut ranked: Vec<(usize, PageId)> = backlink_counts.into_iter().zip(neighbors).collect();
+        ranked.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        let neighbors = ranked.into_iter().take(self.max_neighbors)
+            .map(|(_, neighbor)| json!({ "id": neighbor, "title": self.titles.get(&neighbor) }))
+            .collect();
+        Ok((neighbors, count))
+    }
+
+    fn article(&self, id: PageId) -> Result<Value, String> {
+        let id = self.resolve(id);
+        let (links, link_count) = self.neighbors(id, &self.links.links(id)?)?;
+        let (backlinks, backlink_count) = self.neighbors(id, self.links.backlinks(id)?)?;
+        Ok(json!({
+            "id": id, "title": self.titles.get(&id),
+            "links": links, "link_count": link_count,
+            "backlinks": backlinks, "backlink_count": backlink_count,
+        }))
+    }
+
+    fn search(&self, query: &str) -> Vec<Value> {
+        let matches: Vec<PageId> = match &self.completer {
+            Some(completer) => completer.complete(query, 0, SEARCH_LIMIT).unwrap_or_default().into_iter().map(|(_, id)| id).collect(),
+            None => {
+                let query = query.trim().to_lowercase();
+                let mut matches: Vec<(&String, PageId)> = self.titles.iter().filter(|(_, title)| title.to_lowercase().starts_with(&query)).map(|(id, title)| (title, *id)).collect();
+                matches.sort_unstable_by_key(|(title, id)| (title.len(), *id));
+                matches.into_iter().take(SEARCH_LIMIT).map(|(_, id)| id).collect()
+            }
+        };
+        matches.into_iter().map(|id| json!({ "id": id, "title": self.titles.get(&id) })).collect()
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, graph: &Graph, page: &str) {
+    // Only the request line matters, the headers are read and dropped
+    let mut reader = BufReader::new(stream.try_clone().expect("Failed to clone connection"));
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() { return; }
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|length| length > 2) {
+        header.clear();
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query_string) = target.split_once('?').unwrap_or((target, ""));
+    let params: FxHashMap<String, String> = query_string.split('&').filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect();
+    info!(path, query_string, "request");
+
+    match path {
+        "/" => write_body(&mut stream, 200, "text/html; charset=utf-8", page),
+        "/neighbors" => {
+            let id = match (params.get("id"), params.get("title")) {
+                (Some(id), _) => id.parse().ok().filter(|id| graph.titles.contains_key(id)),
+                (None, Some(title)) => graph.find(title),
+                (None, None) => {
+                    write_response(&mut stream, 400, &json!({ "error": "Expected /neighbors?title=TITLE or /neighbors?id=ID" }));
+                    return;
+                }
+            };
+            let Some(id) = id else {
+                write_response(&mut stream, 404, &json!({ "error": "No such article" }));
+                return;
+            };
+            match graph.article(id) {
+                Ok(body) => write_response(&mut stream, 200, &body),
+                Err(error) => write_response(&mut stream, 500, &json!({ "error": error })),
+            }
+        }
+        "/search" => {
+            let query = params.get("q").map(String::as_str).unwrap_or("").trim();
+            write_response(&mut stream, 200, &Value::Array(if query.is_empty() { Vec::new() } else { graph.search(query) }));
+        }
+        _ => write_response(&mut stream, 404, &json!({ "error": format!("No route for {}", path) })),
+    }
+}
+
+// Serves a web page for exploring the link graph: starting from an article (the title after the data path, or one
+// searched for on the page), each click on a node adds the articles it links to and, optionally, the ones linking to
+// it, drawn with D3's force layout. Behind it, GET /neighbors?title=... (or ?id=...) returns an article's links and
+// backlinks as JSON, at most --max-neighbors of each with the most linked-to first, and GET /search?q=... completes
+// titles. The page loads D3 from a CDN, or from --d3 URL for use offline.
+pub fn viz(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let port: u16 = args.parse_value("port").unwrap_or(DEFAULT_PORT);
+    let Some(titles) = load_titles(data_path) else {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let links = LinkStore::open(data_path);
+    // Backlinks need the whole graph, so it's loaded now rather than on the first click
+    if let Err(err) = links.all_links() {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+    let graph = Arc::new(Graph {
+        links,
+        titles,
+        pages: load_page_info(data_path).unwrap_or_default(),
+        completer: TitleCompleter::open(data_path),
+        max_neighbors: args.parse_value("max-neighbors").unwrap_or(DEFAULT_MAX_NEIGHBORS),
+    });
+
+    let initial_title = args.positional[1..].join(" ");
+    let page = Arc::new(PAGE
+        .replace("D3_URL", args.value("d3").unwrap_or(D3_URL))
+        .replace("INITIAL_TITLE", &serde_json::to_string(&initial_title).unwrap().replace('<', "\\u003c")));
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|err| {
+        eprintln!("Error: Unable to listen on port {}: {}", port, err);
+        std::process::exit(1);
+    });
+    let query = if initial_title.is_empty() { String::new() } else { format!("?title={}", initial_title.replace(' ', "+")) };
+    println!("Serving the link graph of {} articles on http://127.0.0.1:{}/{}", graph.titles.len(), port, query);
+
+    let pool = ThreadPool::new(config().threads);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let graph = Arc::clone(&graph);
+                let page = Arc::clone(&page);
+                pool.execute(move || handle_connection(stream, &graph, &page));
+            }
+            Err(err) => warn!("Failed to accept connection: {}", err),
+        }
+    }
+}
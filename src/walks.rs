@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use indicatif::ProgressIterator;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use crate::graph::{build_dense_graph, build_undirected_graph};
+use crate::helpers::{Args, create_progress_bar};
+use crate::split::{load_graph, load_titles};
+
+// Walks are generated and written this many start nodes at a time, to bound memory
+const BLOCK_SIZE: usize = 65536;
+
+// A node2vec walk from `start`. After stepping from t to v, the next node x is picked from v's neighbors with weight
+// 1/p if x is t, 1 if x is also a neighbor of t, and 1/q otherwise, using rejection sampling so no per-edge tables
+// are needed. Neighbor lists must be sorted.
+fn walk<'a>(neighbors: &impl Fn(usize) -> &'a [u32], start: usize, length: usize, p: f64, q: f64, rng: &mut StdRng) -> Vec<u32> {
+    let mut path = vec![start as u32];
+    let max_weight = (1.0 / p).max(1.0).max(1.0 / q);
+    while path.len() < length {
+        let current = *path.last().unwrap() as usize;
+        let candidates = neighbors(current);
+        if candidates.is_empty() { break; }
+        let next = match path.len() {
+            1 => candidates[rng.gen_range(0..candidates.len())],
+            _ => {
+                let previous = path[path.len() - 2];
+                loop {
+                    let candidate = candidates[rng.gen_range(0..candidates.len())];
+                    let weight = if candidate == previous {
+                        1.0 / p
+                    } else if neighbors(previous as usize).binary_search(&candidate).is_ok() {
+                        1.0
+                    } else {
+                        1.0 / q
+                    };
+                    if rng.gen::<f64>() * max_weight < weight { break candidate; }
+                }
+            }
+        };
+        path.push(next);
+    }
+    path
+}
+
+pub fn walks(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let p: f64 = args.parse_value("p").unwrap_or(1.0);
+    let q: f64 = args.parse_value("q").unwrap_or(1.0);
+    let length: usize = args.parse_value("length").unwrap_or(80);
+    let walks_per_node: usize = args.parse_value("walks").unwrap_or(10);
+    let seed: u64 = args.parse_value("seed").unwrap_or(0);
+    let tokens = args.value("tokens").unwrap_or("ids");
+    if p.is_nan() || q.is_nan() || p <= 0.0 || q <= 0.0 || length < 2 || walks_per_node == 0 {
+        eprintln!("Error: --p and --q must be positive, --length at least 2 and --walks at least 1");
+        std::process::exit(1);
+    }
+    if tokens != "ids" && tokens != "titles" {
+        eprintln!("Error: Unknown token type {} (expected ids or titles)", tokens);
+        std::process::exit(1);
+    }
+    let output_path = args.value("output").map(PathBuf::from).unwrap_or_else(|| data_path.join("walks.txt"));
+
+    let Some(links) = load_graph(data_path) else {
+        eprintln!("Error: Unable to locate links.bin in {}, run the index command first", data_path.to_str().unwrap());
+        std::process::exit(1);
+    };
+    let graph = build_dense_graph(&links);
+    drop(links);
+
+    // Titles become single tokens by swapping spaces for underscores, as word2vec tools expect
+    let token_strings: Vec<String> = match tokens {
+        "titles" => {
+            let titles = load_titles(data_path).unwrap();
+            graph.ids.iter().map(|id| titles.get(id).map_or_else(|| id.to_string(), |title| title.replace(' ', "_"))).collect()
+        }
+        _ => graph.ids.iter().map(|id| id.to_string()).collect(),
+    };
+
+    // Walks follow links in either direction unless --directed is given
+    let undirected = (!args.flag("directed")).then(|| build_undirected_graph(&graph));
+    let neighbors = |node: usize| match &undirected {
+        Some(undirected) => undirected.neighbors(node),
+        None => graph.successors(node),
+    };
+
+    let mut output_file = BufWriter::new(File::create(&output_path).expect("Failed to create output file"));
+    let starts: Vec<usize> = (0..graph.node_count()).filter(|&node| !neighbors(node).is_empty()).collect();
+    let blocks = starts.chunks(BLOCK_SIZE).count() * walks_per_node;
+    let mut walk_count = 0;
+    for (round, block) in (0..walks_per_node).flat_map(|round| starts.chunks(BLOCK_SIZE).map(move |block| (round, block)))
+        .progress_with(create_progress_bar(blocks as u64, "Generating walks")) {
+        // Each walk gets its own RNG seeded from its start node and round, so the output doesn't depend on threading
+        let lines: Vec<String> = block.par_iter().map(|&start| {
+            let mut rng = StdRng::seed_from_u64(seed ^ ((round as u64) << 32) ^ start as u64);
+            let path = walk(&neighbors, start, length, p, q, &mut rng);
+            path.iter().map(|&node| token_strings[node as usize].as_str()).collect::<Vec<_>>().join(" ")
+        }).collect();
+        for line in &lines {
+            writeln!(output_file, "{}", line).expect("Failed to write output file");
+        }
+        walk_count += lines.len();
+    }
+    output_file.flush().expect("Failed to flush output file");
+    println!("Wrote {} walks of up to {} articles to {}", walk_count, length, output_path.to_str().unwrap());
+}
@@ -0,0 +1,232 @@
+use std::fs::{File, create_dir_all, read_dir, remove_dir_all, rename};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use crate::helpers::{Args, ProgressReader, create_progress_bar, hash_file};
+use crate::storage;
+#[cfg(feature = "async-io")]
+use crate::{aio, helpers::create_bytes_progress_bar};
+
+const DEFAULT_MIRROR: &str = "https://dumps.wikimedia.org";
+const DONE_FILE: &str = ".watch-done";  // marks a snapshot that was downloaded and processed
+const LATEST_LINK: &str = "latest";
+
+type DumpFile = (String, Option<String>);  // (name, md5)
+
+// Parses durations like 90s, 30m, 24h or 7d
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: u64 = value[..split].parse().ok()?;
+    let unit = match &value[split..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount * unit)).filter(|duration| !duration.is_zero())
+}
+
+// Returns the snapshot dates listed on the mirror's page for the wiki, newest first
+fn list_snapshots(mirror: &str, wiki: &str) -> Result<Vec<String>, String> {
+    let (mut reader, _) = storage::open(mirror).reader(&format!("{}/", wiki))?;
+    let mut listing = String::new();
+    reader.read_to_string(&mut listing).map_err(|err| err.to_string())?;
+    let mut dates: Vec<String> = listing.split("href=\"").skip(1)
+        .filter_map(|link| link.split('"').next()?.strip_suffix('/'))
+        .filter(|date| date.len() == 8 && date.bytes().all(|byte| byte.is_ascii_digit()))
+        .map(str::to_string)
+        .collect();
+    dates.sort_unstable_by(|a, b| b.cmp(a));
+    dates.dedup();
+    Ok(dates)
+}
+
+// Returns the (name, md5) of the multistream dump and its index if the jobs that write them have finished. They're
+// listed under whichever job produced them, which differs between small wikis and those whose dumps are split.
+fn finished_files(mirror: &str, wiki: &str, date: &str) -> Result<Option<Vec<DumpFile>>, String> {
+    let (mut reader, _) = storage::open(mirror).reader(&format!("{}/{}/dumpstatus.json", wiki, date))?;
+    let mut body = String::new();
+    reader.read_to_string(&mut body).map_err(|err| err.to_string())?;
+    let status: serde_json::Value = serde_json::from_str(&body).map_err(|err| format!("invalid dumpstatus.json: {}", err))?;
+    let jobs = status["jobs"].as_object().ok_or("dumpstatus.json has no jobs")?;
+
+    let prefix = format!("{}-{}", wiki, date);
+    let mut files = Vec::new();
+    for name in [format!("{}-pages-articles-multistream-index.txt.bz2", prefix), format!("{}-pages-articles-multistream.xml.bz2", prefix)] {
+        let Some(job) = jobs.values().find(|job| job["files"].get(&name).is_some()) else { return Ok(None) };
+        if job["status"].as_str() != Some("done") {
+            return Ok(None);
+        }
+        files.push((name.clone(), job["files"][&name]["md5"].as_str().map(str::to_string)));
+    }
+    Ok(Some(files))
+}
+
+fn download(mirror: &str, wiki: &str, date: &str, name: &str, md5: Option<&str>, snapshot_dir: &Path) -> Result<(), String> {
+    if snapshot_dir.join(name).exists() {
+        return Ok(());
+    }
+    let (mut reader, length) = storage::open(mirror).reader(&format!("{}/{}/{}", wiki, date, name))?;
+    let mut file = File::create(snapshot_dir.join(format!("{}.partial", name))).map_err(|err| err.to_string())?;
+    let progress_bar = create_progress_bar(length.unwrap_or(0), &format!("Downloading {}", name));
+    std::io::copy(&mut ProgressReader::new(&mut reader, progress_bar), &mut file).map_err(|err| err.to_string())?;
+    finish_download(name, md5, snapshot_dir)
+}
+
+// Checks a downloaded file against its MD5 and moves it into place
+fn finish_download(name: &str, md5: Option<&str>, snapshot_dir: &Path) -> Result<(), String> {
+    let partial_path = snapshot_dir.join(format!("{}.partial", name));
+    if let Some(expected) = md5 {
+        let actual = hash_file(&partial_path);
+        if actual != expected {
+            std::fs::remove_file(&partial_path).ok();
+            return Err(format!("{} has MD5 {} but dumpstatus.json lists {}", name, actual, expected));
+        }
+    }
+    rename(&partial_path, snapshot_dir.join(name)).map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "async-io"))]
+fn download_files(mirror: &str, wiki: &str, date: &str, files: &[DumpFile], snapshot_dir: &Path) -> Result<(), String> {
+    files.iter().try_for_each(|(name, md5)| download(mirror, wiki, date, name, md5.as_deref(), snapshot_dir))
+}
+
+// Downloads the files at the same time, streaming each one to disk on the async runtime. A mirror that's a local
+// directory is copied from one file at a time.
+#[cfg(feature = "async-io")]
+fn download_files(mirror: &str, wiki: &str, date: &str, files: &[DumpFile], snapshot_dir: &Path) -> Result<(), String> {
+    let storage = storage::open(mirror);
+    let files: Vec<&DumpFile> = files.iter().filter(|(name, _)| !snapshot_dir.join(name).exists()).collect();
+    let Some(requests) = files.iter().map(|(name, _)| storage.http_request(&format!("{}/{}/{}", wiki, date, name), None)).collect::<Option<Vec<_>>>() else {
+        return files.iter().try_for_each(|(name, md5)| download(mirror, wiki, date, name, md5.as_deref(), snapshot_dir));
+    };
+    let progress_bar = create_bytes_progress_bar(0, &format!("Downloading {} files", files.len()));
+    aio::runtime().block_on(async {
+        let mut downloads = tokio::task::JoinSet::new();
+        for ((name, _), request) in files.iter().zip(requests) {
+            downloads.spawn(aio::download(request, snapshot_dir.join(format!("{}.partial", name)), progress_bar.clone()));
+        }
+        while let Some(result) = downloads.join_next().await {
+            result.map_err(|err| err.to_string())??;
+        }
+        Ok::<_, String>(())
+    })?;
+    progress_bar.finish_and_clear();
+    files.iter().try_for_each(|(name, md5)| finish_download(name, md5.as_deref(), snapshot_dir))
+}
+
+// Runs another command of this program on a snapshot, as a separate process so each one gets its own settings
+fn run(args: &[String]) -> Result<(), String> {
+    info!("Running {}", args.join(" "));
+    let executable = std::env::current_exe().map_err(|err| err.to_string())?;
+    let status = Command::new(executable).args(args).status().map_err(|err| err.to_string())?;
+    if status.success() { Ok(()) } else { Err(format!("{} exited with {}", args[0], status)) }
+}
+
+fn process_snapshot(args: &Args, data_path: &Path, mirror: &str, wiki: &str, date: &str, files: &[DumpFile]) -> Result<(), String> {
+    let snapshot_dir = data_path.join(date);
+    create_dir_all(&snapshot_dir).map_err(|err| err.to_string())?;
+    download_files(mirror, wiki, date, files, &snapshot_dir)?;
+
+    let snapshot = snapshot_dir.to_str().unwrap().to_string();
+    let mut shared = vec!["--dump-prefix".to_string(), format!("{}-{}", wiki, date)];
+    for option in ["config", "threads"] {
+        if let Some(value) = args.value(option) {
+            shared.extend([format!("--{}", option), value.to_string()]);
+        }
+    }
+    run(&[["index".to_string(), snapshot.clone()].as_slice(), &shared].concat())?;
+    for format in args.value("exports").into_iter().flat_map(|formats| formats.split(',')) {
+        let output = snapshot_dir.join(format).to_str().unwrap().to_string();
+        run(&[["export".to_string(), snapshot.clone(), "--format".to_string(), format.to_string(), "--output".to_string(), output].as_slice(), &shared].concat())?;
+    }
+    File::create(snapshot_dir.join(DONE_FILE)).map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// Points `latest` at the newest processed snapshot and deletes all but the newest `keep` of them
+fn rotate(data_path: &Path, keep: usize) {
+    let mut processed: Vec<PathBuf> = read_dir(data_path).into_iter().flatten().map_while(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.join(DONE_FILE).exists() && !path.is_symlink())
+        .collect();
+    processed.sort_unstable_by(|a, b| b.cmp(a));
+    let Some(newest) = processed.first() else { return };
+
+    // Swap the link in one step so readers never see it missing
+    let latest = data_path.join(LATEST_LINK);
+    let temporary = data_path.join(format!("{}.new", LATEST_LINK));
+    std::fs::remove_file(&temporary).ok();
+    #[cfg(unix)]
+    if let Err(err) = std::os::unix::fs::symlink(newest.file_name().unwrap(), &temporary).and_then(|_| rename(&temporary, &latest)) {
+        warn!("Failed to point {} at {}: {}", latest.to_str().unwrap(), newest.to_str().unwrap(), err);
+    }
+    for old in processed.iter().skip(keep) {
+        info!("Removing old snapshot {}", old.to_str().unwrap());
+        if let Err(err) = remove_dir_all(old) {
+            warn!("Failed to remove {}: {}", old.to_str().unwrap(), err);
+        }
+    }
+}
+
+// Processes the newest finished snapshot, unless one at least as new has already been processed. Snapshots are
+// listed as soon as they start, so the newest one is often still being written.
+fn check_snapshots(args: &Args, data_path: &Path, mirror: &str, wiki: &str, keep: usize) {
+    let dates = match list_snapshots(mirror, wiki) {
+        Ok(dates) => dates,
+        Err(err) => {
+            warn!("Failed to list {} snapshots on {}: {}", wiki, mirror, err);
+            return;
+        }
+    };
+    for date in &dates {
+        if data_path.join(date).join(DONE_FILE).exists() {
+            info!("No snapshots of {} newer than {} are finished", wiki, date);
+            return;
+        }
+        let files = match finished_files(mirror, wiki, date) {
+            Ok(Some(files)) => files,
+            Ok(None) => { info!("{} {} isn't finished yet", wiki, date); continue; }
+            Err(err) => { warn!("Failed to check the status of {} {}: {}", wiki, date, err); continue; }
+        };
+        match process_snapshot(args, data_path, mirror, wiki, date, &files) {
+            Ok(()) => {
+                info!("Processed {} {}", wiki, date);
+                rotate(data_path, keep);
+            }
+            Err(err) => error!("Failed to process {} {}, will retry: {}", wiki, date, err),
+        }
+        return;
+    }
+    warn!("No finished snapshots of {} found on {}", wiki, mirror);
+}
+
+// Checks for new snapshots of a wiki every --every interval and, once a multistream dump is finished, downloads it
+// into data_path/DATE, indexes it, runs any --exports and rotates: data_path/latest points at the newest processed
+// snapshot and only the newest --keep are kept. A snapshot that fails is tried again at the next check.
+pub fn watch(args: &Args) {
+    let data_path = Path::new(&args.positional[0]);
+    let wiki = args.value("wiki").unwrap_or("enwiki");
+    let mirror = args.value("mirror").unwrap_or(DEFAULT_MIRROR).trim_end_matches('/');
+    let every = args.value("every").map_or(Some(Duration::from_secs(24 * 60 * 60)), parse_duration).unwrap_or_else(|| {
+        eprintln!("Error: Invalid value for --every: {} (expected a duration like 30m, 24h or 7d)", args.value("every").unwrap());
+        std::process::exit(1);
+    });
+    let keep: usize = args.parse_value("keep").unwrap_or(2);
+    if keep == 0 {
+        eprintln!("Error: --keep must be at least 1");
+        std::process::exit(1);
+    }
+    create_dir_all(data_path).expect("Failed to create data directory");
+
+    loop {
+        check_snapshots(args, data_path, mirror, wiki, keep);
+        if args.flag("once") {
+            break;
+        }
+        std::thread::sleep(every);
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+use serde_json::json;
+use crate::corpus::plain_text;
+use crate::helpers::PageId;
+
+// Headings that start a part-of-speech section, from the Wiktionary entry layout guide. They sit at level 3 under
+// the language heading, or at level 4 when a word has several etymologies.
+const PARTS_OF_SPEECH: [&str; 37] = [
+    "Noun", "Proper noun", "Verb", "Adjective", "Adverb", "Pronoun", "Preposition", "Postposition", "Conjunction",
+    "Interjection", "Determiner", "Article", "Numeral", "Particle", "Participle", "Classifier", "Counter", "Prefix",
+    "Suffix", "Infix", "Interfix", "Circumfix", "Affix", "Root", "Phrase", "Prepositional phrase", "Proverb", "Idiom",
+    "Contraction", "Abbreviation", "Initialism", "Acronym", "Symbol", "Letter", "Punctuation mark", "Romanization",
+    "Han character",
+];
+
+pub struct Entry {
+    pub language: String,
+    pub part_of_speech: String,
+    pub definitions: Vec<String>,
+}
+
+// Splits a Wiktionary page into one entry per language and part of speech, with the plain text of its numbered
+// definitions. Definitions that consist only of a template, like {{plural of|en|cat}}, come out empty and are left
+// out, along with the example sentences and quotations under each definition.
+pub fn extract_entries(text: &str) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut language = None;
+    let mut in_part_of_speech = false;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.starts_with("==") && line.ends_with("==") && line.len() > 4 {
+            let level = line.len() - line.trim_start_matches('=').len();
+            let heading = line.trim_matches('=').trim();
+            if level == 2 {
+                language = Some(heading.to_string());
+            }
+            in_part_of_speech = level > 2 && PARTS_OF_SPEECH.contains(&heading);
+            if let (true, Some(language)) = (in_part_of_speech, &language) {
+                entries.push(Entry { language: language.clone(), part_of_speech: heading.to_string(), definitions: Vec::new() });
+            }
+        } else if in_part_of_speech && line.starts_with('#') {
+            let definition = line.trim_start_matches('#');
+            if definition.starts_with([':', '*']) { continue; }
+            let definition = plain_text(definition);
+            if !definition.is_empty() {
+                entries.last_mut().unwrap().definitions.push(definition);
+            }
+        }
+    }
+    entries.retain(|entry| !entry.definitions.is_empty());
+    entries
+}
+
+// Writes a page's entries as NDJSON, one object per language and part of speech, keeping only the given language if
+// there is one
+pub fn write_entries(output: &mut Vec<u8>, id: PageId, title: &str, text: &str, language: Option<&str>) {
+    for entry in extract_entries(text) {
+        if language.is_some_and(|language| language != entry.language) { continue; }
+        let record = json!({ "id": id, "title": title, "language": entry.language, "part_of_speech": entry.part_of_speech, "definitions": entry.definitions });
+        writeln!(output, "{}", record).expect("Failed to encode entry");
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, create_dir_all};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::config::config;
+use crate::helpers::Args;
+use crate::site::{SiteFile, SiteOutput, render_site};
+
+// The ZIM archive format read by Kiwix and other offline readers, as described at https://wiki.openzim.org/wiki/ZIM_file_format.
+// This writes version 6.1, where all the content is in the C namespace, metadata in M and the main page in W.
+const MAGIC: u32 = 72173914;
+const MAJOR_VERSION: u16 = 6;
+const MINOR_VERSION: u16 = 1;
+const HEADER_SIZE: u64 = 80;
+const NO_PAGE: u32 = u32::MAX;
+const REDIRECT: u16 = 0xffff;  // the MIME type field of redirect entries
+const ZSTD_COMPRESSION: u8 = 5;
+// Blobs are grouped into clusters of about this many bytes before compression, as libzim does
+const CLUSTER_SIZE: usize = 2 << 20;
+const MIME_TYPES: [&str; 5] = ["text/html", "text/css", "application/javascript", "text/plain", "application/octet-stream+zimlisting"];
+
+// ISO 639-3 codes for the languages of the largest wikis, since ZIM metadata uses those rather than the wiki's code
+const LANGUAGES: [(&str, &str); 16] = [
+    ("en", "eng"), ("de", "deu"), ("fr", "fra"), ("es", "spa"), ("it", "ita"), ("ja", "jpn"), ("ru", "rus"), ("pt", "por"),
+    ("zh", "zho"), ("nl", "nld"), ("pl", "pol"), ("sv", "swe"), ("ar", "ara"), ("uk", "ukr"), ("vi", "vie"), ("fa", "fas"),
+];
+
+enum Target {
+    Blob { mime_type: u16, cluster: u32, blob: u32 },
+    Redirect(u8, String),  // the namespace and path of the entry it points to
+}
+
+struct Entry {
+    namespace: u8,
+    path: String,
+    title: String,  // empty when it's the same as the path
+    target: Target,
+}
+
+// Prefixes a cluster's blobs with their offsets and compresses it
+fn compress_cluster(blobs: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 * (blobs.len() + 1) + blobs.iter().map(Vec::len).sum::<usize>());
+    let mut offset = 4 * (blobs.len() + 1);
+    for blob in blobs {
+        data.extend((offset as u32).to_le_bytes());
+        offset += blob.len();
+    }
+    data.extend((offset as u32).to_le_bytes());
+    for blob in blobs {
+        data.extend(blob);
+    }
+    let mut cluster = vec![ZSTD_COMPRESSION];
+    cluster.extend(zstd::bulk::compress(&data, 0).expect("Failed to compress cluster"));
+    cluster
+}
+
+// Writes clusters to the file as they fill up, keeping the directory entries in memory until the archive is finished,
+// when they're sorted and written after the clusters along with the pointer lists and the header
+struct ZimWriter {
+    file: BufWriter<File>,
+    position: u64,
+    entries: Vec<Entry>,
+    cluster_positions: Vec<u64>,
+}
+
+impl ZimWriter {
+    fn create(path: &Path) -> Self {
+        let mut file = BufWriter::new(File::create(path).expect("Failed to create ZIM file"));
+        file.write_all(&[0; HEADER_SIZE as usize]).expect("Failed to write ZIM file");
+        let mut position = HEADER_SIZE;
+        for mime_type in MIME_TYPES {
+            file.write_all(mime_type.as_bytes()).and_then(|_| file.write_all(&[0])).expect("Failed to write ZIM file");
+            position += mime_type.len() as u64 + 1;
+        }
+        file.write_all(&[0]).expect("Failed to write ZIM file");
+        ZimWriter { file, position: position + 1, entries: Vec::new(), cluster_positions: Vec::new() }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.file.write_all(bytes).expect("Failed to write ZIM file");
+        self.position += bytes.len() as u64;
+    }
+
+    // Writes a compressed cluster and adds an entry for each of its blobs, given by (namespace, path, title, MIME type)
+    fn add_cluster(&mut self, cluster: &[u8], blobs: Vec<(u8, String, String, &str)>) {
+        let cluster_number = self.cluster_positions.len() as u32;
+        self.cluster_positions.push(self.position);
+        self.write(cluster);
+        for (blob, (namespace, path, title, mime_type)) in blobs.into_iter().enumerate() {
+            let mime_type = MIME_TYPES.iter().position(|known| *known == mime_type).expect("Unknown MIME type") as u16;
+            let title = if title == path { String::new() } else { title };
+            self.entries.push(Entry { namespace, path, title, target: Target::Blob { mime_type, cluster: cluster_number, blob: blob as u32 } });
+        }
+    }
+
+    fn finish(mut self, path: &Path, metadata: &[(&str, String)]) {
+        let (blobs, contents): (Vec<_>, Vec<_>) = metadata.iter()
+            .map(|(name, value)| ((b'M', name.to_string(), String::new(), "text/plain"), value.clone().into_bytes()))
+            .unzip();
+        self.add_cluster(&compress_cluster(&contents), blobs);
+
+        // The listing of front articles, the ones with titles, goes in a cluster of its own once the entries are sorted
+        let listing_cluster = self.cluster_positions.len() as u32;
+        let listing_mime_type = MIME_TYPES.iter().position(|known| known.ends_with("zimlisting")).unwrap() as u16;
+        self.entries.push(Entry {
+            namespace: b'X', path: "listing/titleOrdered/v1".to_string(), title: String::new(),
+            target: Target::Blob { mime_type: listing_mime_type, cluster: listing_cluster, blob: 0 },
+        });
+        self.entries.push(Entry { namespace: b'W', path: "mainPage".to_string(), title: String::new(), target: Target::Redirect(b'C', "index.html".to_string()) });
+        // A redirect to a page that wasn't rendered, like one outside the main namespace, would leave the archive
+        // invalid, and so would a redirect to a redirect that's dropped for that reason
+        loop {
+            let paths: HashSet<(u8, String)> = self.entries.iter().map(|entry| (entry.namespace, entry.path.clone())).collect();
+            let count = self.entries.len();
+            self.entries.retain(|entry| match &entry.target {
+                Target::Redirect(namespace, target) => paths.contains(&(*namespace, target.clone())),
+                Target::Blob { .. } => true,
+            });
+            if self.entries.len() == count { break; }
+        }
+        self.entries.sort_unstable_by(|a, b| (a.namespace, &a.path).cmp(&(b.namespace, &b.path)));
+        let entries = std::mem::take(&mut self.entries);
+        let indexes: HashMap<(u8, &str), u32> = entries.iter().enumerate().map(|(index, entry)| ((entry.namespace, entry.path.as_str()), index as u32)).collect();
+        let title_order = |indexes: &mut Vec<u32>, entries: &[Entry]| {
+            let title = |index: &u32| { let entry = &entries[*index as usize]; (entry.namespace, if entry.title.is_empty() { &entry.path } else { &entry.title }) };
+            indexes.sort_by(|a, b| title(a).cmp(&title(b)));
+        };
+        let mut front_articles: Vec<u32> = (0..entries.len() as u32)
+            .filter(|&index| entries[index as usize].namespace == b'C' && !entries[index as usize].title.is_empty())
+            .collect();
+        title_order(&mut front_articles, &entries);
+        let listing: Vec<u8> = front_articles.iter().flat_map(|index| index.to_le_bytes()).collect();
+        self.cluster_positions.push(self.position);
+        self.write(&compress_cluster(&[listing]));
+
+        let mut entry_positions = Vec::with_capacity(entries.len());
+        let mut buffer = Vec::new();
+        for entry in &entries {
+            entry_positions.push(self.position + buffer.len() as u64);
+            match &entry.target {
+                Target::Blob { mime_type, cluster, blob } => {
+                    buffer.extend(mime_type.to_le_bytes());
+                    buffer.extend([0, entry.namespace]);  // no extra parameters
+                    buffer.extend(0u32.to_le_bytes());  // revision
+                    buffer.extend(cluster.to_le_bytes());
+                    buffer.extend(blob.to_le_bytes());
+                }
+                Target::Redirect(namespace, target) => {
+                    let index = indexes[&(*namespace, target.as_str())];
+                    buffer.extend(REDIRECT.to_le_bytes());
+                    buffer.extend([0, entry.namespace]);
+                    buffer.extend(0u32.to_le_bytes());
+                    buffer.extend(index.to_le_bytes());
+                }
+            }
+            buffer.extend(entry.path.as_bytes());
+            buffer.push(0);
+            buffer.extend(entry.title.as_bytes());
+            buffer.push(0);
+        }
+        self.write(&buffer);
+
+        let url_pointers_position = self.position;
+        let pointers: Vec<u8> = entry_positions.iter().flat_map(|position| position.to_le_bytes()).collect();
+        self.write(&pointers);
+        let title_pointers_position = self.position;
+        let mut title_pointers: Vec<u32> = (0..entries.len() as u32).collect();
+        title_order(&mut title_pointers, &entries);
+        self.write(&title_pointers.iter().flat_map(|index| index.to_le_bytes()).collect::<Vec<u8>>());
+        let cluster_pointers_position = self.position;
+        self.write(&self.cluster_positions.iter().flat_map(|position| position.to_le_bytes()).collect::<Vec<u8>>());
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend(MAGIC.to_le_bytes());
+        header.extend(MAJOR_VERSION.to_le_bytes());
+        header.extend(MINOR_VERSION.to_le_bytes());
+        header.extend(rand::random::<[u8; 16]>());  // UUID
+        header.extend((entries.len() as u32).to_le_bytes());
+        header.extend((self.cluster_positions.len() as u32).to_le_bytes());
+        header.extend(url_pointers_position.to_le_bytes());
+        header.extend(title_pointers_position.to_le_bytes());
+        header.extend(cluster_pointers_position.to_le_bytes());
+        header.extend(HEADER_SIZE.to_le_bytes());  // the MIME type list comes straight after the header
+        header.extend(indexes[&(b'W', "mainPage")].to_le_bytes());
+        header.extend(NO_PAGE.to_le_bytes());  // layout page
+        header.extend(self.position.to_le_bytes());  // the checksum goes at the end
+        let mut file = self.file.into_inner().expect("Failed to write ZIM file");
+        file.seek(SeekFrom::Start(0)).and_then(|_| file.write_all(&header)).expect("Failed to write ZIM file");
+        drop(file);
+
+        // The archive ends with the MD5 of everything before it
+        let mut context = md5::Context::new();
+        std::io::copy(&mut File::open(path).expect("Failed to read ZIM file"), &mut context).expect("Failed to read ZIM file");
+        let mut file = OpenOptions::new().append(true).open(path).expect("Failed to open ZIM file");
+        file.write_all(&context.finalize().0).expect("Failed to write ZIM file");
+    }
+}
+
+struct ZimArchive(Mutex<ZimWriter>);
+
+impl SiteOutput for ZimArchive {
+    // Compresses the batch's clusters before taking the lock, so the threads rendering articles aren't held up
+    fn add(&self, files: Vec<SiteFile>) {
+        let mut clusters = vec![(Vec::new(), Vec::new(), 0)];
+        let mut redirects = Vec::new();
+        for file in files {
+            match file {
+                SiteFile::Data { path, title, mime_type, contents } => {
+                    let (blobs, contents_list, size) = clusters.last_mut().unwrap();
+                    *size += contents.len();
+                    blobs.push((b'C', path, title, mime_type));
+                    contents_list.push(contents);
+                    if *size >= CLUSTER_SIZE {
+                        clusters.push((Vec::new(), Vec::new(), 0));
+                    }
+                }
+                SiteFile::Redirect { path, title, target, .. } => {
+                    let target = target.split('#').next().unwrap().to_string();
+                    redirects.push(Entry { namespace: b'C', path, title, target: Target::Redirect(b'C', target) });
+                }
+            }
+        }
+        let clusters: Vec<_> = clusters.into_iter()
+            .filter(|(blobs, _, _)| !blobs.is_empty())
+            .map(|(blobs, contents, _)| (compress_cluster(&contents), blobs))
+            .collect();
+        let mut writer = self.0.lock().unwrap();
+        for (cluster, blobs) in clusters {
+            writer.add_cluster(&cluster, blobs);
+        }
+        writer.entries.extend(redirects);
+    }
+}
+
+// The wiki's language as an ISO 639-3 code, from --language or the dump prefix like enwiki-20240801
+fn language(args: &Args) -> String {
+    if let Some(language) = args.value("language") {
+        return language.to_string();
+    }
+    let code: String = config().dump_prefix.chars().take_while(|c| c.is_ascii_lowercase()).collect();
+    let code = code.strip_suffix("wiktionary").or_else(|| code.strip_suffix("wiki")).unwrap_or(&code);
+    LANGUAGES.iter().find(|(wiki, _)| *wiki == code).map_or(code.to_string(), |(_, iso)| iso.to_string())
+}
+
+// Packages the pages build-site renders into output_dir/<dump prefix>.zim for Kiwix and other offline readers, with
+// the same --titles and --quality selection. Kiwix's own title search works over the article titles, and the search
+// page at the main page works too in readers that run scripts.
+pub fn export_zim(args: &Args, data_path: &Path, output_dir: &Path) {
+    let prefix = &config().dump_prefix;
+    let date = prefix.rsplit('-').next().filter(|date| date.len() == 8 && date.bytes().all(|c| c.is_ascii_digit()))
+        .map(|date| format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]));
+    let Some(date) = args.value("date").map(str::to_string).or(date) else {
+        eprintln!("Error: Unable to read a date from the dump prefix {}, pass --date YYYY-MM-DD", prefix);
+        std::process::exit(1);
+    };
+    create_dir_all(output_dir).expect("Failed to create output directory");
+    let output_path: PathBuf = output_dir.join(format!("{}.zim", prefix));
+    let archive = Arc::new(ZimArchive(Mutex::new(ZimWriter::create(&output_path))));
+    let (articles, redirects) = render_site(args, data_path, archive.clone());
+
+    let name = prefix.split('-').next().unwrap();
+    let metadata = [
+        ("Name", name.to_string()),
+        ("Title", format!("{} {}", name, date)),
+        ("Description", format!("Articles from the {} dump", prefix)),
+        ("Language", language(args)),
+        ("Creator", "Wikipedia".to_string()),
+        ("Publisher", "wikipedia".to_string()),
+        ("Date", date),
+        ("Scraper", format!("wikipedia {}", env!("CARGO_PKG_VERSION"))),
+    ];
+    let writer = Arc::into_inner(archive).expect("ZIM archive still in use").0.into_inner().unwrap();
+    writer.finish(&output_path, &metadata);
+    println!("Packaged {} articles and {} redirects into {}", articles, redirects, output_path.to_str().unwrap());
+}
@@ -0,0 +1,127 @@
+// Runs the pipeline end to end on a dump from gen-testdata: index, analyse and dump through the binary, then reads
+// the files they wrote back through the library.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wikipedia::links::{LinksVersion, PageId, get_article_byte_string, load_links, parse_record, read_header};
+use wikipedia::split::RecordIndex;
+
+const ARTICLES: u32 = 200;
+const CHUNK_SIZE: u32 = 50;
+
+// A fresh directory under the system temp directory, removed when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("wikipedia-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Failed to create temp directory");
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Runs a command from the temp directory, so a wikipedia.toml in the working directory can't change the paths
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_wikipedia")).args(args).arg("-q").current_dir(dir).output().expect("Failed to run wikipedia");
+    assert!(output.status.success(), "wikipedia {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("Output isn't valid UTF-8")
+}
+
+fn generate(dir: &Path, name: &str, seed: u64) -> PathBuf {
+    let (articles, chunk_size, seed) = (ARTICLES.to_string(), CHUNK_SIZE.to_string(), seed.to_string());
+    run(dir, &["gen-testdata", name, "--articles", &articles, "--chunk-size", &chunk_size, "--seed", &seed]);
+    dir.join(name)
+}
+
+#[test]
+fn gen_testdata_is_deterministic() {
+    let dir = TempDir::new("deterministic");
+    let first = generate(&dir.0, "first", 7);
+    let second = generate(&dir.0, "second", 7);
+    let other = generate(&dir.0, "other", 8);
+    let files = |path: &Path| {
+        let mut files: Vec<(String, Vec<u8>)> = fs::read_dir(path).unwrap()
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.file_name().into_string().unwrap(), fs::read(entry.path()).unwrap()))
+            .collect();
+        files.sort();
+        files
+    };
+    assert_eq!(files(&first).len(), 2);
+    assert_eq!(files(&first), files(&second));
+    assert_ne!(files(&first), files(&other));
+}
+
+#[test]
+fn index_analyse_and_dump_test_data() {
+    let dir = TempDir::new("pipeline");
+    let data_path = generate(&dir.0, "data", 0);
+    let data = data_path.to_str().unwrap();
+
+    run(&dir.0, &["index", data]);
+    for file_name in ["links.bin", "titles.bin", "graph.bin", "offsets.idx", "titles.fst"] {
+        assert!(data_path.join(file_name).exists(), "index didn't write {}", file_name);
+    }
+
+    // Every record in links.bin encodes back to the same bytes
+    let buffer = fs::read(data_path.join("links.bin")).unwrap();
+    let (version, mut offset) = read_header(&buffer).unwrap();
+    assert_eq!(version, LinksVersion::V5);
+    let mut records = Vec::new();
+    while offset < buffer.len() {
+        let (record, next) = parse_record(&buffer, offset, version).unwrap();
+        assert_eq!(get_article_byte_string(record.article_id, &record.title, &record.info, &record.links), &buffer[offset..next]);
+        records.push(record);
+        offset = next;
+    }
+
+    // Every tenth page is a category and every tenth a template, and only mainspace pages get records
+    let mainspace = (1..=ARTICLES).filter(|id| id % 10 != 0 && id % 10 != 5).count();
+    assert_eq!(records.len(), mainspace);
+    assert!(records.iter().all(|record| record.info.namespace == 0));
+    assert!(records.iter().any(|record| record.info.redirect));
+    assert!(records.iter().any(|record| !record.links.is_empty()));
+
+    // The whole-file loader and the split files agree with the records
+    let graph = load_links(&data_path.join("links.bin"));
+    let record_index = RecordIndex::open(&data_path);
+    for record in &records {
+        assert_eq!(graph.titles[&record.article_id], record.title);
+        assert_eq!(graph.pages[&record.article_id], record.info);
+        assert_eq!(graph.links[&record.article_id], record.links);
+        assert_eq!(record_index.title(record.article_id).as_ref(), Some(&record.title));
+        assert_eq!(record_index.links(record.article_id).as_ref(), Some(&record.links));
+    }
+    assert_eq!(record_index.title(ARTICLES as PageId + 1), None);
+
+    let analysis = run(&dir.0, &["analyse", data]);
+    let total_links: usize = records.iter().map(|record| record.links.len()).sum();
+    assert!(analysis.contains(&format!("Total articles: {}\n", records.len())), "{}", analysis);
+    assert!(analysis.contains(&format!("Total links: {}\n", total_links)), "{}", analysis);
+    assert!(data_path.join("broken-redirects.tsv").exists());
+    assert!(data_path.join("double-redirects.tsv").exists());
+
+    // Each chunk of the dump becomes one file, with a JSON header line before each article's text
+    run(&dir.0, &["dump", data, "--metadata", "json"]);
+    let chunks_path = data_path.join("chunks");
+    let mut dumped = HashMap::new();
+    for chunk in 0..ARTICLES.div_ceil(CHUNK_SIZE) {
+        let contents = fs::read_to_string(chunks_path.join(format!("{:0>6}.txt", chunk))).unwrap();
+        for header in contents.lines().filter(|line| line.starts_with("{\"id\"")) {
+            let header: serde_json::Value = serde_json::from_str(header).unwrap();
+            dumped.insert(header["id"].as_u64().unwrap() as PageId, header["title"].as_str().unwrap().to_string());
+        }
+    }
+    let expected: HashMap<PageId, String> = records.iter().map(|record| (record.article_id, record.title.clone())).collect();
+    assert_eq!(dumped, expected);
+    let manifest = fs::read_to_string(chunks_path.join("manifest.txt")).unwrap();
+    assert_eq!(manifest.lines().count() as u32, ARTICLES.div_ceil(CHUNK_SIZE));
+}
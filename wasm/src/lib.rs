@@ -0,0 +1,87 @@
+// WebAssembly bindings for querying the files the index command writes, once a page has fetched them as static files.
+// Build with `wasm-pack build --target web --release` from this directory, then:
+//
+//   import init, { Graph } from "./pkg/wikipedia_wasm.js";
+//   await init();
+//   const bytes = new Uint8Array(await (await fetch("links.bin")).arrayBuffer());
+//   const graph = new Graph(bytes);
+//   graph.shortestPath(graph.find("Alan Turing"), graph.find("Philosophy")).map(id => graph.title(id));
+//
+// Records answers lookups by ID straight from offsets.idx, titles.bin and graph.bin without decoding them up front.
+
+use std::collections::HashMap;
+use rustc_hash::FxHashMap;
+use wasm_bindgen::prelude::*;
+use wikipedia::links::PageId;
+use wikipedia::query::{RecordReader, backlinks, parse_link_graph, shortest_path};
+
+// The whole link graph from links.bin, with titles and backlinks
+#[wasm_bindgen]
+pub struct Graph {
+    links: FxHashMap<PageId, Vec<PageId>>,
+    backlinks: FxHashMap<PageId, Vec<PageId>>,
+    titles: FxHashMap<PageId, String>,
+    ids: HashMap<String, PageId>,  // lowercase title -> id
+}
+
+#[wasm_bindgen]
+impl Graph {
+    #[wasm_bindgen(constructor)]
+    pub fn new(links_file: &[u8]) -> Result<Graph, JsError> {
+        let graph = parse_link_graph(links_file).map_err(|err| JsError::new(&err))?;
+        let ids = graph.titles.iter().map(|(id, title)| (title.to_lowercase(), *id)).collect();
+        Ok(Graph { backlinks: backlinks(&graph.links), links: graph.links, titles: graph.titles, ids })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.links.len()
+    }
+
+    // The ID of the article with the given title, ignoring case
+    pub fn find(&self, title: &str) -> Option<PageId> {
+        self.ids.get(&title.trim().to_lowercase()).copied()
+    }
+
+    pub fn title(&self, id: PageId) -> Option<String> {
+        self.titles.get(&id).cloned()
+    }
+
+    pub fn links(&self, id: PageId) -> Vec<PageId> {
+        self.links.get(&id).cloned().unwrap_or_default()
+    }
+
+    pub fn backlinks(&self, id: PageId) -> Vec<PageId> {
+        self.backlinks.get(&id).cloned().unwrap_or_default()
+    }
+
+    // The IDs of the articles on a shortest path of links from `source` to `target`, both included, or undefined if
+    // `target` can't be reached
+    #[wasm_bindgen(js_name = shortestPath)]
+    pub fn shortest_path(&self, source: PageId, target: PageId) -> Option<Vec<PageId>> {
+        shortest_path(&self.links, source, target)
+    }
+}
+
+// Titles and outgoing links by article ID from the split files
+#[wasm_bindgen]
+pub struct Records {
+    reader: RecordReader<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl Records {
+    #[wasm_bindgen(constructor)]
+    pub fn new(offsets_file: Vec<u8>, titles_file: Vec<u8>, graph_file: Vec<u8>) -> Result<Records, JsError> {
+        let reader = RecordReader::new(offsets_file, titles_file, graph_file).map_err(|err| JsError::new(&err))?;
+        Ok(Records { reader })
+    }
+
+    pub fn title(&self, id: PageId) -> Result<Option<String>, JsError> {
+        self.reader.title(id).map_err(|err| JsError::new(&err))
+    }
+
+    pub fn links(&self, id: PageId) -> Result<Option<Vec<PageId>>, JsError> {
+        self.reader.links(id).map_err(|err| JsError::new(&err))
+    }
+}